@@ -0,0 +1,113 @@
+//! CPU throughput: Klaus Dormann's 6502 functional test, if it has been
+//! checked out, and instruction mixes that each lean on one part of the
+//! decoding and dispatch path. Everything is measured in emulated cycles
+//! per second, on a flat 64KB memory so the bus costs little.
+//!
+//! ```text
+//! cargo bench --bench cpu [FILTER...]
+//! ```
+
+mod harness;
+
+use harness::Harness;
+use nesters::asm;
+use nesters::bus::BusEvent;
+use nesters::cpu::{Cpu, Engine, Variant};
+
+const IMAGE: &str = "6502_65C02_functional_tests/bin_files/6502_functional_test.bin";
+
+/// Cycles run per sample. The functional test takes about 96 million in
+/// all, so this is its opening stretch, which covers every official
+/// opcode.
+const CYCLES: u64 = 10_000_000;
+
+fn main() {
+    let harness = Harness::from_args();
+
+    match std::fs::read(IMAGE) {
+        Ok(mut image) => {
+            image.resize(0x10000, 0);
+            run(&harness, "functional", &image, 0x0400);
+        }
+        Err(_) => eprintln!("skipping functional: couldn't read {IMAGE}"),
+    }
+
+    let alu = asm!(
+        ".org $0200",
+        "loop: LDA $10",
+        "CLC; ADC #$37; EOR $11; AND #$F7; ORA $12,X",
+        "SEC; SBC $0300,Y; CMP #$40; CPX $13; BIT $14",
+        "STA $10; SED; ADC #$19; CLD; STA $11",
+        "TAX; INY; DEX; TYA; ADC ($20),Y",
+        "JMP loop",
+    );
+    let branch = asm!(
+        ".org $0200",
+        "loop: LDX #8",
+        "down: DEX; BNE down",
+        "LDA $10; BEQ zero; BMI negative",
+        "zero: CLC; BCC carry",
+        "negative: SEC",
+        "carry: INC $10; BVS loop; BPL loop",
+        "JMP loop",
+    );
+    let rmw = asm!(
+        ".org $0200",
+        "loop: INC $10; ASL $11; ROR $0300,X; DEC $0400,X",
+        "LSR $12,X; ROL $0500; ASL A; ROR A",
+        "INX",
+        "JMP loop",
+    );
+    for (name, program) in [("alu", alu), ("branch", branch), ("rmw", rmw)] {
+        let mut ram = vec![0; 0x10000];
+        program.load(&mut ram);
+        run(&harness, name, &ram, program.origin);
+    }
+}
+
+/// Runs `ram` from `pc` directly through `Cpu::clock` and through each
+/// engine, and shows how much faster the instruction-level engine is.
+fn run(harness: &Harness, name: &str, ram: &[u8], pc: u16) {
+    harness.bench(&format!("{name}/clock"), "cycles", || {
+        clock(&mut ram.to_vec(), pc)
+    });
+    let rates = [Engine::Cycle, Engine::Instruction].map(|engine| {
+        let label = match engine {
+            Engine::Cycle => "cycle",
+            Engine::Instruction => "instruction",
+        };
+        harness.bench(&format!("{name}/{label}"), "cycles", || {
+            step(engine, &mut ram.to_vec(), pc)
+        })
+    });
+    if let [Some(cycle), Some(instruction)] = rates {
+        println!("{name:<28} {:>10.2}x", instruction / cycle);
+    }
+}
+
+fn clock(ram: &mut [u8], pc: u16) -> u64 {
+    let mut cpu = Cpu::new(Variant::Nmos6502);
+    cpu.set_pc(pc);
+    let mut event = BusEvent::Read(pc);
+    for _ in 0..CYCLES {
+        let (addr, data) = match event {
+            BusEvent::Read(addr) => (addr, ram[addr as usize]),
+            BusEvent::Write(addr, data) => {
+                ram[addr as usize] = data;
+                (addr, data)
+            }
+        };
+        event = cpu.clock(addr, data);
+    }
+    CYCLES
+}
+
+fn step(engine: Engine, ram: &mut [u8], pc: u16) -> u64 {
+    let mut cpu = Cpu::new(Variant::Nmos6502);
+    cpu.set_pc(pc);
+    let mut cycles = 0;
+    while cycles < CYCLES {
+        cycles += cpu.step(engine, ram) as u64;
+    }
+    cycles
+}
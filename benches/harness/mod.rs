@@ -0,0 +1,62 @@
+//! A small timing harness shared by the benchmarks. Each benchmark runs
+//! once to warm up and then [`SAMPLES`] more times, and the median rate is
+//! reported along with the spread, which keeps one noisy sample from
+//! hiding or faking a regression.
+
+use std::time::Instant;
+
+pub const SAMPLES: usize = 7;
+
+/// Runs the benchmarks whose names contain one of the command line
+/// arguments, or all of them when there are none.
+pub struct Harness {
+    filters: Vec<String>,
+}
+
+impl Harness {
+    pub fn from_args() -> Harness {
+        Harness {
+            // Cargo passes `--bench`.
+            filters: std::env::args()
+                .skip(1)
+                .filter(|arg| !arg.starts_with('-'))
+                .collect(),
+        }
+    }
+
+    /// Times `sample`, which does some work and returns how many `unit`s
+    /// of it were done, and prints the rate. Returns the median rate, or
+    /// `None` if `name` was filtered out.
+    pub fn bench(&self, name: &str, unit: &str, mut sample: impl FnMut() -> u64) -> Option<f64> {
+        if !self.filters.is_empty() && !self.filters.iter().any(|f| name.contains(f.as_str())) {
+            return None;
+        }
+        sample();
+        let mut rates: Vec<f64> = (0..SAMPLES)
+            .map(|_| {
+                let start = Instant::now();
+                let work = sample();
+                work as f64 / start.elapsed().as_secs_f64()
+            })
+            .collect();
+        rates.sort_by(f64::total_cmp);
+        let median = rates[SAMPLES / 2];
+        println!(
+            "{name:<28} {:>10} {unit}/s  (min {}, max {})",
+            scaled(median),
+            scaled(rates[0]),
+            scaled(rates[SAMPLES - 1]),
+        );
+        Some(median)
+    }
+}
+
+fn scaled(rate: f64) -> String {
+    if rate >= 1e6 {
+        format!("{:.2}M", rate / 1e6)
+    } else if rate >= 1e3 {
+        format!("{:.2}k", rate / 1e3)
+    } else {
+        format!("{rate:.1}")
+    }
+}
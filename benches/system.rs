@@ -0,0 +1,103 @@
+//! Whole-console speed in frames per second: a built-in NROM program that
+//! keeps rendering, sprite DMA and NMIs busy, and any ROMs named in
+//! `NESTERS_BENCH_ROMS`, a list of `.nes` files or directories of them
+//! separated as in `PATH`.
+//!
+//! ```text
+//! NESTERS_BENCH_ROMS=roms cargo bench --bench system [FILTER...]
+//! ```
+
+mod harness;
+
+use harness::Harness;
+use nesters::asm;
+use nesters::nes::Nes;
+use std::path::{Path, PathBuf};
+
+/// Frames run per sample, a second of NTSC.
+const FRAMES: u64 = 60;
+
+fn main() {
+    let harness = Harness::from_args();
+    run(&harness, "nrom-demo", &demo());
+    for path in roms() {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        match std::fs::read(&path) {
+            Ok(image) => run(&harness, &name, &image),
+            Err(e) => eprintln!("skipping {}: {e}", path.display()),
+        }
+    }
+}
+
+fn run(harness: &Harness, name: &str, image: &[u8]) {
+    let mut nes = match Nes::from_ines(image) {
+        Ok(nes) => nes,
+        Err(e) => {
+            eprintln!("skipping {name}: {e}");
+            return;
+        }
+    };
+    // Past the boot code, which most programs spend a few frames in.
+    for _ in 0..FRAMES {
+        nes.run_frame();
+    }
+    harness.bench(name, "frames", || {
+        for _ in 0..FRAMES {
+            nes.run_frame();
+        }
+        FRAMES
+    });
+}
+
+fn roms() -> Vec<PathBuf> {
+    let Some(paths) = std::env::var_os("NESTERS_BENCH_ROMS") else {
+        return Vec::new();
+    };
+    let mut roms = Vec::new();
+    for path in std::env::split_paths(&paths) {
+        match std::fs::read_dir(&path) {
+            Ok(entries) => {
+                let mut found: Vec<_> = entries
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| path.extension() == Some("nes".as_ref()))
+                    .collect();
+                found.sort();
+                roms.extend(found);
+            }
+            Err(_) => roms.push(Path::new(&path).to_path_buf()),
+        }
+    }
+    roms
+}
+
+/// An NROM-128 image that fills the screen with tiles, turns on the
+/// background and sprites, and then counts in its main loop while the NMI
+/// handler copies sprites and scrolls.
+fn demo() -> Vec<u8> {
+    let program = asm!(
+        ".org $C000",
+        "reset: SEI; CLD; LDX #$FF; TXS",
+        "wait1: BIT $2002; BPL wait1",
+        "wait2: BIT $2002; BPL wait2",
+        // Palettes.
+        "LDA #$3F; STA $2006; LDA #$00; STA $2006; LDX #$00",
+        "palette: TXA; STA $2007; INX; CPX #$20; BNE palette",
+        // Both nametables, with their attributes.
+        "LDA #$20; STA $2006; LDA #$00; STA $2006; LDY #$08; LDX #$00",
+        "tiles: STX $2007; INX; BNE tiles; DEY; BNE tiles",
+        // Sprites, spread over the screen.
+        "sprites: TXA; STA $0200,X; INX; BNE sprites",
+        "LDA #$80; STA $2000",
+        "LDA #$1E; STA $2001",
+        "main: INC $00; LDA $00; CLC; ADC $01; STA $01; ASL $02; JMP main",
+        "nmi: PHA; TXA; PHA",
+        "LDA #$00; STA $2003; LDA #$02; STA $4014",
+        "INC $10; LDX $10; STX $2005; STX $2005",
+        "PLA; TAX; PLA; RTI",
+        "irq: RTI",
+        ".org $FFFA",
+        ".word nmi, reset, irq",
+    );
+    let chr: Vec<u8> = (0..0x2000).map(|i| (i * 7 + i / 16) as u8).collect();
+    nesters::cartridge::nrom_image(&program.bytes, &chr)
+}
@@ -0,0 +1,134 @@
+use std::f64::consts::PI;
+
+const PHASES: usize = 64;
+const TAPS: usize = 16;
+
+/// Band-limited resampler. Level changes are recorded as deltas at CPU-clock
+/// resolution and spread over the output samples with a windowed-sinc step,
+/// so square waves come out without aliasing at any output rate.
+#[derive(Clone)]
+pub(super) struct BlipBuffer {
+    samples_per_clock: f64,
+    /// Sample position of clock 0 of the current frame.
+    offset: f64,
+    buffer: Vec<f32>,
+    kernel: Box<[[f32; TAPS]]>,
+    integrator: f32,
+    filters: OutputFilters,
+}
+
+impl BlipBuffer {
+    pub(super) fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        BlipBuffer {
+            samples_per_clock: sample_rate as f64 / clock_rate,
+            offset: 0.0,
+            buffer: vec![0.0; TAPS],
+            kernel: Self::kernel(),
+            integrator: 0.0,
+            filters: OutputFilters::new(sample_rate),
+        }
+    }
+
+    /// Impulse responses for each sub-sample phase, each normalised to unit sum.
+    fn kernel() -> Box<[[f32; TAPS]]> {
+        let cutoff = 0.9;
+        (0..=PHASES)
+            .map(|phase| {
+                let frac = phase as f64 / PHASES as f64;
+                let mut taps = [0.0f64; TAPS];
+                for (k, tap) in taps.iter_mut().enumerate() {
+                    let x = k as f64 - (TAPS / 2 - 1) as f64 - frac;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (PI * cutoff * x).sin() / (PI * cutoff * x)
+                    };
+                    let t = x / (TAPS / 2) as f64;
+                    let window = if t.abs() >= 1.0 {
+                        0.0
+                    } else {
+                        0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos()
+                    };
+                    *tap = sinc * window;
+                }
+                let sum: f64 = taps.iter().sum();
+                taps.map(|tap| (tap / sum) as f32)
+            })
+            .collect()
+    }
+
+    /// Records a change of `delta` in the input level at `clock` CPU cycles into
+    /// the current frame.
+    pub(super) fn add_delta(&mut self, clock: u32, delta: f32) {
+        let position = self.offset + clock as f64 * self.samples_per_clock;
+        let index = position as usize;
+        let phase = ((position - index as f64) * PHASES as f64) as usize;
+
+        if self.buffer.len() < index + TAPS {
+            self.buffer.resize(index + TAPS, 0.0);
+        }
+        for (sample, tap) in self.buffer[index..index + TAPS]
+            .iter_mut()
+            .zip(self.kernel[phase].iter())
+        {
+            *sample += delta * tap;
+        }
+    }
+
+    /// Ends a frame of `clocks` CPU cycles and appends the finished samples to `out`.
+    pub(super) fn end_frame(&mut self, clocks: u32, out: &mut Vec<f32>) {
+        let end = self.offset + clocks as f64 * self.samples_per_clock;
+        let count = end as usize;
+
+        if self.buffer.len() < count + TAPS {
+            self.buffer.resize(count + TAPS, 0.0);
+        }
+        for &impulse in &self.buffer[..count] {
+            self.integrator += impulse;
+            out.push(self.filters.process(self.integrator));
+        }
+
+        self.buffer.drain(..count);
+        self.offset = end - count as f64;
+    }
+}
+
+/// The console's analog output stage: two high-pass filters (90 Hz, 440 Hz)
+/// and a 14 kHz low-pass.
+#[derive(Clone)]
+struct OutputFilters {
+    hp90: f32,
+    hp440: f32,
+    lp14k: f32,
+    hp90_prev: f32,
+    hp90_out: f32,
+    hp440_prev: f32,
+    hp440_out: f32,
+    lp_out: f32,
+}
+
+impl OutputFilters {
+    fn new(sample_rate: u32) -> Self {
+        let dt = 1.0 / sample_rate as f32;
+        let rc = |hz: f32| 1.0 / (2.0 * std::f32::consts::PI * hz);
+        OutputFilters {
+            hp90: rc(90.0) / (rc(90.0) + dt),
+            hp440: rc(440.0) / (rc(440.0) + dt),
+            lp14k: dt / (rc(14000.0) + dt),
+            hp90_prev: 0.0,
+            hp90_out: 0.0,
+            hp440_prev: 0.0,
+            hp440_out: 0.0,
+            lp_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        self.hp90_out = self.hp90 * (self.hp90_out + input - self.hp90_prev);
+        self.hp90_prev = input;
+        self.hp440_out = self.hp440 * (self.hp440_out + self.hp90_out - self.hp440_prev);
+        self.hp440_prev = self.hp90_out;
+        self.lp_out += self.lp14k * (self.hp440_out - self.lp_out);
+        self.lp_out
+    }
+}
@@ -0,0 +1,155 @@
+/// Delta modulation channel with its memory reader.
+#[derive(Clone)]
+pub(super) struct Dmc {
+    irq_enabled: bool,
+    loop_flag: bool,
+    rate_index: u8,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    pub(super) bytes_remaining: u16,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    sample_buffer: Option<u8>,
+    pub(super) irq: bool,
+}
+
+impl Dmc {
+    pub(super) fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            loop_flag: false,
+            rate_index: 0,
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            sample_buffer: None,
+            irq: false,
+        }
+    }
+
+    pub(super) fn write(&mut self, reg: u16, data: u8) {
+        match reg & 0x03 {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.loop_flag = data & 0x40 != 0;
+                self.rate_index = data & 0x0F;
+            }
+            1 => {
+                self.output_level = data & 0x7F;
+            }
+            2 => {
+                self.sample_address = 0xC000 | (data as u16) << 6;
+            }
+            _ => {
+                self.sample_length = (data as u16) << 4 | 1;
+            }
+        }
+    }
+
+    /// Reset keeps only the low bit of the output level.
+    pub(super) fn reset(&mut self) {
+        self.output_level &= 0x01;
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Clocked every CPU cycle; `rates` is the region's rate table.
+    pub(super) fn clock_timer(&mut self, rates: &[u16; 16]) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = rates[self.rate_index as usize] - 1;
+
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Address the memory reader wants fetched, if the sample buffer is empty.
+    pub(super) fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn dma_complete(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+crate::state::stateful!(Dmc {
+    irq_enabled,
+    loop_flag,
+    rate_index,
+    timer,
+    output_level,
+    sample_address,
+    sample_length,
+    current_address,
+    bytes_remaining,
+    shift,
+    bits_remaining,
+    silence,
+    sample_buffer,
+    irq,
+});
@@ -0,0 +1,97 @@
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Volume envelope shared by the pulse and noise channels.
+#[derive(Default, Clone)]
+pub(super) struct Envelope {
+    pub(super) start: bool,
+    pub(super) loop_flag: bool,
+    pub(super) constant: bool,
+    pub(super) period: u8,
+    pub(super) divider: u8,
+    pub(super) decay: u8,
+}
+
+impl Envelope {
+    pub(super) fn write(&mut self, data: u8) {
+        self.loop_flag = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.period = data & 0x0F;
+    }
+
+    /// Clocked by the frame counter's quarter-frame signal.
+    pub(super) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.loop_flag {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.constant {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub(super) struct LengthCounter {
+    pub(super) enabled: bool,
+    pub(super) halt: bool,
+    pub(super) counter: u8,
+}
+
+impl LengthCounter {
+    pub(super) fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[index as usize & 0x1F];
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    /// Clocked by the frame counter's half-frame signal.
+    pub(super) fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+crate::state::stateful!(Envelope {
+    start,
+    loop_flag,
+    constant,
+    period,
+    divider,
+    decay,
+});
+
+crate::state::stateful!(LengthCounter {
+    enabled,
+    halt,
+    counter,
+});
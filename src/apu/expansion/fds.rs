@@ -0,0 +1,263 @@
+use super::{ExpansionAudio, PULSE_LEVEL};
+
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+const MOD_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+/// Modulation table entry that resets the counter instead of adding to it.
+const MOD_RESET: u8 = 4;
+
+/// Famicom Disk System audio: a 64-step, 6-bit wavetable channel with a
+/// volume envelope and a frequency modulation unit, at $4040-$4092.
+///
+/// At full volume the channel is about 2.4 times as loud as a 2A03 pulse.
+/// The RAM adapter's output low-pass (around 2 kHz) is included.
+#[derive(Clone)]
+pub struct FdsAudio {
+    wave: [u8; 64],
+    wave_write: bool,
+    wave_halt: bool,
+    wave_freq: u16,
+    wave_acc: u32,
+    wave_pos: u8,
+    volume: Envelope,
+    envelope_halt: bool,
+    envelope_speed: u8,
+    master_volume: u8,
+    mod_table: [u8; 64],
+    mod_halt: bool,
+    mod_freq: u16,
+    mod_acc: u32,
+    mod_pos: u8,
+    mod_counter: i8,
+    modulation: Envelope,
+    sample: u8,
+    filtered: f32,
+}
+
+/// The volume and modulation gain envelopes share this layout.
+#[derive(Default, Clone)]
+struct Envelope {
+    disabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.disabled = data & 0x80 != 0;
+        self.increase = data & 0x40 != 0;
+        self.speed = data & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.timer = 0;
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        FdsAudio::new()
+    }
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave: [0; 64],
+            wave_write: false,
+            wave_halt: true,
+            wave_freq: 0,
+            wave_acc: 0,
+            wave_pos: 0,
+            volume: Envelope::default(),
+            envelope_halt: false,
+            envelope_speed: 0xE8,
+            master_volume: 0,
+            mod_table: [0; 64],
+            mod_halt: true,
+            mod_freq: 0,
+            mod_acc: 0,
+            mod_pos: 0,
+            mod_counter: 0,
+            modulation: Envelope::default(),
+            sample: 0,
+            filtered: 0.0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => {
+                self.wave[addr as usize - 0x4040] = data & 0x3F;
+            }
+            0x4080 => self.volume.write(data),
+            0x4082 => self.wave_freq = (self.wave_freq & 0x0F00) | data as u16,
+            0x4083 => {
+                self.wave_freq = (self.wave_freq & 0x00FF) | (data as u16 & 0x0F) << 8;
+                self.wave_halt = data & 0x80 != 0;
+                self.envelope_halt = data & 0x40 != 0;
+                if self.wave_halt {
+                    self.wave_acc = 0;
+                    self.wave_pos = 0;
+                }
+            }
+            0x4084 => self.modulation.write(data),
+            0x4085 => self.mod_counter = ((data << 1) as i8) >> 1,
+            0x4086 => self.mod_freq = (self.mod_freq & 0x0F00) | data as u16,
+            0x4087 => {
+                self.mod_freq = (self.mod_freq & 0x00FF) | (data as u16 & 0x0F) << 8;
+                self.mod_halt = data & 0x80 != 0;
+                if self.mod_halt {
+                    self.mod_acc = 0;
+                }
+            }
+            0x4088 if self.mod_halt => {
+                // Each write fills two consecutive entries of the 64-step table.
+                let pos = self.mod_pos as usize & 0x3E;
+                self.mod_table[pos] = data & 0x07;
+                self.mod_table[pos + 1] = data & 0x07;
+                self.mod_pos = (self.mod_pos + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.wave_write = data & 0x80 != 0;
+                self.master_volume = data & 0x03;
+            }
+            0x408A => self.envelope_speed = data,
+            _ => {}
+        }
+    }
+
+    /// Reads the wave RAM and gain registers; other addresses are not driven.
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407F => Some(self.wave[addr as usize - 0x4040]),
+            0x4090 => Some(self.volume.gain),
+            0x4092 => Some(self.modulation.gain),
+            _ => None,
+        }
+    }
+
+    /// Wave frequency after modulation, following the RAM adapter's
+    /// rounding.
+    fn modulated_pitch(&self) -> u32 {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= self.wave_freq as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (self.wave_freq as i32 + temp).max(0) as u32
+    }
+
+    fn clock_modulator(&mut self) {
+        if self.mod_halt || self.mod_freq == 0 {
+            return;
+        }
+        self.mod_acc += self.mod_freq as u32;
+        if self.mod_acc < 0x10000 {
+            return;
+        }
+        self.mod_acc &= 0xFFFF;
+
+        let entry = self.mod_table[self.mod_pos as usize];
+        self.mod_counter = if entry == MOD_RESET {
+            0
+        } else {
+            // The counter is 7-bit signed.
+            let counter = self.mod_counter.wrapping_add(MOD_STEPS[entry as usize]);
+            (counter << 1) >> 1
+        };
+        self.mod_pos = (self.mod_pos + 1) & 0x3F;
+    }
+}
+
+impl ExpansionAudio for FdsAudio {
+    fn clock(&mut self) {
+        if !self.envelope_halt && self.envelope_speed != 0 {
+            self.volume.clock(self.envelope_speed);
+            self.modulation.clock(self.envelope_speed);
+        }
+
+        self.clock_modulator();
+
+        if !self.wave_halt && !self.wave_write {
+            self.wave_acc += self.modulated_pitch();
+            if self.wave_acc >= 0x10000 {
+                self.wave_acc &= 0xFFFF;
+                self.wave_pos = (self.wave_pos + 1) & 0x3F;
+            }
+            self.sample = self.wave[self.wave_pos as usize];
+        }
+
+        let level = self.sample as f32 * self.volume.gain.min(32) as f32 / (63.0 * 32.0)
+            * MASTER_VOLUME[self.master_volume as usize]
+            * 2.4
+            * PULSE_LEVEL;
+        // One-pole low-pass at about 2 kHz, run at the CPU rate.
+        self.filtered += (level - self.filtered) * 0.007;
+    }
+
+    fn output(&self) -> f32 {
+        self.filtered
+    }
+}
+
+crate::state::stateful!(FdsAudio {
+    wave,
+    wave_write,
+    wave_halt,
+    wave_freq,
+    wave_acc,
+    wave_pos,
+    volume,
+    envelope_halt,
+    envelope_speed,
+    master_volume,
+    mod_table,
+    mod_halt,
+    mod_freq,
+    mod_acc,
+    mod_pos,
+    mod_counter,
+    modulation,
+    sample,
+    filtered,
+});
+
+crate::state::stateful!(Envelope {
+    disabled,
+    increase,
+    speed,
+    gain,
+    timer,
+});
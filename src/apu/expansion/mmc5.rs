@@ -0,0 +1,143 @@
+use super::ExpansionAudio;
+use crate::apu::pulse::Pulse;
+
+/// Envelope and length counter clock: a fixed 240 Hz rather than the APU's
+/// frame counter.
+const FRAME_PERIOD: u16 = 7457;
+
+/// Nintendo MMC5 audio: two 2A03-style pulses without sweep, plus an 8-bit
+/// PCM channel.
+///
+/// The pulses go through the same DAC curve as the APU's; the PCM DAC is
+/// treated like the DMC's at twice the resolution.
+#[derive(Clone)]
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    pcm: u8,
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    frame_timer: u16,
+    odd_cycle: bool,
+}
+
+impl Default for Mmc5Audio {
+    fn default() -> Self {
+        Mmc5Audio::new()
+    }
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Mmc5Audio {
+            pulse1: Pulse::without_sweep(),
+            pulse2: Pulse::without_sweep(),
+            pcm: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            frame_timer: FRAME_PERIOD,
+            odd_cycle: false,
+        }
+    }
+
+    /// Writes a register at $5000-$5015.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulse1.write(addr, data),
+            0x5004..=0x5007 => self.pulse2.write(addr, data),
+            0x5010 => {
+                self.pcm_read_mode = data & 0x01 != 0;
+                self.pcm_irq_enabled = data & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode && data != 0 => self.pcm = data,
+            0x5015 => {
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads $5010 or $5015; other addresses are not driven.
+    pub fn read(&mut self, addr: u16) -> Option<u8> {
+        let data = self.peek(addr);
+        if addr == 0x5010 {
+            self.pcm_irq = false;
+        }
+        data
+    }
+
+    /// What [`Mmc5Audio::read`] would return, without acknowledging the IRQ.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5010 => Some((self.pcm_irq as u8) << 7 | self.pcm_read_mode as u8),
+            0x5015 => {
+                Some(self.pulse1.length.active() as u8 | (self.pulse2.length.active() as u8) << 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// In PCM read mode, CPU reads from $8000-$BFFF are captured as samples;
+    /// a zero byte raises the PCM IRQ instead.
+    pub fn snoop_read(&mut self, addr: u16, data: u8) {
+        if self.pcm_read_mode && (0x8000..=0xBFFF).contains(&addr) {
+            if data == 0 {
+                self.pcm_irq = self.pcm_irq_enabled;
+            } else {
+                self.pcm = data;
+            }
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.pcm_irq
+    }
+}
+
+impl ExpansionAudio for Mmc5Audio {
+    fn clock(&mut self) {
+        self.frame_timer -= 1;
+        if self.frame_timer == 0 {
+            self.frame_timer = FRAME_PERIOD;
+            for pulse in [&mut self.pulse1, &mut self.pulse2] {
+                pulse.envelope.clock();
+                pulse.length.clock();
+            }
+        }
+
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+    }
+
+    fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+        let pcm_out = if self.pcm == 0 {
+            0.0
+        } else {
+            159.79 / (2.0 * 22638.0 / self.pcm as f32 + 100.0)
+        };
+        pulse_out + pcm_out
+    }
+}
+
+crate::state::stateful!(Mmc5Audio {
+    pulse1,
+    pulse2,
+    pcm,
+    pcm_read_mode,
+    pcm_irq_enabled,
+    pcm_irq,
+    frame_timer,
+    odd_cycle,
+});
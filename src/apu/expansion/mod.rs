@@ -0,0 +1,219 @@
+//! Sound chips found on cartridges, mixed in after the 2A03's own channels.
+//!
+//! Each chip reports its level on the same scale as the APU mixer, where a
+//! 2A03 pulse at full volume swings about 0.15. Relative levels follow the
+//! commonly measured values; boards vary, so they are approximate.
+
+mod fds;
+mod mmc5;
+mod n163;
+mod sunsoft5b;
+mod vrc6;
+mod vrc7;
+
+pub use fds::FdsAudio;
+pub use mmc5::Mmc5Audio;
+pub use n163::N163Audio;
+pub use sunsoft5b::Sunsoft5bAudio;
+pub use vrc6::Vrc6Audio;
+pub use vrc7::Vrc7Audio;
+
+/// A cartridge-side sound source.
+pub trait ExpansionAudio {
+    /// Advances the chip by one CPU cycle.
+    fn clock(&mut self);
+    /// Current output level, to be added to the APU mix.
+    fn output(&self) -> f32;
+}
+
+/// Level of a 2A03 pulse channel at volume 15, the reference for the mix levels.
+const PULSE_LEVEL: f32 = 0.1494;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts rising edges of the chip output over `cycles` CPU cycles.
+    fn rising_edges(chip: &mut impl ExpansionAudio, cycles: usize) -> usize {
+        let mut last = chip.output();
+        let mut edges = 0;
+        for _ in 0..cycles {
+            chip.clock();
+            let level = chip.output();
+            if level > last {
+                edges += 1;
+            }
+            last = level;
+        }
+        edges
+    }
+
+    #[test]
+    fn vrc6_pulse_frequency() {
+        let mut vrc6 = Vrc6Audio::new();
+        // 50% duty, volume 15, period 99: 16 * 100 cycles per wave.
+        vrc6.write(0x9000, 0x7F);
+        vrc6.write(0x9001, 99);
+        vrc6.write(0x9002, 0x80);
+        assert_eq!(rising_edges(&mut vrc6, 16 * 100 * 10), 10);
+    }
+
+    #[test]
+    fn mmc5_pulse_and_pcm() {
+        let mut mmc5 = Mmc5Audio::new();
+        // 50% duty, constant volume 15, period 99: 16 * 100 cycles per wave.
+        mmc5.write(0x5015, 0x01);
+        mmc5.write(0x5000, 0xBF);
+        mmc5.write(0x5002, 99);
+        mmc5.write(0x5003, 0x08);
+        assert_eq!(mmc5.peek(0x5015), Some(0x01));
+        assert_eq!(rising_edges(&mut mmc5, 16 * 100 * 10), 10);
+
+        // In read mode, a zero byte read from $8000-$BFFF raises the IRQ.
+        mmc5.write(0x5011, 0x80);
+        mmc5.write(0x5010, 0x81);
+        mmc5.snoop_read(0x8000, 0x40);
+        mmc5.snoop_read(0xC000, 0x00);
+        assert!(!mmc5.irq());
+        mmc5.snoop_read(0x8001, 0x00);
+        assert!(mmc5.irq());
+        assert_eq!(mmc5.read(0x5010), Some(0x81));
+        assert!(!mmc5.irq());
+    }
+
+    #[test]
+    fn vrc7_release_follows_region() {
+        use crate::region::Region;
+
+        // Cycles from key-off until a flute note on channel 0 falls silent.
+        let release_cycles = |region| {
+            let mut chip = Vrc7Audio::new();
+            chip.set_region(region);
+            let mut write = |reg, data| {
+                chip.write_addr(reg);
+                chip.write_data(data);
+            };
+            write(0x30, 0x40);
+            write(0x10, 0x00);
+            write(0x20, 0x15);
+            for _ in 0..20_000 {
+                chip.clock();
+            }
+            assert_ne!(chip.output(), 0.0);
+            chip.write_addr(0x20);
+            chip.write_data(0x05);
+            (1..).find(|_| {
+                chip.clock();
+                chip.output() == 0.0
+            })
+        };
+        let ntsc = release_cycles(Region::Ntsc).unwrap();
+        let pal = release_cycles(Region::Pal).unwrap();
+        // The release takes the same time, so fewer of PAL's slower cycles.
+        let ratio = ntsc as f64 / pal as f64;
+        let expected = Region::Ntsc.cpu_clock() / Region::Pal.cpu_clock();
+        assert!((ratio - expected).abs() < 0.01, "{ntsc} vs {pal} cycles");
+
+        let mut chip = Vrc7Audio::new();
+        chip.write_addr(0x30);
+        chip.write_data(0x40);
+        chip.write_addr(0x20);
+        chip.write_data(0x15);
+        chip.set_silenced(true);
+        for _ in 0..1000 {
+            chip.clock();
+        }
+        assert_eq!(chip.output(), 0.0);
+    }
+
+    #[test]
+    fn fds_wave_and_modulation() {
+        // Waves of a square wavetable over `cycles`, counted where the
+        // filtered output turns from falling to rising.
+        fn waves(chip: &mut FdsAudio, cycles: usize) -> usize {
+            let (mut last, mut falling, mut turns) = (chip.output(), false, 0);
+            for _ in 0..cycles {
+                chip.clock();
+                let level = chip.output();
+                if level < last {
+                    falling = true;
+                } else if level > last && falling {
+                    falling = false;
+                    turns += 1;
+                }
+                last = level;
+            }
+            turns
+        }
+
+        let mut chip = FdsAudio::new();
+        chip.write(0x4089, 0x80);
+        for step in 0..64 {
+            chip.write(0x4040 + step, if step < 32 { 0x3F } else { 0 });
+        }
+        chip.write(0x4089, 0x00);
+        assert_eq!(chip.read(0x4040), Some(0x3F));
+        assert_eq!(chip.read(0x407F), Some(0x00));
+
+        // Fixed gain 32, pitch $400: 64 * $10000 / $400 cycles per wave.
+        chip.write(0x4080, 0xA0);
+        chip.write(0x4082, 0x00);
+        chip.write(0x4083, 0x04);
+        assert_eq!(chip.read(0x4090), Some(0x20));
+        assert_eq!(waves(&mut chip, 4096 * 10), 10);
+        assert!(chip.output() > 0.0);
+
+        // A modulation counter of 32 at gain 32 doubles the pitch.
+        chip.write(0x4084, 0xA0);
+        chip.write(0x4085, 32);
+        assert_eq!(chip.read(0x4092), Some(0x20));
+        assert_eq!(waves(&mut chip, 4096 * 10), 20);
+
+        // Halting the wave holds its last sample.
+        chip.write(0x4083, 0x80);
+        assert_eq!(waves(&mut chip, 4096 * 10), 0);
+    }
+
+    #[test]
+    fn sunsoft5b_tone_frequency() {
+        let mut chip = Sunsoft5bAudio::new();
+        let mut write = |reg, data| {
+            chip.write_addr(reg);
+            chip.write_data(data);
+        };
+        // Channel A, period 50: 32 * 50 cycles per wave, tone only.
+        write(0x00, 50);
+        write(0x07, 0x3E);
+        write(0x08, 0x0F);
+        assert_eq!(rising_edges(&mut chip, 32 * 50 * 10), 10);
+    }
+
+    #[test]
+    fn n163_channels_share_the_dac() {
+        let mut chip = N163Audio::new();
+        // A constant waveform of 15 at address 0, length 4, volume 15.
+        chip.write_addr(0x80);
+        chip.write_data(0xFF);
+        chip.write_data(0xFF);
+        chip.write_addr(0x78 | 0x80);
+        for data in [0, 0, 0, 0, 0xFC, 0, 0, 0x0F] {
+            chip.write_data(data);
+        }
+        for _ in 0..30 {
+            chip.clock();
+        }
+        let single = chip.output();
+        assert!(single > 0.0);
+
+        // With two channels enabled, channel 6 (silent) takes every other slot.
+        chip.write_addr(0x7F);
+        chip.write_data(0x1F);
+        let mut levels = Vec::new();
+        for _ in 0..60 {
+            chip.clock();
+            levels.push(chip.output());
+        }
+        assert!(levels.contains(&single));
+        assert!(levels.contains(&0.0));
+    }
+}
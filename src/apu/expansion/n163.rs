@@ -0,0 +1,139 @@
+use super::{ExpansionAudio, PULSE_LEVEL};
+
+/// CPU cycles spent updating each channel.
+const CHANNEL_CYCLES: u8 = 15;
+
+/// Namco 163: up to eight 4-bit wavetable channels sharing 128 bytes of
+/// internal RAM with their registers.
+///
+/// The chip has a single DAC and updates one channel every 15 CPU cycles,
+/// outputting only that channel until the next. This multiplexing is
+/// reproduced as is, so more channels means quieter channels and, with all
+/// eight enabled, the audible whine of the real chip. A full-volume channel
+/// is taken to be about 1.5 times a 2A03 pulse, in the middle of the range
+/// measured across boards.
+#[derive(Clone)]
+pub struct N163Audio {
+    ram: [u8; 0x80],
+    addr: u8,
+    auto_increment: bool,
+    disabled: bool,
+    cycle: u8,
+    channel: u8,
+    output: i8,
+}
+
+impl Default for N163Audio {
+    fn default() -> Self {
+        N163Audio::new()
+    }
+}
+
+impl N163Audio {
+    pub fn new() -> Self {
+        N163Audio {
+            ram: [0; 0x80],
+            addr: 0,
+            auto_increment: false,
+            disabled: false,
+            cycle: 0,
+            channel: 7,
+            output: 0,
+        }
+    }
+
+    /// $F800: address port; bit 7 enables auto-increment.
+    pub fn write_addr(&mut self, data: u8) {
+        self.addr = data & 0x7F;
+        self.auto_increment = data & 0x80 != 0;
+    }
+
+    /// $4800 write: data port.
+    pub fn write_data(&mut self, data: u8) {
+        self.ram[self.addr as usize] = data;
+        self.advance();
+    }
+
+    /// $4800 read: data port.
+    pub fn read_data(&mut self) -> u8 {
+        let data = self.peek_data();
+        self.advance();
+        data
+    }
+
+    /// The byte [`N163Audio::read_data`] would return, without advancing.
+    pub fn peek_data(&self) -> u8 {
+        self.ram[self.addr as usize]
+    }
+
+    fn advance(&mut self) {
+        if self.auto_increment {
+            self.addr = (self.addr + 1) & 0x7F;
+        }
+    }
+
+    /// $E000 bit 6 silences the chip.
+    pub fn set_disabled(&mut self, disabled: bool) {
+        self.disabled = disabled;
+    }
+
+    /// Number of enabled channels, from $7F bits 4-6.
+    fn channels(&self) -> u8 {
+        (self.ram[0x7F] >> 4 & 0x07) + 1
+    }
+
+    fn update_channel(&mut self, channel: u8) {
+        let base = 0x40 + channel as usize * 8;
+        let reg = &self.ram[base..base + 8];
+        let freq = reg[0] as u32 | (reg[2] as u32) << 8 | (reg[4] as u32 & 0x03) << 16;
+        let mut phase = reg[1] as u32 | (reg[3] as u32) << 8 | (reg[5] as u32) << 16;
+        let length = 0x100 - (reg[4] as u32 & 0xFC);
+        let wave_addr = reg[6] as u32;
+        let volume = (reg[7] & 0x0F) as i8;
+
+        phase = (phase + freq) % (length << 16);
+        let index = ((phase >> 16) + wave_addr) & 0xFF;
+        let nibble = (self.ram[index as usize >> 1] >> ((index & 1) * 4)) & 0x0F;
+        self.output = (nibble as i8 - 8) * volume;
+
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+    }
+}
+
+impl ExpansionAudio for N163Audio {
+    fn clock(&mut self) {
+        if self.disabled {
+            self.output = 0;
+            return;
+        }
+        self.cycle += 1;
+        if self.cycle < CHANNEL_CYCLES {
+            return;
+        }
+        self.cycle = 0;
+
+        // Channels run from 7 downwards; with n enabled, the last is 8 - n.
+        if self.channel <= 8 - self.channels() {
+            self.channel = 7;
+        } else {
+            self.channel -= 1;
+        }
+        self.update_channel(self.channel);
+    }
+
+    fn output(&self) -> f32 {
+        self.output as f32 * (1.5 * PULSE_LEVEL / 120.0)
+    }
+}
+
+crate::state::stateful!(N163Audio {
+    ram,
+    addr,
+    auto_increment,
+    disabled,
+    cycle,
+    channel,
+    output,
+});
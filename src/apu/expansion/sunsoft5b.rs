@@ -0,0 +1,197 @@
+use super::{ExpansionAudio, PULSE_LEVEL};
+
+/// Sunsoft 5B, a licensed YM2149F (AY-3-8910 family): three square channels
+/// with a shared noise generator and envelope, on a logarithmic volume scale.
+///
+/// A channel at full volume is taken to be about twice as loud as a 2A03
+/// pulse.
+#[derive(Clone)]
+pub struct Sunsoft5bAudio {
+    regs: [u8; 16],
+    addr: u8,
+    volume_table: [f32; 32],
+    prescaler: u8,
+    tone_counter: [u16; 3],
+    tone_high: [bool; 3],
+    noise_counter: u8,
+    noise_half: bool,
+    lfsr: u32,
+    envelope_counter: u16,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+impl Default for Sunsoft5bAudio {
+    fn default() -> Self {
+        Sunsoft5bAudio::new()
+    }
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Self {
+        // 32 steps of 1.5 dB, the lowest being silence.
+        let mut volume_table = [0.0; 32];
+        for (i, level) in volume_table.iter_mut().enumerate().skip(1) {
+            *level = 10f32.powf((i as f32 - 31.0) * 1.5 / 20.0);
+        }
+
+        Sunsoft5bAudio {
+            regs: [0; 16],
+            addr: 0,
+            volume_table,
+            prescaler: 0,
+            tone_counter: [0; 3],
+            tone_high: [false; 3],
+            noise_counter: 0,
+            noise_half: false,
+            lfsr: 1,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+        }
+    }
+
+    /// $C000: selects the register for the next data write.
+    pub fn write_addr(&mut self, data: u8) {
+        self.addr = data;
+    }
+
+    /// $E000: writes the selected register. Addresses above 15 are ignored.
+    pub fn write_data(&mut self, data: u8) {
+        if self.addr > 0x0F {
+            return;
+        }
+        self.regs[self.addr as usize] = data;
+        if self.addr == 0x0D {
+            self.envelope_attack = data & 0x04 != 0;
+            self.envelope_step = 0;
+            self.envelope_counter = 0;
+            self.envelope_holding = false;
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let period =
+            self.regs[channel * 2] as u16 | (self.regs[channel * 2 + 1] as u16 & 0x0F) << 8;
+        period.max(1)
+    }
+
+    fn clock_envelope(&mut self) {
+        let period = (self.regs[0x0B] as u16 | (self.regs[0x0C] as u16) << 8).max(1);
+        self.envelope_counter += 1;
+        if self.envelope_counter < period {
+            return;
+        }
+        self.envelope_counter = 0;
+        if self.envelope_holding {
+            return;
+        }
+
+        self.envelope_step += 1;
+        if self.envelope_step < 32 {
+            return;
+        }
+
+        let shape = self.regs[0x0D];
+        let continue_flag = shape & 0x08 != 0;
+        let alternate = shape & 0x02 != 0;
+        let hold = shape & 0x01 != 0;
+        if !continue_flag {
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+            self.envelope_step = 31;
+        } else if hold {
+            self.envelope_holding = true;
+            self.envelope_step = 31;
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+        } else {
+            self.envelope_step = 0;
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            31 - self.envelope_step
+        }
+    }
+}
+
+impl ExpansionAudio for Sunsoft5bAudio {
+    /// Tones toggle every `period` ticks of a /16 prescaler; the envelope
+    /// steps at twice that rate, and noise at half.
+    fn clock(&mut self) {
+        self.prescaler = (self.prescaler + 1) & 0x0F;
+        if self.prescaler & 0x07 == 0 {
+            self.clock_envelope();
+        }
+        if self.prescaler != 0 {
+            return;
+        }
+
+        for channel in 0..3 {
+            self.tone_counter[channel] += 1;
+            if self.tone_counter[channel] >= self.tone_period(channel) {
+                self.tone_counter[channel] = 0;
+                self.tone_high[channel] = !self.tone_high[channel];
+            }
+        }
+
+        self.noise_half = !self.noise_half;
+        if self.noise_half {
+            self.noise_counter += 1;
+            if self.noise_counter >= (self.regs[0x06] & 0x1F).max(1) {
+                self.noise_counter = 0;
+                let bit = (self.lfsr ^ (self.lfsr >> 3)) & 1;
+                self.lfsr = (self.lfsr >> 1) | bit << 16;
+            }
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let mixer = self.regs[0x07];
+        let noise_high = self.lfsr & 1 != 0;
+        let mut sum = 0.0;
+        for channel in 0..3 {
+            let tone = self.tone_high[channel] || mixer & (1 << channel) != 0;
+            let noise = noise_high || mixer & (8 << channel) != 0;
+            if !(tone && noise) {
+                continue;
+            }
+            let volume = self.regs[0x08 + channel];
+            let level = if volume & 0x10 != 0 {
+                self.envelope_level()
+            } else if volume & 0x0F == 0 {
+                0
+            } else {
+                (volume & 0x0F) * 2 + 1
+            };
+            sum += self.volume_table[level as usize];
+        }
+        sum * 2.0 * PULSE_LEVEL
+    }
+}
+
+// The volume table is fixed at construction.
+crate::state::stateful!(Sunsoft5bAudio {
+    regs,
+    addr,
+    prescaler,
+    tone_counter,
+    tone_high,
+    noise_counter,
+    noise_half,
+    lfsr,
+    envelope_counter,
+    envelope_step,
+    envelope_attack,
+    envelope_holding,
+});
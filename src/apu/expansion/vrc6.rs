@@ -0,0 +1,186 @@
+use super::{ExpansionAudio, PULSE_LEVEL};
+
+/// Konami VRC6: two pulse channels with 8 duty settings and a sawtooth.
+///
+/// A VRC6 pulse at full volume is about as loud as a 2A03 pulse.
+#[derive(Default, Clone)]
+pub struct Vrc6Audio {
+    pulse: [Vrc6Pulse; 2],
+    saw: Vrc6Saw,
+    halt: bool,
+    /// Period shift from $9003: 0, 4 or 8.
+    shift: u8,
+}
+
+#[derive(Default, Clone)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    /// Digitized mode: output the volume regardless of duty.
+    constant: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.constant = data & 0x80 != 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (data as u16 & 0x0F) << 8;
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.constant || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct Vrc6Saw {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (data as u16 & 0x0F) << 8;
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    /// The accumulator gains the rate on every other step and resets after
+    /// the seventh addition.
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step.is_multiple_of(2) {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a register at $9000-$9003, $A000-$A002 or $B000-$B002, with the
+    /// board's address line swap already undone.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        let reg = addr & 0x0003;
+        match (addr & 0xF000, reg) {
+            (0x9000, 3) => {
+                self.halt = data & 0x01 != 0;
+                self.shift = if data & 0x04 != 0 {
+                    8
+                } else if data & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            (0x9000, _) => self.pulse[0].write(reg, data),
+            (0xA000, 0..=2) => self.pulse[1].write(reg, data),
+            (0xB000, 0..=2) => self.saw.write(reg, data),
+            _ => {}
+        }
+    }
+}
+
+impl ExpansionAudio for Vrc6Audio {
+    fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse[0].clock(self.shift);
+        self.pulse[1].clock(self.shift);
+        self.saw.clock(self.shift);
+    }
+
+    fn output(&self) -> f32 {
+        let sum = self.pulse[0].output() + self.pulse[1].output() + self.saw.output();
+        sum as f32 * (PULSE_LEVEL / 15.0)
+    }
+}
+
+crate::state::stateful!(Vrc6Audio {
+    pulse,
+    saw,
+    halt,
+    shift,
+});
+
+crate::state::stateful!(Vrc6Pulse {
+    volume,
+    duty,
+    constant,
+    period,
+    enabled,
+    timer,
+    step,
+});
+
+crate::state::stateful!(Vrc6Saw {
+    rate,
+    period,
+    enabled,
+    timer,
+    step,
+    accumulator,
+});
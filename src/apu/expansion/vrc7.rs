@@ -0,0 +1,433 @@
+use super::{ExpansionAudio, PULSE_LEVEL};
+use crate::region::Region;
+use crate::state::{StateError, StateReader, StateWriter, Stateful};
+use std::f32::consts::TAU;
+
+/// CPU cycles per OPLL output sample (the chip runs at 3.58 MHz / 72).
+const SAMPLE_CYCLES: u8 = 36;
+/// Attenuation at which the envelope counts as finished.
+const MAX_ATTENUATION: f32 = 48.0;
+
+/// The VRC7's built-in instruments 1-15, in the custom patch register layout.
+#[rustfmt::skip]
+const ROM_PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy bell
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth bass
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+const MULTIPLIER: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+/// Key scale attenuation in dB at block 7, indexed by the top 4 F-number bits.
+const KSL_TABLE: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25,
+    42.0,
+];
+
+/// Konami VRC7: the FM synthesis core of a YM2413 (OPLL) with six melodic
+/// two-operator channels, one custom and 15 fixed instruments, and no rhythm
+/// section.
+///
+/// This is a floating point model of the OPLL rather than a bit-exact one:
+/// envelope timing, key scaling, feedback and the AM/PM LFOs follow the
+/// datasheet curves. A full-scale channel swings about as much as a 2A03
+/// pulse.
+#[derive(Clone)]
+pub struct Vrc7Audio {
+    region: Region,
+    addr: u8,
+    custom: [u8; 8],
+    channels: [FmChannel; 6],
+    silenced: bool,
+    cycle: u8,
+    am_phase: f32,
+    pm_phase: f32,
+    output: f32,
+}
+
+#[derive(Default, Clone)]
+struct FmChannel {
+    fnum: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [f32; 2],
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    #[default]
+    Off,
+}
+
+#[derive(Clone, Default)]
+struct Operator {
+    phase: f32,
+    attenuation: f32,
+    stage: Stage,
+}
+
+/// One operator's half of a patch.
+struct OperatorPatch {
+    am: bool,
+    vibrato: bool,
+    sustained: bool,
+    ksr: bool,
+    multiplier: f32,
+    ksl: u8,
+    rectify: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8,
+}
+
+impl OperatorPatch {
+    fn new(patch: &[u8; 8], carrier: bool) -> Self {
+        let i = carrier as usize;
+        OperatorPatch {
+            am: patch[i] & 0x80 != 0,
+            vibrato: patch[i] & 0x40 != 0,
+            sustained: patch[i] & 0x20 != 0,
+            ksr: patch[i] & 0x10 != 0,
+            multiplier: MULTIPLIER[patch[i] as usize & 0x0F],
+            ksl: patch[2 + i] >> 6,
+            rectify: patch[3] & if carrier { 0x10 } else { 0x08 } != 0,
+            attack: patch[4 + i] >> 4,
+            decay: patch[4 + i] & 0x0F,
+            sustain_level: patch[6 + i] >> 4,
+            release: patch[6 + i] & 0x0F,
+        }
+    }
+}
+
+impl Operator {
+    fn key_on(&mut self) {
+        if self.stage == Stage::Off {
+            self.attenuation = MAX_ATTENUATION;
+        }
+        self.phase = 0.0;
+        self.stage = Stage::Attack;
+    }
+
+    /// Time in seconds for a 48 dB decay at the 6-bit effective rate.
+    fn decay_time(rate: u8) -> f32 {
+        4.0 * 2f32.powf(-((rate as f32 - 4.0) / 4.0))
+    }
+
+    fn effective_rate(rate: u8, rks: u8) -> u8 {
+        if rate == 0 {
+            0
+        } else {
+            (rate * 4 + rks).min(63)
+        }
+    }
+
+    fn clock_envelope(&mut self, patch: &OperatorPatch, rks: u8, release: u8, sample_rate: f32) {
+        let decay = |attenuation: &mut f32, rate: u8| {
+            let rate = Self::effective_rate(rate, rks);
+            if rate > 0 {
+                *attenuation += MAX_ATTENUATION / (Self::decay_time(rate) * sample_rate);
+            }
+        };
+
+        match self.stage {
+            Stage::Attack => {
+                let rate = Self::effective_rate(patch.attack, rks);
+                if rate >= 60 {
+                    self.attenuation = 0.0;
+                } else if rate > 0 {
+                    // Attack is exponential and roughly four times faster.
+                    let samples = Self::decay_time(rate) / 4.0 * sample_rate;
+                    self.attenuation -= self.attenuation * (8.0 / samples).min(1.0) + 0.001;
+                }
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                decay(&mut self.attenuation, patch.decay);
+                if self.attenuation >= patch.sustain_level as f32 * 3.0 {
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {
+                if !patch.sustained {
+                    decay(&mut self.attenuation, patch.release);
+                }
+            }
+            Stage::Release => decay(&mut self.attenuation, release),
+            Stage::Off => {}
+        }
+
+        if self.attenuation >= MAX_ATTENUATION {
+            self.attenuation = MAX_ATTENUATION;
+            if self.stage != Stage::Attack {
+                self.stage = Stage::Off;
+            }
+        }
+    }
+
+    fn output(
+        &mut self,
+        patch: &OperatorPatch,
+        increment: f32,
+        offset: f32,
+        attenuation: f32,
+    ) -> f32 {
+        self.phase = (self.phase + increment * patch.multiplier).fract();
+        if self.stage == Stage::Off {
+            return 0.0;
+        }
+        let mut wave = (TAU * (self.phase + offset)).sin();
+        if patch.rectify && wave < 0.0 {
+            wave = 0.0;
+        }
+        wave * 10f32.powf(-(self.attenuation + attenuation) / 20.0)
+    }
+}
+
+impl FmChannel {
+    fn write_fnum_low(&mut self, data: u8) {
+        self.fnum = (self.fnum & 0x100) | data as u16;
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.fnum = (self.fnum & 0xFF) | (data as u16 & 0x01) << 8;
+        self.block = (data >> 1) & 0x07;
+        self.sustain = data & 0x20 != 0;
+
+        let key = data & 0x10 != 0;
+        if key && !self.key {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !key && self.key {
+            for op in [&mut self.modulator, &mut self.carrier] {
+                if op.stage != Stage::Off {
+                    op.stage = Stage::Release;
+                }
+            }
+        }
+        self.key = key;
+    }
+
+    fn sample(&mut self, patch: &[u8; 8], am: f32, pm: f32, sample_rate: f32) -> f32 {
+        let modulator = OperatorPatch::new(patch, false);
+        let carrier = OperatorPatch::new(patch, true);
+
+        let rks = self.block * 2 + (self.fnum >> 8) as u8;
+        let ksl_db = (KSL_TABLE[self.fnum as usize >> 5] - 6.0 * (7 - self.block) as f32).max(0.0);
+        let ksl = |ksl: u8| ksl_db * [0.0, 0.5, 1.0, 2.0][ksl as usize];
+        let base = self.fnum as f32 * (1 << self.block) as f32 / (1 << 19) as f32;
+        let increment = |vibrato: bool| if vibrato { base * (1.0 + pm) } else { base };
+        let tremolo = |enabled: bool| if enabled { am } else { 0.0 };
+
+        // Key-off release: the channel's sustain flag forces a slow release,
+        // percussive patches otherwise fade at a fixed medium rate.
+        let release = |op: &OperatorPatch| {
+            if self.sustain {
+                5
+            } else if op.sustained {
+                op.release
+            } else {
+                7
+            }
+        };
+        let (mod_release, car_release) = (release(&modulator), release(&carrier));
+
+        let rks_for = |op: &OperatorPatch| if op.ksr { rks } else { rks >> 2 };
+        self.modulator
+            .clock_envelope(&modulator, rks_for(&modulator), mod_release, sample_rate);
+        self.carrier
+            .clock_envelope(&carrier, rks_for(&carrier), car_release, sample_rate);
+
+        let feedback_level = patch[3] & 0x07;
+        let feedback = if feedback_level == 0 {
+            0.0
+        } else {
+            (self.feedback[0] + self.feedback[1]) * 2f32.powi(feedback_level as i32 - 6)
+        };
+        let total_level = (patch[2] & 0x3F) as f32 * 0.75;
+        let mod_out = self.modulator.output(
+            &modulator,
+            increment(modulator.vibrato),
+            feedback,
+            total_level + ksl(modulator.ksl) + tremolo(modulator.am),
+        );
+        self.feedback = [self.feedback[1], mod_out];
+
+        self.carrier.output(
+            &carrier,
+            increment(carrier.vibrato),
+            mod_out * 2.0,
+            self.volume as f32 * 3.0 + ksl(carrier.ksl) + tremolo(carrier.am),
+        )
+    }
+}
+
+impl Default for Vrc7Audio {
+    fn default() -> Self {
+        Vrc7Audio::new()
+    }
+}
+
+impl Vrc7Audio {
+    pub fn new() -> Self {
+        Vrc7Audio {
+            region: Region::Ntsc,
+            addr: 0,
+            custom: [0; 8],
+            channels: Default::default(),
+            silenced: false,
+            cycle: 0,
+            am_phase: 0.0,
+            pm_phase: 0.0,
+            output: 0.0,
+        }
+    }
+
+    /// Sets the CPU clock the chip's timing is derived from.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// $9010: selects the register for the next data write.
+    pub fn write_addr(&mut self, data: u8) {
+        self.addr = data;
+    }
+
+    /// $9030: writes the selected register.
+    pub fn write_data(&mut self, data: u8) {
+        let channel = (self.addr & 0x0F) as usize;
+        match self.addr {
+            0x00..=0x07 => self.custom[self.addr as usize] = data,
+            0x10..=0x15 => self.channels[channel].write_fnum_low(data),
+            0x20..=0x25 => self.channels[channel].write_control(data),
+            0x30..=0x35 => {
+                self.channels[channel].instrument = data >> 4;
+                self.channels[channel].volume = data & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    /// $E000 bit 6 holds the sound core in reset.
+    pub fn set_silenced(&mut self, silenced: bool) {
+        if silenced && !self.silenced {
+            *self = Vrc7Audio {
+                region: self.region,
+                silenced,
+                ..Vrc7Audio::new()
+            };
+        }
+        self.silenced = silenced;
+    }
+}
+
+impl ExpansionAudio for Vrc7Audio {
+    fn clock(&mut self) {
+        if self.silenced {
+            return;
+        }
+        self.cycle += 1;
+        if self.cycle < SAMPLE_CYCLES {
+            return;
+        }
+        self.cycle = 0;
+
+        let sample_rate = self.region.cpu_clock() as f32 / SAMPLE_CYCLES as f32;
+        // Tremolo: 0 to 4.8 dB at 3.7 Hz. Vibrato: about 14 cents at 6.4 Hz.
+        self.am_phase = (self.am_phase + 3.7 / sample_rate).fract();
+        self.pm_phase = (self.pm_phase + 6.4 / sample_rate).fract();
+        let am = 2.4 * (1.0 + (TAU * self.am_phase).sin());
+        let pm = 0.008 * (TAU * self.pm_phase).sin();
+
+        let custom = self.custom;
+        self.output = self
+            .channels
+            .iter_mut()
+            .map(|channel| {
+                let patch = match channel.instrument {
+                    0 => &custom,
+                    n => &ROM_PATCHES[n as usize - 1],
+                };
+                channel.sample(patch, am, pm, sample_rate)
+            })
+            .sum();
+    }
+
+    fn output(&self) -> f32 {
+        self.output * (PULSE_LEVEL / 2.0)
+    }
+}
+
+// The region is console configuration, set again by the memory map.
+crate::state::stateful!(Vrc7Audio {
+    addr,
+    custom,
+    channels,
+    silenced,
+    cycle,
+    am_phase,
+    pm_phase,
+    output,
+});
+
+crate::state::stateful!(FmChannel {
+    fnum,
+    block,
+    key,
+    sustain,
+    instrument,
+    volume,
+    modulator,
+    carrier,
+    feedback,
+});
+
+crate::state::stateful!(Operator {
+    phase,
+    attenuation,
+    stage,
+});
+
+impl Stateful for Stage {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&(*self as u8));
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        *self = match r.read::<u8>()? {
+            0 => Stage::Attack,
+            1 => Stage::Decay,
+            2 => Stage::Sustain,
+            3 => Stage::Release,
+            4 => Stage::Off,
+            _ => return Err(StateError::Invalid("VRC7 envelope stage")),
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use crate::region::Region;
+
+/// Signals produced by one frame counter step.
+#[derive(Default, Clone, Copy)]
+pub(super) struct FrameSignals {
+    pub(super) quarter: bool,
+    pub(super) half: bool,
+}
+
+#[derive(Default, Clone)]
+pub(super) struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    pub(super) irq: bool,
+    cycle: u32,
+    step: usize,
+    /// Mode written to $4017, applied after a 3 or 4 cycle delay.
+    pending: Option<(bool, u8)>,
+}
+
+impl FrameCounter {
+    /// Handles a $4017 write. `odd_cycle` selects the 4-cycle reset delay.
+    pub(super) fn write(&mut self, data: u8, odd_cycle: bool) {
+        self.irq_inhibit = data & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.pending = Some((data & 0x80 != 0, if odd_cycle { 4 } else { 3 }));
+    }
+
+    /// Reset button: the last mode written to $4017 is applied again.
+    pub(super) fn reset(&mut self) {
+        self.irq = false;
+        self.pending = Some((self.five_step, 3));
+    }
+
+    /// Clocked every CPU cycle.
+    pub(super) fn clock(&mut self, region: Region) -> FrameSignals {
+        let mut signals = FrameSignals::default();
+
+        if let Some((five_step, delay)) = self.pending {
+            if delay > 1 {
+                self.pending = Some((five_step, delay - 1));
+            } else {
+                self.pending = None;
+                self.five_step = five_step;
+                self.cycle = 0;
+                self.step = 0;
+                // Entering 5-step mode clocks the units immediately.
+                if five_step {
+                    signals.quarter = true;
+                    signals.half = true;
+                }
+                return signals;
+            }
+        }
+
+        self.cycle += 1;
+        if self.cycle == region.frame_counter_steps(self.five_step)[self.step] {
+            match self.step {
+                0 | 2 => signals.quarter = true,
+                1 | 4 => {
+                    signals.quarter = true;
+                    signals.half = true;
+                }
+                _ => {}
+            }
+
+            if !self.five_step && self.step >= 3 && !self.irq_inhibit {
+                self.irq = true;
+            }
+
+            self.step += 1;
+            if self.step == 6 {
+                self.step = 0;
+                self.cycle = 0;
+            }
+        }
+
+        signals
+    }
+}
+
+crate::state::stateful!(FrameCounter {
+    five_step,
+    irq_inhibit,
+    irq,
+    cycle,
+    step,
+    pending,
+});
@@ -0,0 +1,72 @@
+use super::envelope::{Envelope, LengthCounter};
+
+#[derive(Clone)]
+pub(super) struct Noise {
+    /// Short mode taps bit 6 instead of bit 1, giving a 93-step metallic loop.
+    short_mode: bool,
+    period_index: u8,
+    timer: u16,
+    shift: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+}
+
+impl Noise {
+    pub(super) fn new() -> Self {
+        Noise {
+            short_mode: false,
+            period_index: 0,
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub(super) fn write(&mut self, reg: u16, data: u8) {
+        match reg & 0x03 {
+            0 => {
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.period_index = data & 0x0F;
+            }
+            3 => {
+                self.length.load(data >> 3);
+                self.envelope.start = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Clocked every CPU cycle; `periods` is the region's rate table.
+    pub(super) fn clock_timer(&mut self, periods: &[u16; 16]) {
+        if self.timer == 0 {
+            self.timer = periods[self.period_index as usize] - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.shift & 0x01 != 0 || !self.length.active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+crate::state::stateful!(Noise {
+    short_mode,
+    period_index,
+    timer,
+    shift,
+    envelope,
+    length,
+});
@@ -0,0 +1,147 @@
+use super::envelope::{Envelope, LengthCounter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Clone)]
+pub(super) struct Pulse {
+    /// Pulse 1 negates with one's complement, pulse 2 with two's complement.
+    ones_complement: bool,
+    /// MMC5 pulses have no sweep unit, and so are never muted by it.
+    has_sweep: bool,
+    duty: u8,
+    sequence: u8,
+    pub(super) timer_period: u16,
+    timer: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    pub(super) fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            has_sweep: true,
+            duty: 0,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_reload: false,
+            sweep_divider: 0,
+        }
+    }
+
+    /// A pulse channel without the sweep unit, as found on the MMC5.
+    pub(super) fn without_sweep() -> Self {
+        Pulse {
+            has_sweep: false,
+            ..Pulse::new(false)
+        }
+    }
+
+    pub(super) fn write(&mut self, reg: u16, data: u8) {
+        match reg & 0x03 {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 if self.has_sweep => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            1 => {}
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | data as u16;
+            }
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.length.load(data >> 3);
+                self.sequence = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle).
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.timer_period
+                .saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.has_sweep && (self.timer_period < 8 || self.target_period() > 0x07FF)
+    }
+
+    /// Clocked by the frame counter's half-frame signal.
+    pub(super) fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.target_period();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.muted()
+            || !self.length.active()
+            || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+crate::state::stateful!(Pulse {
+    duty,
+    sequence,
+    timer_period,
+    timer,
+    envelope,
+    length,
+    sweep_enabled,
+    sweep_period,
+    sweep_negate,
+    sweep_shift,
+    sweep_reload,
+    sweep_divider,
+});
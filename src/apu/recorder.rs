@@ -0,0 +1,125 @@
+use super::{Apu, Channel};
+use crate::wav::WavWriter;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+type FileWriter = WavWriter<BufWriter<File>>;
+
+/// Captures the APU output to mono 16-bit WAV files, one frame at a time.
+///
+/// When stems are enabled on the APU, each channel is also written next to
+/// the mix, e.g. `song.wav` gets `song.pulse1.wav`, `song.triangle.wav`, ...
+pub struct AudioRecorder {
+    mix: FileWriter,
+    stems: Vec<(Channel, FileWriter)>,
+}
+
+impl AudioRecorder {
+    pub fn create(path: impl AsRef<Path>, apu: &Apu) -> io::Result<Self> {
+        let path = path.as_ref();
+        let open = |path: &Path| -> io::Result<FileWriter> {
+            WavWriter::new(BufWriter::new(File::create(path)?), apu.sample_rate(), 1)
+        };
+
+        let mix = open(path)?;
+        let mut stems = Vec::new();
+        if apu.stems_enabled() {
+            for channel in Channel::ALL {
+                stems.push((channel, open(&Self::stem_path(path, channel))?));
+            }
+        }
+        Ok(AudioRecorder { mix, stems })
+    }
+
+    /// Path of the stem file for `channel` when recording to `path`.
+    pub fn stem_path(path: &Path, channel: Channel) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{stem}.{}.wav", channel.name()))
+    }
+
+    /// Appends the samples of the frame just ended by [`Apu::end_frame`].
+    pub fn record_frame(&mut self, apu: &Apu) -> io::Result<()> {
+        self.mix.write_samples(apu.samples())?;
+        for (channel, writer) in &mut self.stems {
+            if let Some(samples) = apu.stem_samples(*channel) {
+                writer.write_samples(samples)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finalises the headers of all files.
+    pub fn finish(self) -> io::Result<()> {
+        self.mix.finish()?;
+        for (_, writer) in self.stems {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::program_image;
+    use crate::nes::Nes;
+    use crate::wav::Wav;
+
+    #[test]
+    fn records_the_mix_and_stems() {
+        // A 440 Hz pulse on channel 1 and a triangle.
+        let program = crate::asm!(
+            ".org $8000",
+            "LDA #$05; STA $4015",
+            "LDA #$BF; STA $4000; LDA #$FD; STA $4002; LDA #$00; STA $4003",
+            "LDA #$FF; STA $4008; LDA #$80; STA $400A; LDA #$00; STA $400B",
+            "loop: JMP loop",
+        );
+        let mut nes = Nes::from_ines(&program_image(&program.bytes)).unwrap();
+        nes.apu_mut().set_stems_enabled(true);
+
+        let dir = std::env::temp_dir().join(format!("nesters-{}-recorder", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.wav");
+        let mut recorder = AudioRecorder::create(&path, nes.apu()).unwrap();
+        for _ in 0..5 {
+            nes.run_frame();
+            recorder.record_frame(nes.apu()).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(24), nes.apu().sample_rate());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(40) as usize, bytes.len() - 44);
+
+        let mix = Wav::read(bytes.as_slice()).unwrap();
+        assert_eq!(
+            (mix.sample_rate, mix.channels),
+            (nes.apu().sample_rate(), 1)
+        );
+        assert!(mix.samples.len() > 4 * 700);
+        let peak = |wav: &Wav| wav.samples.iter().map(|s| s.unsigned_abs()).max();
+        for channel in Channel::ALL {
+            let stem_path = AudioRecorder::stem_path(&path, channel);
+            let stem = Wav::read(std::fs::File::open(&stem_path).unwrap()).unwrap();
+            assert_eq!(stem.samples.len(), mix.samples.len(), "{}", channel.name());
+            let silent = peak(&stem) == Some(0);
+            let playing = matches!(channel, Channel::Pulse1 | Channel::Triangle);
+            assert_eq!(silent, !playing, "{}", channel.name());
+        }
+
+        // FNV-1a of the mix as first recorded, so any change to the output
+        // shows up here.
+        let hash = bytes.iter().fold(0xCBF2_9CE4_8422_2325u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(hash, 0x0453_5916_3FB6_73EF);
+    }
+}
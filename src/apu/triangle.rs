@@ -0,0 +1,82 @@
+use super::envelope::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Default, Clone)]
+pub(super) struct Triangle {
+    timer_period: u16,
+    timer: u16,
+    sequence: u8,
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    pub(super) length: LengthCounter,
+}
+
+impl Triangle {
+    pub(super) fn reset_phase(&mut self) {
+        self.sequence = 0;
+    }
+
+    pub(super) fn write(&mut self, reg: u16, data: u8) {
+        match reg & 0x03 {
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = data & 0x7F;
+            }
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | data as u16;
+            }
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.length.load(data >> 3);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.sequence = (self.sequence + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocked by the frame counter's quarter-frame signal.
+    pub(super) fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        SEQUENCE[self.sequence as usize]
+    }
+}
+
+crate::state::stateful!(Triangle {
+    timer_period,
+    timer,
+    sequence,
+    control,
+    linear_reload_value,
+    linear_counter,
+    linear_reload,
+    length,
+});
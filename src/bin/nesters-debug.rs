@@ -0,0 +1,506 @@
+//! Command-line debugger.
+//!
+//! ```text
+//! nesters-debug [--raw] [--symbols FILE]... [--frame-limit N] [--batch FILE | --gdb ADDR] ROM
+//! ```
+//!
+//! `ROM` is an iNES image, or with `--raw` a bare PRG image of up to 32KB
+//! that is mapped to end at $FFFF, vectors included. `--symbols` loads
+//! labels from ld65 debug info (`.dbg`), NESASM (`.fns`) or Mesen (`.mlb`)
+//! files, chosen by extension. Commands are read from
+//! standard input, or from `FILE` with `--batch`, in which case each one is
+//! echoed and the first error ends the run with a failure status.
+//! `continue` and `finish` give up after `--frame-limit` frames, 600 in
+//! batch mode and unlimited otherwise by default. With the
+//! `gdb` feature, `--gdb` serves one GDB remote protocol client instead, on
+//! a TCP address such as `127.0.0.1:2345` or on `unix:PATH`.
+
+use nesters::debug::{parse_number, Access, CodeDataLog, Debugger, Expr, StopReason};
+use nesters::nes::Nes;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: nesters-debug [--raw] [--symbols FILE]... [--frame-limit N] \
+    [--batch FILE | --gdb ADDR] ROM";
+
+/// How many frames `continue` and `finish` run in batch mode before giving
+/// up, so a script that misses its breakpoint still ends.
+const BATCH_FRAME_LIMIT: u64 = 600;
+
+const HELP: &str = "\
+ADDR is a number such as $C000 or a label from a symbol file
+break ADDR [if COND]  break at ADDR, optionally only when COND holds (b)
+break if COND         break before any instruction where COND holds
+                      COND is an expression such as `a == $40 && [$0300] > 3`
+                      over registers, flags (n v d i z c), [byte] and {word}
+                      memory reads, cycle, frame, scanline, dot and labels
+watch ADDR[-END] [r|w|rw] [if COND]
+                      stop after an instruction reads or writes the range,
+                      writes only by default; COND can also use addr and
+                      value of the access (w)
+delete ID             remove a breakpoint or watchpoint (del)
+enable ID, disable ID
+breakpoints           list breakpoints and watchpoints (bl)
+step [N]              run N instructions (s)
+next [N]              like step, running subroutine calls through (n)
+finish [N]            run until the current subroutine returns, for at
+                      most N frames
+frame [N]             run to the start of the Nth next frame (f)
+continue [N]          run until a breakpoint, for at most N frames (c)
+trace [N]             run N instructions, printing each one (t)
+regs                  show registers (r)
+mem ADDR [LEN]        dump memory (m)
+disasm [ADDR] [N]     disassemble N instructions from ADDR or PC (d)
+symbols FILE          load labels from a .dbg, .fns or .mlb file
+cdl [start|stop|clear]
+                      show, start, stop or empty the code/data log, which
+                      marks the ROM bytes used as code and data
+cdl load FILE         log on from an FCEUX .cdl file
+cdl save FILE         write the log as an FCEUX .cdl file
+reset                 press the reset button
+quit                  exit (q)";
+
+struct Session {
+    debugger: Debugger,
+    /// Frames `continue` and `finish` run without a count of their own.
+    frame_limit: Option<u64>,
+}
+
+impl Session {
+    /// Runs one command line. Returns false on quit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args: Vec<&str> = args.split_whitespace().collect();
+        match command {
+            "" => {}
+            "break" | "b" => self.add_breakpoint(line[command.len()..].trim())?,
+            "watch" | "w" => {
+                let (spec, condition) = self.split_condition(line[command.len()..].trim())?;
+                let args: Vec<&str> = spec.split_whitespace().collect();
+                let range = args.first().ok_or("watch needs an address")?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (start, end),
+                    None => (*range, *range),
+                };
+                let (start, end) = (self.address(Some(&start))?, self.address(Some(&end))?);
+                let access = match args.get(1).copied().unwrap_or("w") {
+                    "r" => Access::Read,
+                    "w" => Access::Write,
+                    "rw" => Access::Any,
+                    access => return Err(format!("bad access '{access}', expected r, w or rw")),
+                };
+                let id = self.debugger.add_watchpoint(start..=end, access, condition);
+                println!("watchpoint {id}");
+            }
+            "delete" | "del" => {
+                let id = id_arg(&args)?;
+                if !self.debugger.remove_breakpoint(id) {
+                    return Err(format!("no breakpoint {id}"));
+                }
+            }
+            "enable" | "disable" => {
+                let id = id_arg(&args)?;
+                if !self
+                    .debugger
+                    .set_breakpoint_enabled(id, command == "enable")
+                {
+                    return Err(format!("no breakpoint {id}"));
+                }
+            }
+            "breakpoints" | "bl" => {
+                for breakpoint in self.debugger.breakpoints() {
+                    let mut text = format!("{:>3}", breakpoint.id);
+                    if let Some(addr) = breakpoint.addr {
+                        text += &format!(" ${addr:04X}");
+                        if let Some(label) = self.debugger.label(addr) {
+                            text += &format!(" ({label})");
+                        }
+                    }
+                    if let Some(condition) = &breakpoint.condition {
+                        text += &format!(" if {condition}");
+                    }
+                    if !breakpoint.enabled {
+                        text += " (disabled)";
+                    }
+                    println!("{text}");
+                }
+                for watchpoint in self.debugger.watchpoints() {
+                    let (start, end) = (watchpoint.range.start(), watchpoint.range.end());
+                    let access = match watchpoint.access {
+                        Access::Read => "r",
+                        Access::Write => "w",
+                        Access::Any => "rw",
+                    };
+                    let mut text = format!(
+                        "{:>3} watch ${start:04X}-${end:04X} {access}",
+                        watchpoint.id
+                    );
+                    if let Some(condition) = &watchpoint.condition {
+                        text += &format!(" if {condition}");
+                    }
+                    if !watchpoint.enabled {
+                        text += " (disabled)";
+                    }
+                    println!("{text}");
+                }
+            }
+            "step" | "s" => self.repeat(&args, Debugger::step_into)?,
+            "next" | "n" => self.repeat(&args, Debugger::step_over)?,
+            "finish" => self.limited(&args, Debugger::step_out)?,
+            "frame" | "f" => self.repeat(&args, Debugger::run_frame)?,
+            "continue" | "c" => self.limited(&args, |debugger| debugger.run_until(|_| false))?,
+            "trace" | "t" => self.repeat(&args, |debugger| {
+                println!("{}", debugger.trace_line());
+                debugger.step_into()
+            })?,
+            "regs" | "r" => self.show_location(),
+            "mem" | "m" => {
+                let addr = self.address(args.first())?;
+                let len = parse_arg(args.get(1), Some(0x40))?;
+                self.dump(addr, len);
+            }
+            "disasm" | "d" => {
+                let mut addr = match args.first() {
+                    Some(_) => self.address(args.first())?,
+                    None => self.debugger.nes().cpu().pc(),
+                };
+                for _ in 0..parse_arg(args.get(1), Some(10))? {
+                    let line = self.debugger.disassemble(addr);
+                    if let Some(label) = self.debugger.label(addr) {
+                        println!("{label}:");
+                    }
+                    let bytes: Vec<String> =
+                        line.bytes.iter().map(|b| format!("{b:02X}")).collect();
+                    let text = self.debugger.line_text(&line);
+                    println!("${addr:04X}  {:<8}  {text}", bytes.join(" "));
+                    addr = line.next_addr();
+                }
+            }
+            "symbols" => {
+                let path = args.first().ok_or("symbols needs a file")?;
+                self.load_symbols(path)?;
+            }
+            "cdl" => self.code_data_log(&args)?,
+            "reset" => {
+                self.debugger.nes_mut().reset();
+                let reason = self.debugger.step_into();
+                self.report(reason);
+            }
+            "help" | "h" | "?" => println!("{HELP}"),
+            "quit" | "q" => return Ok(false),
+            _ => return Err(format!("unknown command '{command}', try 'help'")),
+        }
+        Ok(true)
+    }
+
+    fn add_breakpoint(&mut self, spec: &str) -> Result<(), String> {
+        let (addr, condition) = self.split_condition(spec)?;
+        let addr = match addr {
+            "" => None,
+            addr => Some(self.address(Some(&addr))?),
+        };
+        if addr.is_none() && condition.is_none() {
+            return Err("break needs an address or a condition".into());
+        }
+        let id = self.debugger.add_breakpoint(addr, condition);
+        println!("breakpoint {id}");
+        Ok(())
+    }
+
+    /// Runs `action` the number of times given by the optional first
+    /// argument, stopping early at a breakpoint.
+    fn repeat(
+        &mut self,
+        args: &[&str],
+        mut action: impl FnMut(&mut Debugger) -> StopReason,
+    ) -> Result<(), String> {
+        let count = parse_arg(args.first(), Some(1))?;
+        let mut reason = StopReason::Done;
+        for _ in 0..count {
+            reason = action(&mut self.debugger);
+            if reason != StopReason::Done {
+                break;
+            }
+        }
+        self.report(reason);
+        Ok(())
+    }
+
+    /// Runs `action` for at most the number of frames given by the
+    /// optional first argument, or the session's limit.
+    fn limited(
+        &mut self,
+        args: &[&str],
+        action: impl FnOnce(&mut Debugger) -> StopReason,
+    ) -> Result<(), String> {
+        let frames = match args.first() {
+            Some(_) => Some(parse_arg(args.first(), None)? as u64),
+            None => self.frame_limit,
+        };
+        self.debugger.set_frame_limit(frames);
+        let reason = action(&mut self.debugger);
+        self.debugger.set_frame_limit(None);
+        self.report(reason);
+        Ok(())
+    }
+
+    fn report(&self, reason: StopReason) {
+        match reason {
+            StopReason::Done => {}
+            StopReason::Breakpoint(id) => println!("breakpoint {id} hit"),
+            StopReason::Watchpoint { id, addr } => {
+                println!("watchpoint {id} hit at ${addr:04X}")
+            }
+            StopReason::Halt(addr) => println!("CPU halts at ${addr:04X}"),
+            StopReason::FrameLimit => println!("frame limit reached"),
+        }
+        self.show_location();
+    }
+
+    fn show_location(&self) {
+        let frame = self.debugger.nes().frame();
+        println!("{} frame:{frame}", self.debugger.trace_line());
+    }
+
+    /// Adds the labels from `path`, in the format its extension implies.
+    fn load_symbols(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let symbols = self.debugger.symbols_mut();
+        let before = symbols.len();
+        let result = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("dbg") => symbols.add_ca65(&text),
+            Some("fns") => symbols.add_fns(&text),
+            Some("mlb") => symbols.add_mlb(&text),
+            _ => return Err(format!("{path}: expected a .dbg, .fns or .mlb file")),
+        };
+        result.map_err(|e| format!("{path}: {e}"))?;
+        println!("{} labels from {path}", symbols.len() - before);
+        Ok(())
+    }
+
+    fn code_data_log(&mut self, args: &[&str]) -> Result<(), String> {
+        let cartridge = self.debugger.nes().cartridge().ok_or("no cartridge")?;
+        match args {
+            [] => {}
+            ["start"] => {
+                if self.debugger.code_data_log().is_none() {
+                    let cdl = CodeDataLog::new(cartridge);
+                    self.debugger.set_code_data_log(Some(cdl));
+                }
+            }
+            ["stop"] => {
+                self.debugger.set_code_data_log(None);
+            }
+            ["clear"] => {
+                let cdl = CodeDataLog::new(cartridge);
+                let running = self.debugger.code_data_log().is_some();
+                self.debugger.set_code_data_log(running.then_some(cdl));
+            }
+            ["load", path] => {
+                let data = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+                let cdl =
+                    CodeDataLog::from_cdl(cartridge, &data).map_err(|e| format!("{path}: {e}"))?;
+                self.debugger.set_code_data_log(Some(cdl));
+            }
+            ["save", path] => {
+                let cdl = self
+                    .debugger
+                    .code_data_log()
+                    .ok_or("the log isn't running")?;
+                std::fs::write(path, cdl.to_cdl()).map_err(|e| format!("{path}: {e}"))?;
+            }
+            _ => return Err("usage: cdl [start|stop|clear|load FILE|save FILE]".into()),
+        }
+        match self.debugger.code_data_log() {
+            Some(cdl) => println!("{cdl}"),
+            None => println!("code/data log stopped"),
+        }
+        Ok(())
+    }
+
+    /// Parses an address argument, which can be a label.
+    fn address(&self, arg: Option<&&str>) -> Result<u16, String> {
+        let arg = arg.ok_or("missing argument")?;
+        self.debugger.parse_address(arg).map_err(|e| e.to_string())
+    }
+
+    /// Splits `SPEC if EXPR` and parses the expression.
+    fn split_condition<'a>(&self, text: &'a str) -> Result<(&'a str, Option<Expr>), String> {
+        let (spec, condition) = match text.strip_prefix("if ") {
+            Some(condition) => ("", condition),
+            None => match text.split_once(" if ") {
+                Some((spec, condition)) => (spec.trim(), condition),
+                None => return Ok((text, None)),
+            },
+        };
+        let condition = self
+            .debugger
+            .parse_expr(condition)
+            .map_err(|e| e.to_string())?;
+        Ok((spec, Some(condition)))
+    }
+
+    fn dump(&self, addr: u16, len: u16) {
+        let memory = self.debugger.nes().memory();
+        let end = addr as u32 + len as u32;
+        let mut row = addr as u32;
+        while row < end {
+            let bytes: Vec<u8> = (row..end.min(row + 16))
+                .map(|addr| memory.peek(addr as u16))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            println!("${row:04X}  {:<47}  {text}", hex.join(" "));
+            row += 16;
+        }
+    }
+}
+
+fn parse_arg(arg: Option<&&str>, default: Option<u16>) -> Result<u16, String> {
+    match (arg, default) {
+        (Some(arg), _) => parse_number(arg).map_err(|e| e.to_string()),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err("missing argument".into()),
+    }
+}
+
+fn id_arg(args: &[&str]) -> Result<usize, String> {
+    args.first()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| "expected a breakpoint id".into())
+}
+
+/// Wraps a bare PRG image in an NROM cartridge with CHR-RAM.
+fn raw_image(prg: &[u8]) -> Result<Vec<u8>, String> {
+    let banks = match prg.len() {
+        0..=0x4000 => 1,
+        0x4001..=0x8000 => 2,
+        len => return Err(format!("raw image is {len} bytes, at most 32768 fit")),
+    };
+    let mut image = vec![b'N', b'E', b'S', 0x1A, banks, 0, 0, 0];
+    image.resize(16, 0);
+    image.resize(16 + banks as usize * 0x4000 - prg.len(), 0xFF);
+    image.extend_from_slice(prg);
+    Ok(image)
+}
+
+fn run() -> Result<(), String> {
+    let mut raw = false;
+    let mut batch = None;
+    let mut gdb = None;
+    let mut rom = None;
+    let mut symbols = Vec::new();
+    let mut frame_limit = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--raw" => raw = true,
+            "--batch" => batch = Some(args.next().ok_or("--batch needs a file")?),
+            "--gdb" => gdb = Some(args.next().ok_or("--gdb needs an address")?),
+            "--symbols" => symbols.push(args.next().ok_or("--symbols needs a file")?),
+            "--frame-limit" => {
+                let frames = args.next().ok_or("--frame-limit needs a count")?;
+                let frames = frames
+                    .parse()
+                    .map_err(|_| format!("bad frame count '{frames}'"))?;
+                frame_limit = Some(frames);
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}\n\n{HELP}");
+                return Ok(());
+            }
+            _ => rom = Some(arg),
+        }
+    }
+    let rom = rom.ok_or(USAGE)?;
+    let data = std::fs::read(&rom).map_err(|e| format!("{rom}: {e}"))?;
+    let image = if raw { raw_image(&data)? } else { data };
+    let nes = Nes::from_ines(&image).map_err(|e| format!("{rom}: {e}"))?;
+
+    let mut session = Session {
+        debugger: Debugger::new(nes),
+        frame_limit: frame_limit.or(batch.is_some().then_some(BATCH_FRAME_LIMIT)),
+    };
+    for path in &symbols {
+        session.load_symbols(path)?;
+    }
+    if let Some(addr) = gdb {
+        return serve_gdb(session.debugger, &addr);
+    }
+    // Through the reset sequence to the first instruction.
+    session.debugger.step_into();
+    session.show_location();
+
+    match batch {
+        Some(path) => {
+            let script = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+            for (number, line) in script.lines().enumerate() {
+                println!("> {line}");
+                match session.execute(line) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => return Err(format!("{path}:{}: {e}", number + 1)),
+                }
+            }
+        }
+        None => {
+            let stdin = io::stdin();
+            loop {
+                print!("(nesters) ");
+                io::stdout().flush().ok();
+                let mut line = String::new();
+                if stdin
+                    .lock()
+                    .read_line(&mut line)
+                    .map_err(|e| e.to_string())?
+                    == 0
+                {
+                    break;
+                }
+                match session.execute(&line) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => println!("error: {e}"),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gdb")]
+fn serve_gdb(mut debugger: Debugger, addr: &str) -> Result<(), String> {
+    use nesters::debug::gdb::GdbServer;
+
+    debugger.step_into();
+    let mut server = GdbServer::new(debugger);
+    println!("waiting for a GDB client on {addr}");
+    let result = match addr.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(path) => server.listen_unix(path),
+        #[cfg(not(unix))]
+        Some(_) => return Err("unix sockets are not supported here".into()),
+        None => server.listen_tcp(addr),
+    };
+    result.map_err(|e| format!("{addr}: {e}"))
+}
+
+#[cfg(not(feature = "gdb"))]
+fn serve_gdb(_debugger: Debugger, _addr: &str) -> Result<(), String> {
+    Err("built without the gdb feature".into())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("nesters-debug: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Runs a directory of test ROMs headlessly and reports on them.
+//!
+//! ```text
+//! nesters-testrom [--junit] [--output FILE] [--timeout FRAMES]
+//!                 [--frames N] [--hashes FILE] [--record FILE]
+//!                 [--allow-unknown] PATH...
+//! ```
+//!
+//! Each `PATH` is an iNES image or a directory searched for `.nes` files.
+//! ROMs report through blargg's status protocol unless `--hashes` lists
+//! them, in which case they pass when the frame after `--frames` frames
+//! has the listed hash. The hash file has lines of `HASH NAME`, `NAME`
+//! being the ROM's path below the directory given; `--record` writes one
+//! for the ROMs that used neither method. The report is JSON, or JUnit XML
+//! with `--junit`, on standard output or to `--output`. The exit status is
+//! a failure if any ROM failed, timed out, could not be loaded or used
+//! neither method, unless `--allow-unknown` is given, as it usually is with
+//! `--record`.
+
+use nesters::testrom::{json_report, junit_report, Outcome, Runner, TestResult};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: nesters-testrom [--junit] [--output FILE] [--timeout FRAMES] \
+                     [--frames N] [--hashes FILE] [--record FILE] [--allow-unknown] PATH...";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("nesters-testrom: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns whether every ROM passed, or reported nothing if that is
+/// allowed.
+fn run() -> Result<bool, String> {
+    let mut runner = Runner::default();
+    let (mut junit, mut allow_unknown) = (false, false);
+    let (mut output, mut hashes, mut record) = (None, None, None);
+    let mut paths = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{flag} needs a value"));
+        match arg.as_str() {
+            "--junit" => junit = true,
+            "--output" => output = Some(value("--output")?),
+            "--timeout" => runner.timeout_frames = frames(&value("--timeout")?)?,
+            "--frames" => runner.screen_frames = frames(&value("--frames")?)?,
+            "--hashes" => hashes = Some(value("--hashes")?),
+            "--record" => record = Some(value("--record")?),
+            "--allow-unknown" => allow_unknown = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(true);
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+    let expected = match &hashes {
+        Some(path) => read_hashes(path)?,
+        None => HashMap::new(),
+    };
+
+    let mut roms = Vec::new();
+    for path in &paths {
+        if path.is_dir() {
+            find_roms(path, path, &mut roms).map_err(|e| format!("{}: {e}", path.display()))?;
+        } else {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            roms.push((name.into_owned(), path.clone()));
+        }
+    }
+
+    let mut results = Vec::new();
+    for (name, path) in roms {
+        let result = match fs::read(&path) {
+            Ok(image) => runner.run(&name, &image, expected.get(&name).copied()),
+            Err(e) => TestResult {
+                name,
+                outcome: Outcome::Error,
+                code: None,
+                message: e.to_string(),
+                hash: 0,
+                frames: 0,
+                time: Default::default(),
+            },
+        };
+        eprintln!(
+            "{:<9} {} {}",
+            result.outcome.name(),
+            result.name,
+            result.message.lines().next().unwrap_or("")
+        );
+        results.push(result);
+    }
+
+    let report = if junit {
+        junit_report(&results)
+    } else {
+        json_report(&results)
+    };
+    match output {
+        Some(path) => fs::write(&path, report).map_err(|e| format!("{path}: {e}"))?,
+        None => print!("{report}"),
+    }
+    if let Some(path) = record {
+        let lines: String = results
+            .iter()
+            .filter(|r| r.outcome == Outcome::Unknown)
+            .map(|r| format!("{:016x} {}\n", r.hash, r.name))
+            .collect();
+        fs::write(&path, lines).map_err(|e| format!("{path}: {e}"))?;
+    }
+    Ok(results
+        .iter()
+        .all(|r| r.outcome == Outcome::Passed || allow_unknown && r.outcome == Outcome::Unknown))
+}
+
+fn frames(text: &str) -> Result<u64, String> {
+    text.parse()
+        .map_err(|_| format!("bad frame count '{text}'"))
+}
+
+fn read_hashes(path: &str) -> Result<HashMap<String, u64>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut hashes = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line
+            .split_once(char::is_whitespace)
+            .and_then(|(hash, name)| {
+                Some((name.trim().to_string(), u64::from_str_radix(hash, 16).ok()?))
+            });
+        let (name, hash) = parsed.ok_or(format!("{path}:{}: expected 'HASH NAME'", number + 1))?;
+        hashes.insert(name, hash);
+    }
+    Ok(hashes)
+}
+
+/// Collects the `.nes` files below `dir`, named by their path from `root`,
+/// in name order.
+fn find_roms(root: &Path, dir: &Path, roms: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_roms(root, &path, roms)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+        {
+            let name = path.strip_prefix(root).unwrap_or(&path);
+            let name = name.to_string_lossy().replace('\\', "/");
+            roms.push((name, path));
+        }
+    }
+    Ok(())
+}
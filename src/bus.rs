@@ -1,9 +1,24 @@
 pub trait BusDevice {
-    fn read(&self, addr: u16) -> u8;
+    /// Takes `&mut self` because reading a register can change it: $2002
+    /// clears the vblank flag, $2007 advances the VRAM address and $4016
+    /// shifts the joypads. Before the PPU was dot-timed this took `&self`.
+    fn read(&mut self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, data: u8);
 }
 
+/// Flat memory, as test programs run in. It has to cover all 64KB.
+impl BusDevice for [u8] {
+    fn read(&mut self, addr: u16) -> u8 {
+        self[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self[addr as usize] = data;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BusEvent {
     Read(u16),
-    Write(u16, u8)
+    Write(u16, u8),
 }
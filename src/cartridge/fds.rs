@@ -0,0 +1,191 @@
+use super::{Header, Mapper, Mirroring};
+use crate::apu::expansion::{ExpansionAudio, FdsAudio};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Mapper 20: the Famicom Disk System RAM adapter. 32KB of PRG RAM at
+/// $6000-$DFFF, the 8KB BIOS (the last 8KB of PRG ROM) at $E000, 8KB of
+/// CHR RAM, the cycle timer IRQ at $4020-$4022 and the FDS sound unit at
+/// $4040-$4092.
+///
+/// The disk drive is not emulated: the drive always reports that no disk
+/// is inserted.
+pub(super) struct Fds {
+    bios: Vec<u8>,
+    ram: Vec<u8>,
+    chr: Vec<u8>,
+    disk_io: bool,
+    sound_io: bool,
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    mirroring: Mirroring,
+    audio: FdsAudio,
+}
+
+/// $4032 with no disk: not inserted, not ready and write protected.
+const NO_DISK: u8 = 0x07;
+/// $4033 bit 7: the battery is good.
+const BATTERY_GOOD: u8 = 0x80;
+
+impl Fds {
+    pub(super) fn new(header: &Header, prg_rom: Vec<u8>) -> Self {
+        let mut bios = prg_rom[prg_rom.len().saturating_sub(0x2000)..].to_vec();
+        bios.resize(0x2000, 0);
+        Fds {
+            bios,
+            ram: vec![0; 0x8000],
+            chr: vec![0; 0x2000],
+            disk_io: false,
+            sound_io: false,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            irq_pending: false,
+            mirroring: header.mirroring,
+            audio: FdsAudio::new(),
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        let data = self.cpu_peek(addr);
+        if addr == 0x4030 && self.disk_io {
+            self.irq_pending = false;
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4030 if self.disk_io => Some(self.irq_pending as u8),
+            0x4031 if self.disk_io => Some(0),
+            0x4032 if self.disk_io => Some(NO_DISK),
+            0x4033 if self.disk_io => Some(BATTERY_GOOD),
+            0x4040..=0x4092 if self.sound_io => self.audio.read(addr),
+            0x6000..=0xDFFF => Some(self.ram[addr as usize - 0x6000]),
+            0xE000..=0xFFFF => Some(self.bios[self.prg_rom_offset(addr)?]),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0xE000..=0xFFFF => Some(addr as usize - 0xE000),
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x6000..=0xDFFF => Some(&mut self.ram[addr as usize - 0x6000]),
+            _ => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.bios[offset])
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | data as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (data as u16) << 8,
+            0x4022 => {
+                self.irq_repeat = data & 0x01 != 0;
+                self.irq_enabled = data & 0x02 != 0 && self.disk_io;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.irq_pending = false;
+                }
+            }
+            0x4023 => {
+                self.disk_io = data & 0x01 != 0;
+                self.sound_io = data & 0x02 != 0;
+                if !self.disk_io {
+                    self.irq_enabled = false;
+                    self.irq_pending = false;
+                }
+            }
+            0x4025 if self.disk_io => {
+                self.mirroring = if data & 0x08 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            0x4040..=0x408A if self.sound_io => self.audio.write(addr, data),
+            0x6000..=0xDFFF => self.ram[addr as usize - 0x6000] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[addr as usize & 0x1FFF]
+    }
+
+    fn chr_rom_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr[addr as usize & 0x1FFF] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock(&mut self) {
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq_pending = true;
+                self.irq_counter = self.irq_reload;
+                self.irq_enabled = self.irq_repeat;
+            } else {
+                self.irq_counter -= 1;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.ram);
+        w.write(&self.chr);
+        w.write(&self.disk_io);
+        w.write(&self.sound_io);
+        w.write(&self.irq_reload);
+        w.write(&self.irq_counter);
+        w.write(&self.irq_repeat);
+        w.write(&self.irq_enabled);
+        w.write(&self.irq_pending);
+        w.write(&self.mirroring);
+        w.write(&self.audio);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.ram)?;
+        r.read_into(&mut self.chr)?;
+        r.read_into(&mut self.disk_io)?;
+        r.read_into(&mut self.sound_io)?;
+        r.read_into(&mut self.irq_reload)?;
+        r.read_into(&mut self.irq_counter)?;
+        r.read_into(&mut self.irq_repeat)?;
+        r.read_into(&mut self.irq_enabled)?;
+        r.read_into(&mut self.irq_pending)?;
+        r.read_into(&mut self.mirroring)?;
+        r.read_into(&mut self.audio)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use super::{bank_offset, banked, banked_mut, Header, Mapper, Mirroring};
+use crate::apu::expansion::{ExpansionAudio, Sunsoft5bAudio};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Mapper 69: Sunsoft FME-7 and 5B. Commands written to $8000 select which
+/// register $A000 sets: eight 1KB CHR banks, ROM or RAM at $6000, three 8KB
+/// PRG banks, mirroring and a 16-bit down-counting cycle IRQ. The 5B adds
+/// its sound chip at $C000/$E000.
+pub(super) struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    command: u8,
+    chr_banks: [usize; 8],
+    /// Bank at $6000, then $8000, $A000 and $C000.
+    prg_banks: [usize; 4],
+    ram_selected: bool,
+    ram_enabled: bool,
+    mirroring: Mirroring,
+    irq_enabled: bool,
+    counter_enabled: bool,
+    counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5bAudio,
+}
+
+impl Fme7 {
+    pub(super) fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        Fme7 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size.max(0x2000)],
+            chr: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            ram_selected: false,
+            ram_enabled: false,
+            mirroring: header.mirroring,
+            irq_enabled: false,
+            counter_enabled: false,
+            counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5bAudio::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = data as usize,
+            0x8 => {
+                self.ram_enabled = data & 0x80 != 0;
+                self.ram_selected = data & 0x40 != 0;
+                self.prg_banks[0] = data as usize & 0x3F;
+            }
+            0x9..=0xB => self.prg_banks[self.command as usize - 0x8] = data as usize & 0x3F,
+            0xC => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xD => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.counter_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.counter = (self.counter & 0xFF00) | data as u16,
+            _ => self.counter = (self.counter & 0x00FF) | (data as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.ram_selected => self
+                .ram_enabled
+                .then(|| banked(&self.prg_ram, self.prg_banks[0], 0x2000, addr)),
+            0x6000..=0xFFFF => Some(self.prg_rom[self.prg_rom_offset(addr)?]),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let len = self.prg_rom.len();
+        match addr {
+            0x6000..=0x7FFF if self.ram_selected => None,
+            0x6000..=0xDFFF => {
+                let bank = self.prg_banks[(addr as usize - 0x6000) / 0x2000];
+                Some(bank_offset(len, bank, 0x2000, addr))
+            }
+            0xE000..=0xFFFF => Some(bank_offset(len, len / 0x2000 - 1, 0x2000, addr)),
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x6000..=0x7FFF if self.ram_selected => self
+                .ram_enabled
+                .then(|| banked_mut(&mut self.prg_ram, self.prg_banks[0], 0x2000, addr)),
+            _ => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.prg_rom[offset])
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.ram_selected && self.ram_enabled => {
+                *banked_mut(&mut self.prg_ram, self.prg_banks[0], 0x2000, addr) = data;
+            }
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(data),
+            0xC000..=0xDFFF => self.audio.write_addr(data),
+            0xE000..=0xFFFF => self.audio.write_data(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        banked(
+            &self.chr,
+            self.chr_banks[addr as usize >> 10 & 0x07],
+            0x400,
+            addr,
+        )
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+        (!self.chr_ram).then(|| bank_offset(self.chr.len(), bank, 0x400, addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_ram {
+            let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+            *banked_mut(&mut self.chr, bank, 0x400, addr) = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock(&mut self) {
+        if self.counter_enabled {
+            self.counter = self.counter.wrapping_sub(1);
+            if self.counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.prg_ram);
+        if self.chr_ram {
+            w.write(&self.chr);
+        }
+        w.write(&self.command);
+        w.write(&self.chr_banks);
+        w.write(&self.prg_banks);
+        w.write(&self.ram_selected);
+        w.write(&self.ram_enabled);
+        w.write(&self.mirroring);
+        w.write(&self.irq_enabled);
+        w.write(&self.counter_enabled);
+        w.write(&self.counter);
+        w.write(&self.irq_pending);
+        w.write(&self.audio);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.prg_ram)?;
+        if self.chr_ram {
+            r.read_into(&mut self.chr)?;
+        }
+        r.read_into(&mut self.command)?;
+        r.read_into(&mut self.chr_banks)?;
+        r.read_into(&mut self.prg_banks)?;
+        r.read_into(&mut self.ram_selected)?;
+        r.read_into(&mut self.ram_enabled)?;
+        r.read_into(&mut self.mirroring)?;
+        r.read_into(&mut self.irq_enabled)?;
+        r.read_into(&mut self.counter_enabled)?;
+        r.read_into(&mut self.counter)?;
+        r.read_into(&mut self.irq_pending)?;
+        r.read_into(&mut self.audio)?;
+        Ok(())
+    }
+}
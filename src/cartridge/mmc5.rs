@@ -0,0 +1,419 @@
+use super::{bank_offset, banked, banked_mut, Header, Mapper, Mirroring};
+use crate::apu::expansion::{ExpansionAudio, Mmc5Audio};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// CPU cycles without a PPU read after which the MMC5 considers rendering
+/// stopped. Hardware uses 3, but the PPU here skips the garbage fetches of
+/// dots 257-320, so the gap between scanline fetches needs covering.
+const IDLE_CYCLES: u8 = 32;
+
+/// Mapper 5: Nintendo MMC5 (ExROM). Four PRG banking modes over ROM and up
+/// to 64KB of RAM, four CHR banking modes with separate sprite and
+/// background sets for 8x16 sprites, per-quadrant nametable mapping with
+/// ExRAM and fill mode, the scanline IRQ, the multiplier and the MMC5 sound
+/// channels.
+///
+/// Extended attribute mode (ExRAM mode 1) and the vertical split are not
+/// emulated. Nametable quadrants mapped to console VRAM follow the closest
+/// standard mirroring.
+pub(super) struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    exram: [u8; 0x400],
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attr: u8,
+    prg_ram_bank: usize,
+    /// $5114-$5117; bit 7 selects ROM.
+    prg_banks: [u8; 4],
+    /// $5120-$5127, used for sprites.
+    chr_a: [usize; 8],
+    /// $5128-$512B, used for the background with 8x16 sprites.
+    chr_b: [usize; 4],
+    chr_upper: usize,
+    last_chr_b: bool,
+    sprite_16: bool,
+    multiplicand: u8,
+    multiplier: u8,
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+    last_nametable_addr: Option<u16>,
+    nametable_matches: u8,
+    /// Nametable and attribute fetches since the scanline started; after the
+    /// 64th, pattern fetches are for sprites until the next one.
+    nametable_fetches: u8,
+    idle_cycles: u8,
+    audio: Mmc5Audio,
+}
+
+impl Mmc5 {
+    pub(super) fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        // iNES 1.0 headers cannot describe the RAM, so assume the largest board.
+        let prg_ram_size = if header.nes2 {
+            header.prg_ram_size.max(0x2000)
+        } else {
+            0x10000
+        };
+        Mmc5 {
+            prg_rom,
+            prg_ram: vec![0; prg_ram_size],
+            chr: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            exram: [0; 0x400],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attr: 0,
+            prg_ram_bank: 0,
+            prg_banks: [0, 0, 0, 0xFF],
+            chr_a: [0; 8],
+            chr_b: [0; 4],
+            chr_upper: 0,
+            last_chr_b: false,
+            sprite_16: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            last_nametable_addr: None,
+            nametable_matches: 0,
+            nametable_fetches: 0,
+            idle_cycles: 0,
+            audio: Mmc5Audio::new(),
+        }
+    }
+
+    /// Resolves a $6000-$FFFF address to (ROM?, 8KB bank).
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        if addr < 0x8000 {
+            return (false, self.prg_ram_bank);
+        }
+        let slot = (addr as usize - 0x8000) >> 13;
+        let (reg, size) = match (self.prg_mode, slot) {
+            (0, _) => (3, 4),
+            (1, 0 | 1) => (1, 2),
+            (1, _) => (3, 2),
+            (2, 0 | 1) => (1, 2),
+            _ => (slot, 1),
+        };
+        let value = self.prg_banks[reg];
+        let rom = reg == 3 || value & 0x80 != 0;
+        let bank = (value as usize & 0x7F & !(size - 1)) + (slot & (size - 1));
+        (rom, bank)
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    /// Resolves a pattern table address to a 1KB CHR bank.
+    fn chr_bank(&self, addr: u16, sprite: bool) -> usize {
+        let slot = (addr as usize >> 10) & 0x07;
+        let use_b = if self.sprite_16 {
+            !sprite
+        } else {
+            self.last_chr_b
+        };
+        let (value, size) = match (self.chr_mode, use_b) {
+            (0, false) => (self.chr_a[7], 8),
+            (1, false) => (self.chr_a[slot | 3], 4),
+            (2, false) => (self.chr_a[slot | 1], 2),
+            (_, false) => (self.chr_a[slot], 1),
+            (0, true) => (self.chr_b[3], 8),
+            // Set B only covers 4KB, repeated in both pattern tables.
+            (1, true) => (self.chr_b[3], 4),
+            (2, true) => (self.chr_b[(slot & 0x03) | 1], 2),
+            (_, true) => (self.chr_b[slot & 0x03], 1),
+        };
+        value * size + (slot & (size - 1))
+    }
+
+    fn detect_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_target && self.irq_target != 0 {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+        }
+        self.nametable_fetches = 0;
+    }
+
+    /// What a nametable quadrant is mapped to: 0/1 console VRAM pages,
+    /// 2 ExRAM, 3 fill mode.
+    fn quadrant(&self, addr: u16) -> u8 {
+        let quadrant = (addr >> 10) & 0x03;
+        (self.nametable_mapping >> (quadrant * 2)) & 0x03
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        let data = match addr {
+            0x5010 | 0x5015 => return self.audio.read(addr),
+            _ => self.cpu_peek(addr),
+        };
+        match (addr, data) {
+            (0x5204, _) => self.irq_pending = false,
+            (0x6000..=0xFFFF, Some(data)) => self.audio.snoop_read(addr, data),
+            _ => {}
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5010 | 0x5015 => self.audio.peek(addr),
+            0x5204 => Some((self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6),
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => Some(self.exram[addr as usize - 0x5C00]),
+            0x6000..=0xFFFF => {
+                let data = match self.prg_bank(addr) {
+                    (true, _) => self.prg_rom[self.prg_rom_offset(addr)?],
+                    (false, bank) => banked(&self.prg_ram, bank, 0x2000, addr),
+                };
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match self.prg_bank(addr) {
+            (true, bank) if addr >= 0x6000 => {
+                Some(bank_offset(self.prg_rom.len(), bank, 0x2000, addr))
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match (addr, self.prg_bank(addr)) {
+            (0x6000..=0xFFFF, (true, _)) => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.prg_rom[offset])
+            }
+            (0x6000..=0xFFFF, (false, bank)) => {
+                Some(banked_mut(&mut self.prg_ram, bank, 0x2000, addr))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5015 => self.audio.write(addr, data),
+            0x5100 => self.prg_mode = data & 0x03,
+            0x5101 => self.chr_mode = data & 0x03,
+            0x5102 => self.prg_ram_protect[0] = data & 0x03,
+            0x5103 => self.prg_ram_protect[1] = data & 0x03,
+            0x5104 => self.exram_mode = data & 0x03,
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attr = data & 0x03,
+            0x5113 => self.prg_ram_bank = data as usize & 0x07,
+            0x5114..=0x5117 => self.prg_banks[addr as usize - 0x5114] = data,
+            0x5120..=0x5127 => {
+                self.chr_a[addr as usize - 0x5120] = data as usize | self.chr_upper << 8;
+                self.last_chr_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_b[addr as usize - 0x5128] = data as usize | self.chr_upper << 8;
+                self.last_chr_b = true;
+            }
+            0x5130 => self.chr_upper = data as usize & 0x03,
+            0x5203 => self.irq_target = data,
+            0x5204 => self.irq_enabled = data & 0x80 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5C00..=0x5FFF if self.exram_mode != 3 => self.exram[addr as usize - 0x5C00] = data,
+            0x6000..=0xFFFF if self.prg_ram_writable() => {
+                if let (false, bank) = self.prg_bank(addr) {
+                    *banked_mut(&mut self.prg_ram, bank, 0x2000, addr) = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.idle_cycles = 0;
+        let sprite = self.nametable_fetches == 64;
+        banked(&self.chr, self.chr_bank(addr, sprite), 0x400, addr)
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        let sprite = self.nametable_fetches == 64;
+        let bank = self.chr_bank(addr, sprite);
+        (!self.chr_ram).then(|| bank_offset(self.chr.len(), bank, 0x400, addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_ram {
+            let bank = self.chr_bank(addr, false);
+            *banked_mut(&mut self.chr, bank, 0x400, addr) = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match [0, 1, 2, 3].map(|q| (self.nametable_mapping >> (q * 2)) & 0x01) {
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            [a, b, _, _] if a != b => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    /// Besides supplying ExRAM and fill nametables, this is how the MMC5
+    /// finds scanlines: the PPU reads the same nametable byte three times
+    /// in a row only at the end of each rendered line.
+    fn nametable_read(&mut self, addr: u16) -> Option<u8> {
+        self.idle_cycles = 0;
+        if self.last_nametable_addr == Some(addr) {
+            self.nametable_matches += 1;
+            if self.nametable_matches == 2 {
+                self.detect_scanline();
+            }
+        } else {
+            self.nametable_matches = 0;
+        }
+        self.last_nametable_addr = Some(addr);
+        self.nametable_fetches = self.nametable_fetches.saturating_add(1);
+
+        match self.quadrant(addr) {
+            2 if self.exram_mode <= 1 => Some(self.exram[addr as usize & 0x3FF]),
+            2 => Some(0),
+            3 if addr & 0x3FF >= 0x3C0 => Some(self.fill_attr * 0x55),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8) -> bool {
+        match self.quadrant(addr) {
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[addr as usize & 0x3FF] = data;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn snoop_write(&mut self, addr: u16, data: u8) {
+        match addr & 0x2007 {
+            0x2000 => self.sprite_16 = data & 0x20 != 0,
+            0x2001 if data & 0x18 == 0 => self.in_frame = false,
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.idle_cycles < IDLE_CYCLES {
+            self.idle_cycles += 1;
+            if self.idle_cycles == IDLE_CYCLES {
+                self.in_frame = false;
+                self.last_nametable_addr = None;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_pending && self.irq_enabled) || self.audio.irq()
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.prg_ram);
+        if self.chr_ram {
+            w.write(&self.chr);
+        }
+        w.write(&self.exram);
+        w.write(&self.prg_mode);
+        w.write(&self.chr_mode);
+        w.write(&self.prg_ram_protect);
+        w.write(&self.exram_mode);
+        w.write(&self.nametable_mapping);
+        w.write(&self.fill_tile);
+        w.write(&self.fill_attr);
+        w.write(&self.prg_ram_bank);
+        w.write(&self.prg_banks);
+        w.write(&self.chr_a);
+        w.write(&self.chr_b);
+        w.write(&self.chr_upper);
+        w.write(&self.last_chr_b);
+        w.write(&self.sprite_16);
+        w.write(&self.multiplicand);
+        w.write(&self.multiplier);
+        w.write(&self.irq_target);
+        w.write(&self.irq_enabled);
+        w.write(&self.irq_pending);
+        w.write(&self.in_frame);
+        w.write(&self.scanline);
+        w.write(&self.last_nametable_addr);
+        w.write(&self.nametable_matches);
+        w.write(&self.nametable_fetches);
+        w.write(&self.idle_cycles);
+        w.write(&self.audio);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.prg_ram)?;
+        if self.chr_ram {
+            r.read_into(&mut self.chr)?;
+        }
+        r.read_into(&mut self.exram)?;
+        r.read_into(&mut self.prg_mode)?;
+        r.read_into(&mut self.chr_mode)?;
+        r.read_into(&mut self.prg_ram_protect)?;
+        r.read_into(&mut self.exram_mode)?;
+        r.read_into(&mut self.nametable_mapping)?;
+        r.read_into(&mut self.fill_tile)?;
+        r.read_into(&mut self.fill_attr)?;
+        r.read_into(&mut self.prg_ram_bank)?;
+        r.read_into(&mut self.prg_banks)?;
+        r.read_into(&mut self.chr_a)?;
+        r.read_into(&mut self.chr_b)?;
+        r.read_into(&mut self.chr_upper)?;
+        r.read_into(&mut self.last_chr_b)?;
+        r.read_into(&mut self.sprite_16)?;
+        r.read_into(&mut self.multiplicand)?;
+        r.read_into(&mut self.multiplier)?;
+        r.read_into(&mut self.irq_target)?;
+        r.read_into(&mut self.irq_enabled)?;
+        r.read_into(&mut self.irq_pending)?;
+        r.read_into(&mut self.in_frame)?;
+        r.read_into(&mut self.scanline)?;
+        r.read_into(&mut self.last_nametable_addr)?;
+        r.read_into(&mut self.nametable_matches)?;
+        r.read_into(&mut self.nametable_fetches)?;
+        r.read_into(&mut self.idle_cycles)?;
+        r.read_into(&mut self.audio)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,739 @@
+mod fds;
+mod fme7;
+mod mmc5;
+mod namco163;
+mod nrom;
+mod vrc6;
+mod vrc7;
+mod vrc_irq;
+
+use crate::region::Region;
+use crate::state::{self, StateError, StateReader, StateWriter, Stateful};
+use fds::Fds;
+use fme7::Fme7;
+use mmc5::Mmc5;
+use namco163::Namco163;
+use nrom::Nrom;
+use vrc6::Vrc6;
+use vrc7::Vrc7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+
+impl Mirroring {
+    /// Maps a PPU nametable address ($2000-$2FFF) to an offset into console VRAM.
+    /// Four-screen boards supply the extra 2KB, so VRAM is 4KB in that case.
+    pub fn nametable_offset(&self, addr: u16) -> usize {
+        let addr = (addr & 0x0FFF) as usize;
+        let table = addr >> 10;
+        let offset = addr & 0x03FF;
+        let page = match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 1,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        page << 10 | offset
+    }
+}
+
+impl Stateful for Mirroring {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&(*self as u8));
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        *self = match r.read::<u8>()? {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::SingleScreenLower,
+            3 => Mirroring::SingleScreenUpper,
+            4 => Mirroring::FourScreen,
+            _ => return Err(StateError::Invalid("mirroring")),
+        };
+        Ok(())
+    }
+}
+
+/// Cartridge-side logic: PRG banking on the CPU bus and CHR banking on the PPU bus.
+pub trait Mapper {
+    /// Reads from $4020-$FFFF. `None` leaves the CPU data bus floating (open bus).
+    /// Defaults to [`Mapper::cpu_peek`], for boards whose reads have no side
+    /// effects.
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        self.cpu_peek(addr)
+    }
+    /// What a read of `addr` would return, without acknowledging IRQs or
+    /// advancing ports; used by debuggers.
+    fn cpu_peek(&self, addr: u16) -> Option<u8>;
+    /// Where in PRG ROM a read of `addr` currently comes from, or `None`
+    /// for RAM, registers and open bus. Lets debuggers tell banks apart.
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize>;
+    /// The PRG ROM or RAM byte a read of `addr` currently comes from, so
+    /// debuggers can patch it without the side effects of a write. `None`
+    /// for registers, open bus and disabled RAM.
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8>;
+    fn cpu_write(&mut self, addr: u16, data: u8);
+    /// Reads from the pattern tables at $0000-$1FFF.
+    fn ppu_read(&mut self, addr: u16) -> u8;
+    /// Where in CHR ROM a read of pattern table `addr` currently comes
+    /// from, or `None` with CHR RAM.
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize>;
+    fn ppu_write(&mut self, addr: u16, data: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    /// Reads from the nametables at $2000-$2FFF. `None` uses console VRAM as
+    /// arranged by [`Mapper::mirroring`]; boards that supply their own
+    /// nametables, or watch the PPU's fetches, override this.
+    fn nametable_read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Writes to the nametables. Returns false to fall through to console VRAM.
+    fn nametable_write(&mut self, _addr: u16, _data: u8) -> bool {
+        false
+    }
+
+    /// Observes CPU writes to console registers below $4020, which the
+    /// cartridge sees on the bus without decoding them.
+    fn snoop_write(&mut self, _addr: u16, _data: u8) {}
+
+    /// Tells the board which console timing it runs under, for sound
+    /// hardware clocked from the CPU.
+    fn set_region(&mut self, _region: Region) {}
+
+    /// Advances on-board timers and sound hardware by one CPU cycle.
+    fn clock(&mut self) {}
+
+    /// State of the cartridge's /IRQ output, true when asserted.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Current expansion audio level, on the same scale as the APU's mix.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// Writes registers, RAM and any CHR-RAM for a save state. ROM is not
+    /// saved.
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError>;
+}
+
+/// Reads `addr` within a `bank_size` window mapped to `bank` of `mem`;
+/// out of range banks wrap around.
+fn banked(mem: &[u8], bank: usize, bank_size: usize, addr: u16) -> u8 {
+    mem[bank_offset(mem.len(), bank, bank_size, addr)]
+}
+
+fn banked_mut(mem: &mut [u8], bank: usize, bank_size: usize, addr: u16) -> &mut u8 {
+    &mut mem[bank_offset(mem.len(), bank, bank_size, addr)]
+}
+
+/// The offset [`banked`] reads from in memory `len` bytes long.
+fn bank_offset(len: usize, bank: usize, bank_size: usize, addr: u16) -> usize {
+    (bank * bank_size + addr as usize % bank_size) % len
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+    pub region: Region,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    BadMagic,
+    Truncated { expected: usize, found: usize },
+    UnsupportedMapper(u16),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::BadMagic => write!(f, "missing iNES signature"),
+            LoadError::Truncated { expected, found } => {
+                write!(
+                    f,
+                    "image truncated: expected {expected} bytes, found {found}"
+                )
+            }
+            LoadError::UnsupportedMapper(mapper) => write!(f, "unsupported mapper {mapper}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Header, LoadError> {
+        if data.len() < HEADER_SIZE || &data[0..4] != b"NES\x1A" {
+            return Err(LoadError::BadMagic);
+        }
+
+        let nes2 = data[7] & 0x0C == 0x08;
+        let mirroring = if data[6] & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if data[6] & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let mut mapper = (data[6] >> 4) as u16 | (data[7] & 0xF0) as u16;
+        let mut submapper = 0;
+        let mut prg_rom_size = data[4] as usize * 0x4000;
+        let mut chr_rom_size = data[5] as usize * 0x2000;
+        let prg_ram_size;
+        let chr_ram_size;
+
+        if nes2 {
+            mapper |= ((data[8] & 0x0F) as u16) << 8;
+            submapper = data[8] >> 4;
+            prg_rom_size = nes2_rom_size(data[4], data[9] & 0x0F, 0x4000);
+            chr_rom_size = nes2_rom_size(data[5], data[9] >> 4, 0x2000);
+            prg_ram_size = nes2_ram_size(data[10] & 0x0F) + nes2_ram_size(data[10] >> 4);
+            chr_ram_size = nes2_ram_size(data[11] & 0x0F) + nes2_ram_size(data[11] >> 4);
+        } else {
+            // Archaic headers often carry garbage ("DiskDude!") in bytes 7-15.
+            if data[12..16].iter().any(|&b| b != 0) {
+                mapper &= 0x0F;
+            }
+            prg_ram_size = 0x2000;
+            chr_ram_size = if chr_rom_size == 0 { 0x2000 } else { 0 };
+        }
+
+        Ok(Header {
+            mapper,
+            submapper,
+            prg_rom_size,
+            chr_rom_size,
+            prg_ram_size,
+            chr_ram_size,
+            mirroring,
+            battery: data[6] & 0x02 != 0,
+            trainer: data[6] & 0x04 != 0,
+            nes2,
+            region: Region::from_header_bytes(data[0..HEADER_SIZE].try_into().unwrap()),
+        })
+    }
+}
+
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0F {
+        // Exponent-multiplier notation: 2^E * (MM*2+1)
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        (1usize << exponent) * multiplier
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * unit
+    }
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+/// The CRC-32 used by zip and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            crc >> 1 ^ 0xEDB8_8320 & (crc & 1).wrapping_neg()
+        })
+    })
+}
+
+/// The MD5 of RFC 1321.
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let sines: [u32; 64] =
+        std::array::from_fn(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32);
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+    for block in message.chunks(64) {
+        let words: [u32; 16] = std::array::from_fn(|i| {
+            u32::from_le_bytes(block[i * 4..i * 4 + 4].try_into().unwrap())
+        });
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => (b & c | !b & d, i),
+                1 => (d & b | !d & c, (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), 7 * i % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(sines[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+pub struct Cartridge {
+    header: Header,
+    mapper: Box<dyn Mapper>,
+    crc32: u32,
+    md5: [u8; 16],
+    /// The board as built from the image, restored by
+    /// [`Cartridge::power_on`].
+    power_on_state: Vec<u8>,
+}
+
+impl Cartridge {
+    /// Loads an iNES or NES 2.0 image.
+    pub fn from_ines(data: &[u8]) -> Result<Cartridge, LoadError> {
+        let header = Header::parse(data)?;
+
+        let prg_start = HEADER_SIZE + if header.trainer { TRAINER_SIZE } else { 0 };
+        let chr_start = prg_start + header.prg_rom_size;
+        let end = chr_start + header.chr_rom_size;
+        if data.len() < end {
+            return Err(LoadError::Truncated {
+                expected: end,
+                found: data.len(),
+            });
+        }
+
+        let crc32 = crc32(&data[prg_start..end]);
+        let md5 = md5(&data[prg_start..end]);
+        let prg_rom = data[prg_start..chr_start].to_vec();
+        let chr_rom = data[chr_start..end].to_vec();
+
+        let mapper: Box<dyn Mapper> = match header.mapper {
+            0 => Box::new(Nrom::new(&header, prg_rom, chr_rom)),
+            5 => Box::new(Mmc5::new(&header, prg_rom, chr_rom)),
+            19 => Box::new(Namco163::new(&header, prg_rom, chr_rom)),
+            20 => Box::new(Fds::new(&header, prg_rom)),
+            24 => Box::new(Vrc6::new(&header, prg_rom, chr_rom, false)),
+            26 => Box::new(Vrc6::new(&header, prg_rom, chr_rom, true)),
+            69 => Box::new(Fme7::new(&header, prg_rom, chr_rom)),
+            85 => Box::new(Vrc7::new(&header, prg_rom, chr_rom)),
+            mapper => return Err(LoadError::UnsupportedMapper(mapper)),
+        };
+
+        let mut w = StateWriter::new();
+        w.chunk(*b"CART", 1, |w| mapper.save_state(w));
+        Ok(Cartridge {
+            header,
+            mapper,
+            crc32,
+            md5,
+            power_on_state: w.finish(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// CRC-32 of the PRG and CHR ROM, as ROM databases list it.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// MD5 of the PRG and CHR ROM, which FCEUX movies record.
+    pub fn md5(&self) -> [u8; 16] {
+        self.md5
+    }
+
+    pub fn mapper(&self) -> &dyn Mapper {
+        self.mapper.as_ref()
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.mapper.as_mut()
+    }
+
+    /// Puts the board back as it was loaded: bank registers, IRQ counters
+    /// and audio reset, PRG and CHR RAM cleared.
+    pub fn power_on(&mut self) {
+        let chunks = state::chunks(&self.power_on_state).expect("power-on state is well formed");
+        let mut r = chunks[0].reader(1).expect("power-on state is version 1");
+        self.mapper
+            .load_state(&mut r)
+            .expect("the mapper loads its own state");
+    }
+
+    /// Saves the board state, tagged with the mapper number, PRG-ROM size
+    /// and ROM CRC so it is only loaded into the same game.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.header.mapper);
+        w.write(&(self.header.prg_rom_size as u32));
+        w.write(&self.crc32);
+        self.mapper.save_state(w);
+    }
+
+    /// Loads a `CART` chunk of the given version. Version 1 chunks have no
+    /// CRC to check.
+    pub fn load_state(&mut self, version: u16, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        let mapper: u16 = r.read()?;
+        let prg_rom_size: u32 = r.read()?;
+        let crc32 = if version >= 2 { r.read()? } else { self.crc32 };
+        if mapper != self.header.mapper
+            || prg_rom_size as usize != self.header.prg_rom_size
+            || crc32 != self.crc32
+        {
+            return Err(StateError::WrongCartridge);
+        }
+        self.mapper.load_state(r)
+    }
+}
+
+/// An NROM image of `prg`, 16KB or 32KB, and `chr`, 8KB. Public for the
+/// benchmarks, not part of the API.
+#[doc(hidden)]
+pub fn nrom_image(prg: &[u8], chr: &[u8]) -> Vec<u8> {
+    let mut image = b"NES\x1A".to_vec();
+    image.extend([(prg.len() / 0x4000) as u8, (chr.len() / 0x2000) as u8]);
+    image.resize(HEADER_SIZE, 0);
+    image.extend_from_slice(prg);
+    image.extend_from_slice(chr);
+    image
+}
+
+/// A 16KB NROM image with `program` at $8000, NOPs after it and blank CHR
+/// ROM. The reset and IRQ vectors both point at the program.
+#[cfg(test)]
+pub(crate) fn program_image(program: &[u8]) -> Vec<u8> {
+    let mut prg = vec![0xEA; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3FFC..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+    nrom_image(&prg, &[0; 0x2000])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A board for `mapper` whose 8KB PRG banks and 1KB CHR banks are
+    /// filled with their bank numbers.
+    fn cartridge(mapper: u16, prg_banks: usize, chr_banks: usize) -> Cartridge {
+        let mut image = b"NES\x1A".to_vec();
+        image.extend([(prg_banks / 2) as u8, (chr_banks / 8) as u8]);
+        image.extend([(mapper as u8) << 4, mapper as u8 & 0xF0]);
+        image.resize(16, 0);
+        for bank in 0..prg_banks {
+            image.extend([bank as u8; 0x2000]);
+        }
+        for bank in 0..chr_banks {
+            image.extend([bank as u8; 0x400]);
+        }
+        Cartridge::from_ines(&image).unwrap()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn md5_check_values() {
+        let hex = |digest: [u8; 16]| digest.map(|b| format!("{b:02x}")).concat();
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex(md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        assert_eq!(hex(md5(&[b'a'; 100])), "36a92cc94a9e0fa21f625f8bfb007adf");
+    }
+
+    #[test]
+    fn mmc5_registers() {
+        let mut cartridge = cartridge(5, 16, 64);
+        let mapper = cartridge.mapper_mut();
+        assert_eq!(mapper.cpu_peek(0xE000), Some(15));
+
+        // 8KB banks; bit 7 picks ROM, and $5117 is always ROM.
+        mapper.cpu_write(0x5114, 0x82);
+        mapper.cpu_write(0x5115, 0x83);
+        mapper.cpu_write(0x5116, 0x01);
+        mapper.cpu_write(0x5117, 0x07);
+        assert_eq!(mapper.cpu_peek(0x8000), Some(2));
+        assert_eq!(mapper.cpu_peek(0xA000), Some(3));
+        assert_eq!(mapper.prg_rom_offset(0xC000), None);
+        assert_eq!(mapper.cpu_peek(0xE000), Some(7));
+
+        // PRG RAM only takes writes once both protect registers are set.
+        mapper.cpu_write(0x5113, 0x01);
+        mapper.cpu_write(0x6000, 0x42);
+        assert_eq!(mapper.cpu_peek(0x6000), Some(0));
+        mapper.cpu_write(0x5102, 0x02);
+        mapper.cpu_write(0x5103, 0x01);
+        mapper.cpu_write(0x6000, 0x42);
+        mapper.cpu_write(0xC001, 0x43);
+        assert_eq!(mapper.cpu_peek(0x6000), Some(0x42));
+        assert_eq!(mapper.cpu_peek(0xC001), Some(0x43));
+
+        mapper.cpu_write(0x5101, 0x03);
+        mapper.cpu_write(0x5127, 0x21);
+        assert_eq!(mapper.ppu_read(0x1C00), 0x21);
+        // In 8KB mode $5127 picks 8KB bank $21, which wraps to 1KB bank 8.
+        mapper.cpu_write(0x5101, 0x00);
+        assert_eq!(mapper.ppu_read(0x0000), 8);
+
+        mapper.cpu_write(0x5205, 12);
+        mapper.cpu_write(0x5206, 34);
+        assert_eq!(mapper.cpu_peek(0x5205), Some(0x98));
+        assert_eq!(mapper.cpu_peek(0x5206), Some(1));
+
+        // Quadrants: VRAM page 0, page 1, ExRAM, fill.
+        mapper.cpu_write(0x5105, 0b11_10_01_00);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        mapper.cpu_write(0x5106, 0x55);
+        mapper.cpu_write(0x5107, 0x02);
+        assert_eq!(mapper.nametable_read(0x2C00), Some(0x55));
+        assert_eq!(mapper.nametable_read(0x2FC0), Some(0xAA));
+        assert!(mapper.nametable_write(0x2800, 0x66));
+        assert_eq!(mapper.nametable_read(0x2800), Some(0x66));
+        assert_eq!(mapper.nametable_read(0x2000), None);
+    }
+
+    #[test]
+    fn namco163_registers() {
+        let mut cartridge = cartridge(19, 16, 64);
+        let mapper = cartridge.mapper_mut();
+        mapper.cpu_write(0xE000, 2);
+        mapper.cpu_write(0xE800, 3);
+        mapper.cpu_write(0xF000, 4);
+        let banks = [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.cpu_peek(addr));
+        assert_eq!(banks, [Some(2), Some(3), Some(4), Some(15)]);
+
+        mapper.cpu_write(0x8000, 5);
+        mapper.cpu_write(0xB800, 6);
+        assert_eq!(mapper.ppu_read(0x0000), 5);
+        assert_eq!(mapper.ppu_read(0x1C00), 6);
+
+        // Values from $E0 pick console VRAM pages, lower ones CHR-ROM.
+        for (addr, data) in [(0xC000, 0xE0), (0xC800, 0xE0), (0xD000, 0xE1), (0xD800, 7)] {
+            mapper.cpu_write(addr, data);
+        }
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        assert_eq!(mapper.nametable_read(0x2000), None);
+        assert_eq!(mapper.nametable_read(0x2C00), Some(7));
+        assert!(mapper.nametable_write(0x2C00, 0));
+
+        // The IRQ fires as the counter reaches $7FFF.
+        mapper.cpu_write(0x5000, 0xFD);
+        mapper.cpu_write(0x5800, 0xFF);
+        assert_eq!(mapper.cpu_peek(0x5800), Some(0xFF));
+        mapper.clock();
+        assert!(!mapper.irq());
+        mapper.clock();
+        assert!(mapper.irq());
+        mapper.cpu_write(0x5800, 0x00);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn fds_registers() {
+        let mut cartridge = cartridge(20, 2, 0);
+        let mapper = cartridge.mapper_mut();
+        // The BIOS is the last 8KB; RAM fills $6000-$DFFF.
+        assert_eq!(mapper.cpu_peek(0xE000), Some(1));
+        mapper.cpu_write(0x6000, 0x12);
+        mapper.cpu_write(0xDFFF, 0x34);
+        assert_eq!(mapper.cpu_peek(0x6000), Some(0x12));
+        assert_eq!(mapper.cpu_peek(0xDFFF), Some(0x34));
+        mapper.ppu_write(0x1FFF, 0x56);
+        assert_eq!(mapper.ppu_read(0x1FFF), 0x56);
+
+        // Disk and sound registers answer only once $4023 enables them.
+        assert_eq!(mapper.cpu_peek(0x4032), None);
+        mapper.cpu_write(0x4089, 0x80);
+        mapper.cpu_write(0x4040, 0x3F);
+        assert_eq!(mapper.cpu_peek(0x4040), None);
+        mapper.cpu_write(0x4023, 0x03);
+        assert_eq!(mapper.cpu_peek(0x4032), Some(0x07));
+        mapper.cpu_write(0x4089, 0x80);
+        mapper.cpu_write(0x4040, 0x3F);
+        assert_eq!(mapper.cpu_peek(0x4040), Some(0x3F));
+
+        mapper.cpu_write(0x4025, 0x08);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.cpu_write(0x4025, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        // The timer fires one cycle after counting down from its reload
+        // value, then reloads when set to repeat.
+        mapper.cpu_write(0x4020, 2);
+        mapper.cpu_write(0x4021, 0);
+        mapper.cpu_write(0x4022, 0x03);
+        for _ in 0..2 {
+            for _ in 0..2 {
+                mapper.clock();
+                assert!(!mapper.irq());
+            }
+            mapper.clock();
+            assert!(mapper.irq());
+            assert_eq!(mapper.cpu_peek(0x4030), Some(0x01));
+            assert_eq!(mapper.cpu_read(0x4030), Some(0x01));
+            assert!(!mapper.irq());
+        }
+        mapper.cpu_write(0x4022, 0x02);
+        for _ in 0..3 {
+            mapper.clock();
+        }
+        assert!(mapper.irq());
+        mapper.cpu_read(0x4030);
+        for _ in 0..10 {
+            mapper.clock();
+        }
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn vrc6_registers() {
+        for (mapper, swap_lines) in [(24, false), (26, true)] {
+            let mut cartridge = cartridge(mapper, 16, 64);
+            let mapper = cartridge.mapper_mut();
+            // Board-side register numbers: mapper 26 swaps A0 and A1.
+            let reg = |addr: u16| match (swap_lines, addr & 0x03) {
+                (true, 1) => addr + 1,
+                (true, 2) => addr - 1,
+                _ => addr,
+            };
+            mapper.cpu_write(0x8000, 2);
+            mapper.cpu_write(0xC000, 7);
+            let banks = [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.cpu_peek(addr));
+            assert_eq!(banks, [Some(4), Some(5), Some(7), Some(15)]);
+
+            mapper.cpu_write(reg(0xD001), 9);
+            mapper.cpu_write(reg(0xE002), 33);
+            assert_eq!(mapper.ppu_read(0x0400), 9);
+            assert_eq!(mapper.ppu_read(0x1800), 33);
+
+            assert_eq!(mapper.cpu_peek(0x6000), None);
+            mapper.cpu_write(reg(0xB003), 0x84);
+            assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+            mapper.cpu_write(0x6000, 0x42);
+            assert_eq!(mapper.cpu_peek(0x6000), Some(0x42));
+
+            // Cycle mode IRQ: the counter reloads from the latch at $FF.
+            mapper.cpu_write(0xF000, 0xFE);
+            mapper.cpu_write(reg(0xF001), 0x06);
+            mapper.clock();
+            assert!(!mapper.irq());
+            mapper.clock();
+            assert!(mapper.irq());
+            mapper.cpu_write(reg(0xF002), 0);
+            assert!(!mapper.irq());
+        }
+    }
+
+    #[test]
+    fn fme7_registers() {
+        let mut cartridge = cartridge(69, 16, 64);
+        let mapper = cartridge.mapper_mut();
+        let mut command = |command: u8, data: u8| {
+            mapper.cpu_write(0x8000, command);
+            mapper.cpu_write(0xA000, data);
+        };
+        command(0x9, 2);
+        command(0xA, 3);
+        command(0xB, 4);
+        command(0x8, 5);
+        command(0x3, 11);
+        command(0xC, 1);
+        let banks = [0x6000, 0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.cpu_peek(addr));
+        assert_eq!(banks, [Some(5), Some(2), Some(3), Some(4), Some(15)]);
+        assert_eq!(mapper.ppu_read(0x0C00), 11);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        // RAM at $6000: selected but disabled is open bus.
+        mapper.cpu_write(0x8000, 0x8);
+        mapper.cpu_write(0xA000, 0x40);
+        assert_eq!(mapper.cpu_peek(0x6000), None);
+        mapper.cpu_write(0xA000, 0xC0);
+        mapper.cpu_write(0x6000, 0x42);
+        assert_eq!(mapper.cpu_peek(0x6000), Some(0x42));
+
+        // The IRQ fires when the counter wraps below zero.
+        mapper.cpu_write(0x8000, 0xE);
+        mapper.cpu_write(0xA000, 1);
+        mapper.cpu_write(0x8000, 0xF);
+        mapper.cpu_write(0xA000, 0);
+        mapper.cpu_write(0x8000, 0xD);
+        mapper.cpu_write(0xA000, 0x81);
+        mapper.clock();
+        assert!(!mapper.irq());
+        mapper.clock();
+        assert!(mapper.irq());
+        mapper.cpu_write(0xA000, 0x81);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn vrc7_registers() {
+        // VRC7a decodes the second register of each pair on A4, VRC7b on A3.
+        for high in [0x10, 0x08] {
+            let mut cartridge = cartridge(85, 16, 64);
+            let mapper = cartridge.mapper_mut();
+            mapper.cpu_write(0x8000, 2);
+            mapper.cpu_write(0x8000 | high, 3);
+            mapper.cpu_write(0x9000, 4);
+            let banks = [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.cpu_peek(addr));
+            assert_eq!(banks, [Some(2), Some(3), Some(4), Some(15)]);
+
+            mapper.cpu_write(0xA000, 9);
+            mapper.cpu_write(0xD000 | high, 33);
+            assert_eq!(mapper.ppu_read(0x0000), 9);
+            assert_eq!(mapper.ppu_read(0x1C00), 33);
+
+            mapper.cpu_write(0xE000, 0x81);
+            assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+            mapper.cpu_write(0x6000, 0x42);
+            assert_eq!(mapper.cpu_peek(0x6000), Some(0x42));
+
+            mapper.cpu_write(0xE000 | high, 0xFE);
+            mapper.cpu_write(0xF000, 0x06);
+            mapper.clock();
+            assert!(!mapper.irq());
+            mapper.clock();
+            assert!(mapper.irq());
+            mapper.cpu_write(0xF000 | high, 0);
+            assert!(!mapper.irq());
+        }
+    }
+}
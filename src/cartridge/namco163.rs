@@ -0,0 +1,216 @@
+use super::{bank_offset, banked, banked_mut, Header, Mapper, Mirroring};
+use crate::apu::expansion::{ExpansionAudio, N163Audio};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Mapper 19: Namco 163. Three switchable 8KB PRG banks and the fixed last
+/// 8KB, eight 1KB CHR banks, nametables selectable between console VRAM and
+/// CHR-ROM, a 15-bit cycle IRQ counter and the wavetable sound chip.
+///
+/// Mapping console VRAM into the pattern tables (CHR registers $E0 and up
+/// with $E800 bits 6/7 clear) is not emulated; those values select CHR-ROM.
+pub(super) struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    nametables: [u8; 4],
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+    audio: N163Audio,
+}
+
+impl Namco163 {
+    pub(super) fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        let vertical = header.mirroring == Mirroring::Vertical;
+        Namco163 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size.max(0x2000)],
+            chr: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametables: if vertical {
+                [0xE0, 0xE1, 0xE0, 0xE1]
+            } else {
+                [0xE0, 0xE0, 0xE1, 0xE1]
+            },
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            audio: N163Audio::new(),
+        }
+    }
+
+    /// CHR-ROM bank backing a nametable quadrant, or None for console VRAM.
+    fn nametable_bank(&self, addr: u16) -> Option<usize> {
+        let bank = self.nametables[(addr as usize >> 10) & 0x03];
+        (bank < 0xE0 && !self.chr_ram).then_some(bank as usize)
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.audio.read_data()),
+            _ => self.cpu_peek(addr),
+        }
+    }
+
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.audio.peek_data()),
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => Some((self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+            0x6000..=0x7FFF => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xFFFF => Some(self.prg_rom[self.prg_rom_offset(addr)?]),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let len = self.prg_rom.len();
+        match addr {
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[(addr as usize - 0x8000) / 0x2000];
+                Some(bank_offset(len, bank, 0x2000, addr))
+            }
+            0xE000..=0xFFFF => Some(bank_offset(len, len / 0x2000 - 1, 0x2000, addr)),
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x6000..=0x7FFF => {
+                let len = self.prg_ram.len();
+                Some(&mut self.prg_ram[(addr as usize - 0x6000) % len])
+            }
+            _ => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.prg_rom[offset])
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.audio.write_data(data),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16 & 0x7F) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = data;
+            }
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) >> 11] = data as usize,
+            0xC000..=0xDFFF => self.nametables[(addr as usize - 0xC000) >> 11] = data,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data as usize & 0x3F;
+                self.audio.set_disabled(data & 0x40 != 0);
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = data as usize & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = data as usize & 0x3F,
+            0xF800..=0xFFFF => self.audio.write_addr(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        banked(
+            &self.chr,
+            self.chr_banks[addr as usize >> 10 & 0x07],
+            0x400,
+            addr,
+        )
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+        (!self.chr_ram).then(|| bank_offset(self.chr.len(), bank, 0x400, addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_ram {
+            let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+            *banked_mut(&mut self.chr, bank, 0x400, addr) = data;
+        }
+    }
+
+    /// Console VRAM pages picked by the nametable registers. Arrangements
+    /// other than the four standard ones fall back to the closest.
+    fn mirroring(&self) -> Mirroring {
+        match self.nametables.map(|bank| bank & 0x01) {
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            [a, b, _, _] if a != b => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn nametable_read(&mut self, addr: u16) -> Option<u8> {
+        self.nametable_bank(addr)
+            .map(|bank| banked(&self.chr, bank, 0x400, addr))
+    }
+
+    fn nametable_write(&mut self, addr: u16, _data: u8) -> bool {
+        // CHR-ROM nametables swallow writes.
+        self.nametable_bank(addr).is_some()
+    }
+
+    fn clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.prg_ram);
+        if self.chr_ram {
+            w.write(&self.chr);
+        }
+        w.write(&self.prg_banks);
+        w.write(&self.chr_banks);
+        w.write(&self.nametables);
+        w.write(&self.irq_counter);
+        w.write(&self.irq_enabled);
+        w.write(&self.irq_pending);
+        w.write(&self.audio);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.prg_ram)?;
+        if self.chr_ram {
+            r.read_into(&mut self.chr)?;
+        }
+        r.read_into(&mut self.prg_banks)?;
+        r.read_into(&mut self.chr_banks)?;
+        r.read_into(&mut self.nametables)?;
+        r.read_into(&mut self.irq_counter)?;
+        r.read_into(&mut self.irq_enabled)?;
+        r.read_into(&mut self.irq_pending)?;
+        r.read_into(&mut self.audio)?;
+        Ok(())
+    }
+}
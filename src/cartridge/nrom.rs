@@ -0,0 +1,96 @@
+use super::{Header, Mapper, Mirroring};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Mapper 0: 16KB or 32KB PRG ROM, 8KB CHR ROM or RAM, no banking.
+pub(super) struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub(super) fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        Nrom {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size.max(0x2000)],
+            chr: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            mirroring: header.mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xFFFF => Some(self.prg_rom[self.prg_rom_offset(addr)?]),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                Some((addr as usize - 0x8000) % self.prg_rom.len())
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x6000..=0x7FFF => {
+                let len = self.prg_ram.len();
+                Some(&mut self.prg_ram[(addr as usize - 0x6000) % len])
+            }
+            _ => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.prg_rom[offset])
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            let len = self.prg_ram.len();
+            self.prg_ram[(addr as usize - 0x6000) % len] = data;
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[addr as usize & 0x1FFF]
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_ram).then_some(addr as usize & 0x1FFF)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_ram {
+            self.chr[addr as usize & 0x1FFF] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.prg_ram);
+        if self.chr_ram {
+            w.write(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.prg_ram)?;
+        if self.chr_ram {
+            r.read_into(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use super::vrc_irq::VrcIrq;
+use super::{bank_offset, banked, banked_mut, Header, Mapper, Mirroring};
+use crate::apu::expansion::{ExpansionAudio, Vrc6Audio};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Mappers 24 and 26: Konami VRC6. A 16KB and an 8KB switchable PRG bank
+/// followed by the fixed last 8KB, eight 1KB CHR banks, the VRC IRQ counter
+/// and the VRC6 sound channels. Mapper 26 boards swap address lines A0/A1.
+///
+/// The CHR-ROM nametable modes of $B003 are not emulated; only its standard
+/// mirroring settings are.
+pub(super) struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    swap_lines: bool,
+    prg_16k: usize,
+    prg_8k: usize,
+    chr_banks: [usize; 8],
+    prg_ram_enabled: bool,
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub(super) fn new(
+        header: &Header,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        swap_lines: bool,
+    ) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        Vrc6 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size.max(0x2000)],
+            chr: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            swap_lines,
+            prg_16k: 0,
+            prg_8k: 0,
+            chr_banks: [0; 8],
+            prg_ram_enabled: false,
+            mirroring: header.mirroring,
+            irq: VrcIrq::default(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()])
+            }
+            0x8000..=0xFFFF => Some(self.prg_rom[self.prg_rom_offset(addr)?]),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let len = self.prg_rom.len();
+        match addr {
+            0x8000..=0xBFFF => Some(bank_offset(len, self.prg_16k, 0x4000, addr)),
+            0xC000..=0xDFFF => Some(bank_offset(len, self.prg_8k, 0x2000, addr)),
+            0xE000..=0xFFFF => Some(bank_offset(len, len / 0x2000 - 1, 0x2000, addr)),
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                let len = self.prg_ram.len();
+                Some(&mut self.prg_ram[(addr as usize - 0x6000) % len])
+            }
+            _ => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.prg_rom[offset])
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = data;
+            }
+            return;
+        }
+
+        let addr = if self.swap_lines {
+            (addr & !0x0003) | (addr & 0x0001) << 1 | (addr & 0x0002) >> 1
+        } else {
+            addr
+        };
+
+        match addr & 0xF003 {
+            0x8000..=0x8003 => self.prg_16k = data as usize & 0x0F,
+            0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 => self.audio.write(addr, data),
+            0xB003 => {
+                self.mirroring = match (data >> 2) & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+                self.prg_ram_enabled = data & 0x80 != 0;
+            }
+            0xC000..=0xC003 => self.prg_8k = data as usize & 0x1F,
+            0xD000..=0xD003 => self.chr_banks[(addr & 0x03) as usize] = data as usize,
+            0xE000..=0xE003 => self.chr_banks[4 + (addr & 0x03) as usize] = data as usize,
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        banked(
+            &self.chr,
+            self.chr_banks[addr as usize >> 10 & 0x07],
+            0x400,
+            addr,
+        )
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+        (!self.chr_ram).then(|| bank_offset(self.chr.len(), bank, 0x400, addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_ram {
+            let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+            *banked_mut(&mut self.chr, bank, 0x400, addr) = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.prg_ram);
+        if self.chr_ram {
+            w.write(&self.chr);
+        }
+        w.write(&self.prg_16k);
+        w.write(&self.prg_8k);
+        w.write(&self.chr_banks);
+        w.write(&self.prg_ram_enabled);
+        w.write(&self.mirroring);
+        w.write(&self.irq);
+        w.write(&self.audio);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.prg_ram)?;
+        if self.chr_ram {
+            r.read_into(&mut self.chr)?;
+        }
+        r.read_into(&mut self.prg_16k)?;
+        r.read_into(&mut self.prg_8k)?;
+        r.read_into(&mut self.chr_banks)?;
+        r.read_into(&mut self.prg_ram_enabled)?;
+        r.read_into(&mut self.mirroring)?;
+        r.read_into(&mut self.irq)?;
+        r.read_into(&mut self.audio)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use super::vrc_irq::VrcIrq;
+use super::{bank_offset, banked, banked_mut, Header, Mapper, Mirroring};
+use crate::apu::expansion::{ExpansionAudio, Vrc7Audio};
+use crate::region::Region;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Mapper 85: Konami VRC7. Three switchable 8KB PRG banks and the fixed last
+/// 8KB, eight 1KB CHR banks, the VRC IRQ counter and the OPLL-derived FM
+/// sound core. VRC7a decodes its second register of each pair on A4, VRC7b
+/// on A3; both are accepted.
+pub(super) struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    prg_ram_enabled: bool,
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    audio: Vrc7Audio,
+}
+
+impl Vrc7 {
+    pub(super) fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        Vrc7 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size.max(0x2000)],
+            chr: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            prg_ram_enabled: false,
+            mirroring: header.mirroring,
+            irq: VrcIrq::default(),
+            audio: Vrc7Audio::new(),
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()])
+            }
+            0x8000..=0xFFFF => Some(self.prg_rom[self.prg_rom_offset(addr)?]),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let len = self.prg_rom.len();
+        match addr {
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[(addr as usize - 0x8000) / 0x2000];
+                Some(bank_offset(len, bank, 0x2000, addr))
+            }
+            0xE000..=0xFFFF => Some(bank_offset(len, len / 0x2000 - 1, 0x2000, addr)),
+            _ => None,
+        }
+    }
+
+    fn prg_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                let len = self.prg_ram.len();
+                Some(&mut self.prg_ram[(addr as usize - 0x6000) % len])
+            }
+            _ => {
+                let offset = self.prg_rom_offset(addr)?;
+                Some(&mut self.prg_rom[offset])
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = data;
+            }
+            return;
+        }
+
+        if addr & 0xF030 == 0x9030 {
+            self.audio.write_data(data);
+            return;
+        }
+        let high = addr & 0x0018 != 0;
+        match (addr & 0xF000, high) {
+            (0x8000, false) => self.prg_banks[0] = data as usize & 0x3F,
+            (0x8000, true) => self.prg_banks[1] = data as usize & 0x3F,
+            (0x9000, false) => self.prg_banks[2] = data as usize & 0x3F,
+            (0x9000, true) => self.audio.write_addr(data),
+            (0xA000..=0xD000, _) => {
+                let index = ((addr as usize - 0xA000) >> 12) * 2 + high as usize;
+                self.chr_banks[index] = data as usize;
+            }
+            (0xE000, false) => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+                self.audio.set_silenced(data & 0x40 != 0);
+                self.prg_ram_enabled = data & 0x80 != 0;
+            }
+            (0xE000, true) => self.irq.write_latch(data),
+            (0xF000, false) => self.irq.write_control(data),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        banked(
+            &self.chr,
+            self.chr_banks[addr as usize >> 10 & 0x07],
+            0x400,
+            addr,
+        )
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+        (!self.chr_ram).then(|| bank_offset(self.chr.len(), bank, 0x400, addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_ram {
+            let bank = self.chr_banks[addr as usize >> 10 & 0x07];
+            *banked_mut(&mut self.chr, bank, 0x400, addr) = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn set_region(&mut self, region: Region) {
+        self.audio.set_region(region);
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.prg_ram);
+        if self.chr_ram {
+            w.write(&self.chr);
+        }
+        w.write(&self.prg_banks);
+        w.write(&self.chr_banks);
+        w.write(&self.prg_ram_enabled);
+        w.write(&self.mirroring);
+        w.write(&self.irq);
+        w.write(&self.audio);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.prg_ram)?;
+        if self.chr_ram {
+            r.read_into(&mut self.chr)?;
+        }
+        r.read_into(&mut self.prg_banks)?;
+        r.read_into(&mut self.chr_banks)?;
+        r.read_into(&mut self.prg_ram_enabled)?;
+        r.read_into(&mut self.mirroring)?;
+        r.read_into(&mut self.irq)?;
+        r.read_into(&mut self.audio)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+/// The IRQ counter shared by Konami's VRC4, VRC6 and VRC7: an 8-bit up
+/// counter clocked either every CPU cycle or once per scanline through a
+/// 341/3 prescaler.
+#[derive(Default, Clone)]
+pub(super) struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pub(super) pending: bool,
+}
+
+impl VrcIrq {
+    pub(super) fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    pub(super) fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 0x01 != 0;
+        self.enabled = data & 0x02 != 0;
+        self.cycle_mode = data & 0x04 != 0;
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub(super) fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub(super) fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if !self.cycle_mode {
+            self.prescaler -= 3;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += 341;
+        }
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+crate::state::stateful!(VrcIrq {
+    latch,
+    counter,
+    prescaler,
+    enabled,
+    enable_after_ack,
+    cycle_mode,
+    pending,
+});
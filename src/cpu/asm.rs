@@ -0,0 +1,845 @@
+//! A small two-pass 6502 assembler, for writing test programs inline.
+//!
+//! Statements are separated by newlines or `;`, and `//` starts a comment;
+//! neither counts inside quotes. Each statement is one of:
+//!
+//! - `label:`, which may also precede any of the others
+//! - `name = expr`, a constant, defined before it is used
+//! - an instruction of the [`Variant`] assembled for, official or
+//!   unofficial, such as `LDA ($10),Y`, `ASL A`, `BNE loop`, `LAX $0200,Y`
+//!   on the NMOS 6502 or `STA ($10)` and `BBS0 $11,loop` on the 65C02
+//! - `.org expr`, moving on to a higher address and padding with zeros
+//! - `.byte` with expressions and `"strings"`, or `.word` with expressions
+//!
+//! Expressions have numbers (`$FF`, `%1010`, `255`, `'A'`), labels, `*` for
+//! the current address, `+ - * / & | ^ << >>`, parentheses, and unary `-`,
+//! `~`, `<` (low byte) and `>` (high byte). Operands known to be below $100
+//! when they are reached use zero page addressing where it exists, so
+//! forward references are always absolute.
+
+use super::disasm::AddrMode;
+use super::opcodes;
+use super::Variant;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based source line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembled code, to be placed from `origin`: the first `.org`, or 0 if
+/// code comes before any.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub labels: HashMap<String, u16>,
+}
+
+impl Program {
+    /// The address after the last byte.
+    pub fn end(&self) -> u16 {
+        self.origin.wrapping_add(self.bytes.len() as u16)
+    }
+
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    /// Copies the bytes into a 64KB memory image.
+    pub fn load(&self, memory: &mut [u8]) {
+        for (i, &byte) in self.bytes.iter().enumerate() {
+            memory[self.origin.wrapping_add(i as u16) as usize] = byte;
+        }
+    }
+}
+
+/// Assembles `source` for the NMOS 6502, or for the variant given before
+/// a `;`, panicking with the error if there is one.
+///
+/// ```
+/// use nesters::asm;
+///
+/// let program = asm!(
+///     ".org $0200",
+///     "start: LDX #3; loop: DEX; BNE loop",
+///     "JMP start",
+/// );
+/// assert_eq!(program.origin, 0x0200);
+/// assert_eq!(program.bytes, [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x4C, 0x00, 0x02]);
+///
+/// let program = asm!(nesters::cpu::Variant::Wdc65C02; "STA ($10); BRA *");
+/// assert_eq!(program.bytes, [0x92, 0x10, 0x80, 0xFE]);
+/// ```
+#[macro_export]
+macro_rules! asm {
+    ($variant:expr; $($source:expr),+ $(,)?) => {
+        $crate::cpu::asm::assemble_for($variant, concat!($($source, "\n"),+))
+            .unwrap_or_else(|e| panic!("{e}"))
+    };
+    ($($source:expr),+ $(,)?) => {
+        $crate::asm!($crate::cpu::Variant::Nmos6502; $($source),+)
+    };
+}
+
+/// Assembles `source` for the NMOS 6502.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    assemble_for(Variant::Nmos6502, source)
+}
+
+/// Assembles `source` with the instructions of `variant`.
+pub fn assemble_for(variant: Variant, source: &str) -> Result<Program, AsmError> {
+    let mut statements = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let comment = unquoted(line).find(|&(i, _)| line[i..].starts_with("//"));
+        let line = &line[..comment.map_or(line.len(), |(i, _)| i)];
+        for text in split_unquoted(line, ';') {
+            let error = |message| AsmError {
+                line: number + 1,
+                message,
+            };
+            let (label, statement) = parse_statement(text).map_err(error)?;
+            if let Some(label) = label {
+                statements.push((number + 1, Statement::Label(label)));
+            }
+            if let Some(statement) = statement {
+                statements.push((number + 1, statement));
+            }
+        }
+    }
+
+    let mut assembler = Assembler {
+        variant,
+        symbols: HashMap::new(),
+        pc: 0,
+        origin: None,
+    };
+    let mut modes = Vec::with_capacity(statements.len());
+    for (line, statement) in &statements {
+        let mode = assembler.place(statement).map_err(|message| AsmError {
+            line: *line,
+            message,
+        })?;
+        modes.push(mode);
+    }
+    let labels = assembler.symbols.clone();
+    let mut program = Program {
+        origin: assembler.origin.unwrap_or(0),
+        bytes: Vec::new(),
+        labels: labels.clone(),
+    };
+    let mut pc = program.origin as i64;
+    for ((line, statement), mode) in statements.iter().zip(modes) {
+        let error = |message| AsmError {
+            line: *line,
+            message,
+        };
+        emit(
+            variant,
+            statement,
+            mode,
+            &labels,
+            &mut pc,
+            &mut program.bytes,
+        )
+        .map_err(error)?;
+    }
+    Ok(program)
+}
+
+#[derive(Debug)]
+enum Statement {
+    Label(String),
+    Constant(String, Expr),
+    Org(Expr),
+    Byte(Vec<Item>),
+    Word(Vec<Expr>),
+    Instruction(String, Operand),
+}
+
+#[derive(Debug)]
+enum Item {
+    Expr(Expr),
+    String(Vec<u8>),
+}
+
+/// An operand as written, before the choice between zero page, absolute
+/// and relative forms.
+#[derive(Debug)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr),
+    IndexedX(Expr),
+    IndexedY(Expr),
+    Indirect(Expr),
+    IndexedIndirect(Expr),
+    IndirectIndexed(Expr),
+    /// A zero page address and a branch target, for BBR and BBS.
+    BitBranch(Expr, Expr),
+}
+
+/// The first pass: addresses of labels and the addressing mode of each
+/// instruction.
+struct Assembler {
+    variant: Variant,
+    symbols: HashMap<String, u16>,
+    pc: i64,
+    origin: Option<u16>,
+}
+
+impl Assembler {
+    fn place(&mut self, statement: &Statement) -> Result<Option<AddrMode>, String> {
+        match statement {
+            Statement::Label(name) => {
+                self.define(name, self.pc)?;
+                self.origin.get_or_insert(0);
+            }
+            Statement::Constant(name, expr) => {
+                let value = expr.eval(&self.symbols, self.pc)?;
+                self.define(name, value)?;
+            }
+            Statement::Org(expr) => {
+                let pc = expr.eval(&self.symbols, self.pc)?;
+                if self.origin.is_some() && pc < self.pc {
+                    return Err(format!(".org ${pc:04X} is behind ${:04X}", self.pc));
+                }
+                self.origin.get_or_insert(pc as u16);
+                self.pc = pc;
+            }
+            Statement::Byte(items) => {
+                self.origin.get_or_insert(0);
+                self.pc += items
+                    .iter()
+                    .map(|item| match item {
+                        Item::Expr(_) => 1,
+                        Item::String(bytes) => bytes.len() as i64,
+                    })
+                    .sum::<i64>();
+            }
+            Statement::Word(exprs) => {
+                self.origin.get_or_insert(0);
+                self.pc += 2 * exprs.len() as i64;
+            }
+            Statement::Instruction(mnemonic, operand) => {
+                self.origin.get_or_insert(0);
+                let mode = self.mode(mnemonic, operand)?;
+                self.pc += 1 + mode.operand_len() as i64;
+                return Ok(Some(mode));
+            }
+        }
+        if !(0..=0x10000).contains(&self.pc) {
+            return Err("past the end of memory".into());
+        }
+        Ok(None)
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        if self.symbols.contains_key(name) {
+            return Err(format!("'{name}' is already defined"));
+        }
+        let value = u16::try_from(value).map_err(|_| format!("'{name}' is out of range"))?;
+        self.symbols.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn mode(&self, mnemonic: &str, operand: &Operand) -> Result<AddrMode, String> {
+        use AddrMode::*;
+        let has = |mode| opcode_for(self.variant, mnemonic, mode).is_some();
+        let zero_page = |expr: &Expr| {
+            expr.eval(&self.symbols, self.pc)
+                .is_ok_and(|value| (0..0x100).contains(&value))
+        };
+        let pick = |expr, short, long| {
+            if zero_page(expr) && has(short) {
+                short
+            } else {
+                long
+            }
+        };
+        let mode = match operand {
+            Operand::None if !has(Implied) => Accumulator,
+            Operand::None => Implied,
+            Operand::Accumulator => Accumulator,
+            Operand::Immediate(_) => Immediate,
+            Operand::Direct(_) if has(Relative) => Relative,
+            Operand::Direct(expr) => pick(expr, ZeroPage, Absolute),
+            Operand::IndexedX(expr) => pick(expr, ZeroPageX, AbsoluteX),
+            Operand::IndexedY(expr) => pick(expr, ZeroPageY, AbsoluteY),
+            Operand::Indirect(_) if has(Indirect) => Indirect,
+            Operand::Indirect(_) => ZeroPageIndirect,
+            Operand::IndexedIndirect(_) if has(AbsoluteIndexedIndirect) => AbsoluteIndexedIndirect,
+            Operand::IndexedIndirect(_) => IndexedIndirect,
+            Operand::IndirectIndexed(_) => IndirectIndexed,
+            Operand::BitBranch(..) => ZeroPageRelative,
+        };
+        if !has(mode) {
+            if !ALL_MODES.iter().any(|&mode| has(mode)) {
+                return Err(format!("unknown instruction '{mnemonic}'"));
+            }
+            return Err(format!("{mnemonic} has no {mode:?} mode"));
+        }
+        Ok(mode)
+    }
+}
+
+const ALL_MODES: [AddrMode; 16] = [
+    AddrMode::Implied,
+    AddrMode::Accumulator,
+    AddrMode::Immediate,
+    AddrMode::ZeroPage,
+    AddrMode::ZeroPageX,
+    AddrMode::ZeroPageY,
+    AddrMode::Absolute,
+    AddrMode::AbsoluteX,
+    AddrMode::AbsoluteY,
+    AddrMode::Indirect,
+    AddrMode::IndexedIndirect,
+    AddrMode::IndirectIndexed,
+    AddrMode::Relative,
+    AddrMode::ZeroPageIndirect,
+    AddrMode::AbsoluteIndexedIndirect,
+    AddrMode::ZeroPageRelative,
+];
+
+/// The second pass for one statement.
+fn emit(
+    variant: Variant,
+    statement: &Statement,
+    mode: Option<AddrMode>,
+    symbols: &HashMap<String, u16>,
+    pc: &mut i64,
+    bytes: &mut Vec<u8>,
+) -> Result<(), String> {
+    let byte = |expr: &Expr, pc| {
+        let value = expr.eval(symbols, pc)?;
+        match value {
+            -0x80..=0xFF => Ok(value as u8),
+            _ => Err(format!("${value:X} does not fit in a byte")),
+        }
+    };
+    let word = |expr: &Expr, pc| {
+        let value = expr.eval(symbols, pc)?;
+        match value {
+            -0x8000..=0xFFFF => Ok(value as u16),
+            _ => Err(format!("${value:X} does not fit in a word")),
+        }
+    };
+    let start = bytes.len();
+    match statement {
+        Statement::Label(_) | Statement::Constant(..) => {}
+        Statement::Org(expr) => {
+            let target = expr.eval(symbols, *pc)?;
+            if !bytes.is_empty() {
+                bytes.resize(bytes.len() + (target - *pc) as usize, 0);
+            }
+            *pc = target;
+            return Ok(());
+        }
+        Statement::Byte(items) => {
+            for item in items {
+                match item {
+                    Item::Expr(expr) => bytes.push(byte(expr, *pc)?),
+                    Item::String(text) => bytes.extend(text),
+                }
+            }
+        }
+        Statement::Word(exprs) => {
+            for expr in exprs {
+                bytes.extend(word(expr, *pc)?.to_le_bytes());
+            }
+        }
+        Statement::Instruction(mnemonic, operand) => {
+            let mode = mode.unwrap();
+            bytes.push(opcode_for(variant, mnemonic, mode).unwrap());
+            let expr = match operand {
+                Operand::None | Operand::Accumulator => None,
+                Operand::BitBranch(addr, target) => {
+                    bytes.push(byte(addr, *pc)?);
+                    Some(target)
+                }
+                Operand::Immediate(expr)
+                | Operand::Direct(expr)
+                | Operand::IndexedX(expr)
+                | Operand::IndexedY(expr)
+                | Operand::Indirect(expr)
+                | Operand::IndexedIndirect(expr)
+                | Operand::IndirectIndexed(expr) => Some(expr),
+            };
+            if let Some(expr) = expr {
+                match mode {
+                    AddrMode::Relative | AddrMode::ZeroPageRelative => {
+                        let next = *pc + 1 + mode.operand_len() as i64;
+                        let offset = expr.eval(symbols, *pc)? - next;
+                        if !(-128..=127).contains(&offset) {
+                            return Err(format!("branch of {offset} bytes is out of range"));
+                        }
+                        bytes.push(offset as u8);
+                    }
+                    _ if mode.operand_len() == 1 => bytes.push(byte(expr, *pc)?),
+                    _ => bytes.extend(word(expr, *pc)?.to_le_bytes()),
+                }
+            }
+        }
+    }
+    *pc += (bytes.len() - start) as i64;
+    Ok(())
+}
+
+/// The opcode for an instruction on `variant`, preferring official opcodes
+/// where an unofficial one does the same.
+fn opcode_for(variant: Variant, mnemonic: &str, mode: AddrMode) -> Option<u8> {
+    let find = |official| {
+        (0..=255).find(|&opcode| {
+            let info = opcodes::info(variant, opcode);
+            info.official == official && info.mnemonic == mnemonic && info.mode == mode
+        })
+    };
+    find(true).or_else(|| find(false))
+}
+
+/// Splits off a leading `label:` and parses the rest.
+fn parse_statement(text: &str) -> Result<(Option<String>, Option<Statement>), String> {
+    let mut text = text.trim();
+    let mut label = None;
+    if let Some((name, rest)) = text.split_once(':') {
+        if is_identifier(name.trim()) {
+            label = Some(name.trim().to_string());
+            text = rest.trim();
+        }
+    }
+    if text.is_empty() {
+        return Ok((label, None));
+    }
+    if let Some((name, value)) = text.split_once('=') {
+        let name = name.trim();
+        if is_identifier(name) {
+            return Ok((
+                label,
+                Some(Statement::Constant(name.into(), Expr::parse(value)?)),
+            ));
+        }
+    }
+    let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let rest = rest.trim();
+    let statement = match word.to_ascii_lowercase().as_str() {
+        ".org" => Statement::Org(Expr::parse(rest)?),
+        ".byte" => Statement::Byte(
+            split_list(rest)
+                .into_iter()
+                .map(|item| match item.strip_prefix('"') {
+                    Some(text) => text
+                        .strip_suffix('"')
+                        .map(|text| Item::String(text.as_bytes().to_vec()))
+                        .ok_or_else(|| format!("unterminated string {item}")),
+                    None => Expr::parse(item).map(Item::Expr),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        ".word" => Statement::Word(
+            split_list(rest)
+                .into_iter()
+                .map(Expr::parse)
+                .collect::<Result<_, _>>()?,
+        ),
+        directive if directive.starts_with('.') => {
+            return Err(format!("unknown directive '{word}'"));
+        }
+        _ => {
+            let mnemonic = word.to_ascii_uppercase();
+            let operand = match split_unquoted(rest, ',')[..] {
+                [addr, target] if mnemonic.starts_with("BBR") || mnemonic.starts_with("BBS") => {
+                    Operand::BitBranch(Expr::parse(addr)?, Expr::parse(target)?)
+                }
+                _ => parse_operand(rest)?,
+            };
+            Statement::Instruction(mnemonic, operand)
+        }
+    };
+    Ok((label, Some(statement)))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits on commas outside quotes, dropping empty items.
+fn split_list(text: &str) -> Vec<&str> {
+    let mut items = split_unquoted(text, ',');
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Splits on `separator` outside quotes, trimming each part.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, _) in unquoted(text).filter(|&(_, c)| c == separator) {
+        parts.push(text[start..i].trim());
+        start = i + separator.len_utf8();
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+/// The characters of `text` outside `"strings"` and `'c'` characters, with
+/// their indices.
+fn unquoted(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    text.char_indices().filter(move |&(_, c)| match quote {
+        Some(open) => {
+            if c == open {
+                quote = None;
+            }
+            false
+        }
+        None if c == '"' || c == '\'' => {
+            quote = Some(c);
+            false
+        }
+        None => true,
+    })
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let upper = text.to_ascii_uppercase();
+    if text.is_empty() {
+        return Ok(Operand::None);
+    }
+    if upper == "A" {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(Operand::Immediate(Expr::parse(value)?));
+    }
+    let compact: String = upper.chars().filter(|c| !c.is_whitespace()).collect();
+    if text.starts_with('(') {
+        if compact.ends_with(",X)") {
+            let inner = &text[1..text.rfind(',').unwrap()];
+            return Ok(Operand::IndexedIndirect(Expr::parse(inner)?));
+        }
+        let close = matching_paren(text).ok_or("unbalanced parentheses")?;
+        let after: String = text[close + 1..].split_whitespace().collect();
+        let inner = &text[1..close];
+        match after.to_ascii_uppercase().as_str() {
+            "" => return Ok(Operand::Indirect(Expr::parse(inner)?)),
+            ",Y" => return Ok(Operand::IndirectIndexed(Expr::parse(inner)?)),
+            _ => {}
+        }
+    }
+    if let Some(comma) = text.rfind(',') {
+        let base = Expr::parse(&text[..comma])?;
+        return match text[comma + 1..].trim().to_ascii_uppercase().as_str() {
+            "X" => Ok(Operand::IndexedX(base)),
+            "Y" => Ok(Operand::IndexedY(base)),
+            index => Err(format!("bad index register '{index}'")),
+        };
+    }
+    Ok(Operand::Direct(Expr::parse(text)?))
+}
+
+/// The index of the parenthesis closing the one `text` starts with.
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Symbol(String),
+    /// `*`, the address of the current statement.
+    Pc,
+    Unary(char, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Binary operators by increasing precedence.
+const LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+impl Expr {
+    fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = ExprParser {
+            text: text.trim(),
+            source: text.trim(),
+        };
+        let expr = parser.binary(0)?;
+        if !parser.text.trim().is_empty() {
+            return Err(format!(
+                "unexpected '{}' in '{}'",
+                parser.text.trim(),
+                parser.source
+            ));
+        }
+        Ok(expr)
+    }
+
+    fn eval(&self, symbols: &HashMap<String, u16>, pc: i64) -> Result<i64, String> {
+        Ok(match self {
+            Expr::Number(value) => *value,
+            Expr::Symbol(name) => *symbols
+                .get(name)
+                .ok_or_else(|| format!("undefined symbol '{name}'"))?
+                as i64,
+            Expr::Pc => pc,
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(symbols, pc)?;
+                match op {
+                    '-' => -value,
+                    '~' => !value,
+                    '<' => value & 0xFF,
+                    _ => (value >> 8) & 0xFF,
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(symbols, pc)?, right.eval(symbols, pc)?);
+                match *op {
+                    "|" => left | right,
+                    "^" => left ^ right,
+                    "&" => left & right,
+                    "<<" => left.wrapping_shl(right as u32),
+                    ">>" => left.wrapping_shr(right as u32),
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    _ if right == 0 => return Err("division by zero".into()),
+                    _ => left / right,
+                }
+            }
+        })
+    }
+}
+
+struct ExprParser<'a> {
+    text: &'a str,
+    source: &'a str,
+}
+
+impl<'a> ExprParser<'a> {
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'operators: loop {
+            self.text = self.text.trim_start();
+            for &op in LEVELS[level] {
+                if let Some(rest) = self.text.strip_prefix(op) {
+                    self.text = rest;
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.text = self.text.trim_start();
+        let mut chars = self.text.chars();
+        let Some(first) = chars.next() else {
+            return Err(format!("missing value in '{}'", self.source));
+        };
+        let rest = chars.as_str();
+        match first {
+            '-' | '~' | '<' | '>' => {
+                self.text = rest;
+                Ok(Expr::Unary(first, Box::new(self.unary()?)))
+            }
+            '(' => {
+                self.text = rest;
+                let expr = self.binary(0)?;
+                self.text = self
+                    .text
+                    .trim_start()
+                    .strip_prefix(')')
+                    .ok_or_else(|| format!("missing ')' in '{}'", self.source))?;
+                Ok(expr)
+            }
+            '*' => {
+                self.text = rest;
+                Ok(Expr::Pc)
+            }
+            '\'' => {
+                let mut chars = rest.chars();
+                let (Some(c), Some('\'')) = (chars.next(), chars.next()) else {
+                    return Err(format!("bad character in '{}'", self.source));
+                };
+                self.text = chars.as_str();
+                Ok(Expr::Number(c as i64))
+            }
+            '$' | '%' => {
+                let radix = if first == '$' { 16 } else { 2 };
+                let digits = self.take(rest, |c| c.is_ascii_alphanumeric());
+                i64::from_str_radix(digits, radix)
+                    .map(Expr::Number)
+                    .map_err(|_| format!("bad number '{first}{digits}'"))
+            }
+            c if c.is_ascii_digit() => {
+                let digits = self.take(self.text, |c| c.is_ascii_alphanumeric());
+                digits
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("bad number '{digits}'"))
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.take(self.text, |c| c.is_ascii_alphanumeric() || c == '_');
+                Ok(Expr::Symbol(name.to_string()))
+            }
+            _ => Err(format!("unexpected '{}' in '{}'", self.text, self.source)),
+        }
+    }
+
+    /// Takes the longest prefix of `from`, a suffix of the remaining text,
+    /// whose characters match.
+    fn take(&mut self, from: &'a str, matches: impl Fn(char) -> bool) -> &'a str {
+        let end = from.find(|c| !matches(c)).unwrap_or(from.len());
+        self.text = &from[end..];
+        &from[..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::BusEvent;
+    use crate::cpu::{Cpu, Variant};
+
+    #[test]
+    fn assembles_each_mode() {
+        let program = crate::asm!(
+            "ptr = $10; table = $0300",
+            ".org $8000",
+            "reset: LDA #<table; STA ptr; LDA #>table; STA ptr+1",
+            "  ASL A; ASL; INC ptr,X; LDX table,Y; LDA (ptr),Y; STA (ptr,X)",
+            "  JMP (vector); BNE reset; JSR done  // forward, so absolute",
+            "  LAX ptr; DCP table,Y; NOP #1",
+            "done: RTS",
+            "vector: .word reset, done - 1",
+            ".byte 'A', \"hi\", -1, %101",
+        );
+        #[rustfmt::skip]
+        let expected = [
+            0xA9, 0x00, 0x85, 0x10, 0xA9, 0x03, 0x85, 0x11,
+            0x0A, 0x0A, 0xF6, 0x10, 0xBE, 0x00, 0x03, 0xB1, 0x10, 0x81, 0x10,
+            0x6C, 0x23, 0x80, 0xD0, 0xE8, 0x20, 0x22, 0x80,
+            0xA7, 0x10, 0xDB, 0x00, 0x03, 0x80, 0x01,
+            0x60,
+            0x00, 0x80, 0x21, 0x80,
+            0x41, 0x68, 0x69, 0xFF, 0x05,
+        ];
+        assert_eq!(program.origin, 0x8000);
+        assert_eq!(program.bytes, expected);
+        assert_eq!(program.label("done"), Some(0x8022));
+
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(error("LDA #1\nLDA $10,Z"), "line 2: bad index register 'Z'");
+        assert_eq!(error("JMP nowhere"), "line 1: undefined symbol 'nowhere'");
+        assert_eq!(error("STA #1"), "line 1: STA has no Immediate mode");
+        assert_eq!(error("FOO"), "line 1: unknown instruction 'FOO'");
+        assert_eq!(
+            error("x: BNE x+200"),
+            "line 1: branch of 198 bytes is out of range"
+        );
+        assert_eq!(
+            error(".org 2; NOP; .org 1"),
+            "line 1: .org $0001 is behind $0003"
+        );
+    }
+
+    #[test]
+    fn assembles_for_a_variant() {
+        let program = crate::asm!(
+            Variant::Wdc65C02;
+            ".org $0200",
+            "start: STZ $10; STA ($10); LDA ($10); INC A; DEC",
+            "  JMP ($1234,X); JMP ($1234)",
+            "  BBS0 $11,start; BBR7 $11, *; BRA start",
+            "  PHX; PLY; TSB $11; WAI; STP",
+            "  .byte \"a;b//c\", ';', '\"' // not a statement or comment until here",
+        );
+        #[rustfmt::skip]
+        let expected = [
+            0x64, 0x10, 0x92, 0x10, 0xB2, 0x10, 0x1A, 0x3A,
+            0x7C, 0x34, 0x12, 0x6C, 0x34, 0x12,
+            0x8F, 0x11, 0xEF, 0x7F, 0x11, 0xFD, 0x80, 0xEA,
+            0xDA, 0x7A, 0x04, 0x11, 0xCB, 0xDB,
+            0x61, 0x3B, 0x62, 0x2F, 0x2F, 0x63, 0x3B, 0x22,
+        ];
+        assert_eq!(program.bytes, expected);
+
+        let error = |variant, source| assemble_for(variant, source).unwrap_err().to_string();
+        assert_eq!(
+            error(Variant::Nmos6502, "STZ $10"),
+            "line 1: unknown instruction 'STZ'"
+        );
+        assert_eq!(
+            error(Variant::Wdc65C02, "LAX $10"),
+            "line 1: unknown instruction 'LAX'"
+        );
+    }
+
+    #[test]
+    fn runs_on_a_cpu() {
+        let program = crate::asm!(
+            ".org $0200",
+            "  LDX #5; LDA #0; CLC",
+            "loop: ADC #3; DEX; BNE loop",
+            "  STA result",
+            "result: .byte 0",
+        );
+        let mut ram = vec![0; 0x10000];
+        program.load(&mut ram);
+        let mut cpu = Cpu::new(Variant::Nmos6502);
+        cpu.set_pc(program.origin);
+        let (mut addr, mut data) = (program.origin, ram[program.origin as usize]);
+        let result = program.label("result").unwrap();
+        while !(cpu.at_instruction_start() && cpu.pc() == result) {
+            match cpu.clock(addr, data) {
+                BusEvent::Read(read) => addr = read,
+                BusEvent::Write(write, value) => {
+                    (addr, data) = (write, value);
+                    ram[addr as usize] = data;
+                }
+            }
+            data = ram[addr as usize];
+        }
+        assert_eq!(ram[result as usize], 15);
+    }
+}
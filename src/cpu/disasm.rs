@@ -0,0 +1,223 @@
+//! A 6502 disassembler, for the official instruction set or everything a
+//! [`Variant`] decodes.
+
+use super::opcodes;
+use super::Variant;
+use std::fmt;
+
+/// How an instruction finds its operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddrMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    /// `(zp,X)`
+    IndexedIndirect,
+    /// `(zp),Y`
+    IndirectIndexed,
+    Relative,
+    /// 65C02 `(zp)`
+    ZeroPageIndirect,
+    /// 65C02 `(abs,X)`
+    AbsoluteIndexedIndirect,
+    /// Rockwell `zp,rel`
+    ZeroPageRelative,
+}
+
+impl AddrMode {
+    /// Operand bytes following the opcode.
+    pub const fn operand_len(self) -> u16 {
+        match self {
+            AddrMode::Implied | AddrMode::Accumulator => 0,
+            AddrMode::Absolute
+            | AddrMode::AbsoluteX
+            | AddrMode::AbsoluteY
+            | AddrMode::Indirect
+            | AddrMode::AbsoluteIndexedIndirect
+            | AddrMode::ZeroPageRelative => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// The mnemonic and addressing mode of `opcode`, or `None` if it is not
+/// an official instruction.
+pub fn opcode(opcode: u8) -> Option<(&'static str, AddrMode)> {
+    let info = opcodes::info(Variant::Nmos6502, opcode);
+    info.official.then_some((info.mnemonic, info.mode))
+}
+
+/// One disassembled instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    /// The opcode and its operand bytes.
+    pub bytes: Vec<u8>,
+    /// `None` for bytes that are not an official opcode, shown as `.db`.
+    pub mnemonic: Option<&'static str>,
+    pub mode: AddrMode,
+}
+
+impl Line {
+    /// Address of the instruction that follows.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    fn operand(&self) -> u16 {
+        match self.bytes.len() {
+            2 => self.bytes[1] as u16,
+            3 => u16::from_le_bytes([self.bytes[1], self.bytes[2]]),
+            _ => 0,
+        }
+    }
+
+    /// Where a jump, call or branch goes, if it is known statically.
+    pub fn target(&self) -> Option<u16> {
+        match (self.mnemonic?, self.mode) {
+            (_, AddrMode::Relative) => {
+                Some(self.next_addr().wrapping_add(self.bytes[1] as i8 as u16))
+            }
+            (_, AddrMode::ZeroPageRelative) => {
+                Some(self.next_addr().wrapping_add(self.bytes[2] as i8 as u16))
+            }
+            ("JMP" | "JSR", AddrMode::Absolute) => Some(self.operand()),
+            _ => None,
+        }
+    }
+
+    /// Like the `Display` output, but with operand addresses that `label`
+    /// names written as the name, e.g. `JSR init` or `LDA buffer,X`.
+    pub fn to_string_with<'a>(&self, label: impl Fn(u16) -> Option<&'a str>) -> String {
+        let Some(mnemonic) = self.mnemonic else {
+            return format!(".db ${:02X}", self.bytes[0]);
+        };
+        let operand = self.operand();
+        let addr = |addr: u16, digits: usize| match label(addr) {
+            Some(name) => name.to_string(),
+            None => format!("${addr:0digits$X}"),
+        };
+        match self.mode {
+            AddrMode::Implied => mnemonic.to_string(),
+            AddrMode::Accumulator => format!("{mnemonic} A"),
+            AddrMode::Immediate => format!("{mnemonic} #${operand:02X}"),
+            AddrMode::ZeroPage => format!("{mnemonic} {}", addr(operand, 2)),
+            AddrMode::ZeroPageX => format!("{mnemonic} {},X", addr(operand, 2)),
+            AddrMode::ZeroPageY => format!("{mnemonic} {},Y", addr(operand, 2)),
+            AddrMode::Absolute => format!("{mnemonic} {}", addr(operand, 4)),
+            AddrMode::AbsoluteX => format!("{mnemonic} {},X", addr(operand, 4)),
+            AddrMode::AbsoluteY => format!("{mnemonic} {},Y", addr(operand, 4)),
+            AddrMode::Indirect => format!("{mnemonic} ({})", addr(operand, 4)),
+            AddrMode::IndexedIndirect => format!("{mnemonic} ({},X)", addr(operand, 2)),
+            AddrMode::IndirectIndexed => format!("{mnemonic} ({}),Y", addr(operand, 2)),
+            AddrMode::Relative => format!("{mnemonic} {}", addr(self.target().unwrap(), 4)),
+            AddrMode::ZeroPageIndirect => format!("{mnemonic} ({})", addr(operand, 2)),
+            AddrMode::AbsoluteIndexedIndirect => format!("{mnemonic} ({},X)", addr(operand, 4)),
+            AddrMode::ZeroPageRelative => format!(
+                "{mnemonic} {},{}",
+                addr(self.bytes[1] as u16, 2),
+                addr(self.target().unwrap(), 4)
+            ),
+        }
+    }
+}
+
+/// Prints the instruction alone, e.g. `LDA ($10),Y` or `BNE $C004`.
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_with(|_| None))
+    }
+}
+
+/// Disassembles the instruction at `addr`, fetching bytes with `peek`.
+pub fn disassemble(addr: u16, peek: impl FnMut(u16) -> u8) -> Line {
+    disassemble_with(addr, peek, opcode)
+}
+
+/// Disassembles the instruction at `addr` as `variant` decodes it,
+/// unofficial opcodes included.
+pub fn disassemble_for(variant: Variant, addr: u16, peek: impl FnMut(u16) -> u8) -> Line {
+    disassemble_with(addr, peek, |opcode| {
+        let info = opcodes::info(variant, opcode);
+        Some((info.mnemonic, info.mode))
+    })
+}
+
+fn disassemble_with(
+    addr: u16,
+    mut peek: impl FnMut(u16) -> u8,
+    decode: impl Fn(u8) -> Option<(&'static str, AddrMode)>,
+) -> Line {
+    let opcode_byte = peek(addr);
+    let (mnemonic, mode) = match decode(opcode_byte) {
+        Some((mnemonic, mode)) => (Some(mnemonic), mode),
+        None => (None, AddrMode::Implied),
+    };
+    let bytes = (0..=mode.operand_len())
+        .map(|offset| peek(addr.wrapping_add(offset)))
+        .collect();
+    Line {
+        addr,
+        bytes,
+        mnemonic,
+        mode,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_each_mode() {
+        let program = [
+            0xB1, 0x10, // LDA ($10),Y
+            0xD0, 0xFC, // BNE *-2
+            0x6C, 0x34, 0x12, // JMP ($1234)
+            0x0A, // ASL A
+            0x02, // not an official opcode
+        ];
+        let peek = |addr: u16| program.get(addr as usize - 0x8000).copied().unwrap_or(0);
+        let mut addr = 0x8000;
+        let mut text = Vec::new();
+        for _ in 0..5 {
+            let line = disassemble(addr, peek);
+            text.push(line.to_string());
+            addr = line.next_addr();
+        }
+        assert_eq!(
+            text,
+            [
+                "LDA ($10),Y",
+                "BNE $8000",
+                "JMP ($1234)",
+                "ASL A",
+                ".db $02"
+            ]
+        );
+        assert_eq!(disassemble(0x8002, peek).target(), Some(0x8000));
+        let label = |addr| (addr == 0x8000).then_some("start");
+        assert_eq!(disassemble(0x8002, peek).to_string_with(label), "BNE start");
+        assert_eq!(
+            disassemble_for(Variant::Nmos6502, 0x8008, peek).to_string(),
+            "JAM"
+        );
+
+        let program = [
+            0x8F, 0x10, 0xFD, // BBS0 $10,*+0
+            0xB2, 0x20, // LDA ($20)
+            0x7C, 0x00, 0x90, // JMP ($9000,X)
+        ];
+        let peek = |addr: u16| program.get(addr as usize - 0x8000).copied().unwrap_or(0);
+        let text = [0x8000, 0x8003, 0x8005]
+            .map(|addr| disassemble_for(Variant::Wdc65C02, addr, peek).to_string());
+        assert_eq!(text, ["BBS0 $10,$8000", "LDA ($20)", "JMP ($9000,X)"]);
+    }
+}
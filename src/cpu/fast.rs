@@ -0,0 +1,388 @@
+//! The instruction-level engine behind [`Cpu::execute`]. It decodes and
+//! computes with the same [`Instruction`]s as [`Cpu::clock`], but performs
+//! each instruction in one go and makes only the bus accesses that carry
+//! data. Cycle counts come from the [`super::opcodes`] tables.
+
+use super::instruction::*;
+use super::{opcodes, Cpu, Variant};
+use crate::bus::{BusDevice, BusEvent};
+
+impl Cpu {
+    /// Runs the next instruction, or interrupt sequence, against `bus` and
+    /// returns the number of cycles [`Cpu::clock`] would have taken.
+    ///
+    /// Registers, flags and memory end up as the cycle-accurate core leaves
+    /// them, but dummy reads and the extra write of read-modify-write
+    /// instructions are skipped, so devices that count accesses see fewer.
+    /// Call it between instructions; an IRQ asserted beforehand is taken
+    /// as held for the whole instruction.
+    pub fn execute<B: BusDevice + ?Sized>(&mut self, bus: &mut B) -> u32 {
+        debug_assert!(self.step == 0, "execute() called mid-instruction");
+        let (nmi, irq_line, i) = (self.nmi, self.irq_line, self.p.i);
+
+        let mut cycles = if self.idle() {
+            1
+        } else if self.rst || self.nmi_poll || self.irq_poll {
+            let int = if self.rst {
+                Interrupt::Rst
+            } else if self.nmi_poll {
+                Interrupt::Nmi
+            } else {
+                Interrupt::Irq
+            };
+            self.inst = Instruction::Stack(StackInstruction::Brk(int));
+            self.run(false, bus);
+            7
+        } else {
+            self.opcode = bus.read(self.pc);
+            self.pc = self.pc.wrapping_add(1);
+            let info = opcodes::info(self.variant, self.opcode);
+            self.inst = info.instruction;
+            info.cycles as u32 + self.run(info.page_penalty, bus)
+        };
+        if self.stall {
+            self.stall = false;
+            cycles += 1;
+        }
+
+        // The cycle-accurate core polls before the last cycle, so I as
+        // CLI, SEI and PLP leave it only counts from the next instruction.
+        let i = match self.inst {
+            Instruction::AccumImpl(AccumImplInstruction::Internal(
+                InternalInstruction::Cli | InternalInstruction::Sei,
+            ))
+            | Instruction::Stack(StackInstruction::Plp) => i,
+            _ => self.p.i,
+        };
+        self.end_cycle(self.pc, nmi, irq_line && !i);
+        cycles
+    }
+
+    /// Performs `self.inst`, whose opcode has been fetched, and returns the
+    /// cycles it took beyond the table's: for crossing a page, if that
+    /// costs `page_penalty`, or for taking a branch.
+    fn run<B: BusDevice + ?Sized>(&mut self, page_penalty: bool, bus: &mut B) -> u32 {
+        let mut extra = 0;
+        match self.inst {
+            Instruction::Stack(stack_instruction) => self.run_stack(stack_instruction, bus),
+            Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+                read_modify_write_instruction,
+            )) => {
+                self.a = read_modify_write_instruction.execute(self, self.a);
+            }
+            Instruction::AccumImpl(AccumImplInstruction::Internal(internal_instruction)) => {
+                internal_instruction.execute(self);
+            }
+            Instruction::Imm(ImmInstruction::Read(read_instruction)) => {
+                let m = self.next_byte(bus);
+                read_instruction.execute(self, m);
+            }
+            Instruction::Abs(AbsInstruction::Jump(_)) => {
+                self.pc = self.next_word(bus);
+            }
+            Instruction::Abs(AbsInstruction::Read(read_instruction)) => {
+                let addr = self.next_word(bus);
+                self.read(read_instruction, addr, bus);
+            }
+            Instruction::Abs(AbsInstruction::Write(write_instruction)) => {
+                let addr = self.next_word(bus);
+                let data = write_instruction.execute(self);
+                bus.write(addr, data);
+            }
+            Instruction::Abs(AbsInstruction::ReadModifyWrite(read_modify_write_instruction)) => {
+                let addr = self.next_word(bus);
+                self.modify(read_modify_write_instruction, addr, bus);
+            }
+            Instruction::ZeroPage(zero_page_instruction) => {
+                let addr = self.next_byte(bus) as u16;
+                match zero_page_instruction {
+                    ZeroPageInstruction::Read(read_instruction) => {
+                        self.read(read_instruction, addr, bus);
+                    }
+                    ZeroPageInstruction::Write(write_instruction) => {
+                        let data = write_instruction.execute(self);
+                        bus.write(addr, data);
+                    }
+                    ZeroPageInstruction::ReadModifyWrite(read_modify_write_instruction) => {
+                        self.modify(read_modify_write_instruction, addr, bus);
+                    }
+                }
+            }
+            Instruction::ZeroPageIdxX(zero_page_idx_instruction)
+            | Instruction::ZeroPageIdxY(zero_page_idx_instruction) => {
+                let index = match self.inst {
+                    Instruction::ZeroPageIdxX(_) => self.x,
+                    _ => self.y,
+                };
+                let addr = self.next_byte(bus).wrapping_add(index) as u16;
+                match zero_page_idx_instruction {
+                    ZeroPageIdxInstruction::Read(read_instruction) => {
+                        self.read(read_instruction, addr, bus);
+                    }
+                    ZeroPageIdxInstruction::Write(write_instruction) => {
+                        let data = write_instruction.execute(self);
+                        bus.write(addr, data);
+                    }
+                    ZeroPageIdxInstruction::ReadModifyWrite(read_modify_write_instruction) => {
+                        self.modify(read_modify_write_instruction, addr, bus);
+                    }
+                }
+            }
+            Instruction::AbsIdxX(abs_idx_instruction)
+            | Instruction::AbsIdxY(abs_idx_instruction) => {
+                self.temp = self.next_byte(bus);
+                let high = self.next_byte(bus);
+                let addr = self.index_abs(high);
+                let crossed = self.temp != 0;
+                let fixed = addr.wrapping_add((crossed as u16) << 8);
+                match abs_idx_instruction {
+                    AbsIdxInstruction::Read(read_instruction) => {
+                        self.read(read_instruction, fixed, bus);
+                        extra = (page_penalty && crossed) as u32;
+                    }
+                    AbsIdxInstruction::Write(write_instruction) => {
+                        let write = self.indexed_write(write_instruction, addr, crossed);
+                        self.write(write, bus);
+                    }
+                    AbsIdxInstruction::ReadModifyWrite(read_modify_write_instruction) => {
+                        self.modify(read_modify_write_instruction, fixed, bus);
+                        extra = (page_penalty && crossed) as u32;
+                    }
+                }
+            }
+            Instruction::Rel(RelInstruction::Branch(branch_instruction)) => {
+                let offset = self.next_byte(bus);
+                if branch_instruction.execute(self) {
+                    extra = self.branch(offset);
+                }
+            }
+            Instruction::IdxInd(idx_ind_instruction) => {
+                let pointer = self.next_byte(bus).wrapping_add(self.x);
+                let addr = self.read_zero_page_word(pointer, bus);
+                match idx_ind_instruction {
+                    IdxIndInstruction::Read(read_instruction) => {
+                        self.read(read_instruction, addr, bus);
+                    }
+                    IdxIndInstruction::Write(write_instruction) => {
+                        let data = write_instruction.execute(self);
+                        bus.write(addr, data);
+                    }
+                    IdxIndInstruction::ReadModifyWrite(read_modify_write_instruction) => {
+                        self.modify(read_modify_write_instruction, addr, bus);
+                    }
+                }
+            }
+            Instruction::IndIdx(ind_idx_instruction) => {
+                let pointer = self.next_byte(bus);
+                let base = self.read_zero_page_word(pointer, bus);
+                let fixed = base.wrapping_add(self.y as u16);
+                let crossed = (fixed ^ base) & 0xFF00 != 0;
+                match ind_idx_instruction {
+                    IndIdxInstruction::Read(read_instruction) => {
+                        self.read(read_instruction, fixed, bus);
+                        extra = (page_penalty && crossed) as u32;
+                    }
+                    IndIdxInstruction::Write(write_instruction) => {
+                        let addr = (base & 0xFF00) | (fixed & 0x00FF);
+                        let write = self.indexed_write(write_instruction, addr, crossed);
+                        self.write(write, bus);
+                    }
+                    IndIdxInstruction::ReadModifyWrite(read_modify_write_instruction) => {
+                        self.modify(read_modify_write_instruction, fixed, bus);
+                    }
+                }
+            }
+            Instruction::AbsInd(_) | Instruction::AbsIdxInd(_)
+                if self.variant == Variant::Wdc65C02 =>
+            {
+                let index = match self.inst {
+                    Instruction::AbsIdxInd(_) => self.x,
+                    _ => 0,
+                };
+                let pointer = self.next_word(bus).wrapping_add(index as u16);
+                self.pc = self.read_word(pointer, pointer.wrapping_add(1), bus);
+            }
+            Instruction::AbsIdxInd(_) => unreachable!(),
+            Instruction::AbsInd(_) => {
+                // The high byte comes from the same page.
+                let pointer = self.next_word(bus);
+                let high = (pointer & 0xFF00) | (pointer as u8).wrapping_add(1) as u16;
+                self.pc = self.read_word(pointer, high, bus);
+            }
+            Instruction::ZeroPageInd(zero_page_ind_instruction) => {
+                let pointer = self.next_byte(bus);
+                let addr = self.read_zero_page_word(pointer, bus);
+                match zero_page_ind_instruction {
+                    ZeroPageIndInstruction::Read(read_instruction) => {
+                        self.read(read_instruction, addr, bus);
+                    }
+                    ZeroPageIndInstruction::Write(write_instruction) => {
+                        let data = write_instruction.execute(self);
+                        bus.write(addr, data);
+                    }
+                }
+            }
+            Instruction::ZeroPageRel(zero_page_rel_instruction) => {
+                let addr = self.next_byte(bus) as u16;
+                let m = bus.read(addr);
+                let offset = self.next_byte(bus);
+                if zero_page_rel_instruction.execute(m) {
+                    extra = self.branch(offset);
+                }
+            }
+            Instruction::Halt(halt_instruction) => {
+                self.halt = Some(halt_instruction);
+            }
+            Instruction::Nop { bytes, .. } => {
+                self.pc = self.pc.wrapping_add(bytes as u16 - 1);
+            }
+            Instruction::Invalid(op) => panic!("Invalid Instruction {op:#04x}"),
+        }
+        extra
+    }
+
+    fn run_stack<B: BusDevice + ?Sized>(
+        &mut self,
+        stack_instruction: StackInstruction,
+        bus: &mut B,
+    ) {
+        match stack_instruction {
+            StackInstruction::Brk(int) => {
+                match int {
+                    // Reset goes through the motions with reads.
+                    Interrupt::Rst => self.s = self.s.wrapping_sub(3),
+                    Interrupt::Irq | Interrupt::Nmi | Interrupt::Brk => {
+                        let mut p = u8::from(self.p);
+                        if let Interrupt::Brk = int {
+                            self.pc = self.pc.wrapping_add(1);
+                            p |= 1 << 4;
+                        }
+                        self.push((self.pc >> 8) as u8, bus);
+                        self.push(self.pc as u8, bus);
+                        self.push(p, bus);
+                    }
+                }
+                self.p.i = true;
+                if self.variant == Variant::Wdc65C02 {
+                    self.p.d = false;
+                }
+                let vector = match int {
+                    Interrupt::Brk | Interrupt::Irq => 0xFFFE,
+                    Interrupt::Nmi => 0xFFFA,
+                    Interrupt::Rst => 0xFFFC,
+                };
+                self.pc = self.read_word(vector, vector + 1, bus);
+                match int {
+                    Interrupt::Rst => self.rst = false,
+                    Interrupt::Nmi => self.nmi = false,
+                    _ => {}
+                }
+            }
+            StackInstruction::Rti => {
+                self.p = self.pull(bus).into();
+                let low = self.pull(bus);
+                self.pc = (self.pull(bus) as u16) << 8 | low as u16;
+            }
+            StackInstruction::Rts => {
+                let low = self.pull(bus);
+                let pc = (self.pull(bus) as u16) << 8 | low as u16;
+                self.pc = pc.wrapping_add(1);
+            }
+            StackInstruction::Pha => {
+                self.push(self.a, bus);
+            }
+            StackInstruction::Phx => {
+                self.push(self.x, bus);
+            }
+            StackInstruction::Phy => {
+                self.push(self.y, bus);
+            }
+            StackInstruction::Php => {
+                self.push(u8::from(self.p) | 1 << 4, bus);
+            }
+            StackInstruction::Pla | StackInstruction::Plx | StackInstruction::Ply => {
+                let data = self.pull(bus);
+                match stack_instruction {
+                    StackInstruction::Plx => self.x = data,
+                    StackInstruction::Ply => self.y = data,
+                    _ => self.a = data,
+                }
+                self.p.set_n(data);
+                self.p.set_z(data);
+            }
+            StackInstruction::Plp => {
+                self.p = self.pull(bus).into();
+            }
+            StackInstruction::Jsr => {
+                // The high byte is read after the pushes, which can
+                // overwrite it.
+                let low = self.next_byte(bus);
+                self.push((self.pc >> 8) as u8, bus);
+                self.push(self.pc as u8, bus);
+                self.pc = (bus.read(self.pc) as u16) << 8 | low as u16;
+            }
+        }
+    }
+
+    fn next_byte<B: BusDevice + ?Sized>(&mut self, bus: &mut B) -> u8 {
+        let data = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        data
+    }
+
+    fn next_word<B: BusDevice + ?Sized>(&mut self, bus: &mut B) -> u16 {
+        let low = self.next_byte(bus);
+        (self.next_byte(bus) as u16) << 8 | low as u16
+    }
+
+    fn read_word<B: BusDevice + ?Sized>(&mut self, low: u16, high: u16, bus: &mut B) -> u16 {
+        let low = bus.read(low);
+        (bus.read(high) as u16) << 8 | low as u16
+    }
+
+    /// Reads a pointer that wraps within the zero page.
+    fn read_zero_page_word<B: BusDevice + ?Sized>(&mut self, addr: u8, bus: &mut B) -> u16 {
+        self.read_word(addr as u16, addr.wrapping_add(1) as u16, bus)
+    }
+
+    fn push<B: BusDevice + ?Sized>(&mut self, data: u8, bus: &mut B) {
+        bus.write(0x100 | self.s as u16, data);
+        self.s = self.s.wrapping_sub(1);
+    }
+
+    fn pull<B: BusDevice + ?Sized>(&mut self, bus: &mut B) -> u8 {
+        self.s = self.s.wrapping_add(1);
+        bus.read(0x100 | self.s as u16)
+    }
+
+    fn read<B: BusDevice + ?Sized>(&mut self, read: ReadInstruction, addr: u16, bus: &mut B) {
+        let m = bus.read(addr);
+        read.execute(self, m);
+    }
+
+    fn write<B: BusDevice + ?Sized>(&mut self, event: BusEvent, bus: &mut B) {
+        if let BusEvent::Write(addr, data) = event {
+            bus.write(addr, data);
+        }
+    }
+
+    fn modify<B: BusDevice + ?Sized>(
+        &mut self,
+        read_modify_write: ReadModifyWriteInstruction,
+        addr: u16,
+        bus: &mut B,
+    ) {
+        let m = bus.read(addr);
+        let data = read_modify_write.execute(self, m);
+        bus.write(addr, data);
+    }
+
+    /// Adds a branch offset to PC, returning the cycles it takes: one,
+    /// and another for crossing a page.
+    fn branch(&mut self, offset: u8) -> u32 {
+        let pc = self.pc.wrapping_add_signed(offset as i8 as i16);
+        let crossed = (pc ^ self.pc) & 0xFF00 != 0;
+        self.pc = pc;
+        1 + crossed as u32
+    }
+}
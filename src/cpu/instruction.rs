@@ -1,4 +1,4 @@
-use super::Cpu;
+use super::{Cpu, Variant};
 
 #[derive(Debug, Clone, Copy)]
 pub(super) enum Instruction {
@@ -15,6 +15,19 @@ pub(super) enum Instruction {
     IdxInd(IdxIndInstruction),
     IndIdx(IndIdxInstruction),
     AbsInd(AbsIndInstruction),
+    /// 65C02 `(zp)`.
+    ZeroPageInd(ZeroPageIndInstruction),
+    /// 65C02 `JMP (abs,X)`.
+    AbsIdxInd(AbsIndInstruction),
+    /// Rockwell `BBR`/`BBS zp,rel`.
+    ZeroPageRel(ZeroPageRelInstruction),
+    Halt(HaltInstruction),
+    /// A 65C02 opcode with no instruction, which skips `bytes` bytes in
+    /// `cycles` cycles.
+    Nop {
+        bytes: u8,
+        cycles: u8,
+    },
     Invalid(u8),
 }
 
@@ -34,6 +47,29 @@ pub(super) enum StackInstruction {
     Pla,
     Plp,
     Jsr,
+    Phx,
+    Phy,
+    Plx,
+    Ply,
+}
+
+impl StackInstruction {
+    pub(super) const fn mnemonic(self) -> &'static str {
+        match self {
+            StackInstruction::Brk(_) => "BRK",
+            StackInstruction::Rti => "RTI",
+            StackInstruction::Rts => "RTS",
+            StackInstruction::Pha => "PHA",
+            StackInstruction::Php => "PHP",
+            StackInstruction::Pla => "PLA",
+            StackInstruction::Plp => "PLP",
+            StackInstruction::Jsr => "JSR",
+            StackInstruction::Phx => "PHX",
+            StackInstruction::Phy => "PHY",
+            StackInstruction::Plx => "PLX",
+            StackInstruction::Ply => "PLY",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -59,9 +95,24 @@ pub(super) enum BranchInstruction {
     Bmi,
     Bvc,
     Bvs,
+    Bra,
 }
 
 impl BranchInstruction {
+    pub(super) const fn mnemonic(self) -> &'static str {
+        match self {
+            BranchInstruction::Bcc => "BCC",
+            BranchInstruction::Bcs => "BCS",
+            BranchInstruction::Bne => "BNE",
+            BranchInstruction::Beq => "BEQ",
+            BranchInstruction::Bpl => "BPL",
+            BranchInstruction::Bmi => "BMI",
+            BranchInstruction::Bvc => "BVC",
+            BranchInstruction::Bvs => "BVS",
+            BranchInstruction::Bra => "BRA",
+        }
+    }
+
     pub(super) fn execute(&self, cpu: &Cpu) -> bool {
         match self {
             BranchInstruction::Bcc => !cpu.p.c,
@@ -72,6 +123,7 @@ impl BranchInstruction {
             BranchInstruction::Bmi => cpu.p.n,
             BranchInstruction::Bvc => !cpu.p.v,
             BranchInstruction::Bvs => cpu.p.v,
+            BranchInstruction::Bra => true,
         }
     }
 }
@@ -99,6 +151,29 @@ pub(super) enum InternalInstruction {
 }
 
 impl InternalInstruction {
+    pub(super) const fn mnemonic(self) -> &'static str {
+        match self {
+            InternalInstruction::Txa => "TXA",
+            InternalInstruction::Txs => "TXS",
+            InternalInstruction::Tax => "TAX",
+            InternalInstruction::Tsx => "TSX",
+            InternalInstruction::Tay => "TAY",
+            InternalInstruction::Tya => "TYA",
+            InternalInstruction::Dex => "DEX",
+            InternalInstruction::Dey => "DEY",
+            InternalInstruction::Clc => "CLC",
+            InternalInstruction::Sec => "SEC",
+            InternalInstruction::Cli => "CLI",
+            InternalInstruction::Sei => "SEI",
+            InternalInstruction::Cld => "CLD",
+            InternalInstruction::Sed => "SED",
+            InternalInstruction::Clv => "CLV",
+            InternalInstruction::Inx => "INX",
+            InternalInstruction::Iny => "INY",
+            InternalInstruction::Nop => "NOP",
+        }
+    }
+
     pub(super) fn execute(&self, cpu: &mut Cpu) {
         match self {
             InternalInstruction::Txa => {
@@ -189,9 +264,58 @@ pub(super) enum ReadInstruction {
     Cpy,
     Cpx,
     Bit,
+    /// 65C02 `BIT #imm`, which only sets Z.
+    BitImm,
+    /// Unofficial NMOS instructions from here on.
+    Nop,
+    Lax,
+    /// `AND` then `LSR A`.
+    Alr,
+    /// `AND` setting C from bit 7.
+    Anc,
+    /// `AND` then `ROR A`, with flags of its own.
+    Arr,
+    /// X = (A & X) - operand, setting flags like `CMP`.
+    Sbx,
+    /// A, X and S all get S & operand.
+    Las,
+    /// Unstable: A = (A | magic) & X & operand.
+    Ane,
+    /// Unstable: A = X = (A | magic) & operand.
+    Lxa,
 }
 
+/// The bits ANE and LXA let through from A; it varies between chips.
+const MAGIC: u8 = 0xEE;
+
 impl ReadInstruction {
+    pub(super) const fn mnemonic(self) -> &'static str {
+        match self {
+            ReadInstruction::Lda => "LDA",
+            ReadInstruction::Ldx => "LDX",
+            ReadInstruction::Ldy => "LDY",
+            ReadInstruction::Eor => "EOR",
+            ReadInstruction::And => "AND",
+            ReadInstruction::Ora => "ORA",
+            ReadInstruction::Adc => "ADC",
+            ReadInstruction::Sbc => "SBC",
+            ReadInstruction::Cmp => "CMP",
+            ReadInstruction::Cpy => "CPY",
+            ReadInstruction::Cpx => "CPX",
+            ReadInstruction::Bit => "BIT",
+            ReadInstruction::BitImm => "BIT",
+            ReadInstruction::Nop => "NOP",
+            ReadInstruction::Lax => "LAX",
+            ReadInstruction::Alr => "ALR",
+            ReadInstruction::Anc => "ANC",
+            ReadInstruction::Arr => "ARR",
+            ReadInstruction::Sbx => "SBX",
+            ReadInstruction::Las => "LAS",
+            ReadInstruction::Ane => "ANE",
+            ReadInstruction::Lxa => "LXA",
+        }
+    }
+
     pub(super) fn execute(&self, cpu: &mut Cpu, m: u8) {
         match self {
             ReadInstruction::Lda => {
@@ -227,7 +351,7 @@ impl ReadInstruction {
             ReadInstruction::Adc => {
                 let c = if cpu.p.c { 1u8 } else { 0u8 };
                 let a = cpu.a;
-                if cpu.p.d {
+                if cpu.p.d && cpu.variant.has_decimal_mode() {
                     let al = (a & 0x0F) as u16 + (m & 0x0F) as u16 + c as u16;
                     let al = al + if al > 9 { 6 } else { 0 };
                     let ah = (a >> 4) as u16 + (m >> 4) as u16 + (if al > 0x0F { 1 } else { 0 });
@@ -237,6 +361,12 @@ impl ReadInstruction {
                     let ah = ah + if ah > 9 { 6 } else { 0 };
                     cpu.p.c = ah > 0x0F;
                     cpu.a = (ah << 4) as u8 | (al & 0x0F) as u8;
+                    if cpu.variant == Variant::Wdc65C02 {
+                        // An extra cycle makes N and Z valid for the BCD result.
+                        cpu.p.set_n(cpu.a);
+                        cpu.p.set_z(cpu.a);
+                        cpu.stall = true;
+                    }
                 } else {
                     cpu.a = a.wrapping_add(m).wrapping_add(c);
                     cpu.p.set_c(a as u16 + m as u16 + c as u16);
@@ -248,7 +378,7 @@ impl ReadInstruction {
             ReadInstruction::Sbc => {
                 let c = if cpu.p.c { 1u8 } else { 0u8 };
                 let a = cpu.a;
-                if cpu.p.d {
+                if cpu.p.d && cpu.variant.has_decimal_mode() {
                     let al = ((a & 0x0F) as u16)
                         .wrapping_sub((m & 0x0F) as u16)
                         .wrapping_sub((1 - c) as u16);
@@ -260,6 +390,20 @@ impl ReadInstruction {
                     cpu.p.set_c(a as u16 + !m as u16 + c as u16);
                     cpu.p.set_v(a, !m, c);
                     cpu.a = (ah << 4) as u8 | (al & 0x0F) as u8;
+                    if cpu.variant == Variant::Wdc65C02 {
+                        // Adjusts the binary difference, which differs from
+                        // the NMOS result for invalid BCD.
+                        let al = (a & 0x0F) as i16 - (m & 0x0F) as i16 + c as i16 - 1;
+                        let mut diff = a as i16 - m as i16 + c as i16 - 1;
+                        if diff < 0 {
+                            diff -= 0x60;
+                        }
+                        if al < 0 {
+                            diff -= 0x06;
+                        }
+                        cpu.a = diff as u8;
+                        cpu.stall = true;
+                    }
                     cpu.p.set_n(cpu.a);
                     cpu.p.set_z(cpu.a);
                 } else {
@@ -303,6 +447,65 @@ impl ReadInstruction {
                 cpu.p.n = m & (1 << 7) != 0;
                 cpu.p.v = m & (1 << 6) != 0;
             }
+            ReadInstruction::BitImm => {
+                cpu.p.set_z(cpu.a & m);
+            }
+            ReadInstruction::Nop => {}
+            ReadInstruction::Lax => {
+                cpu.a = m;
+                cpu.x = m;
+                cpu.p.set_n(m);
+                cpu.p.set_z(m);
+            }
+            ReadInstruction::Alr => {
+                cpu.a &= m;
+                cpu.a = ReadModifyWriteInstruction::Lsr.execute(cpu, cpu.a);
+            }
+            ReadInstruction::Anc => {
+                ReadInstruction::And.execute(cpu, m);
+                cpu.p.c = cpu.p.n;
+            }
+            ReadInstruction::Arr => {
+                let t = cpu.a & m;
+                let carry = if cpu.p.c { 0x80 } else { 0 };
+                cpu.a = t >> 1 | carry;
+                cpu.p.set_z(cpu.a);
+                if cpu.p.d && cpu.variant.has_decimal_mode() {
+                    cpu.p.n = carry != 0;
+                    cpu.p.v = (t ^ cpu.a) & 0x40 != 0;
+                    if (t & 0x0F) + (t & 0x01) > 5 {
+                        cpu.a = (cpu.a & 0xF0) | (cpu.a.wrapping_add(6) & 0x0F);
+                    }
+                    cpu.p.c = (t & 0xF0) as u16 + (t & 0x10) as u16 > 0x50;
+                    if cpu.p.c {
+                        cpu.a = cpu.a.wrapping_add(0x60);
+                    }
+                } else {
+                    cpu.p.set_n(cpu.a);
+                    cpu.p.c = cpu.a & 0x40 != 0;
+                    cpu.p.v = (cpu.a ^ cpu.a << 1) & 0x40 != 0;
+                }
+            }
+            ReadInstruction::Sbx => {
+                let ax = cpu.a & cpu.x;
+                cpu.x = ax.wrapping_sub(m);
+                cpu.p.c = ax >= m;
+                cpu.p.set_n(cpu.x);
+                cpu.p.set_z(cpu.x);
+            }
+            ReadInstruction::Las => {
+                cpu.s &= m;
+                cpu.a = cpu.s;
+                cpu.x = cpu.s;
+                cpu.p.set_n(cpu.s);
+                cpu.p.set_z(cpu.s);
+            }
+            ReadInstruction::Ane => {
+                ReadInstruction::Lda.execute(cpu, (cpu.a | MAGIC) & cpu.x & m);
+            }
+            ReadInstruction::Lxa => {
+                ReadInstruction::Lax.execute(cpu, (cpu.a | MAGIC) & m);
+            }
         };
     }
 }
@@ -312,16 +515,58 @@ pub(super) enum WriteInstruction {
     Sta,
     Stx,
     Sty,
+    Stz,
+    /// Unofficial: stores A & X.
+    Sax,
+    /// Unstable unofficial stores, see [`WriteInstruction::is_unstable`].
+    Sha,
+    Shx,
+    Shy,
+    /// Sets S to A & X, then stores like `SHA`.
+    Tas,
 }
 
 impl WriteInstruction {
+    pub(super) const fn mnemonic(self) -> &'static str {
+        match self {
+            WriteInstruction::Sta => "STA",
+            WriteInstruction::Stx => "STX",
+            WriteInstruction::Sty => "STY",
+            WriteInstruction::Stz => "STZ",
+            WriteInstruction::Sax => "SAX",
+            WriteInstruction::Sha => "SHA",
+            WriteInstruction::Shx => "SHX",
+            WriteInstruction::Shy => "SHY",
+            WriteInstruction::Tas => "TAS",
+        }
+    }
+
     pub(super) fn execute(&self, cpu: &mut Cpu) -> u8 {
         match self {
             WriteInstruction::Sta => cpu.a,
-            WriteInstruction::Stx => cpu.x,
-            WriteInstruction::Sty => cpu.y,
+            WriteInstruction::Stx | WriteInstruction::Shx => cpu.x,
+            WriteInstruction::Sty | WriteInstruction::Shy => cpu.y,
+            WriteInstruction::Stz => 0,
+            WriteInstruction::Sax | WriteInstruction::Sha => cpu.a & cpu.x,
+            WriteInstruction::Tas => {
+                cpu.s = cpu.a & cpu.x;
+                cpu.s
+            }
         }
     }
+
+    /// The `SH*` stores and `TAS` AND the value with the high byte of the
+    /// base address plus one, and when indexing crosses a page the value
+    /// also replaces the high byte of the address.
+    pub(super) fn is_unstable(&self) -> bool {
+        matches!(
+            self,
+            WriteInstruction::Sha
+                | WriteInstruction::Shx
+                | WriteInstruction::Shy
+                | WriteInstruction::Tas
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -332,9 +577,52 @@ pub(super) enum ReadModifyWriteInstruction {
     Ror,
     Inc,
     Dec,
+    Tsb,
+    Trb,
+    /// Rockwell `RMBn`, clearing bit n.
+    Rmb(u8),
+    /// Rockwell `SMBn`, setting bit n.
+    Smb(u8),
+    /// Unofficial: `ASL` then `ORA`.
+    Slo,
+    /// `ROL` then `AND`.
+    Rla,
+    /// `LSR` then `EOR`.
+    Sre,
+    /// `ROR` then `ADC`.
+    Rra,
+    /// `DEC` then `CMP`.
+    Dcp,
+    /// `INC` then `SBC`.
+    Isc,
 }
 
 impl ReadModifyWriteInstruction {
+    pub(super) const fn mnemonic(self) -> &'static str {
+        match self {
+            ReadModifyWriteInstruction::Asl => "ASL",
+            ReadModifyWriteInstruction::Lsr => "LSR",
+            ReadModifyWriteInstruction::Rol => "ROL",
+            ReadModifyWriteInstruction::Ror => "ROR",
+            ReadModifyWriteInstruction::Inc => "INC",
+            ReadModifyWriteInstruction::Dec => "DEC",
+            ReadModifyWriteInstruction::Tsb => "TSB",
+            ReadModifyWriteInstruction::Trb => "TRB",
+            ReadModifyWriteInstruction::Rmb(bit) => [
+                "RMB0", "RMB1", "RMB2", "RMB3", "RMB4", "RMB5", "RMB6", "RMB7",
+            ][bit as usize],
+            ReadModifyWriteInstruction::Smb(bit) => [
+                "SMB0", "SMB1", "SMB2", "SMB3", "SMB4", "SMB5", "SMB6", "SMB7",
+            ][bit as usize],
+            ReadModifyWriteInstruction::Slo => "SLO",
+            ReadModifyWriteInstruction::Rla => "RLA",
+            ReadModifyWriteInstruction::Sre => "SRE",
+            ReadModifyWriteInstruction::Rra => "RRA",
+            ReadModifyWriteInstruction::Dcp => "DCP",
+            ReadModifyWriteInstruction::Isc => "ISC",
+        }
+    }
+
     pub(super) fn execute(&self, cpu: &mut Cpu, m: u8) -> u8 {
         match self {
             ReadModifyWriteInstruction::Asl => {
@@ -385,6 +673,46 @@ impl ReadModifyWriteInstruction {
                 cpu.p.set_z(m);
                 m
             }
+            ReadModifyWriteInstruction::Tsb => {
+                cpu.p.set_z(cpu.a & m);
+                m | cpu.a
+            }
+            ReadModifyWriteInstruction::Trb => {
+                cpu.p.set_z(cpu.a & m);
+                m & !cpu.a
+            }
+            ReadModifyWriteInstruction::Rmb(bit) => m & !(1 << bit),
+            ReadModifyWriteInstruction::Smb(bit) => m | 1 << bit,
+            ReadModifyWriteInstruction::Slo => {
+                let m = ReadModifyWriteInstruction::Asl.execute(cpu, m);
+                ReadInstruction::Ora.execute(cpu, m);
+                m
+            }
+            ReadModifyWriteInstruction::Rla => {
+                let m = ReadModifyWriteInstruction::Rol.execute(cpu, m);
+                ReadInstruction::And.execute(cpu, m);
+                m
+            }
+            ReadModifyWriteInstruction::Sre => {
+                let m = ReadModifyWriteInstruction::Lsr.execute(cpu, m);
+                ReadInstruction::Eor.execute(cpu, m);
+                m
+            }
+            ReadModifyWriteInstruction::Rra => {
+                let m = ReadModifyWriteInstruction::Ror.execute(cpu, m);
+                ReadInstruction::Adc.execute(cpu, m);
+                m
+            }
+            ReadModifyWriteInstruction::Dcp => {
+                let m = ReadModifyWriteInstruction::Dec.execute(cpu, m);
+                ReadInstruction::Cmp.execute(cpu, m);
+                m
+            }
+            ReadModifyWriteInstruction::Isc => {
+                let m = ReadModifyWriteInstruction::Inc.execute(cpu, m);
+                ReadInstruction::Sbc.execute(cpu, m);
+                m
+            }
         }
     }
 }
@@ -437,12 +765,14 @@ pub(super) enum RelInstruction {
 #[derive(Debug, Clone, Copy)]
 pub(super) enum IdxIndInstruction {
     Read(ReadInstruction),
+    ReadModifyWrite(ReadModifyWriteInstruction),
     Write(WriteInstruction),
 }
 
 #[derive(Debug, Clone, Copy)]
 pub(super) enum IndIdxInstruction {
     Read(ReadInstruction),
+    ReadModifyWrite(ReadModifyWriteInstruction),
     Write(WriteInstruction),
 }
 
@@ -451,253 +781,394 @@ pub(super) enum AbsIndInstruction {
     Jump(JumpInstruction),
 }
 
-impl From<u8> for Instruction {
-    fn from(opcode: u8) -> Instruction {
-        match opcode {
-            0x00 => Instruction::Stack(StackInstruction::Brk(Interrupt::Brk)),
-            0x01 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Ora)),
-            0x05 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Ora)),
-            0x06 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Asl,
-            )),
-            0x08 => Instruction::Stack(StackInstruction::Php),
-            0x09 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Ora)),
-            0x0A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Asl,
-            )),
-            0x0D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Ora)),
-            0x0E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Asl,
-            )),
-            0x10 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bpl)),
-            0x11 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Ora)),
-            0x15 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Ora)),
-            0x16 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Asl,
-            )),
-            0x18 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Clc))
-            }
-            0x19 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Ora)),
-            0x1D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Ora)),
-            0x1E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Asl,
-            )),
-            0x20 => Instruction::Stack(StackInstruction::Jsr),
-            0x21 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::And)),
-            0x24 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Bit)),
-            0x25 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::And)),
-            0x26 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Rol,
-            )),
-            0x28 => Instruction::Stack(StackInstruction::Plp),
-            0x29 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::And)),
-            0x2A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Rol,
-            )),
-            0x2C => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Bit)),
-            0x2D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::And)),
-            0x2E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Rol,
-            )),
-            0x30 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bmi)),
-            0x31 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::And)),
-            0x35 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::And)),
-            0x36 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Rol,
-            )),
-            0x38 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Sec))
-            }
-            0x39 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::And)),
-            0x3D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::And)),
-            0x3E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Rol,
-            )),
-            0x40 => Instruction::Stack(StackInstruction::Rti),
-            0x41 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Eor)),
-            0x45 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Eor)),
-            0x46 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Lsr,
-            )),
-            0x48 => Instruction::Stack(StackInstruction::Pha),
-            0x49 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Eor)),
-            0x4A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Lsr,
-            )),
-            0x4C => Instruction::Abs(AbsInstruction::Jump(JumpInstruction::Jmp)),
-            0x4D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Eor)),
-            0x4E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Lsr,
-            )),
-            0x50 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bvc)),
-            0x51 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Eor)),
-            0x55 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Eor)),
-            0x56 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Lsr,
-            )),
-            0x58 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Cli))
-            }
-            0x59 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Eor)),
-            0x5D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Eor)),
-            0x5E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Lsr,
-            )),
-            0x60 => Instruction::Stack(StackInstruction::Rts),
-            0x61 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Adc)),
-            0x65 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Adc)),
-            0x66 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Ror,
-            )),
-            0x68 => Instruction::Stack(StackInstruction::Pla),
-            0x69 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Adc)),
-            0x6A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Ror,
-            )),
-            0x6C => Instruction::AbsInd(AbsIndInstruction::Jump(JumpInstruction::Jmp)),
-            0x6D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Adc)),
-            0x6E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Ror,
-            )),
-            0x70 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bvs)),
-            0x71 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Adc)),
-            0x75 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Adc)),
-            0x76 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Ror,
-            )),
-            0x78 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Sei))
-            }
-            0x79 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Adc)),
-            0x7D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Adc)),
-            0x7E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Ror,
-            )),
-            0x81 => Instruction::IdxInd(IdxIndInstruction::Write(WriteInstruction::Sta)),
-            0x84 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Sty)),
-            0x85 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Sta)),
-            0x86 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Stx)),
-            0x88 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Dey))
-            }
-            0x8A => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Txa))
-            }
-            0x8C => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Sty)),
-            0x8D => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Sta)),
-            0x8E => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Stx)),
-            0x90 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bcc)),
-            0x91 => Instruction::IndIdx(IndIdxInstruction::Write(WriteInstruction::Sta)),
-            0x94 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Write(WriteInstruction::Sty)),
-            0x95 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Write(WriteInstruction::Sta)),
-            0x96 => Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Write(WriteInstruction::Stx)),
-            0x98 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tya))
-            }
-            0x99 => Instruction::AbsIdxY(AbsIdxInstruction::Write(WriteInstruction::Sta)),
-            0x9A => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Txs))
-            }
-            0x9D => Instruction::AbsIdxX(AbsIdxInstruction::Write(WriteInstruction::Sta)),
-            0xA0 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Ldy)),
-            0xA1 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Lda)),
-            0xA2 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Ldx)),
-            0xA4 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Ldy)),
-            0xA5 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Lda)),
-            0xA6 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Ldx)),
-            0xA8 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tay))
-            }
-            0xA9 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Lda)),
-            0xAA => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tax))
-            }
-            0xAC => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Ldy)),
-            0xAD => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Lda)),
-            0xAE => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Ldx)),
-            0xB0 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bcs)),
-            0xB1 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Lda)),
-            0xB4 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Ldy)),
-            0xB5 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Lda)),
-            0xB6 => Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Read(ReadInstruction::Ldx)),
-            0xB8 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Clv))
-            }
-            0xB9 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Lda)),
-            0xBA => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tsx))
-            }
-            0xBC => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Ldy)),
-            0xBD => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Lda)),
-            0xBE => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Ldx)),
-            0xC0 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Cpy)),
-            0xC1 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Cmp)),
-            0xC4 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Cpy)),
-            0xC5 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Cmp)),
-            0xC6 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Dec,
-            )),
-            0xC8 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Iny))
-            }
-            0xC9 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Cmp)),
-            0xCA => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Dex))
-            }
-            0xCC => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Cpy)),
-            0xCD => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Cmp)),
-            0xCE => Instruction::Abs(AbsInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Dec,
-            )),
-            0xD0 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bne)),
-            0xD1 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Cmp)),
-            0xD5 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Cmp)),
-            0xD6 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Dec,
-            )),
-            0xD8 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Cld))
-            }
-            0xD9 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Cmp)),
-            0xDD => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Cmp)),
-            0xDE => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Dec,
-            )),
-            0xE0 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Cpx)),
-            0xE1 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Sbc)),
-            0xE4 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Cpx)),
-            0xE5 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Sbc)),
-            0xE6 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Inc,
-            )),
-            0xE8 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Inx))
-            }
-            0xE9 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Sbc)),
-            0xEA => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Nop))
-            }
-            0xEC => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Cpx)),
-            0xED => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Sbc)),
-            0xEE => Instruction::Abs(AbsInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Inc,
-            )),
-            0xF0 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Beq)),
-            0xF1 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Sbc)),
-            0xF5 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Sbc)),
-            0xF6 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Inc,
-            )),
-            0xF8 => {
-                Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Sed))
-            }
-            0xF9 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Sbc)),
-            0xFD => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Sbc)),
-            0xFE => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
-                ReadModifyWriteInstruction::Inc,
-            )),
-            op => Instruction::Invalid(op),
+#[derive(Debug, Clone, Copy)]
+pub(super) enum ZeroPageIndInstruction {
+    Read(ReadInstruction),
+    Write(WriteInstruction),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum ZeroPageRelInstruction {
+    /// Branch if bit n of the zero page byte is clear.
+    Bbr(u8),
+    /// Branch if bit n is set.
+    Bbs(u8),
+}
+
+impl ZeroPageRelInstruction {
+    pub(super) fn execute(&self, m: u8) -> bool {
+        match *self {
+            ZeroPageRelInstruction::Bbr(bit) => m & 1 << bit == 0,
+            ZeroPageRelInstruction::Bbs(bit) => m & 1 << bit != 0,
+        }
+    }
+}
+
+/// 65C02 instructions that stop the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum HaltInstruction {
+    /// Waits for an interrupt. An IRQ wakes the CPU even when I is set,
+    /// carrying on with the next instruction instead of the handler.
+    Wai,
+    /// Stops until reset.
+    Stp,
+}
+
+impl Instruction {
+    /// Decodes `opcode` as `variant` runs it, from the tables in
+    /// [`super::opcodes`].
+    pub(super) fn decode(opcode: u8, variant: Variant) -> Instruction {
+        super::opcodes::info(variant, opcode).instruction
+    }
+}
+
+/// NMOS parts decode every opcode to something. Those left out of the
+/// official set run combinations of the official instructions' parts.
+pub(super) const fn decode_nmos(opcode: u8) -> Instruction {
+    use ReadInstruction as R;
+    use ReadModifyWriteInstruction as Rmw;
+    use WriteInstruction as W;
+    match opcode {
+        0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => {
+            Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Nop))
+        }
+        0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => Instruction::Imm(ImmInstruction::Read(R::Nop)),
+        0x04 | 0x44 | 0x64 => Instruction::ZeroPage(ZeroPageInstruction::Read(R::Nop)),
+        0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => {
+            Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(R::Nop))
+        }
+        0x0C => Instruction::Abs(AbsInstruction::Read(R::Nop)),
+        0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => {
+            Instruction::AbsIdxX(AbsIdxInstruction::Read(R::Nop))
         }
+        // JAM locks the CPU up until reset, as STP does.
+        op if op & 0x9F == 0x02 || op & 0x1F == 0x12 => Instruction::Halt(HaltInstruction::Stp),
+        0x0B | 0x2B => Instruction::Imm(ImmInstruction::Read(R::Anc)),
+        0x4B => Instruction::Imm(ImmInstruction::Read(R::Alr)),
+        0x6B => Instruction::Imm(ImmInstruction::Read(R::Arr)),
+        0x8B => Instruction::Imm(ImmInstruction::Read(R::Ane)),
+        0xAB => Instruction::Imm(ImmInstruction::Read(R::Lxa)),
+        0xCB => Instruction::Imm(ImmInstruction::Read(R::Sbx)),
+        0xEB => Instruction::Imm(ImmInstruction::Read(R::Sbc)),
+        0x83 => Instruction::IdxInd(IdxIndInstruction::Write(W::Sax)),
+        0x87 => Instruction::ZeroPage(ZeroPageInstruction::Write(W::Sax)),
+        0x8F => Instruction::Abs(AbsInstruction::Write(W::Sax)),
+        0x97 => Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Write(W::Sax)),
+        0x93 => Instruction::IndIdx(IndIdxInstruction::Write(W::Sha)),
+        0x9F => Instruction::AbsIdxY(AbsIdxInstruction::Write(W::Sha)),
+        0x9B => Instruction::AbsIdxY(AbsIdxInstruction::Write(W::Tas)),
+        0x9C => Instruction::AbsIdxX(AbsIdxInstruction::Write(W::Shy)),
+        0x9E => Instruction::AbsIdxY(AbsIdxInstruction::Write(W::Shx)),
+        0xA3 => Instruction::IdxInd(IdxIndInstruction::Read(R::Lax)),
+        0xA7 => Instruction::ZeroPage(ZeroPageInstruction::Read(R::Lax)),
+        0xAF => Instruction::Abs(AbsInstruction::Read(R::Lax)),
+        0xB3 => Instruction::IndIdx(IndIdxInstruction::Read(R::Lax)),
+        0xB7 => Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Read(R::Lax)),
+        0xBF => Instruction::AbsIdxY(AbsIdxInstruction::Read(R::Lax)),
+        0xBB => Instruction::AbsIdxY(AbsIdxInstruction::Read(R::Las)),
+        // The read-modify-write column: opcodes $x3, $x7 and $xF.
+        op if op & 0x03 == 0x03 => {
+            let rmw = match op >> 5 {
+                0 => Rmw::Slo,
+                1 => Rmw::Rla,
+                2 => Rmw::Sre,
+                3 => Rmw::Rra,
+                6 => Rmw::Dcp,
+                7 => Rmw::Isc,
+                _ => return Instruction::Invalid(op),
+            };
+            match op & 0x1C {
+                0x00 => Instruction::IdxInd(IdxIndInstruction::ReadModifyWrite(rmw)),
+                0x04 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(rmw)),
+                0x0C => Instruction::Abs(AbsInstruction::ReadModifyWrite(rmw)),
+                0x10 => Instruction::IndIdx(IndIdxInstruction::ReadModifyWrite(rmw)),
+                0x14 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(rmw)),
+                0x18 => Instruction::AbsIdxY(AbsIdxInstruction::ReadModifyWrite(rmw)),
+                0x1C => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(rmw)),
+                _ => Instruction::Invalid(op),
+            }
+        }
+        op => decode_official(op),
+    }
+}
+
+/// The 65C02 adds to the NMOS instruction set and turns the rest of the
+/// opcodes into NOPs of various lengths.
+pub(super) const fn decode_65c02(opcode: u8) -> Instruction {
+    use ReadModifyWriteInstruction::{Rmb, Smb, Trb, Tsb};
+    const fn zp_ind(read: ReadInstruction) -> Instruction {
+        Instruction::ZeroPageInd(ZeroPageIndInstruction::Read(read))
+    }
+    let bit = (opcode >> 4) & 0x07;
+    match opcode {
+        0x04 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(Tsb)),
+        0x0C => Instruction::Abs(AbsInstruction::ReadModifyWrite(Tsb)),
+        0x14 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(Trb)),
+        0x1C => Instruction::Abs(AbsInstruction::ReadModifyWrite(Trb)),
+        0x12 => zp_ind(ReadInstruction::Ora),
+        0x32 => zp_ind(ReadInstruction::And),
+        0x52 => zp_ind(ReadInstruction::Eor),
+        0x72 => zp_ind(ReadInstruction::Adc),
+        0x92 => Instruction::ZeroPageInd(ZeroPageIndInstruction::Write(WriteInstruction::Sta)),
+        0xB2 => zp_ind(ReadInstruction::Lda),
+        0xD2 => zp_ind(ReadInstruction::Cmp),
+        0xF2 => zp_ind(ReadInstruction::Sbc),
+        0x1A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Inc,
+        )),
+        0x3A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Dec,
+        )),
+        0x34 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Bit)),
+        0x3C => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Bit)),
+        0x89 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::BitImm)),
+        0x5A => Instruction::Stack(StackInstruction::Phy),
+        0x7A => Instruction::Stack(StackInstruction::Ply),
+        0xDA => Instruction::Stack(StackInstruction::Phx),
+        0xFA => Instruction::Stack(StackInstruction::Plx),
+        0x64 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Stz)),
+        0x74 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Write(WriteInstruction::Stz)),
+        0x9C => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Stz)),
+        0x9E => Instruction::AbsIdxX(AbsIdxInstruction::Write(WriteInstruction::Stz)),
+        0x7C => Instruction::AbsIdxInd(AbsIndInstruction::Jump(JumpInstruction::Jmp)),
+        0x80 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bra)),
+        0xCB => Instruction::Halt(HaltInstruction::Wai),
+        0xDB => Instruction::Halt(HaltInstruction::Stp),
+        op if op & 0x8F == 0x07 => {
+            Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(Rmb(bit)))
+        }
+        op if op & 0x8F == 0x87 => {
+            Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(Smb(bit)))
+        }
+        op if op & 0x8F == 0x0F => Instruction::ZeroPageRel(ZeroPageRelInstruction::Bbr(bit)),
+        op if op & 0x8F == 0x8F => Instruction::ZeroPageRel(ZeroPageRelInstruction::Bbs(bit)),
+        op => match decode_official(op) {
+            Instruction::Invalid(op) => {
+                let (bytes, cycles) = match op {
+                    0x44 => (2, 3),
+                    0x54 | 0xD4 | 0xF4 => (2, 4),
+                    0x5C => (3, 8),
+                    0xDC | 0xFC => (3, 4),
+                    op if op & 0x0F == 0x02 => (2, 2),
+                    _ => (1, 1),
+                };
+                Instruction::Nop { bytes, cycles }
+            }
+            instruction => instruction,
+        },
+    }
+}
+
+/// The official NMOS instruction set.
+pub(super) const fn decode_official(opcode: u8) -> Instruction {
+    match opcode {
+        0x00 => Instruction::Stack(StackInstruction::Brk(Interrupt::Brk)),
+        0x01 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Ora)),
+        0x05 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Ora)),
+        0x06 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Asl,
+        )),
+        0x08 => Instruction::Stack(StackInstruction::Php),
+        0x09 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Ora)),
+        0x0A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Asl,
+        )),
+        0x0D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Ora)),
+        0x0E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Asl,
+        )),
+        0x10 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bpl)),
+        0x11 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Ora)),
+        0x15 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Ora)),
+        0x16 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Asl,
+        )),
+        0x18 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Clc)),
+        0x19 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Ora)),
+        0x1D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Ora)),
+        0x1E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Asl,
+        )),
+        0x20 => Instruction::Stack(StackInstruction::Jsr),
+        0x21 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::And)),
+        0x24 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Bit)),
+        0x25 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::And)),
+        0x26 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Rol,
+        )),
+        0x28 => Instruction::Stack(StackInstruction::Plp),
+        0x29 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::And)),
+        0x2A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Rol,
+        )),
+        0x2C => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Bit)),
+        0x2D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::And)),
+        0x2E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Rol,
+        )),
+        0x30 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bmi)),
+        0x31 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::And)),
+        0x35 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::And)),
+        0x36 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Rol,
+        )),
+        0x38 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Sec)),
+        0x39 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::And)),
+        0x3D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::And)),
+        0x3E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Rol,
+        )),
+        0x40 => Instruction::Stack(StackInstruction::Rti),
+        0x41 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Eor)),
+        0x45 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Eor)),
+        0x46 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Lsr,
+        )),
+        0x48 => Instruction::Stack(StackInstruction::Pha),
+        0x49 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Eor)),
+        0x4A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Lsr,
+        )),
+        0x4C => Instruction::Abs(AbsInstruction::Jump(JumpInstruction::Jmp)),
+        0x4D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Eor)),
+        0x4E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Lsr,
+        )),
+        0x50 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bvc)),
+        0x51 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Eor)),
+        0x55 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Eor)),
+        0x56 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Lsr,
+        )),
+        0x58 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Cli)),
+        0x59 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Eor)),
+        0x5D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Eor)),
+        0x5E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Lsr,
+        )),
+        0x60 => Instruction::Stack(StackInstruction::Rts),
+        0x61 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Adc)),
+        0x65 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Adc)),
+        0x66 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Ror,
+        )),
+        0x68 => Instruction::Stack(StackInstruction::Pla),
+        0x69 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Adc)),
+        0x6A => Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Ror,
+        )),
+        0x6C => Instruction::AbsInd(AbsIndInstruction::Jump(JumpInstruction::Jmp)),
+        0x6D => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Adc)),
+        0x6E => Instruction::Abs(AbsInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Ror,
+        )),
+        0x70 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bvs)),
+        0x71 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Adc)),
+        0x75 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Adc)),
+        0x76 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Ror,
+        )),
+        0x78 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Sei)),
+        0x79 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Adc)),
+        0x7D => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Adc)),
+        0x7E => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Ror,
+        )),
+        0x81 => Instruction::IdxInd(IdxIndInstruction::Write(WriteInstruction::Sta)),
+        0x84 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Sty)),
+        0x85 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Sta)),
+        0x86 => Instruction::ZeroPage(ZeroPageInstruction::Write(WriteInstruction::Stx)),
+        0x88 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Dey)),
+        0x8A => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Txa)),
+        0x8C => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Sty)),
+        0x8D => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Sta)),
+        0x8E => Instruction::Abs(AbsInstruction::Write(WriteInstruction::Stx)),
+        0x90 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bcc)),
+        0x91 => Instruction::IndIdx(IndIdxInstruction::Write(WriteInstruction::Sta)),
+        0x94 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Write(WriteInstruction::Sty)),
+        0x95 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Write(WriteInstruction::Sta)),
+        0x96 => Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Write(WriteInstruction::Stx)),
+        0x98 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tya)),
+        0x99 => Instruction::AbsIdxY(AbsIdxInstruction::Write(WriteInstruction::Sta)),
+        0x9A => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Txs)),
+        0x9D => Instruction::AbsIdxX(AbsIdxInstruction::Write(WriteInstruction::Sta)),
+        0xA0 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Ldy)),
+        0xA1 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Lda)),
+        0xA2 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Ldx)),
+        0xA4 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Ldy)),
+        0xA5 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Lda)),
+        0xA6 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Ldx)),
+        0xA8 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tay)),
+        0xA9 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Lda)),
+        0xAA => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tax)),
+        0xAC => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Ldy)),
+        0xAD => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Lda)),
+        0xAE => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Ldx)),
+        0xB0 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bcs)),
+        0xB1 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Lda)),
+        0xB4 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Ldy)),
+        0xB5 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Lda)),
+        0xB6 => Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Read(ReadInstruction::Ldx)),
+        0xB8 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Clv)),
+        0xB9 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Lda)),
+        0xBA => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Tsx)),
+        0xBC => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Ldy)),
+        0xBD => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Lda)),
+        0xBE => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Ldx)),
+        0xC0 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Cpy)),
+        0xC1 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Cmp)),
+        0xC4 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Cpy)),
+        0xC5 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Cmp)),
+        0xC6 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Dec,
+        )),
+        0xC8 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Iny)),
+        0xC9 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Cmp)),
+        0xCA => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Dex)),
+        0xCC => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Cpy)),
+        0xCD => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Cmp)),
+        0xCE => Instruction::Abs(AbsInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Dec,
+        )),
+        0xD0 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Bne)),
+        0xD1 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Cmp)),
+        0xD5 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Cmp)),
+        0xD6 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Dec,
+        )),
+        0xD8 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Cld)),
+        0xD9 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Cmp)),
+        0xDD => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Cmp)),
+        0xDE => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Dec,
+        )),
+        0xE0 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Cpx)),
+        0xE1 => Instruction::IdxInd(IdxIndInstruction::Read(ReadInstruction::Sbc)),
+        0xE4 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Cpx)),
+        0xE5 => Instruction::ZeroPage(ZeroPageInstruction::Read(ReadInstruction::Sbc)),
+        0xE6 => Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Inc,
+        )),
+        0xE8 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Inx)),
+        0xE9 => Instruction::Imm(ImmInstruction::Read(ReadInstruction::Sbc)),
+        0xEA => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Nop)),
+        0xEC => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Cpx)),
+        0xED => Instruction::Abs(AbsInstruction::Read(ReadInstruction::Sbc)),
+        0xEE => Instruction::Abs(AbsInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Inc,
+        )),
+        0xF0 => Instruction::Rel(RelInstruction::Branch(BranchInstruction::Beq)),
+        0xF1 => Instruction::IndIdx(IndIdxInstruction::Read(ReadInstruction::Sbc)),
+        0xF5 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(ReadInstruction::Sbc)),
+        0xF6 => Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Inc,
+        )),
+        0xF8 => Instruction::AccumImpl(AccumImplInstruction::Internal(InternalInstruction::Sed)),
+        0xF9 => Instruction::AbsIdxY(AbsIdxInstruction::Read(ReadInstruction::Sbc)),
+        0xFD => Instruction::AbsIdxX(AbsIdxInstruction::Read(ReadInstruction::Sbc)),
+        0xFE => Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
+            ReadModifyWriteInstruction::Inc,
+        )),
+        op => Instruction::Invalid(op),
     }
 }
@@ -1,12 +1,82 @@
+pub mod asm;
+pub mod disasm;
+mod fast;
 mod flags;
 mod instruction;
-use super::bus::BusEvent;
+pub mod opcodes;
+use super::bus::{BusDevice, BusEvent};
+use crate::state::{StateError, StateReader, StateWriter, Stateful};
 
 use flags::*;
 use instruction::*;
 
+/// Which member of the 6502 family a [`Cpu`] behaves as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// The original NMOS 6502, with BCD arithmetic when D is set.
+    #[default]
+    Nmos6502,
+    /// The Ricoh 2A03 (2A07 on PAL) in the NES. D can be set and pushed,
+    /// but ADC and SBC ignore it.
+    Ricoh2A03,
+    /// The CMOS 65C02 as made by WDC, with Rockwell's bit instructions.
+    /// Besides the new instructions and addressing modes, `JMP ($xxFF)`
+    /// reads its high byte from the next page, N and Z are valid after
+    /// decimal ADC and SBC at the cost of a cycle, interrupts clear D, and
+    /// read-modify-write instructions read twice instead of writing twice.
+    Wdc65C02,
+}
+
+impl Variant {
+    /// Whether ADC and SBC honour the D flag.
+    pub fn has_decimal_mode(self) -> bool {
+        match self {
+            Variant::Nmos6502 | Variant::Wdc65C02 => true,
+            Variant::Ricoh2A03 => false,
+        }
+    }
+}
+
+/// How [`Cpu::step`] runs an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Cycle by cycle through [`Cpu::clock`], making every bus access the
+    /// chip does.
+    #[default]
+    Cycle,
+    /// In one go through [`Cpu::execute`], for when only the results
+    /// matter and speed does.
+    Instruction,
+}
+
+/// What a read by the CPU is for, as told by [`Cpu::fetch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fetch {
+    /// The first byte of an instruction.
+    Opcode,
+    /// The first byte of an instruction reached through a pointer, by
+    /// `JMP ($xxxx)` or the 65C02's `JMP ($xxxx,X)`.
+    IndirectOpcode,
+    /// The bytes of an instruction after the opcode.
+    Operand,
+    /// Half of an address the instruction goes on to use.
+    Pointer,
+    /// The value an instruction works on.
+    Data,
+    /// The value an instruction works on, found through a pointer.
+    IndirectData,
+    /// A byte pulled from the stack.
+    Stack,
+    /// Half of an interrupt vector.
+    Vector,
+    /// A read made only because the CPU reads on every cycle it doesn't
+    /// write, whose value goes unused.
+    Dummy,
+}
+
 #[derive(Default)]
 pub struct Cpu {
+    variant: Variant,
     step: u8,
     pc: u16,
     s: u8,
@@ -15,17 +85,45 @@ pub struct Cpu {
     y: u8,
     p: Flags,
     inst: Instruction,
+    /// The opcode `inst` was decoded from, kept for save states.
+    opcode: u8,
     temp: u8,
     irq: bool,
     nmi: bool,
     rst: bool,
+    irq_poll: bool,
+    nmi_poll: bool,
+    /// The IRQ line whether or not I masks it, which wakes WAI.
+    irq_line: bool,
+    /// Sits out the next cycle, for the 65C02's decimal correction.
+    stall: bool,
+    halt: Option<HaltInstruction>,
 }
 
 impl Cpu {
-    pub fn new() -> Self {
-        Cpu::default()
+    /// A CPU with every register zero, which starts by executing whatever
+    /// is on the bus. Consoles use [`Cpu::power_on`].
+    pub fn new(variant: Variant) -> Self {
+        Cpu {
+            variant,
+            ..Cpu::default()
+        }
     }
 
+    /// The state at power-on: registers zero with the reset line held, so
+    /// the reset sequence leaves S at $FD and I set.
+    pub fn power_on(variant: Variant) -> Self {
+        let mut cpu = Cpu::new(variant);
+        cpu.rst();
+        cpu
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Asserts reset. The current instruction is abandoned; A, X and Y are
+    /// kept, S drops by three and I is set.
     pub fn rst(&mut self) {
         self.rst = true;
     }
@@ -34,40 +132,291 @@ impl Cpu {
         if !self.p.i {
             self.irq = true;
         }
+        self.irq_line = true;
     }
 
     pub fn nmi(&mut self) {
         self.nmi = true;
     }
 
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn s(&self) -> u8 {
+        self.s
+    }
+
+    /// The status register, with bit 5 set and B clear.
+    pub fn p(&self) -> u8 {
+        self.p.into()
+    }
+
+    pub fn set_a(&mut self, a: u8) {
+        self.a = a;
+    }
+
+    pub fn set_x(&mut self, x: u8) {
+        self.x = x;
+    }
+
+    pub fn set_y(&mut self, y: u8) {
+        self.y = y;
+    }
+
+    pub fn set_s(&mut self, s: u8) {
+        self.s = s;
+    }
+
+    pub fn set_p(&mut self, p: u8) {
+        self.p = p.into();
+    }
+
+    /// Moves PC between instructions. The next opcode is still whatever is
+    /// passed to the next [`Cpu::clock`], so the bus has to fetch from the
+    /// new PC too; [`crate::nes::Nes::set_pc`] does both.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// True between instructions: the next cycle decodes the opcode at
+    /// [`Cpu::pc`] or begins an interrupt sequence.
+    pub fn at_instruction_start(&self) -> bool {
+        self.step == 0 && !self.stall
+    }
+
+    /// What the read returned by the last [`Cpu::clock`] is for. Means
+    /// nothing when that was a write.
+    pub fn fetch(&self) -> Fetch {
+        use Fetch::*;
+        let cmos = self.variant == Variant::Wdc65C02;
+        match (self.inst, self.step) {
+            // Sitting out a cycle, or reading an opcode that an interrupt
+            // sequence then throws away.
+            (_, 0) if self.stall || self.halt == Some(HaltInstruction::Stp) => Dummy,
+            (_, 0) if self.rst || self.nmi_poll || self.irq_poll => Dummy,
+            (Instruction::AbsInd(_) | Instruction::AbsIdxInd(_), 0) => IndirectOpcode,
+            (_, 0) => Opcode,
+            (
+                Instruction::Stack(StackInstruction::Brk(
+                    Interrupt::Rst | Interrupt::Nmi | Interrupt::Irq,
+                )),
+                1,
+            ) => Dummy,
+            (_, 1) if opcodes::info(self.variant, self.opcode).bytes > 1 => Operand,
+            (_, 1) => Dummy,
+            (Instruction::Stack(stack_instruction), step) => match (stack_instruction, step) {
+                (StackInstruction::Brk(_), 5 | 6) => Vector,
+                (StackInstruction::Rti, 3..=5)
+                | (StackInstruction::Rts, 3 | 4)
+                | (
+                    StackInstruction::Pla
+                    | StackInstruction::Plx
+                    | StackInstruction::Ply
+                    | StackInstruction::Plp,
+                    3,
+                ) => Stack,
+                (StackInstruction::Jsr, 5) => Operand,
+                _ => Dummy,
+            },
+            (
+                Instruction::Abs(_)
+                | Instruction::AbsIdxX(_)
+                | Instruction::AbsIdxY(_)
+                | Instruction::AbsInd(_)
+                | Instruction::AbsIdxInd(_),
+                2,
+            ) => Operand,
+            (Instruction::Abs(AbsInstruction::Read(_) | AbsInstruction::ReadModifyWrite(_)), 3)
+            | (Instruction::ZeroPage(_), 2)
+            | (Instruction::ZeroPageIdxX(_) | Instruction::ZeroPageIdxY(_), 3) => Data,
+            (
+                Instruction::AbsIdxX(AbsIdxInstruction::Read(_))
+                | Instruction::AbsIdxY(AbsIdxInstruction::Read(_)),
+                3,
+            ) if self.temp == 0 => Data,
+            (
+                Instruction::ZeroPage(_)
+                | Instruction::ZeroPageIdxX(_)
+                | Instruction::ZeroPageIdxY(_)
+                | Instruction::Abs(_),
+                _,
+            ) => Dummy,
+            (Instruction::AbsIdxX(_) | Instruction::AbsIdxY(_), 4) => Data,
+            (Instruction::IdxInd(_), 3 | 4) => Pointer,
+            (Instruction::IdxInd(_), 5) => IndirectData,
+            (Instruction::IndIdx(_), 2 | 3) => Pointer,
+            // Indexing that crossed a page reads a page short first.
+            (Instruction::IndIdx(IndIdxInstruction::Read(_)), 4)
+                if self.temp.checked_add(self.y).is_some() =>
+            {
+                IndirectData
+            }
+            (
+                Instruction::IndIdx(
+                    IndIdxInstruction::Read(_) | IndIdxInstruction::ReadModifyWrite(_),
+                ),
+                5,
+            ) => IndirectData,
+            (Instruction::AbsInd(_) | Instruction::AbsIdxInd(_), 3) if !cmos => Pointer,
+            (Instruction::AbsInd(_) | Instruction::AbsIdxInd(_), 4) => Pointer,
+            (Instruction::AbsInd(_) | Instruction::AbsIdxInd(_), 5) if cmos => Pointer,
+            (Instruction::ZeroPageInd(_), 2 | 3) => Pointer,
+            (Instruction::ZeroPageInd(_), 4) => IndirectData,
+            (Instruction::ZeroPageRel(_), 2) => Data,
+            (Instruction::ZeroPageRel(_), 3) => Operand,
+            _ => Dummy,
+        }
+    }
+
+    /// Whether WAI or STP has stopped the clock.
+    pub fn halted(&self) -> bool {
+        self.halt.is_some()
+    }
+
+    /// Whether the CPU sits out this cycle, reading PC: after decimal
+    /// arithmetic on the 65C02, or while halted. Wakes it if it can.
+    fn idle(&mut self) -> bool {
+        if self.stall {
+            self.stall = false;
+            return true;
+        }
+        match self.halt {
+            Some(HaltInstruction::Wai) if !(self.rst || self.nmi || self.irq_line) => true,
+            Some(HaltInstruction::Stp) if !self.rst => true,
+            _ => {
+                self.halt = None;
+                false
+            }
+        }
+    }
+
+    /// The extra cycle of a read-modify-write instruction, where NMOS
+    /// parts write the unmodified value back and the 65C02 reads again.
+    fn rmw_dummy(&self, addr: u16, data: u8) -> BusEvent {
+        match self.variant {
+            Variant::Wdc65C02 => BusEvent::Read(addr),
+            Variant::Nmos6502 | Variant::Ricoh2A03 => BusEvent::Write(addr, data),
+        }
+    }
+
+    /// Adds the index to the absolute address whose low byte is in `temp`
+    /// and high byte is `high`, without carrying into the high byte. `temp`
+    /// is left 1 if the carry is still to come, 0 if not.
+    fn index_abs(&mut self, high: u8) -> u16 {
+        let index = match self.inst {
+            Instruction::AbsIdxX(_) => self.x,
+            _ => self.y,
+        };
+        let (low, crossed) = self.temp.overflowing_add(index);
+        self.temp = crossed as u8;
+        (high as u16) << 8 | low as u16
+    }
+
+    /// The write of an indexed store. `addr` is as first formed from base
+    /// and index, a page short if indexing `crossed` one.
+    fn indexed_write(&mut self, write: WriteInstruction, addr: u16, crossed: bool) -> BusEvent {
+        let mut data = write.execute(self);
+        let mut target = if crossed {
+            addr.wrapping_add(0x100)
+        } else {
+            addr
+        };
+        if write.is_unstable() {
+            data &= ((addr >> 8) as u8).wrapping_add(1);
+            if crossed {
+                target = (data as u16) << 8 | (addr & 0x00FF);
+            }
+        }
+        BusEvent::Write(target, data)
+    }
+
+    /// Runs the next instruction against `bus` with either engine and
+    /// returns the cycles it took. Call it between instructions; an IRQ
+    /// asserted beforehand is held for the whole instruction.
+    pub fn step<B: BusDevice + ?Sized>(&mut self, engine: Engine, bus: &mut B) -> u32 {
+        if engine == Engine::Instruction {
+            return self.execute(bus);
+        }
+        let irq = self.irq_line;
+        let mut event = BusEvent::Read(self.pc);
+        let mut cycles = 0;
+        loop {
+            let (addr, data) = match event {
+                BusEvent::Read(addr) => (addr, bus.read(addr)),
+                BusEvent::Write(addr, data) => {
+                    bus.write(addr, data);
+                    (addr, data)
+                }
+            };
+            if irq {
+                self.irq();
+            }
+            event = self.clock(addr, data);
+            cycles += 1;
+            if self.at_instruction_start() {
+                return cycles;
+            }
+        }
+    }
+
     /// Advances the CPU by one clock cycle. Returns true when bus action is read.
     pub fn clock(&mut self, mut addr: u16, mut data: u8) -> BusEvent {
+        // Interrupt lines as they stood at the end of the previous cycle.
+        let (nmi, irq) = (self.nmi, self.irq);
+
+        if self.step == 0 && self.idle() {
+            return self.end_cycle(self.pc, nmi, irq);
+        }
+
         if self.step == 0 {
             self.inst = if self.rst {
                 Instruction::Stack(StackInstruction::Brk(Interrupt::Rst))
-            } else if self.nmi {
+            } else if self.nmi_poll {
                 Instruction::Stack(StackInstruction::Brk(Interrupt::Nmi))
-            } else if self.irq {
+            } else if self.irq_poll {
                 Instruction::Stack(StackInstruction::Brk(Interrupt::Irq))
             } else {
-                data.into()
+                self.opcode = data;
+                Instruction::decode(data, self.variant)
             };
         }
 
         self.step += 1;
 
         if self.step == 1 {
-            self.pc += 1;
+            // Hardware interrupts discard the fetched opcode without advancing PC.
+            match self.inst {
+                Instruction::Stack(StackInstruction::Brk(
+                    Interrupt::Rst | Interrupt::Nmi | Interrupt::Irq,
+                )) => {}
+                _ => self.pc = self.pc.wrapping_add(1),
+            }
             addr = self.pc;
+            if let Instruction::Nop { bytes: 1, .. } = self.inst {
+                self.step = 0;
+            }
         } else {
             match self.inst {
                 Instruction::Stack(stack_instruction) => match stack_instruction {
                     StackInstruction::Brk(int) => match self.step {
                         2 => {
-                            self.pc += match int {
-                                Interrupt::Brk | Interrupt::Rst => 1,
-                                Interrupt::Nmi | Interrupt::Irq => 0,
-                            };
+                            self.pc = self.pc.wrapping_add(match int {
+                                Interrupt::Brk => 1,
+                                Interrupt::Rst | Interrupt::Nmi | Interrupt::Irq => 0,
+                            });
 
                             addr = 0x100 | self.s as u16;
                             self.s = self.s.wrapping_sub(1);
@@ -76,7 +425,7 @@ impl Cpu {
                                 Interrupt::Rst => {}
                                 Interrupt::Irq | Interrupt::Nmi | Interrupt::Brk => {
                                     data = ((self.pc & 0xFF00) >> 8) as u8;
-                                    return BusEvent::Write ( addr, data );
+                                    return BusEvent::Write(addr, data);
                                 }
                             }
                         }
@@ -88,7 +437,7 @@ impl Cpu {
                                 Interrupt::Rst => {}
                                 Interrupt::Irq | Interrupt::Nmi | Interrupt::Brk => {
                                     data = (self.pc & 0x00FF) as u8;
-                                    return BusEvent::Write ( addr, data );
+                                    return BusEvent::Write(addr, data);
                                 }
                             }
                         }
@@ -96,20 +445,23 @@ impl Cpu {
                             addr = 0x100 | self.s as u16;
                             self.s = self.s.wrapping_sub(1);
                             match int {
-                                Interrupt::Rst => {}
+                                Interrupt::Rst => self.p.i = true,
                                 Interrupt::Irq | Interrupt::Nmi => {
                                     data = self.p.into();
                                     self.p.i = true;
-                                    return BusEvent::Write ( addr, data );
+                                    return BusEvent::Write(addr, data);
                                 }
                                 Interrupt::Brk => {
                                     data = u8::from(self.p) | 1u8 << 4; //assert B with BRK
                                     self.p.i = true;
-                                    return BusEvent::Write ( addr, data );
+                                    return BusEvent::Write(addr, data);
                                 }
                             }
                         }
                         5 => {
+                            if self.variant == Variant::Wdc65C02 {
+                                self.p.d = false;
+                            }
                             addr = match int {
                                 Interrupt::Brk | Interrupt::Irq => 0xFFFE,
                                 Interrupt::Nmi => 0xFFFA,
@@ -180,30 +532,36 @@ impl Cpu {
                             addr = self.pc;
                         }
                         6 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             addr = self.pc;
                             self.step = 0;
                         }
                         _ => unreachable!(),
                     },
-                    StackInstruction::Pha => match self.step {
-                        2 => {
-                            addr = self.s as u16 + 0x100;
-                            data = self.a;
-                            return BusEvent::Write ( addr, data );
-                        }
-                        3 => {
-                            self.s = self.s.wrapping_sub(1);
-                            addr = self.pc;
-                            self.step = 0;
+                    StackInstruction::Pha | StackInstruction::Phx | StackInstruction::Phy => {
+                        match self.step {
+                            2 => {
+                                addr = self.s as u16 + 0x100;
+                                data = match stack_instruction {
+                                    StackInstruction::Phx => self.x,
+                                    StackInstruction::Phy => self.y,
+                                    _ => self.a,
+                                };
+                                return BusEvent::Write(addr, data);
+                            }
+                            3 => {
+                                self.s = self.s.wrapping_sub(1);
+                                addr = self.pc;
+                                self.step = 0;
+                            }
+                            _ => unreachable!(),
                         }
-                        _ => unreachable!(),
-                    },
+                    }
                     StackInstruction::Php => match self.step {
                         2 => {
                             addr = self.s as u16 + 0x100;
                             data = u8::from(self.p) | (1u8 << 4); // assert B for PHP
-                            return BusEvent::Write ( addr, data );
+                            return BusEvent::Write(addr, data);
                         }
                         3 => {
                             self.s = self.s.wrapping_sub(1);
@@ -212,23 +570,29 @@ impl Cpu {
                         }
                         _ => unreachable!(),
                     },
-                    StackInstruction::Pla => match self.step {
-                        2 => {
-                            addr = self.s as u16 + 0x100;
-                        }
-                        3 => {
-                            self.s = self.s.wrapping_add(1);
-                            addr = self.s as u16 + 0x100;
-                        }
-                        4 => {
-                            self.a = data;
-                            self.p.set_n(self.a);
-                            self.p.set_z(self.a);
-                            addr = self.pc;
-                            self.step = 0;
+                    StackInstruction::Pla | StackInstruction::Plx | StackInstruction::Ply => {
+                        match self.step {
+                            2 => {
+                                addr = self.s as u16 + 0x100;
+                            }
+                            3 => {
+                                self.s = self.s.wrapping_add(1);
+                                addr = self.s as u16 + 0x100;
+                            }
+                            4 => {
+                                match stack_instruction {
+                                    StackInstruction::Plx => self.x = data,
+                                    StackInstruction::Ply => self.y = data,
+                                    _ => self.a = data,
+                                }
+                                self.p.set_n(data);
+                                self.p.set_z(data);
+                                addr = self.pc;
+                                self.step = 0;
+                            }
+                            _ => unreachable!(),
                         }
-                        _ => unreachable!(),
-                    },
+                    }
                     StackInstruction::Plp => match self.step {
                         2 => {
                             addr = self.s as u16 + 0x100;
@@ -246,20 +610,20 @@ impl Cpu {
                     },
                     StackInstruction::Jsr => match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             self.temp = data;
                             addr = self.s as u16 + 0x100;
                         }
                         3 => {
                             addr = self.s as u16 + 0x100;
                             data = ((self.pc & 0xFF00) >> 8) as u8;
-                            return BusEvent::Write ( addr, data );
+                            return BusEvent::Write(addr, data);
                         }
                         4 => {
                             self.s = self.s.wrapping_sub(1);
                             addr = self.s as u16 + 0x100;
                             data = (self.pc & 0x00FF) as u8;
-                            return BusEvent::Write ( addr, data );
+                            return BusEvent::Write(addr, data);
                         }
                         5 => {
                             self.s = self.s.wrapping_sub(1);
@@ -294,7 +658,7 @@ impl Cpu {
                 },
                 Instruction::Imm(ImmInstruction::Read(read_instruction)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         let m = data;
                         read_instruction.execute(self, m);
 
@@ -306,7 +670,7 @@ impl Cpu {
                 },
                 Instruction::Abs(AbsInstruction::Jump(_)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = self.pc;
                         self.temp = data;
                     }
@@ -319,12 +683,12 @@ impl Cpu {
                 },
                 Instruction::Abs(AbsInstruction::Read(read_instruction)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = self.pc;
                         self.temp = data;
                     }
                     3 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = (data as u16) << 8 | self.temp as u16;
                     }
                     4 => {
@@ -336,15 +700,15 @@ impl Cpu {
                 },
                 Instruction::Abs(AbsInstruction::Write(write_instruction)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = self.pc;
                         self.temp = data;
                     }
                     3 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = (data as u16) << 8 | self.temp as u16;
                         data = write_instruction.execute(self);
-                        return BusEvent::Write ( addr, data );
+                        return BusEvent::Write(addr, data);
                     }
                     4 => {
                         addr = self.pc;
@@ -356,21 +720,21 @@ impl Cpu {
                     read_modify_write_instruction,
                 )) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = self.pc;
                         self.temp = data;
                     }
                     3 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = (data as u16) << 8 | self.temp as u16;
                     }
                     4 => {
                         self.temp = read_modify_write_instruction.execute(self, data);
-                        return BusEvent::Write ( addr, data );
+                        return self.rmw_dummy(addr, data);
                     }
                     5 => {
                         data = self.temp;
-                        return BusEvent::Write ( addr, data );
+                        return BusEvent::Write(addr, data);
                     }
                     6 => {
                         addr = self.pc;
@@ -382,7 +746,7 @@ impl Cpu {
                 Instruction::ZeroPage(ZeroPageInstruction::Read(read_instruction)) => {
                     match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             addr = data as u16;
                         }
                         3 => {
@@ -396,10 +760,10 @@ impl Cpu {
                 Instruction::ZeroPage(ZeroPageInstruction::Write(write_instruction)) => {
                     match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             addr = data as u16;
                             data = write_instruction.execute(self);
-                            return BusEvent::Write ( addr, data );
+                            return BusEvent::Write(addr, data);
                         }
                         3 => {
                             addr = self.pc;
@@ -412,16 +776,16 @@ impl Cpu {
                     read_modify_write_instruction,
                 )) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = data as u16;
                     }
                     3 => {
                         self.temp = read_modify_write_instruction.execute(self, data);
-                        return BusEvent::Write ( addr, data );
+                        return self.rmw_dummy(addr, data);
                     }
                     4 => {
                         data = self.temp;
-                        return BusEvent::Write ( addr, data );
+                        return BusEvent::Write(addr, data);
                     }
                     5 => {
                         addr = self.pc;
@@ -433,7 +797,7 @@ impl Cpu {
                 | Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Read(read_instruction)) => {
                     match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             addr = data as u16;
                         }
                         3 => {
@@ -456,7 +820,7 @@ impl Cpu {
                 | Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Write(write_instruction)) => {
                     match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             addr = data as u16;
                         }
                         3 => {
@@ -467,7 +831,7 @@ impl Cpu {
                             } as u16;
                             addr &= 0x00FF;
                             data = write_instruction.execute(self);
-                            return BusEvent::Write ( addr, data );
+                            return BusEvent::Write(addr, data);
                         }
                         4 => {
                             addr = self.pc;
@@ -480,7 +844,7 @@ impl Cpu {
                     read_modify_write_instruction,
                 )) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = data as u16;
                     }
                     3 => {
@@ -489,11 +853,11 @@ impl Cpu {
                     }
                     4 => {
                         self.temp = data;
-                        return BusEvent::Write ( addr, data );
+                        return self.rmw_dummy(addr, data);
                     }
                     5 => {
                         data = read_modify_write_instruction.execute(self, self.temp);
-                        return BusEvent::Write ( addr, data );
+                        return BusEvent::Write(addr, data);
                     }
                     6 => {
                         addr = self.pc;
@@ -508,29 +872,21 @@ impl Cpu {
                 | Instruction::AbsIdxY(AbsIdxInstruction::Read(read_instruction)) => {
                     match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             self.temp = data;
                             addr = self.pc;
                         }
                         3 => {
-                            self.pc += 1;
-                            let base_addr = (data as u16) << 8 | (self.temp as u16);
-                            addr = base_addr + match self.inst {
-                                Instruction::AbsIdxX(_) => self.x,
-                                Instruction::AbsIdxY(_) => self.y,
-                                _ => unreachable!(),
-                            } as u16;
-                            if (base_addr & 0xFF00) != (addr & 0xff00) {
-                                self.temp = 1;
-                            } else {
-                                self.temp = 0;
-                            }
+                            self.pc = self.pc.wrapping_add(1);
+                            addr = self.index_abs(data);
                         }
                         4 => {
                             if self.temp == 0 {
                                 read_instruction.execute(self, data);
                                 addr = self.pc;
                                 self.step = 0;
+                            } else {
+                                addr = addr.wrapping_add(0x100);
                             }
                         }
                         5 => {
@@ -545,22 +901,16 @@ impl Cpu {
                 | Instruction::AbsIdxY(AbsIdxInstruction::Write(write_instruction)) => {
                     match self.step {
                         2 => {
-                            self.pc += 1;
+                            self.pc = self.pc.wrapping_add(1);
                             self.temp = data;
                             addr = self.pc;
                         }
                         3 => {
-                            self.pc += 1;
-                            let base_addr = (data as u16) << 8 | (self.temp as u16);
-                            addr = base_addr + match self.inst {
-                                Instruction::AbsIdxX(_) => self.x,
-                                Instruction::AbsIdxY(_) => self.y,
-                                _ => unreachable!(),
-                            } as u16;
+                            self.pc = self.pc.wrapping_add(1);
+                            addr = self.index_abs(data);
                         }
                         4 => {
-                            data = write_instruction.execute(self);
-                            return BusEvent::Write ( addr, data );
+                            return self.indexed_write(write_instruction, addr, self.temp != 0);
                         }
                         5 => {
                             addr = self.pc;
@@ -571,38 +921,42 @@ impl Cpu {
                 }
                 Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(
                     read_modify_write_instruction,
+                ))
+                | Instruction::AbsIdxY(AbsIdxInstruction::ReadModifyWrite(
+                    read_modify_write_instruction,
                 )) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         self.temp = data;
                         addr = self.pc;
                     }
                     3 => {
-                        self.pc += 1;
-                        let base_addr = (data as u16) << 8 | (self.temp as u16);
-                        addr = base_addr + match self.inst {
-                            Instruction::AbsIdxX(_) => self.x,
-                            Instruction::AbsIdxY(_) => self.y,
-                            _ => unreachable!(),
-                        } as u16;
-                        if (base_addr & 0xFF00) != (addr & 0xff00) {
-                            self.temp = 1;
-                        } else {
-                            self.temp = 0;
+                        self.pc = self.pc.wrapping_add(1);
+                        addr = self.index_abs(data);
+                        // The 65C02 skips the dummy read for shifts and
+                        // rotates that stay in the page.
+                        if self.variant == Variant::Wdc65C02
+                            && self.temp == 0
+                            && !matches!(
+                                read_modify_write_instruction,
+                                ReadModifyWriteInstruction::Inc | ReadModifyWriteInstruction::Dec
+                            )
+                        {
+                            self.step += 1;
                         }
                     }
                     4 => {
                         if self.temp != 0 {
-                            addr += 0x100;
+                            addr = addr.wrapping_add(0x100);
                         }
                     }
                     5 => {
                         self.temp = data;
-                        return BusEvent::Write ( addr, data );
+                        return self.rmw_dummy(addr, data);
                     }
                     6 => {
                         data = read_modify_write_instruction.execute(self, self.temp);
-                        return BusEvent::Write ( addr, data );
+                        return BusEvent::Write(addr, data);
                     }
                     7 => {
                         addr = self.pc;
@@ -610,13 +964,13 @@ impl Cpu {
                     }
                     _ => unreachable!(),
                 },
-                Instruction::AbsIdxY(AbsIdxInstruction::ReadModifyWrite(_)) => {
-                    unreachable!()
-                }
                 Instruction::Rel(RelInstruction::Branch(branch_instruction)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        // Taken or not, the next opcode is read while the
+                        // offset is added.
+                        self.pc = self.pc.wrapping_add(1);
                         self.temp = data;
+                        addr = self.pc;
                         if branch_instruction.execute(self) {
                             let j = (self.temp as i8) as i16;
                             let pc = self.pc.wrapping_add_signed(j);
@@ -629,22 +983,25 @@ impl Cpu {
                         } else {
                             self.step = 0;
                         }
-                        addr = self.pc;
                     }
                     3 => {
                         if self.temp == 0 {
                             self.step = 0;
+                            addr = self.pc;
+                        } else {
+                            // Read before the carry reaches the high byte.
+                            addr = (addr & 0xFF00) | (self.pc & 0x00FF);
                         }
-                        addr = self.pc;
                     }
                     4 => {
                         self.step = 0;
+                        addr = self.pc;
                     }
                     _ => unreachable!(),
                 },
                 Instruction::IdxInd(IdxIndInstruction::Read(read_instruction)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = data as u16;
                     }
                     3 => {
@@ -666,10 +1023,80 @@ impl Cpu {
                     }
                     _ => unreachable!(),
                 },
+                Instruction::IdxInd(IdxIndInstruction::ReadModifyWrite(
+                    read_modify_write_instruction,
+                )) => match self.step {
+                    2 => {
+                        self.pc = self.pc.wrapping_add(1);
+                        addr = data as u16;
+                    }
+                    3 => {
+                        addr = addr.wrapping_add(self.x as u16) & 0x00FF;
+                    }
+                    4 => {
+                        self.temp = data;
+                        addr = addr.wrapping_add(1) & 0x00FF;
+                    }
+                    5 => {
+                        addr = (data as u16) << 8 | self.temp as u16;
+                    }
+                    6 => {
+                        self.temp = read_modify_write_instruction.execute(self, data);
+                        return self.rmw_dummy(addr, data);
+                    }
+                    7 => {
+                        data = self.temp;
+                        return BusEvent::Write(addr, data);
+                    }
+                    8 => {
+                        addr = self.pc;
+                        self.step = 0;
+                    }
+                    _ => unreachable!(),
+                },
+                Instruction::IndIdx(IndIdxInstruction::ReadModifyWrite(
+                    read_modify_write_instruction,
+                )) => match self.step {
+                    2 => {
+                        self.pc = self.pc.wrapping_add(1);
+                        addr = data as u16;
+                    }
+                    3 => {
+                        self.temp = data;
+                        addr = addr.wrapping_add(1) & 0x00FF;
+                    }
+                    4 => {
+                        let adl_idx = self.temp.wrapping_add(self.y);
+                        if adl_idx < self.temp {
+                            self.temp = 1;
+                        } else {
+                            self.temp = 0;
+                        }
+                        addr = (data as u16) << 8 | adl_idx as u16;
+                    }
+                    5 => {
+                        if self.temp != 0 {
+                            addr = addr.wrapping_add(0x100);
+                        }
+                    }
+                    6 => {
+                        self.temp = read_modify_write_instruction.execute(self, data);
+                        return self.rmw_dummy(addr, data);
+                    }
+                    7 => {
+                        data = self.temp;
+                        return BusEvent::Write(addr, data);
+                    }
+                    8 => {
+                        addr = self.pc;
+                        self.step = 0;
+                    }
+                    _ => unreachable!(),
+                },
                 Instruction::IdxInd(IdxIndInstruction::Write(write_instruction)) => match self.step
                 {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = data as u16;
                     }
                     3 => {
@@ -684,7 +1111,7 @@ impl Cpu {
                     5 => {
                         addr = (data as u16) << 8 | self.temp as u16;
                         data = write_instruction.execute(self);
-                        return BusEvent::Write ( addr, data );
+                        return BusEvent::Write(addr, data);
                     }
                     6 => {
                         addr = self.pc;
@@ -694,7 +1121,7 @@ impl Cpu {
                 },
                 Instruction::IndIdx(IndIdxInstruction::Read(read_instruction)) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = data as u16;
                     }
                     3 => {
@@ -708,7 +1135,7 @@ impl Cpu {
                     5 => {
                         let adl_idx = (addr & 0x00FF) as u8;
                         if adl_idx < self.temp {
-                            addr += 0x100;
+                            addr = addr.wrapping_add(0x100);
                         } else {
                             read_instruction.execute(self, data);
                             addr = self.pc;
@@ -725,7 +1152,7 @@ impl Cpu {
                 Instruction::IndIdx(IndIdxInstruction::Write(write_instruction)) => match self.step
                 {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = data as u16;
                     }
                     3 => {
@@ -737,12 +1164,8 @@ impl Cpu {
                         addr = (data as u16) << 8 | adl_idx as u16;
                     }
                     5 => {
-                        let adl_idx = (addr & 0x00FF) as u8;
-                        if adl_idx < self.temp {
-                            addr += 0x100;
-                        }
-                        data = write_instruction.execute(self);
-                        return BusEvent::Write ( addr, data );
+                        let crossed = ((addr & 0x00FF) as u8) < self.temp;
+                        return self.indexed_write(write_instruction, addr, crossed);
                     }
                     6 => {
                         addr = self.pc;
@@ -750,14 +1173,46 @@ impl Cpu {
                     }
                     _ => unreachable!(),
                 },
+                Instruction::AbsInd(_) | Instruction::AbsIdxInd(_)
+                    if self.variant == Variant::Wdc65C02 =>
+                {
+                    match self.step {
+                        2 => {
+                            self.pc = self.pc.wrapping_add(1);
+                            self.temp = data;
+                            addr = self.pc;
+                        }
+                        3 => {
+                            let index = match self.inst {
+                                Instruction::AbsIdxInd(_) => self.x,
+                                _ => 0,
+                            };
+                            addr =
+                                ((data as u16) << 8 | self.temp as u16).wrapping_add(index as u16);
+                        }
+                        4 => {}
+                        5 => {
+                            // No page wrap, unlike NMOS.
+                            self.temp = data;
+                            addr = addr.wrapping_add(1);
+                        }
+                        6 => {
+                            self.pc = (data as u16) << 8 | self.temp as u16;
+                            self.step = 0;
+                            addr = self.pc;
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                Instruction::AbsIdxInd(_) => unreachable!(),
                 Instruction::AbsInd(_) => match self.step {
                     2 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         self.temp = data;
                         addr = self.pc;
                     }
                     3 => {
-                        self.pc += 1;
+                        self.pc = self.pc.wrapping_add(1);
                         addr = (data as u16) << 8 | self.temp as u16;
                     }
                     4 => {
@@ -774,92 +1229,935 @@ impl Cpu {
                     }
                     _ => unreachable!(),
                 },
+                Instruction::ZeroPageInd(ZeroPageIndInstruction::Read(read_instruction)) => {
+                    match self.step {
+                        2 => {
+                            self.pc = self.pc.wrapping_add(1);
+                            addr = data as u16;
+                        }
+                        3 => {
+                            self.temp = data;
+                            addr = addr.wrapping_add(1) & 0x00FF;
+                        }
+                        4 => {
+                            addr = (data as u16) << 8 | self.temp as u16;
+                        }
+                        5 => {
+                            read_instruction.execute(self, data);
+                            addr = self.pc;
+                            self.step = 0;
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                Instruction::ZeroPageInd(ZeroPageIndInstruction::Write(write_instruction)) => {
+                    match self.step {
+                        2 => {
+                            self.pc = self.pc.wrapping_add(1);
+                            addr = data as u16;
+                        }
+                        3 => {
+                            self.temp = data;
+                            addr = addr.wrapping_add(1) & 0x00FF;
+                        }
+                        4 => {
+                            addr = (data as u16) << 8 | self.temp as u16;
+                            data = write_instruction.execute(self);
+                            return BusEvent::Write(addr, data);
+                        }
+                        5 => {
+                            addr = self.pc;
+                            self.step = 0;
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                Instruction::ZeroPageRel(zero_page_rel_instruction) => match self.step {
+                    2 => {
+                        self.pc = self.pc.wrapping_add(1);
+                        addr = data as u16;
+                    }
+                    3 => {
+                        self.temp = zero_page_rel_instruction.execute(data) as u8;
+                        addr = self.pc;
+                    }
+                    4 => {
+                        // temp becomes 0 if not taken, 1 if taken within the
+                        // page and 2 if taken to another page.
+                        self.pc = self.pc.wrapping_add(1);
+                        if self.temp != 0 {
+                            let pc = self.pc.wrapping_add_signed(data as i8 as i16);
+                            if (pc & 0xFF00) != (self.pc & 0xFF00) {
+                                self.temp = 2;
+                            }
+                            self.pc = pc;
+                        }
+                        addr = self.pc;
+                    }
+                    5 | 6 => {
+                        if self.temp + 5 == self.step {
+                            self.step = 0;
+                        }
+                        addr = self.pc;
+                    }
+                    7 => {
+                        self.step = 0;
+                        addr = self.pc;
+                    }
+                    _ => unreachable!(),
+                },
+                Instruction::Halt(halt_instruction) => match self.step {
+                    2 => {
+                        addr = self.pc;
+                    }
+                    3 => {
+                        self.halt = Some(halt_instruction);
+                        addr = self.pc;
+                        self.step = 0;
+                    }
+                    _ => unreachable!(),
+                },
+                Instruction::Nop { bytes, cycles } => {
+                    if self.step == 2 {
+                        self.pc = self.pc.wrapping_add(bytes as u16 - 1);
+                    }
+                    if self.step == cycles {
+                        self.step = 0;
+                    }
+                    addr = self.pc;
+                }
                 Instruction::Invalid(op) => panic!("Invalid Instruction {op:#04x}"),
             };
         }
 
-        // handle reset immediately
-        if self.rst {
+        self.end_cycle(addr, nmi, irq)
+    }
+
+    /// Finishes a cycle that reads `addr`; `nmi` and `irq` are the
+    /// interrupt lines as they were at its start.
+    fn end_cycle(&mut self, addr: u16, nmi: bool, irq: bool) -> BusEvent {
+        // Reset aborts whatever instruction is running, but not the reset
+        // sequence itself.
+        if self.rst
+            && !matches!(
+                self.inst,
+                Instruction::Stack(StackInstruction::Brk(Interrupt::Rst))
+            )
+        {
             self.step = 0;
         }
 
+        // Interrupts are polled on the penultimate cycle of each instruction,
+        // so the decision for the next instruction uses the lines as they were
+        // before this, its last cycle.
+        if self.step == 0 {
+            self.nmi_poll = nmi && self.nmi;
+            self.irq_poll = irq;
+        }
+
         // IRQ is level triggered - needs to be set each clock.
         self.irq = false;
+        self.irq_line = false;
 
-        BusEvent::Read ( addr )
+        BusEvent::Read(addr)
+    }
+}
+
+impl Stateful for Cpu {
+    fn save(&self, w: &mut StateWriter) {
+        // Hardware interrupt sequences have no opcode of their own.
+        let interrupt = match self.inst {
+            Instruction::Stack(StackInstruction::Brk(Interrupt::Rst)) => 1u8,
+            Instruction::Stack(StackInstruction::Brk(Interrupt::Nmi)) => 2,
+            Instruction::Stack(StackInstruction::Brk(Interrupt::Irq)) => 3,
+            _ => 0,
+        };
+        w.write(&self.step);
+        w.write(&self.pc);
+        w.write(&self.s);
+        w.write(&self.a);
+        w.write(&self.x);
+        w.write(&self.y);
+        w.write(&u8::from(self.p));
+        w.write(&self.opcode);
+        w.write(&interrupt);
+        w.write(&self.temp);
+        w.write(&self.irq);
+        w.write(&self.nmi);
+        w.write(&self.rst);
+        w.write(&self.irq_poll);
+        w.write(&self.nmi_poll);
+        let halt = match self.halt {
+            None => 0u8,
+            Some(HaltInstruction::Wai) => 1,
+            Some(HaltInstruction::Stp) => 2,
+        };
+        w.write(&halt);
+        w.write(&self.stall);
+        w.write(&self.irq_line);
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.step)?;
+        r.read_into(&mut self.pc)?;
+        r.read_into(&mut self.s)?;
+        r.read_into(&mut self.a)?;
+        r.read_into(&mut self.x)?;
+        r.read_into(&mut self.y)?;
+        self.p = r.read::<u8>()?.into();
+        r.read_into(&mut self.opcode)?;
+        self.inst = match r.read::<u8>()? {
+            0 => Instruction::decode(self.opcode, self.variant),
+            1 => Instruction::Stack(StackInstruction::Brk(Interrupt::Rst)),
+            2 => Instruction::Stack(StackInstruction::Brk(Interrupt::Nmi)),
+            3 => Instruction::Stack(StackInstruction::Brk(Interrupt::Irq)),
+            _ => return Err(StateError::Invalid("CPU interrupt")),
+        };
+        r.read_into(&mut self.temp)?;
+        r.read_into(&mut self.irq)?;
+        r.read_into(&mut self.nmi)?;
+        r.read_into(&mut self.rst)?;
+        r.read_into(&mut self.irq_poll)?;
+        r.read_into(&mut self.nmi_poll)?;
+        // Version 1 chunks end here.
+        if r.is_empty() {
+            self.halt = None;
+            self.stall = false;
+            self.irq_line = false;
+            return Ok(());
+        }
+        self.halt = match r.read::<u8>()? {
+            0 => None,
+            1 => Some(HaltInstruction::Wai),
+            2 => Some(HaltInstruction::Stp),
+            _ => return Err(StateError::Invalid("CPU halt")),
+        };
+        r.read_into(&mut self.stall)?;
+        r.read_into(&mut self.irq_line)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::bus::BusDevice;
-    use std::fs::File;
-    use std::io::prelude::*;
-    use std::ops::DerefMut;
-    use std::path::Path;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
 
-    #[test]
-    fn _6502_functional_test() {
-        struct Ram([u8; 65536]);
-        let mut ram = Ram([0; 65536]);
+    /// A program from Klaus Dormann's test suite. The shipped images are
+    /// full 64KB memory dumps; the decimal and interrupt tests have to be
+    /// assembled into `bin_files` first, so they are ignored by default.
+    /// A missing image fails the test.
+    struct Klaus {
+        name: &'static str,
+        variant: Variant,
+        start: u16,
+        /// Where the program keeps the number of the running test, or its
+        /// error flag for the decimal test.
+        status: u16,
+        /// The interrupt test's feedback register, with IRQ in bit 0 and
+        /// NMI in bit 1.
+        feedback: Option<u16>,
+        /// Whether the program ends with STP ($DB), as the decimal test
+        /// does even on the NMOS 6502.
+        ends_with_stp: bool,
+    }
 
-        impl BusDevice for Ram {
-            fn read(&self, addr: u16) -> u8 {
-                self.0[addr as usize]
+    /// Where and how a program stopped.
+    #[derive(Debug)]
+    struct Trap {
+        pc: u16,
+        status: u8,
+    }
+
+    impl Klaus {
+        fn path(&self, extension: &str) -> PathBuf {
+            Path::new("6502_65C02_functional_tests/bin_files")
+                .join(self.name)
+                .with_extension(extension)
+        }
+
+        fn load(&self) -> Vec<u8> {
+            let path = self.path("bin");
+            let mut ram = fs::read(&path)
+                .unwrap_or_else(|why| panic!("Couldn't read {}: {}", path.display(), why));
+            ram.resize(0x10000, 0);
+            ram
+        }
+
+        /// The address of the `success` macro's `jmp *`, from the listing.
+        fn success(&self) -> u16 {
+            let path = self.path("lst");
+            let listing = fs::read_to_string(&path)
+                .unwrap_or_else(|why| panic!("Couldn't read {}: {}", path.display(), why));
+            listing
+                .lines()
+                .filter(|line| line.contains(";test passed, no errors"))
+                .find_map(|line| {
+                    let (addr, _) = line.split_once(" : ")?;
+                    u16::from_str_radix(addr.split_whitespace().last()?, 16).ok()
+                })
+                .unwrap_or_else(|| panic!("No success trap in {}", path.display()))
+        }
+
+        /// Runs until the program jumps or branches to itself, halts, or
+        /// reaches STP if it ends with one.
+        fn run(&self, ram: &mut [u8], engine: Engine) -> Trap {
+            let mut cpu = Cpu::new(self.variant);
+            cpu.pc = self.start;
+            let mut lines = 0;
+            let mut last_pc = None;
+            for _ in 0u64..100_000_000 {
+                let stp = self.ends_with_stp && ram[cpu.pc as usize] == 0xDB;
+                if last_pc == Some(cpu.pc) || cpu.halted() || stp {
+                    break;
+                }
+                last_pc = Some(cpu.pc);
+                match engine {
+                    // Clocked here rather than by step() so that writes to
+                    // the feedback register count from the next cycle.
+                    Engine::Cycle => {
+                        let (mut addr, mut data) = (cpu.pc, ram[cpu.pc as usize]);
+                        loop {
+                            if lines & 0x01 != 0 {
+                                cpu.irq();
+                            }
+                            match cpu.clock(addr, data) {
+                                BusEvent::Read(read) => addr = read,
+                                BusEvent::Write(write, value) => {
+                                    (addr, data) = (write, value);
+                                    ram[addr as usize] = data;
+                                    if Some(addr) == self.feedback {
+                                        Klaus::signal(&mut cpu, &mut lines, data);
+                                    }
+                                }
+                            }
+                            data = ram[addr as usize];
+                            if cpu.at_instruction_start() {
+                                break;
+                            }
+                        }
+                    }
+                    Engine::Instruction => {
+                        if lines & 0x01 != 0 {
+                            cpu.irq();
+                        }
+                        cpu.execute(ram);
+                        if let Some(feedback) = self.feedback {
+                            Klaus::signal(&mut cpu, &mut lines, ram[feedback as usize]);
+                        }
+                    }
+                }
+            }
+            Trap {
+                pc: cpu.pc,
+                status: ram[self.status as usize],
             }
-        
-            fn write(&mut self, addr: u16, data: u8) {
-                self.0[addr as usize] = data
+        }
+
+        /// Drives the interrupt lines from a feedback register value,
+        /// with NMI on its rising edge.
+        fn signal(cpu: &mut Cpu, lines: &mut u8, feedback: u8) {
+            if feedback & 0x02 != 0 && *lines & 0x02 == 0 {
+                cpu.nmi();
             }
+            *lines = feedback;
         }
 
-        impl std::ops::Deref for Ram {
-            type Target = [u8; 65536];
-        
-            fn deref(&self) -> &Self::Target {
-                &self.0
+        /// Runs the program and checks that it reached its success trap.
+        fn check(&self) {
+            let image = self.load();
+            let success = self.success();
+            for engine in [Engine::Cycle, Engine::Instruction] {
+                let trap = self.run(&mut image.clone(), engine);
+                assert_eq!(
+                    trap.pc, success,
+                    "{} trapped at ${:04X} in test ${:02X} ({engine:?})",
+                    self.name, trap.pc, trap.status
+                );
             }
         }
+    }
+
+    #[test]
+    fn _6502_functional_test() {
+        Klaus {
+            name: "6502_functional_test",
+            variant: Variant::Nmos6502,
+            start: 0x0400,
+            status: 0x0200,
+            feedback: None,
+            ends_with_stp: false,
+        }
+        .check();
+    }
+
+    #[test]
+    fn _65c02_extended_opcodes_test() {
+        Klaus {
+            name: "65C02_extended_opcodes_test",
+            variant: Variant::Wdc65C02,
+            start: 0x0400,
+            status: 0x0200,
+            feedback: None,
+            ends_with_stp: false,
+        }
+        .check();
+    }
+
+    #[test]
+    #[ignore = "6502_decimal_test.a65 has to be assembled first"]
+    fn _6502_decimal_test() {
+        let test = Klaus {
+            name: "6502_decimal_test",
+            variant: Variant::Nmos6502,
+            start: 0x0200,
+            status: 0x000B,
+            feedback: None,
+            ends_with_stp: true,
+        };
+        let ram = test.load();
+        for engine in [Engine::Cycle, Engine::Instruction] {
+            let trap = test.run(&mut ram.clone(), engine);
+            assert_eq!(
+                trap.status, 0,
+                "decimal test failed, stopped at ${:04X} ({engine:?})",
+                trap.pc
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "6502_interrupt_test.a65 has to be assembled first"]
+    fn _6502_interrupt_test() {
+        Klaus {
+            name: "6502_interrupt_test",
+            variant: Variant::Nmos6502,
+            start: 0x0400,
+            status: 0x0200,
+            feedback: Some(0xBFFC),
+            ends_with_stp: false,
+        }
+        .check();
+    }
 
-        impl std::ops::DerefMut for Ram {
-            fn deref_mut(&mut self) -> &mut Self::Target {
-                &mut self.0
+    /// Runs Tom Harte's SingleStepTests for `variant` from
+    /// `test_roms/65x02/DIR/v1`, one JSON file of 10,000 cases per opcode.
+    /// Each case sets up registers and memory, runs one instruction and
+    /// checks the registers, memory and every bus cycle. Opcodes in `skip`
+    /// are not run; the rest must all be present and pass.
+    fn single_step_tests(variant: Variant, dir: &str, skip: &[u8]) {
+        let dir = Path::new("test_roms/65x02").join(dir).join("v1");
+        assert!(dir.is_dir(), "{} not found", dir.display());
+        let mut failures = Vec::new();
+        for opcode in (0..=255u8).filter(|opcode| !skip.contains(opcode)) {
+            let path = dir.join(format!("{opcode:02x}.json"));
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(why) => {
+                    failures.push(format!(
+                        "{opcode:02x}: couldn't read {}: {why}",
+                        path.display()
+                    ));
+                    continue;
+                }
+            };
+            let cases: Value = serde_json::from_str(&text)
+                .unwrap_or_else(|why| panic!("{}: {why}", path.display()));
+            let failed = cases
+                .as_array()
+                .expect("an array of cases")
+                .iter()
+                .filter_map(|case| single_step(variant, case).err())
+                .collect::<Vec<_>>();
+            if let Some(first) = failed.first() {
+                failures.push(format!(
+                    "{opcode:02x}: {} failed, first {first}",
+                    failed.len()
+                ));
             }
         }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
 
-        let path = Path::new("6502_65C02_functional_tests/bin_files/6502_functional_test.bin");
-        let mut file = match File::open(path) {
-            Ok(file) => file,
-            Err(why) => panic!("Couldn't open {}: {}", path.display(), why),
+    /// Runs one case, describing the first difference found.
+    fn single_step(variant: Variant, case: &Value) -> Result<(), String> {
+        let name = case["name"].as_str().unwrap();
+        let (initial, expected) = (&case["initial"], &case["final"]);
+        let register = |state: &Value, name: &str| state[name].as_u64().unwrap();
+        let cells = |state: &Value| {
+            state["ram"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|cell| {
+                    (
+                        cell[0].as_u64().unwrap() as u16,
+                        cell[1].as_u64().unwrap() as u8,
+                    )
+                })
+                .collect::<Vec<_>>()
         };
+        let mut memory = cells(initial).into_iter().collect::<HashMap<_, _>>();
+
+        let mut cpu = Cpu::new(variant);
+        cpu.pc = register(initial, "pc") as u16;
+        cpu.s = register(initial, "s") as u8;
+        cpu.a = register(initial, "a") as u8;
+        cpu.x = register(initial, "x") as u8;
+        cpu.y = register(initial, "y") as u8;
+        cpu.set_p(register(initial, "p") as u8);
 
-        match file.read(ram.deref_mut()) {
-            Ok(n) => assert_eq!(n, 0x10000),
-            Err(why) => panic!("Couldn't read {}: {}", path.display(), why),
+        let cycles = case["cycles"].as_array().unwrap();
+        let (mut addr, mut data) = (cpu.pc, memory.get(&cpu.pc).copied().unwrap_or(0));
+        let mut trace = vec![(addr, data, "read")];
+        for _ in 1..cycles.len() {
+            let kind = match cpu.clock(addr, data) {
+                BusEvent::Read(read) => {
+                    (addr, data) = (read, memory.get(&read).copied().unwrap_or(0));
+                    "read"
+                }
+                BusEvent::Write(write, value) => {
+                    (addr, data) = (write, value);
+                    memory.insert(addr, data);
+                    "write"
+                }
+            };
+            trace.push((addr, data, kind));
+        }
+        // The last cycle finishes the instruction while fetching the next.
+        cpu.clock(addr, data);
+        if !cpu.at_instruction_start() {
+            return Err(format!(
+                "{name}: still running after {} cycles",
+                cycles.len()
+            ));
         }
 
-        let mut addr = 0x400;
-        let mut data = ram.read(addr);
+        for (i, cycle) in cycles.iter().enumerate() {
+            let expected = (
+                cycle[0].as_u64().unwrap() as u16,
+                cycle[1].as_u64().unwrap() as u8,
+                cycle[2].as_str().unwrap(),
+            );
+            if trace[i] != expected {
+                return Err(format!(
+                    "{name}: cycle {i} was {:?}, expected {expected:?}",
+                    trace[i]
+                ));
+            }
+        }
+        let registers = [
+            ("pc", cpu.pc as u64),
+            ("s", cpu.s as u64),
+            ("a", cpu.a as u64),
+            ("x", cpu.x as u64),
+            ("y", cpu.y as u64),
+        ];
+        for (register_name, value) in registers {
+            if value != register(expected, register_name) {
+                return Err(format!("{name}: {register_name} was {value:#x}"));
+            }
+        }
+        // B and bit 5 are not flags, only what gets pushed.
+        if (cpu.p() ^ register(expected, "p") as u8) & 0xCF != 0 {
+            return Err(format!("{name}: p was {:#04x}", cpu.p()));
+        }
+        for (addr, value) in cells(expected) {
+            if memory.get(&addr).copied() != Some(value) {
+                return Err(format!("{name}: ${addr:04x} was {:?}", memory.get(&addr)));
+            }
+        }
+        Ok(())
+    }
 
-        let mut cpu = Cpu::new();
-        cpu.pc = addr;
-        cpu.step = 0;
+    /// The JAM opcodes, which lock up rather than finish.
+    const NMOS_SKIP: [u8; 12] = [
+        0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+    ];
 
-        for _ in 0u64..96241364 {
+    #[test]
+    #[ignore = "needs test_roms/65x02"]
+    fn single_step_nmos6502() {
+        single_step_tests(Variant::Nmos6502, "6502", &NMOS_SKIP);
+    }
+
+    #[test]
+    #[ignore = "needs test_roms/65x02"]
+    fn single_step_ricoh2a03() {
+        single_step_tests(Variant::Ricoh2A03, "nes6502", &NMOS_SKIP);
+    }
+
+    #[test]
+    #[ignore = "needs test_roms/65x02"]
+    fn single_step_wdc65c02() {
+        // WAI and STP do not finish.
+        single_step_tests(Variant::Wdc65C02, "wdc65c02", &[0xCB, 0xDB]);
+    }
+
+    #[test]
+    fn single_step_checks_the_cycle_count() {
+        let case = |cycles: &str| {
+            let text = format!(
+                r#"{{"name": "a9 01", "cycles": [{cycles}],
+                "initial": {{"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                    "ram": [[512, 169], [513, 1]]}},
+                "final": {{"pc": 514, "s": 253, "a": 1, "x": 0, "y": 0, "p": 36,
+                    "ram": [[512, 169], [513, 1]]}}}}"#
+            );
+            serde_json::from_str::<Value>(&text).unwrap()
+        };
+        let lda = case(r#"[512, 169, "read"], [513, 1, "read"]"#);
+        assert_eq!(single_step(Variant::Nmos6502, &lda), Ok(()));
+        // A case listing too few cycles leaves the instruction unfinished.
+        let short = case(r#"[512, 169, "read"]"#);
+        assert!(single_step(Variant::Nmos6502, &short)
+            .unwrap_err()
+            .contains("still running"));
+    }
+
+    /// Runs `program` from $0200 until it falls off the end.
+    fn run_program(variant: Variant, program: &[u8]) -> (Vec<u8>, Cpu) {
+        let mut ram = vec![0u8; 0x10000];
+        ram[0x0200..0x0200 + program.len()].copy_from_slice(program);
+        let mut cpu = Cpu::new(variant);
+        cpu.pc = 0x0200;
+        let (mut addr, mut data) = (0x0200, ram[0x0200]);
+        while cpu.pc() < 0x0200 + program.len() as u16 || !cpu.at_instruction_start() {
             match cpu.clock(addr, data) {
-                BusEvent::Read ( addr_new ) => {
-                    data = ram.read(addr_new);
-                    addr = addr_new;
+                BusEvent::Read(read) => addr = read,
+                BusEvent::Write(write, value) => {
+                    (addr, data) = (write, value);
+                    ram[addr as usize] = data;
                 }
-                BusEvent::Write ( addr_new, data_new ) => {
-                    ram.write(addr_new, data_new);
-                    data = data_new;
-                    addr = addr_new;
+            }
+            data = ram[addr as usize];
+        }
+        (ram, cpu)
+    }
+
+    #[test]
+    fn decimal_mode_by_variant() {
+        #[rustfmt::skip]
+        let program = [
+            0xF8,       // SED
+            0x18,       // CLC
+            0xA9, 0x09, // LDA #$09
+            0x69, 0x01, // ADC #$01
+            0x85, 0x00, // STA $00
+            0x38,       // SEC
+            0xA9, 0x10, // LDA #$10
+            0xE9, 0x01, // SBC #$01
+            0x85, 0x01, // STA $01
+        ];
+        let run = |variant| {
+            let (ram, cpu) = run_program(variant, &program);
+            assert_ne!(cpu.p() & 0x08, 0, "D flag is kept");
+            [ram[0], ram[1]]
+        };
+        assert_eq!(run(Variant::Nmos6502), [0x10, 0x09]);
+        assert_eq!(run(Variant::Ricoh2A03), [0x0A, 0x0F]);
+        assert_eq!(run(Variant::Wdc65C02), [0x10, 0x09]);
+    }
+
+    #[test]
+    fn wdc65c02_opcodes() {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0xFF,       // LDA #$FF
+            0x85, 0x10,       // STA $10
+            0x64, 0x10,       // STZ $10
+            0xA9, 0x0C,       // LDA #$0C
+            0x85, 0x11,       // STA $11
+            0xA9, 0x05,       // LDA #$05
+            0x04, 0x11,       // TSB $11       ; $11 = $0D
+            0xA9, 0x04,       // LDA #$04
+            0x14, 0x11,       // TRB $11       ; $11 = $09
+            0xF7, 0x11,       // SMB7 $11      ; $11 = $89
+            0x8F, 0x11, 0x02, // BBS0 $11,+2
+            0xA9, 0xEE,       // LDA #$EE      ; skipped
+            0xA2, 0x42,       // LDX #$42
+            0xDA,             // PHX
+            0x7A,             // PLY
+            0x84, 0x12,       // STY $12
+            0xA9, 0x13,       // LDA #$13
+            0x85, 0x20,       // STA $20
+            0x1A,             // INC A
+            0x92, 0x20,       // STA ($20)     ; $13 = $14
+            0x80, 0x02,       // BRA +2
+            0x64, 0x13,       // STZ $13       ; skipped
+            0x6C, 0xFF, 0x02, // JMP ($02FF)   ; no page wrap
+        ];
+        let mut code = program.to_vec();
+        code.resize(0xFF, 0xEA);
+        // ($02FF) = $0301, just past the end of the code.
+        code.extend_from_slice(&[0x01, 0x03]);
+        let (ram, cpu) = run_program(Variant::Wdc65C02, &code);
+        assert_eq!(ram[0x10..0x14], [0x00, 0x89, 0x42, 0x14]);
+        assert_eq!(cpu.pc(), 0x0301);
+    }
+
+    #[test]
+    fn wai_wakes_on_interrupts() {
+        let program = crate::asm!(
+            Variant::Wdc65C02;
+            ".org $0200",
+            "  SEI; WAI; INX; WAI",
+            "after: INY",
+            "done: JMP done",
+            "irq: INC $10; RTI",
+            "nmi: INC $11; RTI",
+            ".org $FFFA",
+            ".word nmi, $0200, irq",
+        );
+        for engine in [Engine::Cycle, Engine::Instruction] {
+            let mut ram = vec![0u8; 0x10000];
+            program.load(&mut ram);
+            let mut cpu = Cpu::new(Variant::Wdc65C02);
+            cpu.set_pc(program.origin);
+            cpu.step(engine, &mut ram[..]);
+            cpu.step(engine, &mut ram[..]);
+            for _ in 0..10 {
+                assert!(cpu.halted(), "{engine:?}");
+                cpu.step(engine, &mut ram[..]);
+            }
+
+            // A masked IRQ wakes it without being taken.
+            cpu.irq();
+            for _ in 0..10 {
+                cpu.step(engine, &mut ram[..]);
+                if cpu.halted() {
+                    break;
                 }
             }
+            assert_eq!(cpu.x(), 1, "{engine:?}");
+            assert_eq!(ram[0x10], 0, "{engine:?}");
+            assert_eq!(cpu.pc(), program.label("after").unwrap(), "{engine:?}");
+
+            // An NMI wakes it and is taken.
+            cpu.nmi();
+            let done = program.label("done").unwrap();
+            for _ in 0..10 {
+                cpu.step(engine, &mut ram[..]);
+            }
+            assert_eq!((cpu.pc(), cpu.y()), (done, 1), "{engine:?}");
+            assert_eq!(ram[0x11], 1, "{engine:?}");
         }
+    }
 
-        assert_eq!(cpu.pc, 0x3469);
+    #[test]
+    fn interrupts_clear_decimal_on_65c02() {
+        let program = crate::asm!(
+            ".org $0200",
+            "  SED; BRK; .byte 0",
+            "  PHP; PLA; STA $12",
+            "done: JMP done",
+            "brk: PHP; PLA; STA $10; RTI",
+            "nmi: PHP; PLA; STA $11; RTI",
+            ".org $FFFA",
+            ".word nmi, $0200, brk",
+        );
+        let done = program.label("done").unwrap();
+        let run = |variant| {
+            let mut ram = vec![0u8; 0x10000];
+            program.load(&mut ram);
+            let mut cpu = Cpu::new(variant);
+            cpu.set_pc(program.origin);
+            while cpu.pc() != done {
+                cpu.step(Engine::Cycle, &mut ram[..]);
+            }
+            cpu.nmi();
+            for _ in 0..10 {
+                cpu.step(Engine::Cycle, &mut ram[..]);
+            }
+            // Bit 5, always pushed as 1, shows that each handler ran.
+            ram[0x10..0x13].iter().map(|p| p & 0x28).collect::<Vec<_>>()
+        };
+        // D is set in the handlers only on NMOS parts, and RTI restores it.
+        assert_eq!(run(Variant::Nmos6502), [0x28, 0x28, 0x28]);
+        assert_eq!(run(Variant::Wdc65C02), [0x20, 0x20, 0x28]);
+    }
+
+    #[test]
+    fn decimal_flags_and_cycles_on_65c02() {
+        let program = crate::asm!(
+            ".org $0200",
+            "  SED; CLC; LDA #$99; ADC #$01",
+            "  ADC #$79",
+            "  CLD; ADC #$01",
+        );
+        // A, N Z C, and the cycles taken by each ADC.
+        let run = |variant, engine| {
+            let mut ram = vec![0u8; 0x10000];
+            program.load(&mut ram);
+            let mut cpu = Cpu::new(variant);
+            cpu.set_pc(program.origin);
+            let mut results = Vec::new();
+            while cpu.pc() < program.end() {
+                let adc = ram[cpu.pc() as usize] == 0x69;
+                let cycles = cpu.step(engine, &mut ram[..]);
+                if adc {
+                    results.push((cpu.a(), cpu.p() & 0x83, cycles));
+                }
+            }
+            results
+        };
+        for engine in [Engine::Cycle, Engine::Instruction] {
+            // $99 + $01 = $00 and $00 + $79 + carry = $80 in BCD, with N and Z
+            // from those results and an extra cycle; then $80 + $01 in binary.
+            assert_eq!(
+                run(Variant::Wdc65C02, engine),
+                [(0x00, 0x03, 3), (0x80, 0x80, 3), (0x81, 0x80, 2)],
+                "{engine:?}"
+            );
+            let nmos = run(Variant::Nmos6502, engine);
+            assert_eq!(
+                nmos.iter()
+                    .map(|&(_, _, cycles)| cycles)
+                    .collect::<Vec<_>>(),
+                [2, 2, 2],
+                "{engine:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn engines_agree() {
+        let program = crate::asm!(
+            ".org $0200",
+            "  LDX #0",
+            "fill: TXA; STA $0300,X; INX; BNE fill",
+            "  LDA #$34; STA $10; LDA #$03; STA $11",
+            "  LDY #$F0; LDA ($10),Y; STA ($10),Y; INY; LDA ($10),Y",
+            "  LDX #$90; INC $0380,X; ASL $0301,X; ROR $20; LSR A; LDA $02F0,X",
+            "  LDX #$22; STA ($EE,X); LDY #3; STX $F0,Y; LDX $F0,Y",
+            "  JSR sub; SED; CLC; LDA #$19; ADC #$28; SBC #$05; CLD",
+            "  BRK; .byte 0",
+            "  CLI; LDA #1; BEQ back; JMP (ptr)",
+            "back: JMP back",
+            "sub: PHP; PHA; LDA #$80; BIT $0380; PLA; PLP; RTS",
+            "brk: INC $40; RTI",
+            "nmi: INC $41; RTI",
+            "ptr: .word done",
+            "done: JMP done",
+            ".org $FFFA",
+            ".word nmi, $0200, brk",
+        );
+        let done = program.label("done").unwrap();
+        for variant in [Variant::Nmos6502, Variant::Ricoh2A03, Variant::Wdc65C02] {
+            let mut cpus = [Cpu::new(variant), Cpu::new(variant)];
+            let mut rams = [vec![0u8; 0x10000], vec![0u8; 0x10000]];
+            for (cpu, ram) in cpus.iter_mut().zip(&mut rams) {
+                program.load(ram);
+                cpu.pc = program.origin;
+            }
+            for instruction in 0.. {
+                assert!(instruction < 5000, "{variant:?} never finished");
+                if instruction == 1000 {
+                    cpus.iter_mut().for_each(Cpu::nmi);
+                }
+                let [cycle, fast] = &mut cpus;
+                let [cycle_ram, fast_ram] = &mut rams;
+                let cycles = cycle.step(Engine::Cycle, &mut cycle_ram[..]);
+                assert_eq!(fast.step(Engine::Instruction, &mut fast_ram[..]), cycles);
+                let registers = |cpu: &Cpu| (cpu.pc, cpu.a, cpu.x, cpu.y, cpu.s, cpu.p());
+                assert_eq!(
+                    registers(cycle),
+                    registers(fast),
+                    "{variant:?} instruction {instruction}"
+                );
+                if cycle.pc == done {
+                    break;
+                }
+            }
+            assert!(rams[0] == rams[1], "{variant:?} memory differs");
+            assert_eq!(rams[0][0x41], 1, "{variant:?} took the NMI");
+        }
+    }
+
+    /// Runs every opcode that finishes, with and without indexing across a
+    /// page, and checks what its reads are said to be for.
+    #[test]
+    fn fetches_are_classified() {
+        use disasm::AddrMode;
+        for variant in [Variant::Nmos6502, Variant::Ricoh2A03, Variant::Wdc65C02] {
+            for opcode in 0..=255u8 {
+                let info = opcodes::info(variant, opcode);
+                if matches!(info.mnemonic, "JAM" | "STP" | "WAI") {
+                    continue;
+                }
+                for index in [0x00, 0xFF] {
+                    let mut ram = vec![0u8; 0x10000];
+                    ram[0x0200..0x0203].copy_from_slice(&[opcode, 0x10, 0x03]);
+                    // Pointers on the zero page and at $0310 lead to $0404.
+                    for addr in [0x000F, 0x0010, 0x0310, 0x040F] {
+                        ram[addr..addr + 2].copy_from_slice(&[0x04, 0x04]);
+                    }
+                    ram[0xFFFE..].copy_from_slice(&[0x00, 0x06]);
+                    let mut cpu = Cpu::new(variant);
+                    cpu.set_pc(0x0200);
+                    cpu.set_x(index);
+                    cpu.set_y(index);
+                    let mut reads = Vec::new();
+                    let mut event = BusEvent::Read(0x0200);
+                    loop {
+                        let (addr, data) = match event {
+                            BusEvent::Read(addr) => (addr, ram[addr as usize]),
+                            BusEvent::Write(addr, data) => {
+                                ram[addr as usize] = data;
+                                (addr, data)
+                            }
+                        };
+                        event = cpu.clock(addr, data);
+                        if let BusEvent::Read(addr) = event {
+                            reads.push((addr, cpu.fetch()));
+                        }
+                        if cpu.at_instruction_start() {
+                            break;
+                        }
+                    }
+
+                    let name = format!("{variant:?} ${opcode:02X} {}", info.mnemonic);
+                    let count = |fetch| reads.iter().filter(|(_, f)| *f == fetch).count();
+                    let indirect_jump = info.mnemonic == "JMP" && info.mode != AddrMode::Absolute;
+                    let last = if indirect_jump {
+                        Fetch::IndirectOpcode
+                    } else {
+                        Fetch::Opcode
+                    };
+                    assert_eq!(reads.last().unwrap(), &(cpu.pc(), last), "{name}");
+                    assert_eq!(count(Fetch::Opcode) + count(Fetch::IndirectOpcode), 1);
+                    // Undocumented NOPs skip reading their last operand.
+                    if !(info.mnemonic == "NOP" && info.bytes == 3) {
+                        let operands: Vec<u16> = reads
+                            .iter()
+                            .filter(|(_, fetch)| *fetch == Fetch::Operand)
+                            .map(|&(addr, _)| addr)
+                            .collect();
+                        let expected: Vec<u16> = (0x0201..0x0200 + info.bytes as u16).collect();
+                        assert_eq!(operands, expected, "{name}");
+                    }
+                    let pointers = match info.mode {
+                        AddrMode::IndexedIndirect
+                        | AddrMode::IndirectIndexed
+                        | AddrMode::ZeroPageIndirect
+                        | AddrMode::Indirect
+                        | AddrMode::AbsoluteIndexedIndirect => 2,
+                        _ => 0,
+                    };
+                    assert_eq!(count(Fetch::Pointer), pointers, "{name}");
+                    let data = count(Fetch::Data) + count(Fetch::IndirectData);
+                    assert!(data <= 1, "{name}");
+                    if count(Fetch::IndirectData) == 1 {
+                        let target =
+                            0x0404 + index as u16 * (info.mode == AddrMode::IndirectIndexed) as u16;
+                        assert!(reads.contains(&(target, Fetch::IndirectData)), "{name}");
+                    }
+                    let vectors = (info.mnemonic == "BRK") as usize * 2;
+                    assert_eq!(count(Fetch::Vector), vectors, "{name}");
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,245 @@
+//! Opcode tables for each [`Variant`], built at compile time. They are the
+//! one description of the instruction sets: the CPU decodes from them, and
+//! the disassembler, assembler and instruction-level engine take names,
+//! addressing modes and timings from them.
+
+use super::disasm::AddrMode;
+use super::instruction::*;
+use super::Variant;
+
+/// What an opcode does on one variant.
+#[derive(Debug, Clone, Copy)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub mode: AddrMode,
+    /// Length with the operand.
+    pub bytes: u8,
+    /// Cycles taken before penalties. Branches take one more when taken
+    /// and another when that crosses a page, and decimal ADC and SBC take
+    /// one more on the 65C02.
+    pub cycles: u8,
+    /// Whether indexing across a page costs a cycle.
+    pub page_penalty: bool,
+    /// False for the unofficial NMOS opcodes and the 65C02's undefined
+    /// NOPs.
+    pub official: bool,
+    pub(super) instruction: Instruction,
+}
+
+static NMOS: [OpcodeInfo; 256] = table(false);
+static CMOS: [OpcodeInfo; 256] = table(true);
+
+pub fn info(variant: Variant, opcode: u8) -> &'static OpcodeInfo {
+    match variant {
+        Variant::Nmos6502 | Variant::Ricoh2A03 => &NMOS[opcode as usize],
+        Variant::Wdc65C02 => &CMOS[opcode as usize],
+    }
+}
+
+const fn table(cmos: bool) -> [OpcodeInfo; 256] {
+    let mut table = [entry(0, cmos); 256];
+    let mut opcode = 1;
+    while opcode < 256 {
+        table[opcode] = entry(opcode as u8, cmos);
+        opcode += 1;
+    }
+    table
+}
+
+const fn entry(opcode: u8, cmos: bool) -> OpcodeInfo {
+    let instruction = if cmos {
+        decode_65c02(opcode)
+    } else {
+        decode_nmos(opcode)
+    };
+    let official = if cmos {
+        !matches!(instruction, Instruction::Nop { .. })
+    } else {
+        !matches!(decode_official(opcode), Instruction::Invalid(_))
+    };
+    let (mode, cycles, page_penalty) = timing(opcode, instruction, cmos);
+    OpcodeInfo {
+        mnemonic: mnemonic(instruction, cmos),
+        mode,
+        bytes: 1 + mode.operand_len() as u8,
+        cycles,
+        page_penalty,
+        official,
+        instruction,
+    }
+}
+
+const fn mnemonic(instruction: Instruction, cmos: bool) -> &'static str {
+    match instruction {
+        Instruction::Stack(stack_instruction) => stack_instruction.mnemonic(),
+        Instruction::AccumImpl(AccumImplInstruction::Internal(internal_instruction)) => {
+            internal_instruction.mnemonic()
+        }
+        Instruction::Imm(ImmInstruction::Read(read_instruction))
+        | Instruction::Abs(AbsInstruction::Read(read_instruction))
+        | Instruction::ZeroPage(ZeroPageInstruction::Read(read_instruction))
+        | Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Read(read_instruction))
+        | Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Read(read_instruction))
+        | Instruction::AbsIdxX(AbsIdxInstruction::Read(read_instruction))
+        | Instruction::AbsIdxY(AbsIdxInstruction::Read(read_instruction))
+        | Instruction::IdxInd(IdxIndInstruction::Read(read_instruction))
+        | Instruction::IndIdx(IndIdxInstruction::Read(read_instruction))
+        | Instruction::ZeroPageInd(ZeroPageIndInstruction::Read(read_instruction)) => {
+            read_instruction.mnemonic()
+        }
+        Instruction::Abs(AbsInstruction::Write(write_instruction))
+        | Instruction::ZeroPage(ZeroPageInstruction::Write(write_instruction))
+        | Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::Write(write_instruction))
+        | Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::Write(write_instruction))
+        | Instruction::AbsIdxX(AbsIdxInstruction::Write(write_instruction))
+        | Instruction::AbsIdxY(AbsIdxInstruction::Write(write_instruction))
+        | Instruction::IdxInd(IdxIndInstruction::Write(write_instruction))
+        | Instruction::IndIdx(IndIdxInstruction::Write(write_instruction))
+        | Instruction::ZeroPageInd(ZeroPageIndInstruction::Write(write_instruction)) => {
+            write_instruction.mnemonic()
+        }
+        Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(rmw))
+        | Instruction::Abs(AbsInstruction::ReadModifyWrite(rmw))
+        | Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(rmw))
+        | Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(rmw))
+        | Instruction::ZeroPageIdxY(ZeroPageIdxInstruction::ReadModifyWrite(rmw))
+        | Instruction::AbsIdxX(AbsIdxInstruction::ReadModifyWrite(rmw))
+        | Instruction::AbsIdxY(AbsIdxInstruction::ReadModifyWrite(rmw))
+        | Instruction::IdxInd(IdxIndInstruction::ReadModifyWrite(rmw))
+        | Instruction::IndIdx(IndIdxInstruction::ReadModifyWrite(rmw)) => rmw.mnemonic(),
+        Instruction::Abs(AbsInstruction::Jump(_))
+        | Instruction::AbsInd(_)
+        | Instruction::AbsIdxInd(_) => "JMP",
+        Instruction::Rel(RelInstruction::Branch(branch_instruction)) => {
+            branch_instruction.mnemonic()
+        }
+        Instruction::ZeroPageRel(ZeroPageRelInstruction::Bbr(bit)) => [
+            "BBR0", "BBR1", "BBR2", "BBR3", "BBR4", "BBR5", "BBR6", "BBR7",
+        ][bit as usize],
+        Instruction::ZeroPageRel(ZeroPageRelInstruction::Bbs(bit)) => [
+            "BBS0", "BBS1", "BBS2", "BBS3", "BBS4", "BBS5", "BBS6", "BBS7",
+        ][bit as usize],
+        Instruction::Halt(HaltInstruction::Wai) => "WAI",
+        Instruction::Halt(HaltInstruction::Stp) if cmos => "STP",
+        Instruction::Halt(HaltInstruction::Stp) => "JAM",
+        Instruction::Nop { .. } => "NOP",
+        Instruction::Invalid(_) => "???",
+    }
+}
+
+/// The addressing mode, base cycles and page penalty, matching what
+/// [`super::Cpu::clock`] does.
+const fn timing(opcode: u8, instruction: Instruction, cmos: bool) -> (AddrMode, u8, bool) {
+    use AddrMode::*;
+    match instruction {
+        Instruction::Stack(stack_instruction) => match stack_instruction {
+            StackInstruction::Brk(_) => (Implied, 7, false),
+            StackInstruction::Rti | StackInstruction::Rts => (Implied, 6, false),
+            StackInstruction::Pha
+            | StackInstruction::Php
+            | StackInstruction::Phx
+            | StackInstruction::Phy => (Implied, 3, false),
+            StackInstruction::Pla
+            | StackInstruction::Plp
+            | StackInstruction::Plx
+            | StackInstruction::Ply => (Implied, 4, false),
+            StackInstruction::Jsr => (Absolute, 6, false),
+        },
+        Instruction::AccumImpl(AccumImplInstruction::ReadModifyWrite(_)) => (Accumulator, 2, false),
+        Instruction::AccumImpl(AccumImplInstruction::Internal(_)) => (Implied, 2, false),
+        Instruction::Imm(_) => (Immediate, 2, false),
+        Instruction::Abs(AbsInstruction::Jump(_)) => (Absolute, 3, false),
+        Instruction::Abs(AbsInstruction::ReadModifyWrite(_)) => (Absolute, 6, false),
+        Instruction::Abs(_) => (Absolute, 4, false),
+        Instruction::ZeroPage(ZeroPageInstruction::ReadModifyWrite(_)) => (ZeroPage, 5, false),
+        Instruction::ZeroPage(_) => (ZeroPage, 3, false),
+        Instruction::ZeroPageIdxX(ZeroPageIdxInstruction::ReadModifyWrite(_)) => {
+            (ZeroPageX, 6, false)
+        }
+        Instruction::ZeroPageIdxX(_) => (ZeroPageX, 4, false),
+        Instruction::ZeroPageIdxY(_) => (ZeroPageY, 4, false),
+        Instruction::AbsIdxX(abs_idx_instruction) | Instruction::AbsIdxY(abs_idx_instruction) => {
+            let mode = match instruction {
+                Instruction::AbsIdxX(_) => AbsoluteX,
+                _ => AbsoluteY,
+            };
+            match abs_idx_instruction {
+                AbsIdxInstruction::Read(_) => (mode, 4, true),
+                AbsIdxInstruction::Write(_) => (mode, 5, false),
+                // The 65C02 only spends the fix-up cycle on shifts and
+                // rotates when it is needed.
+                AbsIdxInstruction::ReadModifyWrite(
+                    ReadModifyWriteInstruction::Inc | ReadModifyWriteInstruction::Dec,
+                ) => (mode, 7, false),
+                AbsIdxInstruction::ReadModifyWrite(_) if cmos => (mode, 6, true),
+                AbsIdxInstruction::ReadModifyWrite(_) => (mode, 7, false),
+            }
+        }
+        Instruction::Rel(_) => (Relative, 2, false),
+        Instruction::IdxInd(IdxIndInstruction::ReadModifyWrite(_)) => (IndexedIndirect, 8, false),
+        Instruction::IdxInd(_) => (IndexedIndirect, 6, false),
+        Instruction::IndIdx(IndIdxInstruction::Read(_)) => (IndirectIndexed, 5, true),
+        Instruction::IndIdx(IndIdxInstruction::Write(_)) => (IndirectIndexed, 6, false),
+        Instruction::IndIdx(IndIdxInstruction::ReadModifyWrite(_)) => (IndirectIndexed, 8, false),
+        Instruction::AbsInd(_) if cmos => (Indirect, 6, false),
+        Instruction::AbsInd(_) => (Indirect, 5, false),
+        Instruction::AbsIdxInd(_) => (AbsoluteIndexedIndirect, 6, false),
+        Instruction::ZeroPageInd(_) => (ZeroPageIndirect, 5, false),
+        Instruction::ZeroPageRel(_) => (ZeroPageRelative, 5, false),
+        Instruction::Halt(_) => (Implied, 3, false),
+        Instruction::Nop { bytes, cycles } => {
+            let mode = match (opcode, bytes) {
+                (0x44, _) => ZeroPage,
+                (0x54 | 0xD4 | 0xF4, _) => ZeroPageX,
+                (_, 1) => Implied,
+                (_, 2) => Immediate,
+                _ => Absolute,
+            };
+            (mode, cycles, false)
+        }
+        Instruction::Invalid(_) => (Implied, 0, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Cpu, Engine};
+
+    /// Runs every opcode with zeroed registers and memory, so nothing
+    /// crosses a page, once with every flag clear and once with every flag
+    /// and every bit of $00 set, so each branch is skipped once.
+    #[test]
+    fn cycles_match_the_core() {
+        for variant in [Variant::Nmos6502, Variant::Ricoh2A03, Variant::Wdc65C02] {
+            for opcode in 0..=255u8 {
+                let info = info(variant, opcode);
+                let cycles = [0x00, 0xFF]
+                    .map(|p| {
+                        let mut ram = vec![0u8; 0x10000];
+                        ram[0x0200] = opcode;
+                        ram[0x0000] = p;
+                        let mut cpu = Cpu::new(variant);
+                        cpu.set_pc(0x0200);
+                        cpu.set_p(p);
+                        cpu.step(Engine::Cycle, &mut ram[..])
+                    })
+                    .into_iter()
+                    .min()
+                    .unwrap();
+                // BRA is taken either way.
+                let taken = (info.mnemonic == "BRA") as u32;
+                assert_eq!(
+                    cycles,
+                    info.cycles as u32 + taken,
+                    "{variant:?} ${opcode:02X} {}",
+                    info.mnemonic
+                );
+            }
+        }
+        let official = (0..=255)
+            .filter(|&opcode| info(Variant::Nmos6502, opcode).official)
+            .count();
+        assert_eq!(official, 151);
+    }
+}
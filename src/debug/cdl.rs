@@ -0,0 +1,291 @@
+//! A code/data logger, recording how a program uses its ROM as it runs:
+//! which PRG bytes were executed, read as data, reached through pointers
+//! or played as DMC samples, and which CHR bytes were drawn or read
+//! through $2007.
+//!
+//! Logs load from and save to FCEUX's `.cdl` format: one byte of flags per
+//! byte of PRG ROM, then one per byte of CHR ROM, with the bits below. PRG
+//! flags also keep which 8KB window of $8000-$FFFF the byte was last used
+//! through, in bits 2 and 3.
+
+use crate::bus::BusEvent;
+use crate::cartridge::Cartridge;
+use crate::cpu::Fetch;
+use crate::memory_map::RomRead;
+use crate::nes::Nes;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CdlError {
+    /// The file doesn't match the cartridge's ROM sizes.
+    WrongSize { expected: usize, found: usize },
+}
+
+impl fmt::Display for CdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CdlError::WrongSize { expected, found } => {
+                write!(f, "log is {found} bytes, but this ROM needs {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CdlError {}
+
+/// Flags for each byte of a cartridge's PRG and CHR ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    /// PRG: run as part of an instruction.
+    pub const CODE: u8 = 0x01;
+    /// PRG: read as a value, a pointer or a vector.
+    pub const DATA: u8 = 0x02;
+    /// PRG: which 8KB window of $8000-$FFFF the byte was last used
+    /// through, numbered from 0 and shifted left by 2.
+    pub const WINDOW: u8 = 0x0C;
+    /// PRG: jumped to through a pointer.
+    pub const INDIRECT_CODE: u8 = 0x10;
+    /// PRG: read through a pointer.
+    pub const INDIRECT_DATA: u8 = 0x20;
+    /// PRG: played by the DMC.
+    pub const PCM: u8 = 0x40;
+    /// CHR: fetched for rendering.
+    pub const DRAWN: u8 = 0x01;
+    /// CHR: read by the CPU through $2007.
+    pub const READ: u8 = 0x02;
+
+    /// An empty log for `cartridge`.
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let header = cartridge.header();
+        CodeDataLog {
+            prg: vec![0; header.prg_rom_size],
+            chr: vec![0; header.chr_rom_size],
+        }
+    }
+
+    /// Reads a `.cdl` file made for `cartridge`.
+    pub fn from_cdl(cartridge: &Cartridge, data: &[u8]) -> Result<Self, CdlError> {
+        let mut log = CodeDataLog::new(cartridge);
+        let expected = log.prg.len() + log.chr.len();
+        if data.len() != expected {
+            return Err(CdlError::WrongSize {
+                expected,
+                found: data.len(),
+            });
+        }
+        let (prg, chr) = data.split_at(log.prg.len());
+        log.prg.copy_from_slice(prg);
+        log.chr.copy_from_slice(chr);
+        Ok(log)
+    }
+
+    pub fn to_cdl(&self) -> Vec<u8> {
+        [&self.prg[..], &self.chr[..]].concat()
+    }
+
+    /// Flags for each byte of PRG ROM.
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    /// Flags for each byte of CHR ROM; empty with CHR RAM.
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    pub fn clear(&mut self) {
+        self.prg.fill(0);
+        self.chr.fill(0);
+    }
+
+    /// Runs one CPU cycle of `nes` as [`Nes::clock`] does, logging the ROM
+    /// it reads. The console should hold the cartridge the log was made
+    /// for.
+    pub fn clock(&mut self, nes: &mut Nes) -> BusEvent {
+        nes.memory_mut().set_rom_read_logging(true);
+        let event = nes.clock();
+        nes.memory_mut().set_rom_read_logging(false);
+        if let BusEvent::Read(addr) = event {
+            let flags = match nes.cpu().fetch() {
+                Fetch::Opcode | Fetch::Operand => Self::CODE,
+                Fetch::IndirectOpcode => Self::CODE | Self::INDIRECT_CODE,
+                Fetch::Data | Fetch::Pointer | Fetch::Vector => Self::DATA,
+                Fetch::IndirectData => Self::DATA | Self::INDIRECT_DATA,
+                Fetch::Stack | Fetch::Dummy => 0,
+            };
+            let offset = nes
+                .cartridge()
+                .and_then(|cartridge| cartridge.mapper().prg_rom_offset(addr));
+            if let Some(flag) = offset.and_then(|offset| self.prg.get_mut(offset)) {
+                *flag |= flags;
+                if flags != 0 && addr >= 0x8000 {
+                    *flag = *flag & !Self::WINDOW | ((addr >> 11) as u8 & Self::WINDOW);
+                }
+            }
+        }
+        for read in nes.memory_mut().take_rom_reads() {
+            let (flag, flags) = match read {
+                RomRead::Pattern { offset, cpu } => (
+                    self.chr.get_mut(offset),
+                    if cpu { Self::READ } else { Self::DRAWN },
+                ),
+                RomRead::Sample { offset } => (self.prg.get_mut(offset), Self::PCM),
+            };
+            if let Some(flag) = flag {
+                *flag |= flags;
+            }
+        }
+        event
+    }
+
+    /// Runs until the PPU completes the frame in progress, logging as
+    /// [`CodeDataLog::clock`] does.
+    pub fn run_frame(&mut self, nes: &mut Nes) {
+        let frame = nes.frame();
+        while nes.frame() == frame {
+            self.clock(nes);
+        }
+    }
+}
+
+/// Counts of logged bytes, such as `PRG 1234 code, 567 data, 31000 unused
+/// of 32768; CHR 4096 drawn, 16 read of 8192`.
+impl fmt::Display for CodeDataLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |log: &[u8], flags: u8| log.iter().filter(|&&b| b & flags != 0).count();
+        let used = Self::CODE | Self::DATA | Self::PCM;
+        write!(
+            f,
+            "PRG {} code, {} data, {} unused of {}",
+            count(&self.prg, Self::CODE),
+            count(&self.prg, Self::DATA | Self::PCM),
+            self.prg.len() - count(&self.prg, used),
+            self.prg.len()
+        )?;
+        if !self.chr.is_empty() {
+            write!(
+                f,
+                "; CHR {} drawn, {} read of {}",
+                count(&self.chr, Self::DRAWN),
+                count(&self.chr, Self::READ),
+                self.chr.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Logs four frames of a program that uses PRG ROM in each way and
+    /// reads CHR ROM through $2007 with the background on.
+    fn logged() -> (crate::cpu::asm::Program, Nes, CodeDataLog) {
+        let program = crate::asm!(
+            ".org $C000",
+            "reset: SEI; LDX #$FF; TXS",
+            "wait1: BIT $2002; BPL wait1",
+            "wait2: BIT $2002; BPL wait2",
+            // The first read of $2007 fills the buffer from $0010.
+            "LDA #$00; STA $2006; LDA #$10; STA $2006; LDA $2007",
+            "LDA table; LDA #<table; STA $10; LDA #>table; STA $11",
+            "LDY #1; LDA ($10),Y",
+            "JMP (vector)",
+            "target: LDA #$0A; STA $2001",
+            "LDA #$0F; STA $4010; LDA #(sample - $C000) / 64; STA $4012",
+            "LDA #0; STA $4013; LDA #$10; STA $4015",
+            "loop: JMP loop",
+            "nmi: RTI",
+            "table: .byte 1, 2",
+            "vector: .word target",
+            ".org $C400",
+            "sample: .byte $55",
+            ".org $FFFA",
+            ".word nmi, reset, nmi",
+        );
+        let image = crate::cartridge::nrom_image(&program.bytes, &[0; 0x2000]);
+        let mut nes = Nes::from_ines(&image).unwrap();
+        let mut cdl = CodeDataLog::new(nes.cartridge().unwrap());
+        for _ in 0..4 {
+            cdl.run_frame(&mut nes);
+        }
+        (program, nes, cdl)
+    }
+
+    #[test]
+    fn logs_prg_code_and_data() {
+        let (program, _, cdl) = logged();
+        let prg = |label: &str, offset: u16| {
+            cdl.prg()[(program.label(label).unwrap() + offset - 0xC000) as usize]
+        };
+        // Everything but the vectors runs from $C000-$DFFF.
+        let window = 2 << 2;
+        assert_eq!(prg("reset", 0), CodeDataLog::CODE | window);
+        assert_eq!(prg("reset", 1), CodeDataLog::CODE | window);
+        assert_eq!(prg("table", 0), CodeDataLog::DATA | window);
+        assert_eq!(
+            prg("table", 1),
+            CodeDataLog::DATA | CodeDataLog::INDIRECT_DATA | window
+        );
+        assert_eq!(prg("vector", 1), CodeDataLog::DATA | window);
+        assert_eq!(
+            prg("target", 0),
+            CodeDataLog::CODE | CodeDataLog::INDIRECT_CODE | window
+        );
+        assert_eq!(prg("sample", 0), CodeDataLog::PCM);
+        assert_eq!(prg("sample", 1), 0);
+        assert_eq!(cdl.prg()[0x3FFC], CodeDataLog::DATA | 3 << 2);
+    }
+
+    #[test]
+    fn logs_chr_drawn_and_read() {
+        let (_, _, cdl) = logged();
+        assert_eq!(cdl.chr()[0x0010], CodeDataLog::READ);
+        assert_eq!(cdl.chr()[0x0000], CodeDataLog::DRAWN);
+        assert_eq!(cdl.chr()[0x1000], 0);
+        assert!(cdl.to_string().ends_with("; CHR 16 drawn, 1 read of 8192"));
+    }
+
+    #[test]
+    fn round_trips_with_chr_flags() {
+        let (_, mut nes, cdl) = logged();
+        let cartridge = nes.cartridge().unwrap();
+        let mut data = cdl.to_cdl();
+        assert_eq!(data.len(), 0x6000);
+        assert_eq!(CodeDataLog::from_cdl(cartridge, &data).as_ref(), Ok(&cdl));
+        assert_eq!(&data[..0x4000], cdl.prg());
+        assert_eq!(data[0x4000], CodeDataLog::DRAWN);
+        assert_eq!(data[0x4010], CodeDataLog::READ);
+        data[0x4020] = CodeDataLog::DRAWN | CodeDataLog::READ;
+
+        let mut loaded = CodeDataLog::from_cdl(cartridge, &data).unwrap();
+        assert_eq!(loaded.chr()[0x0020], CodeDataLog::DRAWN | CodeDataLog::READ);
+        assert_eq!(loaded.to_cdl(), data);
+
+        // A loaded log carries on where the saved one stopped.
+        loaded.run_frame(&mut nes);
+        assert_eq!(loaded.chr()[0x0000], CodeDataLog::DRAWN);
+        assert_eq!(loaded.chr()[0x0020], CodeDataLog::DRAWN | CodeDataLog::READ);
+        loaded.clear();
+        assert!(loaded.to_cdl().iter().all(|&flags| flags == 0));
+    }
+
+    #[test]
+    fn rejects_logs_of_the_wrong_size() {
+        let (_, nes, cdl) = logged();
+        let data = cdl.to_cdl();
+        assert_eq!(
+            CodeDataLog::from_cdl(nes.cartridge().unwrap(), &data[1..]),
+            Err(CdlError::WrongSize {
+                expected: 0x6000,
+                found: 0x5FFF
+            })
+        );
+    }
+}
@@ -0,0 +1,474 @@
+//! Breakpoint conditions, such as `a == $40 && [$0300] > 3 && frame > 120`.
+//!
+//! Values are signed 64-bit integers and comparisons give 1 or 0; an
+//! expression holds when it is non-zero. Operators bind as in Rust, so
+//! `value & $80 == $80` needs no parentheses. Tightest first:
+//!
+//! | Operators            | Meaning                          |
+//! |----------------------|----------------------------------|
+//! | `!x` `~x` `-x`       | logical not, complement, negate  |
+//! | `*` `/` `%`          | division by zero gives 0         |
+//! | `+` `-`              |                                  |
+//! | `<<` `>>`            |                                  |
+//! | `&`, then `^`, `\|`  | bitwise                          |
+//! | `==` `!=` `<` `<=` `>` `>=` | comparison                |
+//! | `&&`, then `\|\|`    | short-circuit                    |
+//!
+//! Numbers are decimal, or hex with `$` or `0x`. `[addr]` reads a byte and
+//! `{addr}` a little-endian word, without side effects. Names are case
+//! insensitive:
+//!
+//! - registers `a` `x` `y` `s` (or `sp`) `p` `pc`
+//! - flags `n` `v` `d` `i` `z` `c`, each 0 or 1
+//! - `cycle` (CPU cycles since power-on), `frame`, `scanline` and `dot`
+//! - for watchpoints, `addr` and `value`: the access being checked
+//! - labels, when parsed with [`Expr::parse_with`], which stand for their
+//!   address under the current mapping, or -1 while their bank is not mapped;
+//!   using a label named like one of the above, such as `Frame`, is an error
+
+use super::symbols::{Location, Symbols};
+use super::ParseError;
+use crate::nes::Nes;
+use std::fmt;
+use std::str::FromStr;
+
+/// A parsed expression. Displays as the text it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Var {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    Pc,
+    Flag(u8),
+    Cycle,
+    Frame,
+    Scanline,
+    Dot,
+    Addr,
+    Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unary {
+    Not,
+    Complement,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binary {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Var(Var),
+    Label(Location),
+    Byte(Box<Node>),
+    Word(Box<Node>),
+    Unary(Unary, Box<Node>),
+    Binary(Binary, Box<Node>, Box<Node>),
+}
+
+/// What an expression is evaluated against.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    pub nes: &'a Nes,
+    /// The address and value of the access a watchpoint is checking.
+    pub access: Option<(u16, u8)>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(nes: &'a Nes) -> Self {
+        Context { nes, access: None }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, ParseError> {
+        Expr::parse_with(text, &Symbols::new())
+    }
+
+    /// Parses `text`, taking names that are not variables from `symbols`.
+    /// Labels are case sensitive, and a name that is both a variable and a
+    /// label is rejected as ambiguous.
+    pub fn parse_with(text: &str, symbols: &Symbols) -> Result<Expr, ParseError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            symbols,
+        };
+        let root = parser.expr(0)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(ParseError(format!(
+                "unexpected '{}' in '{text}'",
+                token.text
+            )));
+        }
+        Ok(Expr {
+            source: text.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn eval(&self, ctx: &Context<'_>) -> i64 {
+        eval(&self.root, ctx)
+    }
+
+    /// Whether the expression is non-zero.
+    pub fn holds(&self, ctx: &Context<'_>) -> bool {
+        self.eval(ctx) != 0
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Expr, ParseError> {
+        Expr::parse(text)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn eval(node: &Node, ctx: &Context<'_>) -> i64 {
+    let peek = |addr: i64| ctx.nes.memory().peek(addr as u16) as i64;
+    match node {
+        Node::Number(value) => *value,
+        Node::Var(var) => {
+            let cpu = ctx.nes.cpu();
+            match var {
+                Var::A => cpu.a() as i64,
+                Var::X => cpu.x() as i64,
+                Var::Y => cpu.y() as i64,
+                Var::S => cpu.s() as i64,
+                Var::P => cpu.p() as i64,
+                Var::Pc => cpu.pc() as i64,
+                Var::Flag(bit) => (cpu.p() >> bit & 1) as i64,
+                Var::Cycle => ctx.nes.cpu_cycles() as i64,
+                Var::Frame => ctx.nes.frame() as i64,
+                Var::Scanline => ctx.nes.ppu().scanline() as i64,
+                Var::Dot => ctx.nes.ppu().dot() as i64,
+                Var::Addr => ctx.access.map_or(0, |(addr, _)| addr as i64),
+                Var::Value => ctx.access.map_or(0, |(_, value)| value as i64),
+            }
+        }
+        Node::Label(location) => location.address(ctx.nes).map_or(-1, |addr| addr as i64),
+        Node::Byte(addr) => peek(eval(addr, ctx)),
+        Node::Word(addr) => {
+            let addr = eval(addr, ctx);
+            peek(addr) | peek(addr.wrapping_add(1)) << 8
+        }
+        Node::Unary(op, operand) => {
+            let value = eval(operand, ctx);
+            match op {
+                Unary::Not => (value == 0) as i64,
+                Unary::Complement => !value,
+                Unary::Negate => value.wrapping_neg(),
+            }
+        }
+        Node::Binary(Binary::And, lhs, rhs) => (eval(lhs, ctx) != 0 && eval(rhs, ctx) != 0) as i64,
+        Node::Binary(Binary::Or, lhs, rhs) => (eval(lhs, ctx) != 0 || eval(rhs, ctx) != 0) as i64,
+        Node::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, ctx), eval(rhs, ctx));
+            match op {
+                Binary::Mul => lhs.wrapping_mul(rhs),
+                Binary::Div => lhs.checked_div(rhs).unwrap_or(0),
+                Binary::Rem => lhs.checked_rem(rhs).unwrap_or(0),
+                Binary::Add => lhs.wrapping_add(rhs),
+                Binary::Sub => lhs.wrapping_sub(rhs),
+                Binary::Shl => lhs.wrapping_shl(rhs as u32),
+                Binary::Shr => lhs.wrapping_shr(rhs as u32),
+                Binary::BitAnd => lhs & rhs,
+                Binary::BitXor => lhs ^ rhs,
+                Binary::BitOr => lhs | rhs,
+                Binary::Eq => (lhs == rhs) as i64,
+                Binary::Ne => (lhs != rhs) as i64,
+                Binary::Lt => (lhs < rhs) as i64,
+                Binary::Le => (lhs <= rhs) as i64,
+                Binary::Gt => (lhs > rhs) as i64,
+                Binary::Ge => (lhs >= rhs) as i64,
+                Binary::And | Binary::Or => unreachable!(),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    text: String,
+}
+
+/// Longest first, so `<=` is not read as `<`.
+const SYMBOLS: [&str; 26] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "+", "-", "*", "/", "%", "&", "|",
+    "^", "!", "~", "(", ")", "[", "]", "{", "}",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        let len = if c == '$' || c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(rest.len(), |len| len + 1);
+            let word = &rest[..len];
+            let kind = if c == '$' || c.is_ascii_digit() {
+                let hex = word.strip_prefix('$').or_else(|| word.strip_prefix("0x"));
+                let value = match hex {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => word.parse(),
+                };
+                Kind::Number(value.map_err(|_| ParseError(format!("bad number '{word}'")))?)
+            } else {
+                Kind::Name(word.to_ascii_lowercase())
+            };
+            tokens.push(Token {
+                kind,
+                text: word.to_string(),
+            });
+            len
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| ParseError(format!("unexpected '{c}' in '{text}'")))?;
+            tokens.push(Token {
+                kind: Kind::Symbol(symbol),
+                text: symbol.to_string(),
+            });
+            symbol.len()
+        };
+        rest = &rest[len..];
+    }
+}
+
+/// Binary operators by precedence level, loosest first.
+const LEVELS: [&[(&str, Binary)]; 9] = [
+    &[("||", Binary::Or)],
+    &[("&&", Binary::And)],
+    &[
+        ("==", Binary::Eq),
+        ("!=", Binary::Ne),
+        ("<", Binary::Lt),
+        ("<=", Binary::Le),
+        (">", Binary::Gt),
+        (">=", Binary::Ge),
+    ],
+    &[("|", Binary::BitOr)],
+    &[("^", Binary::BitXor)],
+    &[("&", Binary::BitAnd)],
+    &[("<<", Binary::Shl), (">>", Binary::Shr)],
+    &[("+", Binary::Add), ("-", Binary::Sub)],
+    &[("*", Binary::Mul), ("/", Binary::Div), ("%", Binary::Rem)],
+];
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    symbols: &'a Symbols,
+}
+
+impl Parser<'_> {
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos)?.kind {
+            Kind::Symbol(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if self.peek_symbol() == Some(symbol) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(ParseError(format!("expected '{symbol}'")))
+        }
+    }
+
+    fn expr(&mut self, level: usize) -> Result<Node, ParseError> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.expr(level + 1)?;
+        while let Some(&(_, op)) = self
+            .peek_symbol()
+            .and_then(|symbol| operators.iter().find(|(s, _)| *s == symbol))
+        {
+            self.pos += 1;
+            let rhs = self.expr(level + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        let op = match self.peek_symbol() {
+            Some("!") => Unary::Not,
+            Some("~") => Unary::Complement,
+            Some("-") => Unary::Negate,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseError("expression ends early".into()))?;
+        self.pos += 1;
+        match token.kind {
+            Kind::Number(value) => Ok(Node::Number(value)),
+            Kind::Name(name) => match (variable(&name), self.symbols.lookup(&token.text)) {
+                (Some(_), Some(_)) => Err(ParseError(format!(
+                    "'{}' is both a label and a built-in variable",
+                    token.text
+                ))),
+                (Some(var), None) => Ok(Node::Var(var)),
+                (None, Some(location)) => Ok(Node::Label(location)),
+                (None, None) => Err(ParseError(format!("unknown name '{}'", token.text))),
+            },
+            Kind::Symbol("(") => {
+                let node = self.expr(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Kind::Symbol("[") => {
+                let node = self.expr(0)?;
+                self.expect("]")?;
+                Ok(Node::Byte(Box::new(node)))
+            }
+            Kind::Symbol("{") => {
+                let node = self.expr(0)?;
+                self.expect("}")?;
+                Ok(Node::Word(Box::new(node)))
+            }
+            Kind::Symbol(_) => Err(ParseError(format!("unexpected '{}'", token.text))),
+        }
+    }
+}
+
+fn variable(name: &str) -> Option<Var> {
+    Some(match name {
+        "a" => Var::A,
+        "x" => Var::X,
+        "y" => Var::Y,
+        "s" | "sp" => Var::S,
+        "p" => Var::P,
+        "pc" => Var::Pc,
+        "n" => Var::Flag(7),
+        "v" => Var::Flag(6),
+        "d" => Var::Flag(3),
+        "i" => Var::Flag(2),
+        "z" => Var::Flag(1),
+        "c" => Var::Flag(0),
+        "cycle" => Var::Cycle,
+        "frame" => Var::Frame,
+        "scanline" => Var::Scanline,
+        "dot" => Var::Dot,
+        "addr" => Var::Addr,
+        "value" => Var::Value,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_eval() {
+        let nes = Nes::new();
+        let ctx = Context {
+            nes: &nes,
+            access: Some((0x2007, 0x40)),
+        };
+        let eval = |text: &str| Expr::parse(text).unwrap().eval(&ctx);
+
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("1 << 4 | 1"), 17);
+        assert_eq!(eval("a | 1 == 1 && 6 & 3 == 2"), 1);
+        assert_eq!(eval("$10 - 0x10 - 1"), -1);
+        assert_eq!(eval("!0 && ~0 == -1 || 1 / 0"), 1);
+        assert_eq!(eval("7 % 0 + 7 / 0"), 0);
+        assert_eq!(eval("P == $20 && !I && !c"), 1);
+        assert_eq!(eval("[$0000] == 0 && {$0000} == 0"), 1);
+        assert_eq!(eval("addr == $2007 && value & $40"), 1);
+        assert_eq!(eval("frame > 120"), 0);
+        assert_eq!(Expr::parse(" A == $40 ").unwrap().to_string(), "A == $40");
+
+        let mut symbols = Symbols::new();
+        symbols.insert("Buffer", Location::Cpu(0x0300));
+        symbols.insert(
+            "Reset",
+            Location::PrgRom {
+                offset: 0,
+                addr: None,
+            },
+        );
+        let eval = |text: &str| Expr::parse_with(text, &symbols).unwrap().eval(&ctx);
+        assert_eq!(eval("Buffer == $300 && [Buffer] == 0"), 1);
+        // No cartridge, so no PRG ROM is mapped.
+        assert_eq!(eval("Reset"), -1);
+        assert!(Expr::parse_with("buffer", &symbols).is_err());
+        symbols.insert("Frame", Location::Cpu(0x0010));
+        assert_eq!(
+            Expr::parse_with("Frame > 1", &symbols)
+                .unwrap_err()
+                .to_string(),
+            "'Frame' is both a label and a built-in variable"
+        );
+
+        for bad in ["", "a ==", "(1", "[2", "foo > 1", "1 2", "a = 1", "$12G4"] {
+            assert!(Expr::parse(bad).is_err(), "{bad} parsed");
+        }
+    }
+}
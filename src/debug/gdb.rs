@@ -0,0 +1,582 @@
+//! A GDB remote serial protocol server, so 6502 code can be debugged with
+//! GDB or any other RSP client.
+//!
+//! GDB has no 6502 target of its own, so the registers are described in
+//! `target.xml`: `a`, `x`, `y`, `s` and `p` as 8-bit registers, then a
+//! 16-bit `pc`, in that order in `g` packets. Memory is the CPU address
+//! space, read with [`MemoryMap::peek`](crate::memory_map::MemoryMap::peek)
+//! and written with [`MemoryMap::patch`](crate::memory_map::MemoryMap::patch),
+//! so ROM can be patched but registers can't be written.
+
+use super::{Access, Debugger, StopReason};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// CPU cycles run between checks for an interrupt from the client.
+const SLICE: u64 = 10_000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nesters.6502">
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="s" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// A stream a client is connected over.
+pub trait Connection: Read + Write {
+    /// Whether the client has sent an interrupt (Ctrl-C) while the target
+    /// was running. Must not block.
+    fn interrupted(&mut self) -> io::Result<bool>;
+}
+
+fn poll_interrupt(stream: &mut impl Read) -> io::Result<bool> {
+    let mut byte = [0];
+    match stream.read(&mut byte) {
+        // A closed connection stops the target too.
+        Ok(0) => Ok(true),
+        Ok(_) => Ok(byte[0] == 0x03),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let interrupted = poll_interrupt(self);
+        self.set_nonblocking(false)?;
+        interrupted
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let interrupted = poll_interrupt(self);
+        self.set_nonblocking(false)?;
+        interrupted
+    }
+}
+
+/// Serves one client at a time over a [`Debugger`].
+pub struct GdbServer {
+    debugger: Debugger,
+    /// Breakpoints and watchpoints set by `Z` packets, by type, address
+    /// and length.
+    points: HashMap<(u8, u16, u16), usize>,
+    last_stop: String,
+    ack: bool,
+    /// A byte read while waiting for an ack that belongs to the next packet.
+    unread: Option<u8>,
+}
+
+impl GdbServer {
+    pub fn new(debugger: Debugger) -> Self {
+        GdbServer {
+            debugger,
+            points: HashMap::new(),
+            last_stop: "S05".into(),
+            ack: true,
+            unread: None,
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Waits for one client on a TCP address and serves it.
+    pub fn listen_tcp(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        stream.set_nodelay(true)?;
+        self.serve(stream)
+    }
+
+    /// Waits for one client on a Unix socket and serves it.
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, path: impl AsRef<std::path::Path>) -> io::Result<()> {
+        let (stream, _) = std::os::unix::net::UnixListener::bind(path)?.accept()?;
+        self.serve(stream)
+    }
+
+    /// Answers packets until the client detaches, kills the target or
+    /// disconnects.
+    pub fn serve(&mut self, mut conn: impl Connection) -> io::Result<()> {
+        self.ack = true;
+        self.unread = None;
+        while let Some(packet) = self.read_packet(&mut conn)? {
+            let reply = match packet.as_str() {
+                "k" => return Ok(()),
+                "D" => {
+                    self.write_packet(&mut conn, "OK")?;
+                    return Ok(());
+                }
+                "QStartNoAckMode" => {
+                    self.write_packet(&mut conn, "OK")?;
+                    self.ack = false;
+                    continue;
+                }
+                _ => self.handle(&packet, &mut conn)?,
+            };
+            self.write_packet(&mut conn, &reply)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str, conn: &mut impl Connection) -> io::Result<String> {
+        if packet.is_empty() {
+            return Ok(String::new());
+        }
+        let (command, args) = packet.split_at(1);
+        let reply = match command {
+            "?" => self.last_stop.clone(),
+            "g" => self.read_registers(),
+            "G" => match hex_bytes(args) {
+                Some(bytes) if bytes.len() == 7 => {
+                    for (index, &byte) in bytes[..5].iter().enumerate() {
+                        self.write_register(index, byte as u16);
+                    }
+                    self.write_register(5, u16::from_le_bytes([bytes[5], bytes[6]]));
+                    "OK".into()
+                }
+                _ => "E01".into(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(index @ 0..=4) => format!("{:02x}", self.read_registers_raw()[index]),
+                Ok(5) => self.read_registers()[10..].into(),
+                _ => "E01".into(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(index, value)| {
+                    let index = usize::from_str_radix(index, 16).ok()?;
+                    let bytes = hex_bytes(value)?;
+                    let value = match bytes[..] {
+                        [value] => value as u16,
+                        [low, high] => u16::from_le_bytes([low, high]),
+                        _ => return None,
+                    };
+                    Some((index, value))
+                });
+                match parsed {
+                    Some((index @ 0..=5, value)) => {
+                        self.write_register(index, value);
+                        "OK".into()
+                    }
+                    _ => "E01".into(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => (0..len)
+                    .map(|offset| {
+                        let data = self.debugger.nes().memory().peek(addr.wrapping_add(offset));
+                        format!("{data:02x}")
+                    })
+                    .collect(),
+                None => "E01".into(),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let data = hex_bytes(data)?;
+                    (data.len() == len as usize).then_some((addr, data))
+                });
+                match parsed {
+                    Some((addr, data)) => {
+                        // All or nothing: a register or open bus in the
+                        // range leaves memory as it was.
+                        let memory = self.debugger.nes_mut().memory_mut();
+                        let addrs = (0..data.len() as u16).map(|offset| addr.wrapping_add(offset));
+                        if addrs.clone().all(|addr| memory.can_patch(addr)) {
+                            for (addr, byte) in addrs.zip(data) {
+                                memory.patch(addr, byte);
+                            }
+                            "OK".into()
+                        } else {
+                            "E02".into()
+                        }
+                    }
+                    None => "E01".into(),
+                }
+            }
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match u16::from_str_radix(args, 16) {
+                        Ok(pc) => self.debugger.nes_mut().set_pc(pc),
+                        Err(_) => return Ok("E01".into()),
+                    }
+                }
+                self.last_stop = if command == "s" {
+                    let reason = self.debugger.step_into();
+                    self.stop_reply(reason)
+                } else {
+                    self.resume(conn)?
+                };
+                self.last_stop.clone()
+            }
+            "Z" | "z" => self.set_point(command == "Z", args),
+            "H" => "OK".into(),
+            "q" => {
+                if args.starts_with("Supported") {
+                    "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".into()
+                } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+                    match parse_range(range) {
+                        Some((offset, len)) => {
+                            let xml = TARGET_XML.as_bytes();
+                            let start = (offset as usize).min(xml.len());
+                            let end = (start + len as usize).min(xml.len());
+                            let more = if end < xml.len() { "m" } else { "l" };
+                            format!("{more}{}", String::from_utf8_lossy(&xml[start..end]))
+                        }
+                        None => "E01".into(),
+                    }
+                } else {
+                    match args {
+                        "Attached" => "1".into(),
+                        "C" => "QC1".into(),
+                        "fThreadInfo" => "m1".into(),
+                        "sThreadInfo" => "l".into(),
+                        _ => String::new(),
+                    }
+                }
+            }
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    /// Runs until something stops the target or the client interrupts.
+    fn resume(&mut self, conn: &mut impl Connection) -> io::Result<String> {
+        loop {
+            let start = self.debugger.nes().cpu_cycles();
+            match self
+                .debugger
+                .run_until(|nes| nes.cpu_cycles() - start >= SLICE)
+            {
+                StopReason::Done => {
+                    if conn.interrupted()? {
+                        return Ok("S02".into());
+                    }
+                }
+                reason => return Ok(self.stop_reply(reason)),
+            }
+        }
+    }
+
+    fn set_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let parsed = (|| {
+            let kind: u8 = fields.next()?.parse().ok()?;
+            let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+            let len = u16::from_str_radix(fields.next()?, 16).ok()?;
+            Some((kind, addr, len))
+        })();
+        let Some(key @ (kind, addr, len)) = parsed else {
+            return "E01".into();
+        };
+        if !insert {
+            return match self.points.remove(&key) {
+                Some(id) => {
+                    self.debugger.remove_breakpoint(id);
+                    "OK".into()
+                }
+                None => "E01".into(),
+            };
+        }
+        let range = addr..=addr.wrapping_add(len.max(1) - 1);
+        let id = match kind {
+            0 | 1 => self.debugger.add_breakpoint(Some(addr), None),
+            2 => self.debugger.add_watchpoint(range, Access::Write, None),
+            3 => self.debugger.add_watchpoint(range, Access::Read, None),
+            4 => self.debugger.add_watchpoint(range, Access::Any, None),
+            _ => return String::new(),
+        };
+        if let Some(old) = self.points.insert(key, id) {
+            self.debugger.remove_breakpoint(old);
+        }
+        "OK".into()
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Done | StopReason::Breakpoint(_) | StopReason::FrameLimit => "S05".into(),
+            StopReason::Watchpoint { id, addr } => {
+                let access = self
+                    .debugger
+                    .watchpoints()
+                    .iter()
+                    .find(|watchpoint| watchpoint.id == id)
+                    .map(|watchpoint| watchpoint.access);
+                let kind = match access {
+                    Some(Access::Read) => "rwatch",
+                    Some(Access::Any) => "awatch",
+                    _ => "watch",
+                };
+                format!("T05{kind}:{addr:04x};")
+            }
+            StopReason::Halt(_) => "S04".into(),
+        }
+    }
+
+    fn read_registers_raw(&self) -> [u8; 7] {
+        let cpu = self.debugger.nes().cpu();
+        let [pc_low, pc_high] = cpu.pc().to_le_bytes();
+        [cpu.a(), cpu.x(), cpu.y(), cpu.s(), cpu.p(), pc_low, pc_high]
+    }
+
+    fn read_registers(&self) -> String {
+        self.read_registers_raw()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn write_register(&mut self, index: usize, value: u16) {
+        let nes = self.debugger.nes_mut();
+        let cpu = nes.cpu_mut();
+        match index {
+            0 => cpu.set_a(value as u8),
+            1 => cpu.set_x(value as u8),
+            2 => cpu.set_y(value as u8),
+            3 => cpu.set_s(value as u8),
+            4 => cpu.set_p(value as u8),
+            _ => nes.set_pc(value),
+        }
+    }
+
+    /// Reads the next packet, acknowledging it. `None` when the client
+    /// disconnects.
+    fn read_packet(&mut self, conn: &mut impl Connection) -> io::Result<Option<String>> {
+        loop {
+            // Skip acks and interrupts received while stopped.
+            loop {
+                match self.read_byte(conn)? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => {}
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte(conn)? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            conn.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum_of(&data));
+            if self.ack {
+                conn.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid || !self.ack {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn write_packet(&mut self, conn: &mut impl Connection, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum_of(data.as_bytes()));
+        loop {
+            conn.write_all(packet.as_bytes())?;
+            conn.flush()?;
+            if !self.ack {
+                return Ok(());
+            }
+            match self.read_byte(conn)? {
+                Some(b'-') => {}
+                Some(b'+') | None => return Ok(()),
+                // The client went on without acking; keep the byte for
+                // the packet reader.
+                Some(byte) => {
+                    self.unread = Some(byte);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// The next byte from the client, or `None` when it disconnects.
+    fn read_byte(&mut self, conn: &mut impl Connection) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.unread.take() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        Ok((conn.read(&mut byte)? != 0).then_some(byte[0]))
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `addr,length` in hex.
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((
+        u16::from_str_radix(addr, 16).ok()?,
+        u16::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::Nes;
+    use std::thread;
+
+    /// A scripted client: sends each packet and returns the reply.
+    struct Client {
+        stream: TcpStream,
+        ack: bool,
+    }
+
+    impl Client {
+        fn send(&mut self, packet: &str) -> String {
+            let sum = checksum_of(packet.as_bytes());
+            write!(self.stream, "${packet}#{sum:02x}").unwrap();
+            if self.ack {
+                assert_eq!(self.byte(), b'+');
+            }
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            assert_eq!(self.byte(), b'$');
+            let mut data = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let sum = [self.byte(), self.byte()];
+            assert_eq!(
+                u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap(),
+                checksum_of(&data)
+            );
+            if self.ack {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(data).unwrap()
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+    }
+
+    #[test]
+    fn scripted_session() {
+        #[rustfmt::skip]
+        let program = [
+            0xA2, 0x05,       // $8000: LDX #$05
+            0xE6, 0x10,       // $8002: loop: INC $10
+            0x4C, 0x02, 0x80, // $8004: JMP loop
+        ];
+        let image = crate::cartridge::program_image(&program);
+        let mut debugger = Debugger::new(Nes::from_ines(&image).unwrap());
+        debugger.step_into();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut client = Client {
+                stream: TcpStream::connect(addr).unwrap(),
+                ack: true,
+            };
+            assert!(client
+                .send("qSupported:swbreak+")
+                .contains("qXfer:features:read+"));
+            assert_eq!(client.send("?"), "S05");
+            // A packet in place of the ack for the last reply is still read.
+            write!(client.stream, "$?#3f").unwrap();
+            assert_eq!(client.byte(), b'+');
+            client.ack = false;
+            assert_eq!(client.reply(), "S05");
+            client.ack = true;
+            assert_eq!(client.send("p1"), "00");
+            assert_eq!(client.send("QStartNoAckMode"), "OK");
+            client.ack = false;
+            assert!(client
+                .send("qXfer:features:read:target.xml:0,1000")
+                .starts_with("l<?xml"));
+
+            assert_eq!(client.send("g"), "000000fd240080");
+            assert_eq!(client.send("s"), "S05");
+            assert_eq!(client.send("p1"), "05");
+
+            assert_eq!(client.send("Z0,8004,1"), "OK");
+            assert_eq!(client.send("c"), "S05");
+            assert_eq!(client.send("p5"), "0480");
+            assert_eq!(client.send("m10,1"), "01");
+            assert_eq!(client.send("z0,8004,1"), "OK");
+
+            assert_eq!(client.send("Z2,10,1"), "OK");
+            assert_eq!(client.send("c"), "T05watch:0010;");
+            assert_eq!(client.send("m10,1"), "02");
+            assert_eq!(client.send("z2,10,1"), "OK");
+            assert_eq!(client.send("Z3,10,1"), "OK");
+            assert_eq!(client.send("c"), "T05rwatch:0010;");
+            assert_eq!(client.send("z3,10,1"), "OK");
+            assert_eq!(client.send("Z4,10,1"), "OK");
+            assert_eq!(client.send("c"), "T05awatch:0010;");
+            assert_eq!(client.send("z4,10,1"), "OK");
+
+            assert_eq!(client.send("M10,2:ff42"), "OK");
+            assert_eq!(client.send("m10,2"), "ff42");
+            assert_eq!(client.send("M8005,1:ea"), "OK");
+            assert_eq!(client.send("m8004,3"), "4cea80");
+            assert_eq!(client.send("M8005,1:02"), "OK");
+            assert_eq!(client.send("M2000,1:80"), "E02");
+            // Nothing is written when part of the range can't be.
+            assert_eq!(client.send("M1ffe,2:aabb"), "OK");
+            assert_eq!(client.send("M1ffe,3:010203"), "E02");
+            assert_eq!(client.send("m1ffe,2"), "aabb");
+            assert_eq!(client.send("P1=aa"), "OK");
+            assert_eq!(&client.send("g")[2..4], "aa");
+
+            // Run freely until interrupted. The server only looks for the
+            // interrupt once running, so it can follow the packet at once.
+            write!(client.stream, "$c#63").unwrap();
+            client.stream.write_all(&[0x03]).unwrap();
+            assert_eq!(client.reply(), "S02");
+            assert_eq!(&client.send("p5")[2..], "80");
+            assert_eq!(client.send("D"), "OK");
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut server = GdbServer::new(debugger);
+        server.serve(stream).unwrap();
+        client.join().unwrap();
+        assert_eq!(server.debugger().nes().cpu().x(), 0xAA);
+    }
+}
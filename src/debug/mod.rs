@@ -0,0 +1,513 @@
+//! Breakpoints and stepping on top of [`Nes`], shared by the debugger
+//! front ends.
+
+mod cdl;
+mod expr;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+mod symbols;
+
+pub use cdl::{CdlError, CodeDataLog};
+pub use expr::{Context, Expr};
+pub use symbols::{Location, Symbols};
+
+use crate::bus::BusEvent;
+use crate::cpu::disasm::{self, Line};
+use crate::cpu::opcodes;
+use crate::nes::Nes;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Why [`Debugger`] handed control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step, frame or run finished.
+    Done,
+    /// Breakpoint with this id was hit before the instruction at PC ran.
+    Breakpoint(usize),
+    /// Watchpoint `id` saw an access to `addr` during the last instruction.
+    Watchpoint { id: usize, addr: u16 },
+    /// The next instruction, JAM or the 65C02's STP, would stop the CPU
+    /// for good.
+    Halt(u16),
+    /// The run used up the frames allowed by [`Debugger::set_frame_limit`].
+    FrameLimit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses `$C000`, `0xC000` or decimal `49152`.
+pub fn parse_number(text: &str) -> Result<u16, ParseError> {
+    let parsed = if let Some(hex) = text.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(hex) = text.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else {
+        text.parse()
+    };
+    parsed.map_err(|_| ParseError(format!("bad number '{text}'")))
+}
+
+/// Stops execution at an address, when a condition holds, or both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: Option<u16>,
+    pub condition: Option<Expr>,
+    pub enabled: bool,
+}
+
+impl Breakpoint {
+    fn hit(&self, nes: &Nes) -> bool {
+        self.enabled
+            && self.addr.is_none_or(|addr| addr == nes.cpu().pc())
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.holds(&Context::new(nes)))
+    }
+}
+
+/// Which CPU accesses a watchpoint catches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Any,
+}
+
+/// Stops execution after an instruction that touches `range`, if
+/// `condition` holds for the access. DMA is not watched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub range: RangeInclusive<u16>,
+    pub access: Access,
+    pub condition: Option<Expr>,
+    pub enabled: bool,
+}
+
+impl Watchpoint {
+    fn hit(&self, nes: &Nes, event: BusEvent) -> Option<u16> {
+        let (addr, value, write) = match event {
+            BusEvent::Read(addr) => (addr, nes.memory().data_bus(), false),
+            BusEvent::Write(addr, data) => (addr, data, true),
+        };
+        let access = match self.access {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::Any => true,
+        };
+        let hit = self.enabled
+            && access
+            && self.range.contains(&addr)
+            && self.condition.as_ref().is_none_or(|condition| {
+                condition.holds(&Context {
+                    nes,
+                    access: Some((addr, value)),
+                })
+            });
+        hit.then_some(addr)
+    }
+}
+
+/// A console under a debugger. Everything runs whole instructions, and
+/// breakpoints are checked before each one except the first, so resuming
+/// from a breakpoint moves past it.
+pub struct Debugger {
+    nes: Nes,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Symbols,
+    cdl: Option<CodeDataLog>,
+    frame_limit: Option<u64>,
+    next_id: usize,
+}
+
+impl Debugger {
+    /// Takes over `nes`, first finishing any instruction it is part way
+    /// through.
+    pub fn new(mut nes: Nes) -> Self {
+        while !nes.cpu().at_instruction_start() {
+            nes.clock();
+        }
+        Debugger {
+            nes,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            symbols: Symbols::new(),
+            cdl: None,
+            frame_limit: None,
+            next_id: 1,
+        }
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    /// The console, for changes such as pressing buttons. Leave it between
+    /// instructions.
+    pub fn nes_mut(&mut self) -> &mut Nes {
+        &mut self.nes
+    }
+
+    pub fn into_nes(self) -> Nes {
+        self.nes
+    }
+
+    /// Adds a breakpoint and returns its id. With neither an address nor a
+    /// condition it stops before every instruction.
+    pub fn add_breakpoint(&mut self, addr: Option<u16>, condition: Option<Expr>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id,
+            addr,
+            condition,
+            enabled: true,
+        });
+        id
+    }
+
+    /// Adds a watchpoint and returns its id, from the same sequence as
+    /// breakpoint ids.
+    pub fn add_watchpoint(
+        &mut self,
+        range: RangeInclusive<u16>,
+        access: Access,
+        condition: Option<Expr>,
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint {
+            id,
+            range,
+            access,
+            condition,
+            enabled: true,
+        });
+        id
+    }
+
+    /// Removes the breakpoint or watchpoint `id`. Returns false if there is
+    /// none.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        self.breakpoints.len() + self.watchpoints.len() != count
+    }
+
+    /// Enables or disables the breakpoint or watchpoint `id`. Returns false
+    /// if there is none.
+    pub fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        if let Some(breakpoint) = self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            breakpoint.enabled = enabled;
+        } else if let Some(watchpoint) = self.watchpoints.iter_mut().find(|wp| wp.id == id) {
+            watchpoint.enabled = enabled;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Labels used by [`Debugger::parse_expr`], disassembly and traces.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut Symbols {
+        &mut self.symbols
+    }
+
+    /// The code/data log being added to as the console runs, if any.
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.cdl.as_ref()
+    }
+
+    /// Starts logging into `cdl`, or with `None` stops. Returns the log
+    /// that was in use.
+    pub fn set_code_data_log(&mut self, cdl: Option<CodeDataLog>) -> Option<CodeDataLog> {
+        std::mem::replace(&mut self.cdl, cdl)
+    }
+
+    /// The label for `addr` under the current mapping.
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.symbols.label(&self.nes, addr)
+    }
+
+    /// Parses an expression that can use the loaded labels.
+    pub fn parse_expr(&self, text: &str) -> Result<Expr, ParseError> {
+        Expr::parse_with(text, &self.symbols)
+    }
+
+    /// Parses a number as [`parse_number`] does, or a label that is mapped.
+    pub fn parse_address(&self, text: &str) -> Result<u16, ParseError> {
+        match self.symbols.lookup(text) {
+            Some(location) => location
+                .address(&self.nes)
+                .ok_or_else(|| ParseError(format!("'{text}' is not mapped"))),
+            None => parse_number(text),
+        }
+    }
+
+    /// Disassembles the instruction at `addr` without disturbing the bus.
+    pub fn disassemble(&self, addr: u16) -> Line {
+        let variant = self.nes.cpu().variant();
+        disasm::disassemble_for(variant, addr, |addr| self.nes.memory().peek(addr))
+    }
+
+    /// The text of `line` with labels for the addresses it uses.
+    pub fn line_text(&self, line: &Line) -> String {
+        line.to_string_with(|addr| self.label(addr))
+    }
+
+    /// One line of an execution trace for the instruction about to run:
+    /// address, label, bytes, instruction, registers and CPU cycle.
+    pub fn trace_line(&self) -> String {
+        let cpu = self.nes.cpu();
+        let line = self.disassemble(cpu.pc());
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{b:02X}")).collect();
+        format!(
+            "${:04X} {:<12} {:<8}  {:<24} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} S:{:02X} CYC:{}",
+            line.addr,
+            self.label(line.addr).unwrap_or(""),
+            bytes.join(" "),
+            self.line_text(&line),
+            cpu.a(),
+            cpu.x(),
+            cpu.y(),
+            cpu.p(),
+            cpu.s(),
+            self.nes.cpu_cycles()
+        )
+    }
+
+    /// Makes each run give up after `frames` frames, or with `None` run
+    /// for as long as it takes.
+    pub fn set_frame_limit(&mut self, frames: Option<u64>) {
+        self.frame_limit = frames;
+    }
+
+    /// Runs one instruction, or one interrupt sequence if one is pending.
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(|_| true)
+    }
+
+    /// Like [`Debugger::step_into`], but runs a JSR through to its return.
+    pub fn step_over(&mut self) -> StopReason {
+        let cpu = self.nes.cpu();
+        let (pc, s) = (cpu.pc(), cpu.s());
+        if self.nes.memory().peek(pc) != 0x20 {
+            return self.step_into();
+        }
+        let ret = pc.wrapping_add(3);
+        self.run_until(|nes| nes.cpu().pc() == ret && nes.cpu().s() >= s)
+    }
+
+    /// Runs until the current subroutine or interrupt handler returns,
+    /// i.e. until the stack pointer rises above where it is now.
+    pub fn step_out(&mut self) -> StopReason {
+        let s = self.nes.cpu().s();
+        self.run_until(|nes| nes.cpu().s() > s)
+    }
+
+    /// Runs until the PPU starts the next frame.
+    pub fn run_frame(&mut self) -> StopReason {
+        let frame = self.nes.frame();
+        self.run_until(|nes| nes.frame() != frame)
+    }
+
+    /// Runs instructions until `done` holds after one, a breakpoint or
+    /// watchpoint is hit, an instruction that halts the CPU comes up or the
+    /// frame limit runs out.
+    pub fn run_until<F: FnMut(&Nes) -> bool>(&mut self, mut done: F) -> StopReason {
+        let end = self.frame_limit.map(|frames| self.nes.frame() + frames);
+        let mut first = true;
+        loop {
+            if !first {
+                if done(&self.nes) {
+                    return StopReason::Done;
+                }
+                if let Some(breakpoint) = self.breakpoints.iter().find(|bp| bp.hit(&self.nes)) {
+                    return StopReason::Breakpoint(breakpoint.id);
+                }
+                if end.is_some_and(|end| self.nes.frame() >= end) {
+                    return StopReason::FrameLimit;
+                }
+            }
+            first = false;
+
+            let cpu = self.nes.cpu();
+            let pc = cpu.pc();
+            let info = opcodes::info(cpu.variant(), self.nes.memory().peek(pc));
+            if matches!(info.mnemonic, "JAM" | "STP") {
+                return StopReason::Halt(pc);
+            }
+            let mut watch = None;
+            loop {
+                let event = match &mut self.cdl {
+                    Some(cdl) => cdl.clock(&mut self.nes),
+                    None => self.nes.clock(),
+                };
+                if watch.is_none() {
+                    watch = self.watchpoints.iter().find_map(|watchpoint| {
+                        let addr = watchpoint.hit(&self.nes, event)?;
+                        Some(StopReason::Watchpoint {
+                            id: watchpoint.id,
+                            addr,
+                        })
+                    });
+                }
+                if self.nes.cpu().at_instruction_start() {
+                    break;
+                }
+            }
+            if let Some(reason) = watch {
+                return reason;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger(program: &[u8]) -> Debugger {
+        let image = crate::cartridge::program_image(program);
+        let mut debugger = Debugger::new(Nes::from_ines(&image).unwrap());
+        // Through the reset sequence.
+        debugger.step_into();
+        debugger
+    }
+
+    #[test]
+    fn frame_limit_ends_runs() {
+        #[rustfmt::skip]
+        let program = [
+            0xE6, 0x10,       // $8000: loop: INC $10
+            0x4C, 0x00, 0x80, // $8002: JMP loop
+        ];
+        let mut debugger = debugger(&program);
+        debugger.set_frame_limit(Some(2));
+        // At the top level, nothing returns.
+        let frame = debugger.nes().frame();
+        assert_eq!(debugger.step_out(), StopReason::FrameLimit);
+        assert_eq!(debugger.nes().frame(), frame + 2);
+        assert_eq!(debugger.run_until(|_| false), StopReason::FrameLimit);
+        assert_eq!(debugger.nes().frame(), frame + 4);
+        assert_eq!(debugger.step_into(), StopReason::Done);
+    }
+
+    #[test]
+    fn steps_and_breakpoints() {
+        #[rustfmt::skip]
+        let program = [
+            0x20, 0x09, 0x80, // $8000: JSR sub
+            0xE6, 0x10,       // $8003: loop: INC $10
+            0x4C, 0x03, 0x80, // $8005: JMP loop
+            0x02,             // $8008: JAM
+            0x20, 0x0D, 0x80, // $8009: sub: JSR inner
+            0x60,             // $800C: RTS
+            0xE8,             // $800D: inner: INX
+            0x60,             // $800E: RTS
+        ];
+        let mut debugger = debugger(&program);
+        assert_eq!(debugger.nes().cpu().pc(), 0x8000);
+
+        assert_eq!(debugger.step_over(), StopReason::Done);
+        assert_eq!(debugger.nes().cpu().pc(), 0x8003);
+        assert_eq!(debugger.nes().cpu().x(), 1);
+
+        let id = debugger.add_breakpoint(Some(0x8003), "[$10] == 3".parse().ok());
+        assert_eq!(debugger.run_frame(), StopReason::Breakpoint(id));
+        assert_eq!(debugger.nes().memory().ram()[0x10], 3);
+        assert!(debugger.remove_breakpoint(id));
+
+        let mut debugger = self::debugger(&program);
+        debugger.step_into();
+        debugger.step_into();
+        assert_eq!(debugger.disassemble(0x800D).to_string(), "INX");
+        assert_eq!(debugger.nes().cpu().pc(), 0x800D);
+        assert_eq!(debugger.step_out(), StopReason::Done);
+        assert_eq!(debugger.nes().cpu().pc(), 0x800C);
+        assert_eq!(debugger.step_out(), StopReason::Done);
+        assert_eq!(debugger.nes().cpu().pc(), 0x8003);
+
+        let mut debugger = self::debugger(&program);
+        let condition = "value == 3 && x == 1".parse().ok();
+        let id = debugger.add_watchpoint(0x0010..=0x0010, Access::Write, condition);
+        assert_eq!(
+            debugger.run_frame(),
+            StopReason::Watchpoint { id, addr: 0x0010 }
+        );
+        assert_eq!(debugger.nes().cpu().pc(), 0x8005);
+        assert_eq!(debugger.nes().memory().ram()[0x10], 3);
+
+        let mut debugger = self::debugger(&program);
+        let symbols = debugger.symbols_mut();
+        let sub = Location::PrgRom {
+            offset: 0x0009,
+            addr: Some(0x8009),
+        };
+        symbols.insert("sub", sub);
+        symbols.insert("counter", Location::Cpu(0x0010));
+        assert_eq!(debugger.parse_address("sub"), Ok(0x8009));
+        let condition = debugger.parse_expr("[counter] == 2").ok();
+        let id = debugger.add_breakpoint(Some(0x8003), condition);
+        assert_eq!(debugger.run_frame(), StopReason::Breakpoint(id));
+        assert!(debugger
+            .trace_line()
+            .starts_with("$8003              E6 10     INC counter "));
+        assert_eq!(debugger.line_text(&debugger.disassemble(0x8000)), "JSR sub");
+
+        let mut debugger = self::debugger(&[0x4C, 0x08, 0x80, 0, 0, 0, 0, 0, 0x02]);
+        assert_eq!(debugger.step_into(), StopReason::Done);
+        assert_eq!(debugger.step_into(), StopReason::Halt(0x8008));
+    }
+
+    #[test]
+    fn steps_through_unofficial_opcodes() {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x42, // $8000: LDA #$42
+            0x85, 0x10, // $8002: STA $10
+            0xA9, 0x00, // $8004: LDA #$00
+            0xA7, 0x10, // $8006: LAX $10
+            0xC7, 0x10, // $8008: DCP $10
+        ];
+        let mut debugger = debugger(&program);
+        assert_eq!(debugger.disassemble(0x8006).to_string(), "LAX $10");
+        assert_eq!(debugger.disassemble(0x8008).to_string(), "DCP $10");
+        for _ in 0..4 {
+            assert_eq!(debugger.step_into(), StopReason::Done);
+        }
+        assert_eq!(debugger.nes().cpu().a(), 0x42);
+        assert_eq!(debugger.nes().cpu().x(), 0x42);
+        assert!(debugger
+            .trace_line()
+            .starts_with("$8008              C7 10     DCP $10 "));
+        assert_eq!(debugger.step_into(), StopReason::Done);
+        assert_eq!(debugger.nes().memory().ram()[0x10], 0x41);
+        assert_eq!(debugger.nes().cpu().pc(), 0x800A);
+    }
+}
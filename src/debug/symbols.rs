@@ -0,0 +1,381 @@
+//! Labels from assembler and emulator symbol files:
+//!
+//! - ca65/ld65 debug info, as written by `ld65 --dbgfile`
+//! - NESASM `.fns` files, lines of `label = $C000`
+//! - Mesen `.mlb` label files, with either the `P:` or the `NesPrgRom:`
+//!   style of memory type
+//!
+//! Labels in PRG ROM are kept as offsets into the ROM rather than CPU
+//! addresses, so one only names an address while its bank is mapped there.
+//! NESASM files carry no bank numbers, so their labels are plain addresses.
+
+use super::ParseError;
+use crate::nes::Nes;
+use std::collections::HashMap;
+
+/// Where a label points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    /// A fixed CPU address: RAM, registers, or unbanked ROM.
+    Cpu(u16),
+    /// An offset into PRG ROM, wherever the mapper has put it. `addr` is
+    /// the address it was assembled for, if the file says.
+    PrgRom { offset: usize, addr: Option<u16> },
+}
+
+impl Location {
+    /// The CPU address of the location under the current mapping, if its
+    /// bank is mapped at all. A bank mapped twice, as with 16KB NROM, gives
+    /// the assembled address if that is one of them, or else the highest.
+    pub fn address(self, nes: &Nes) -> Option<u16> {
+        match self {
+            Location::Cpu(addr) => Some(addr),
+            Location::PrgRom { offset, addr } => addr
+                .into_iter()
+                .chain(
+                    (0x6000..=0xE000)
+                        .rev()
+                        .step_by(0x2000)
+                        .map(|window| window + (offset % 0x2000) as u16),
+                )
+                .find(|&addr| prg_rom_offset(nes, addr) == Some(offset)),
+        }
+    }
+}
+
+fn prg_rom_offset(nes: &Nes, addr: u16) -> Option<usize> {
+    nes.cartridge()?.mapper().prg_rom_offset(addr)
+}
+
+/// A set of labels, looked up by name or by address.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    names: HashMap<String, Location>,
+    cpu: HashMap<u16, String>,
+    prg_rom: HashMap<usize, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Adds a label. A name given twice keeps its latest location; an
+    /// address with two names is shown with the first.
+    pub fn insert(&mut self, name: &str, location: Location) {
+        let name = name.to_string();
+        self.names.insert(name.clone(), location);
+        match location {
+            Location::Cpu(addr) => self.cpu.entry(addr).or_insert(name),
+            Location::PrgRom { offset, .. } => self.prg_rom.entry(offset).or_insert(name),
+        };
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Location> {
+        self.names.get(name).copied()
+    }
+
+    /// The label for CPU address `addr` under the current mapping.
+    pub fn label(&self, nes: &Nes, addr: u16) -> Option<&str> {
+        prg_rom_offset(nes, addr)
+            .and_then(|offset| self.prg_rom.get(&offset))
+            .or_else(|| self.cpu.get(&addr))
+            .map(String::as_str)
+    }
+
+    /// Adds the labels from ld65 debug info. Cheap local labels (`@loop`)
+    /// and imports are skipped.
+    pub fn add_ca65(&mut self, text: &str) -> Result<(), ParseError> {
+        struct Segment {
+            start: usize,
+            rom_offset: Option<usize>,
+        }
+
+        let mut segments = HashMap::new();
+        let mut symbols = Vec::new();
+        let mut header = 0;
+        for (number, line) in text.lines().enumerate() {
+            let bad = || ParseError(format!("line {}: bad ca65 debug info", number + 1));
+            let Some((kind, fields)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let fields: HashMap<&str, &str> = fields
+                .split(',')
+                .filter_map(|field| field.trim().split_once('='))
+                .collect();
+            let number = |key: &str| fields.get(key).and_then(|value| parse_int(value));
+            match kind {
+                "seg" => {
+                    let id = number("id").ok_or_else(bad)?;
+                    let start = number("start").ok_or_else(bad)?;
+                    let rom = fields.get("type") == Some(&"ro");
+                    let offset = number("ooffs").filter(|_| rom);
+                    // iNES output starts with the header, as a segment of its
+                    // own that isn't mapped into ROM space; PRG ROM follows.
+                    if offset == Some(0) && start < 0x6000 {
+                        header = number("size").ok_or_else(bad)?;
+                    }
+                    segments.insert(
+                        id,
+                        Segment {
+                            start,
+                            rom_offset: offset.filter(|_| start >= 0x6000),
+                        },
+                    );
+                }
+                "sym" => {
+                    let name = fields.get("name").ok_or_else(bad)?.trim_matches('"');
+                    if name.starts_with('@') || fields.get("type") == Some(&"imp") {
+                        continue;
+                    }
+                    let Some(value) = number("val") else {
+                        continue;
+                    };
+                    symbols.push((name, value, number("seg")));
+                }
+                _ => {}
+            }
+        }
+
+        for (name, value, segment) in symbols {
+            let segment = segment.and_then(|id| segments.get(&id));
+            let location = match segment {
+                Some(Segment {
+                    start,
+                    rom_offset: Some(offset),
+                }) if value >= *start && *offset >= header => Location::PrgRom {
+                    offset: offset - header + value - start,
+                    addr: Some(value as u16),
+                },
+                _ => Location::Cpu(value as u16),
+            };
+            self.insert(name, location);
+        }
+        Ok(())
+    }
+
+    /// Adds the labels from a NESASM `.fns` file.
+    pub fn add_fns(&mut self, text: &str) -> Result<(), ParseError> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let bad = || ParseError(format!("line {}: expected 'label = $addr'", number + 1));
+            let (name, value) = line.split_once('=').ok_or_else(bad)?;
+            let addr = value
+                .trim()
+                .strip_prefix('$')
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .ok_or_else(bad)?;
+            self.insert(name.trim(), Location::Cpu(addr));
+        }
+        Ok(())
+    }
+
+    /// Adds the labels from a Mesen label file. Work and save RAM labels
+    /// are taken to be at $6000 onwards, ignoring any RAM banking, and
+    /// memory types for other systems are skipped.
+    pub fn add_mlb(&mut self, text: &str) -> Result<(), ParseError> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = || ParseError(format!("line {}: expected 'TYPE:ADDR:label'", number + 1));
+            let mut fields = line.splitn(4, ':');
+            let (Some(kind), Some(addr), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(bad());
+            };
+            let start = addr.split('-').next().unwrap_or(addr);
+            let offset = usize::from_str_radix(start, 16).map_err(|_| bad())?;
+            let location = match kind {
+                "P" | "NesPrgRom" => Location::PrgRom { offset, addr: None },
+                "R" | "NesInternalRam" => Location::Cpu(offset as u16 & 0x07FF),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => Location::Cpu(0x6000 + offset as u16),
+                "G" | "NesMemory" => Location::Cpu(offset as u16),
+                _ => continue,
+            };
+            // Lines with only a comment have no name.
+            if !name.is_empty() {
+                self.insert(name, location);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_int(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA65: &str = "\
+version\tmajor=2,minor=0
+seg\tid=0,name=\"HEADER\",start=0x000000,size=0x0010,addrsize=absolute,type=ro,oname=\"a.nes\",ooffs=0
+seg\tid=1,name=\"CODE\",start=0x00C000,size=0x4000,addrsize=absolute,type=ro,oname=\"a.nes\",ooffs=16
+seg\tid=2,name=\"ZEROPAGE\",start=0x000000,size=0x0002,addrsize=zeropage,type=rw
+sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=1,ref=4,val=0xC010,seg=1,type=lab
+sym\tid=1,name=\"@loop\",addrsize=absolute,scope=0,def=2,val=0xC012,seg=1,type=lab,parent=0
+sym\tid=2,name=\"counter\",addrsize=zeropage,scope=0,def=3,val=0x1,seg=2,type=lab
+sym\tid=3,name=\"PPUCTRL\",addrsize=absolute,scope=0,def=5,val=0x2000,type=equ
+";
+
+    /// A 16KB NROM board, mapped at both $8000 and $C000.
+    fn nrom() -> Nes {
+        Nes::from_ines(&crate::cartridge::nrom_image(&[0; 0x4000], &[0; 0x2000])).unwrap()
+    }
+
+    #[test]
+    fn ca65_labels() {
+        let nes = nrom();
+        let mut symbols = Symbols::new();
+        symbols.add_ca65(CA65).unwrap();
+        let reset = symbols.lookup("reset").unwrap();
+        assert_eq!(
+            reset,
+            Location::PrgRom {
+                offset: 0x10,
+                addr: Some(0xC010)
+            }
+        );
+        assert_eq!(symbols.lookup("counter"), Some(Location::Cpu(0x01)));
+        assert_eq!(symbols.lookup("PPUCTRL"), Some(Location::Cpu(0x2000)));
+        assert_eq!(symbols.lookup("@loop"), None);
+        assert_eq!(reset.address(&nes), Some(0xC010));
+        assert_eq!(symbols.label(&nes, 0x8010), Some("reset"));
+        assert_eq!(symbols.label(&nes, 0x2000), Some("PPUCTRL"));
+        let unplaced = Location::PrgRom {
+            offset: 0x10,
+            addr: None,
+        };
+        assert_eq!(unplaced.address(&nes), Some(0xC010));
+
+        // The header is found by where it is, whatever it is called.
+        let mut renamed = Symbols::new();
+        renamed
+            .add_ca65(&CA65.replace("\"HEADER\"", "\"INES\""))
+            .unwrap();
+        assert_eq!(renamed.lookup("reset"), Some(reset));
+    }
+
+    #[test]
+    fn malformed_ca65_lines() {
+        let error = |text: &str| Symbols::new().add_ca65(text).unwrap_err().0;
+        // A segment without its start, a symbol without a name, an id
+        // that isn't a number.
+        assert_eq!(
+            error("version\tmajor=2\nseg\tid=0,name=\"CODE\",size=0x10"),
+            "line 2: bad ca65 debug info"
+        );
+        assert_eq!(
+            error("sym\tid=0,val=0xC000,seg=1"),
+            "line 1: bad ca65 debug info"
+        );
+        assert_eq!(
+            error("seg\tid=x,start=0xC000"),
+            "line 1: bad ca65 debug info"
+        );
+        // A header segment must give its size.
+        assert_eq!(
+            error("seg\tid=0,start=0x0,type=ro,ooffs=0"),
+            "line 1: bad ca65 debug info"
+        );
+        // Symbols without a value and unknown line kinds are skipped.
+        let mut symbols = Symbols::new();
+        symbols
+            .add_ca65("sym\tid=0,name=\"later\"\nscope\tid=0,name=\"\"\nfile\n")
+            .unwrap();
+        assert!(symbols.is_empty());
+    }
+
+    #[test]
+    fn labels_in_switched_banks() {
+        use crate::bus::BusDevice;
+
+        // FME7 with four 8KB PRG banks; $8000 starts out on bank 0.
+        let mut image = crate::cartridge::nrom_image(&[0; 0x8000], &[0; 0x2000]);
+        image[6] = 69 << 4;
+        image[7] = 69 & 0xF0;
+        let mut nes = Nes::from_ines(&image).unwrap();
+
+        let mut symbols = Symbols::new();
+        let dbg = "\
+seg\tid=0,name=\"HEADER\",start=0x000000,size=0x0010,type=ro,ooffs=0
+seg\tid=1,name=\"BANK1\",start=0x008000,size=0x2000,type=ro,ooffs=8208
+sym\tid=0,name=\"banked\",val=0x8010,seg=1,type=lab
+";
+        symbols.add_ca65(dbg).unwrap();
+        symbols.add_mlb("P:2020:also_banked\n").unwrap();
+        let banked = symbols.lookup("banked").unwrap();
+        let also_banked = symbols.lookup("also_banked").unwrap();
+        assert_eq!(
+            banked,
+            Location::PrgRom {
+                offset: 0x2010,
+                addr: Some(0x8010)
+            }
+        );
+        assert_eq!(banked.address(&nes), None);
+        assert_eq!(also_banked.address(&nes), None);
+        assert_eq!(symbols.label(&nes, 0x8010), None);
+
+        // Bank 1 into $A000, then into $8000.
+        nes.memory_mut().write(0x8000, 0x0A);
+        nes.memory_mut().write(0xA000, 1);
+        assert_eq!(banked.address(&nes), Some(0xA010));
+        assert_eq!(symbols.label(&nes, 0xA010), Some("banked"));
+        nes.memory_mut().write(0xA000, 0);
+        nes.memory_mut().write(0x8000, 0x09);
+        nes.memory_mut().write(0xA000, 1);
+        assert_eq!(banked.address(&nes), Some(0x8010));
+        assert_eq!(also_banked.address(&nes), Some(0x8020));
+        assert_eq!(symbols.label(&nes, 0x8010), Some("banked"));
+        assert_eq!(symbols.label(&nes, 0xA010), None);
+    }
+
+    #[test]
+    fn fns_labels() {
+        let nes = nrom();
+        let mut symbols = Symbols::new();
+        symbols
+            .add_fns(";File : game.asm\nreset       = $C010\nnmi = $C100\n")
+            .unwrap();
+        assert_eq!(symbols.lookup("nmi"), Some(Location::Cpu(0xC100)));
+        // Plain addresses don't follow the ROM to its mirror.
+        assert_eq!(symbols.label(&nes, 0xC010), Some("reset"));
+        assert_eq!(symbols.label(&nes, 0x8010), None);
+        assert!(symbols.add_fns("reset $C010").is_err());
+        assert!(symbols.add_fns("reset = C010").is_err());
+    }
+
+    #[test]
+    fn mlb_labels() {
+        let nes = nrom();
+        let mut symbols = Symbols::new();
+        let mlb = "P:0010:reset\nR:0701:flags:saved\nNesWorkRam:0000-0003:score\nP:0011::only a comment\nSnesPrgRom:0000:other\n";
+        symbols.add_mlb(mlb).unwrap();
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.lookup("flags"), Some(Location::Cpu(0x0701)));
+        assert_eq!(symbols.lookup("score"), Some(Location::Cpu(0x6000)));
+        assert_eq!(symbols.label(&nes, 0xC010), Some("reset"));
+        assert!(symbols.add_mlb("P:zz:reset").is_err());
+        assert!(symbols.add_mlb("P:0010").is_err());
+    }
+}
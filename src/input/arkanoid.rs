@@ -0,0 +1,96 @@
+use super::InputDevice;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Taito's Arkanoid controller (Vaus): a knob whose potentiometer is
+/// digitized when the strobe goes high, then shifted out inverted, MSB
+/// first, alongside the fire button.
+///
+/// The NES version plugs into port 2, with the button on D4 and the serial
+/// data on D3 of $4017. The Famicom version sits on the expansion port, with
+/// the button on D1 of $4016 and the data on D1 of $4017.
+#[derive(Debug, Clone)]
+pub struct Arkanoid {
+    famicom: bool,
+    position: u8,
+    button: bool,
+    shift: u8,
+}
+
+impl Arkanoid {
+    pub fn new() -> Self {
+        Arkanoid {
+            famicom: false,
+            // Roughly the middle of the knob's travel.
+            position: 0xA0,
+            button: false,
+            shift: 0,
+        }
+    }
+
+    /// The Famicom version, for the expansion port.
+    pub fn famicom() -> Self {
+        Arkanoid {
+            famicom: true,
+            ..Arkanoid::new()
+        }
+    }
+
+    /// Sets the knob reading. Real controllers cover about $62-$F2;
+    /// games calibrate against what they see.
+    pub fn set_position(&mut self, position: u8) {
+        self.position = position;
+    }
+
+    pub fn position(&self) -> u8 {
+        self.position
+    }
+
+    pub fn set_button(&mut self, pressed: bool) {
+        self.button = pressed;
+    }
+}
+
+impl Default for Arkanoid {
+    fn default() -> Self {
+        Arkanoid::new()
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn write(&mut self, data: u8) {
+        if data & 0x01 != 0 {
+            self.shift = !self.position;
+        }
+    }
+
+    fn read(&mut self, addr: u16, _ppu: &Ppu) -> u8 {
+        let button = self.button as u8;
+        match (self.famicom, addr) {
+            (true, 0x4016) => button << 1,
+            (false, 0x4016) => 0,
+            (famicom, _) => {
+                let bit = self.shift >> 7;
+                self.shift <<= 1;
+                if famicom {
+                    bit << 1
+                } else {
+                    bit << 3 | button << 4
+                }
+            }
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.position);
+        w.write(&self.button);
+        w.write(&self.shift);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.position)?;
+        r.read_into(&mut self.button)?;
+        r.read_into(&mut self.shift)?;
+        Ok(())
+    }
+}
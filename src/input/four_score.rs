@@ -0,0 +1,133 @@
+use super::{InputDevice, Joypad, Port};
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// One half of the NES Four Score. Each controller port carries its own
+/// chain: the first joypad, the second, then an 8-bit signature telling the
+/// game an adapter is present. Later reads return 1.
+///
+/// On port 1 the joypads are players 1 and 3, on port 2 players 2 and 4.
+#[derive(Debug, Clone)]
+pub struct FourScore {
+    pads: [Joypad; 2],
+    signature: u8,
+    shift: u32,
+    remaining: u8,
+    strobe: bool,
+}
+
+impl FourScore {
+    /// The half for `port`, which must be a controller port.
+    pub fn new(port: Port) -> Self {
+        let signature = match port {
+            Port::One => 0x08,
+            Port::Two => 0x04,
+            Port::Expansion => panic!("the Four Score plugs into the controller ports"),
+        };
+        FourScore {
+            pads: Default::default(),
+            signature,
+            shift: 0,
+            remaining: 0,
+            strobe: false,
+        }
+    }
+
+    /// The first (0) or second (1) joypad on this port.
+    pub fn joypad(&self, index: usize) -> &Joypad {
+        &self.pads[index]
+    }
+
+    pub fn joypad_mut(&mut self, index: usize) -> &mut Joypad {
+        &mut self.pads[index]
+    }
+
+    fn latch(&mut self) {
+        self.shift = self.pads[0].buttons().0 as u32
+            | (self.pads[1].buttons().0 as u32) << 8
+            | (self.signature as u32) << 16;
+        self.remaining = 24;
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, _addr: u16, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        if self.remaining == 0 {
+            return 1;
+        }
+        let bit = (self.shift & 0x01) as u8;
+        if !self.strobe {
+            self.shift >>= 1;
+            self.remaining -= 1;
+        }
+        bit
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.pads);
+        w.write(&self.shift);
+        w.write(&self.remaining);
+        w.write(&self.strobe);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.pads)?;
+        r.read_into(&mut self.shift)?;
+        r.read_into(&mut self.remaining)?;
+        r.read_into(&mut self.strobe)?;
+        Ok(())
+    }
+}
+
+/// The simple Famicom four-player adapter: players 3 and 4 on the expansion
+/// port, read through D1 of $4016 and $4017 alongside the built-in pads.
+#[derive(Debug, Clone, Default)]
+pub struct FamicomFourPlayer {
+    pads: [Joypad; 2],
+}
+
+impl FamicomFourPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Player 3's (0) or player 4's (1) joypad.
+    pub fn joypad(&self, index: usize) -> &Joypad {
+        &self.pads[index]
+    }
+
+    pub fn joypad_mut(&mut self, index: usize) -> &mut Joypad {
+        &mut self.pads[index]
+    }
+}
+
+impl InputDevice for FamicomFourPlayer {
+    fn write(&mut self, data: u8) {
+        for pad in &mut self.pads {
+            pad.write_strobe(data & 0x01 != 0);
+        }
+    }
+
+    fn read(&mut self, addr: u16, _ppu: &Ppu) -> u8 {
+        self.pads[addr as usize & 0x01].read() << 1
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.pads);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.pads)?;
+        Ok(())
+    }
+}
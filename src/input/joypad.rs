@@ -0,0 +1,141 @@
+use super::InputDevice;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter, Stateful};
+use std::ops::{BitOr, BitOrAssign};
+
+/// Joypad button state, one bit per button in the order they are shifted out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Buttons(pub u8);
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0x00);
+    pub const A: Buttons = Buttons(0x01);
+    pub const B: Buttons = Buttons(0x02);
+    pub const SELECT: Buttons = Buttons(0x04);
+    pub const START: Buttons = Buttons(0x08);
+    pub const UP: Buttons = Buttons(0x10);
+    pub const DOWN: Buttons = Buttons(0x20);
+    pub const LEFT: Buttons = Buttons(0x40);
+    pub const RIGHT: Buttons = Buttons(0x80);
+
+    pub fn contains(self, buttons: Buttons) -> bool {
+        self.0 & buttons.0 == buttons.0
+    }
+
+    pub fn set(&mut self, buttons: Buttons, pressed: bool) {
+        if pressed {
+            self.0 |= buttons.0;
+        } else {
+            self.0 &= !buttons.0;
+        }
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, rhs: Buttons) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The standard controller: a 4021 shift register latched while the strobe
+/// is high, then shifted out one button per read. Once all eight have been
+/// read, the serial input is tied high, so further reads return 1.
+#[derive(Debug, Clone, Default)]
+pub struct Joypad {
+    buttons: Buttons,
+    shift: u8,
+    /// Bits still in the register from the last latch; the rest are 1s.
+    remaining: u8,
+    strobe: bool,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    /// Sets the buttons currently held. Takes effect at the next latch.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn latch(&mut self) {
+        self.shift = self.buttons.0;
+        self.remaining = 8;
+    }
+
+    /// Handles the strobe line driven by $4016 bit 0.
+    pub fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.latch();
+        }
+    }
+
+    /// Reads the serial output (bit 0). While the strobe is high the
+    /// register keeps reloading, so this returns the A button.
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        if self.remaining == 0 {
+            return 1;
+        }
+        let bit = self.shift & 0x01;
+        if !self.strobe {
+            self.shift >>= 1;
+            self.remaining -= 1;
+        }
+        bit
+    }
+}
+
+impl InputDevice for Joypad {
+    fn write(&mut self, data: u8) {
+        self.write_strobe(data & 0x01 != 0);
+    }
+
+    fn read(&mut self, _addr: u16, _ppu: &Ppu) -> u8 {
+        Joypad::read(self)
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(self);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(self)
+    }
+}
+
+impl Stateful for Buttons {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&self.0);
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.0)
+    }
+}
+
+crate::state::stateful!(Joypad {
+    buttons,
+    shift,
+    remaining,
+    strobe,
+});
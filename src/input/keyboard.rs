@@ -0,0 +1,96 @@
+use super::InputDevice;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// A key on the Family BASIC keyboard. The value encodes its place in the
+/// matrix: row * 8 + column * 4 + data line (0 for D1 up to 3 for D4).
+#[rustfmt::skip]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Key {
+    RightBracket = 0, LeftBracket, Return, F8, Stop, Yen, RightShift, Kana,
+    Semicolon = 8, Colon, At, F7, Caret, Minus, Slash, Underscore,
+    K = 16, L, O, F6, Num0, P, Comma, Period,
+    J = 24, U, I, F5, Num8, Num9, N, M,
+    H = 32, G, Y, F4, Num6, Num7, V, B,
+    D = 40, R, T, F3, Num4, Num5, C, F,
+    A = 48, S, W, F2, Num3, E, Z, X,
+    Ctr = 56, Q, Escape, F1, Num2, Num1, Grph, LeftShift,
+    Left = 64, Right, Up, ClrHome, Ins, Del, Space, Down,
+}
+
+const ROWS: u8 = 9;
+
+/// The Family BASIC keyboard on the expansion port. Writes to $4016 select
+/// a half-row of the 9x8 key matrix: bit 2 enables the keyboard, bit 0
+/// returns to row 0 and bit 1 picks the column, with the row advancing each
+/// time it falls from 1 to 0. $4017 D1-D4 read the selected keys, 0 meaning
+/// pressed.
+#[derive(Debug, Clone, Default)]
+pub struct FamilyKeyboard {
+    keys: u128,
+    enabled: bool,
+    row: u8,
+    column: u8,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&mut self, key: Key) {
+        self.keys |= 1 << key as u8;
+    }
+
+    pub fn release(&mut self, key: Key) {
+        self.keys &= !(1 << key as u8);
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = 0;
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.keys & 1 << key as u8 != 0
+    }
+}
+
+impl InputDevice for FamilyKeyboard {
+    fn write(&mut self, data: u8) {
+        self.enabled = data & 0x04 != 0;
+        let column = (data >> 1) & 0x01;
+        if data & 0x01 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row = (self.row + 1).min(ROWS);
+        }
+        self.column = column;
+    }
+
+    fn read(&mut self, addr: u16, _ppu: &Ppu) -> u8 {
+        if !self.enabled || addr != 0x4017 {
+            return 0;
+        }
+        if self.row >= ROWS {
+            return 0x1E;
+        }
+        let pressed = (self.keys >> (self.row * 8 + self.column * 4)) as u8 & 0x0F;
+        !pressed << 1 & 0x1E
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.keys);
+        w.write(&self.enabled);
+        w.write(&self.row);
+        w.write(&self.column);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.keys)?;
+        r.read_into(&mut self.enabled)?;
+        r.read_into(&mut self.row)?;
+        r.read_into(&mut self.column)?;
+        Ok(())
+    }
+}
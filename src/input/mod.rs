@@ -0,0 +1,346 @@
+mod arkanoid;
+mod four_score;
+mod joypad;
+mod keyboard;
+mod power_pad;
+mod zapper;
+
+pub use arkanoid::Arkanoid;
+pub use four_score::{FamicomFourPlayer, FourScore};
+pub use joypad::{Buttons, Joypad};
+pub use keyboard::{FamilyKeyboard, Key};
+pub use power_pad::PowerPad;
+pub use zapper::Zapper;
+
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+use std::any::Any;
+
+/// Something plugged into a controller port or the Famicom expansion port.
+///
+/// Devices see every write to $4016 and return the data lines they drive
+/// for $4016 and $4017 in their register bit positions. Controller ports
+/// only carry OUT0 and D0/D3/D4; the expansion port carries OUT0-OUT2 and
+/// D1-D4 of both registers, so other bits are masked off by [`InputPorts`].
+pub trait InputDevice: Any {
+    /// Handles a write to $4016; bits 0-2 are the OUT lines.
+    fn write(&mut self, data: u8);
+
+    /// Reads `addr` ($4016 or $4017). The PPU is passed for light guns,
+    /// which sense the picture as it is drawn.
+    fn read(&mut self, addr: u16, ppu: &Ppu) -> u8;
+
+    /// Writes the device's state, including what the host has set, for a
+    /// save state.
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError>;
+}
+
+/// Where a device is connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Port {
+    /// Controller port 1, read through $4016.
+    One,
+    /// Controller port 2, read through $4017.
+    Two,
+    /// The Famicom expansion port, read through both registers.
+    Expansion,
+}
+
+/// The devices connected to the console. Standard joypads are in both
+/// controller ports at power-on and the expansion port is empty.
+pub struct InputPorts {
+    ports: [Option<Box<dyn InputDevice>>; 2],
+    expansion: Option<Box<dyn InputDevice>>,
+}
+
+impl Default for InputPorts {
+    fn default() -> Self {
+        InputPorts::new()
+    }
+}
+
+impl InputPorts {
+    pub fn new() -> Self {
+        InputPorts {
+            ports: [Some(Box::new(Joypad::new())), Some(Box::new(Joypad::new()))],
+            expansion: None,
+        }
+    }
+
+    fn slot(&self, port: Port) -> &Option<Box<dyn InputDevice>> {
+        match port {
+            Port::One => &self.ports[0],
+            Port::Two => &self.ports[1],
+            Port::Expansion => &self.expansion,
+        }
+    }
+
+    fn slot_mut(&mut self, port: Port) -> &mut Option<Box<dyn InputDevice>> {
+        match port {
+            Port::One => &mut self.ports[0],
+            Port::Two => &mut self.ports[1],
+            Port::Expansion => &mut self.expansion,
+        }
+    }
+
+    /// Plugs `device` into `port`, replacing whatever was there.
+    pub fn connect<D: InputDevice>(&mut self, port: Port, device: D) {
+        *self.slot_mut(port) = Some(Box::new(device));
+    }
+
+    /// Unplugs and returns the device in `port`.
+    pub fn disconnect(&mut self, port: Port) -> Option<Box<dyn InputDevice>> {
+        self.slot_mut(port).take()
+    }
+
+    /// Connects a Four Score, which takes both controller ports.
+    pub fn connect_four_score(&mut self) {
+        self.connect(Port::One, FourScore::new(Port::One));
+        self.connect(Port::Two, FourScore::new(Port::Two));
+    }
+
+    /// The device in `port`, if it is a `D`.
+    pub fn device<D: InputDevice>(&self, port: Port) -> Option<&D> {
+        let device: &dyn Any = self.slot(port).as_deref()?;
+        device.downcast_ref()
+    }
+
+    pub fn device_mut<D: InputDevice>(&mut self, port: Port) -> Option<&mut D> {
+        let device: &mut dyn Any = self.slot_mut(port).as_deref_mut()?;
+        device.downcast_mut()
+    }
+
+    /// Shorthand for the usual case of a joypad in a controller port.
+    pub fn joypad_mut(&mut self, port: Port) -> Option<&mut Joypad> {
+        self.device_mut(port)
+    }
+
+    /// Handles a CPU write to $4016.
+    pub fn write(&mut self, data: u8) {
+        for device in self.ports.iter_mut().flatten() {
+            device.write(data & 0x01);
+        }
+        if let Some(device) = &mut self.expansion {
+            device.write(data & 0x07);
+        }
+    }
+
+    /// Saves each connected device. Loading expects the same devices to be
+    /// connected.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        for slot in self.ports.iter().chain([&self.expansion]) {
+            w.write(&slot.is_some());
+            if let Some(device) = slot {
+                device.save_state(w);
+            }
+        }
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        for slot in self.ports.iter_mut().chain([&mut self.expansion]) {
+            if r.read::<bool>()? != slot.is_some() {
+                return Err(StateError::Invalid("different input devices connected"));
+            }
+            if let Some(device) = slot {
+                device.load_state(r)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a CPU read of $4016 or $4017, returning D0-D4. Undriven
+    /// lines read as 0.
+    pub fn read(&mut self, addr: u16, ppu: &Ppu) -> u8 {
+        let port = &mut self.ports[addr as usize & 0x01];
+        let mut data = port
+            .as_mut()
+            .map_or(0, |device| device.read(addr, ppu) & 0x19);
+        if let Some(device) = &mut self.expansion {
+            data |= device.read(addr, ppu) & 0x1E;
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_bits(ports: &mut InputPorts, addr: u16, mask: u8, count: usize) -> Vec<u8> {
+        let ppu = Ppu::new();
+        (0..count)
+            .map(|_| (ports.read(addr, &ppu) & mask != 0) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn four_score_reports_four_pads_and_signature() {
+        let mut ports = InputPorts::new();
+        ports.connect_four_score();
+        let four_score = ports.device_mut::<FourScore>(Port::One).unwrap();
+        four_score.joypad_mut(0).set_buttons(Buttons::A);
+        four_score.joypad_mut(1).set_buttons(Buttons::START);
+        let four_score = ports.device_mut::<FourScore>(Port::Two).unwrap();
+        four_score.joypad_mut(1).set_buttons(Buttons::RIGHT);
+
+        ports.write(1);
+        ports.write(0);
+        let port1 = read_bits(&mut ports, 0x4016, 0x01, 25);
+        let port2 = read_bits(&mut ports, 0x4017, 0x01, 25);
+        #[rustfmt::skip]
+        assert_eq!(port1, [
+            1, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 0,
+            1,
+        ]);
+        #[rustfmt::skip]
+        assert_eq!(port2, [
+            0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 1,
+            0, 0, 1, 0, 0, 0, 0, 0,
+            1,
+        ]);
+    }
+
+    #[test]
+    fn keyboard_scans_rows() {
+        let mut ports = InputPorts::new();
+        let mut keyboard = FamilyKeyboard::new();
+        keyboard.press(Key::Return);
+        keyboard.press(Key::Space);
+        ports.connect(Port::Expansion, keyboard);
+
+        let ppu = Ppu::new();
+        let mut rows = Vec::new();
+        ports.write(0x05);
+        for _ in 0..9 {
+            ports.write(0x04);
+            rows.push(ports.read(0x4017, &ppu) & 0x1E);
+            ports.write(0x06);
+            rows.push(ports.read(0x4017, &ppu) & 0x1E);
+        }
+        // Return is row 0, column 0, bit 3; Space is row 8, column 1, bit 3.
+        assert_eq!(rows[0], 0x16);
+        assert_eq!(rows[17], 0x16);
+        assert!(rows[1..17].iter().all(|&row| row == 0x1E));
+
+        ports.write(0x00);
+        assert_eq!(ports.read(0x4017, &ppu) & 0x1E, 0);
+    }
+
+    #[test]
+    fn zapper_senses_the_beam() {
+        let mut ports = InputPorts::new();
+        let mut zapper = Zapper::new();
+        zapper.aim(Some((100, 50)));
+        ports.connect(Port::Two, zapper);
+
+        // With rendering off and v pointing at the backdrop, the screen is
+        // drawn white.
+        let mut vram = vec![0; 0x4000];
+        let mut ppu = Ppu::new();
+        let point_at_backdrop = |ppu: &mut Ppu, vram: &mut Vec<u8>| {
+            ppu.cpu_write(0x2006, 0x3F, &mut vram[..]);
+            ppu.cpu_write(0x2006, 0x00, &mut vram[..]);
+            for _ in 0..3 {
+                ppu.clock(&mut vram[..]);
+            }
+        };
+        point_at_backdrop(&mut ppu, &mut vram);
+        ppu.cpu_write(0x2007, 0x30, &mut vram[..]);
+        point_at_backdrop(&mut ppu, &mut vram);
+        let mut read_at = |scanline, ports: &mut InputPorts| {
+            while ppu.scanline() != scanline {
+                ppu.clock(&mut vram[..]);
+            }
+            // D0 of $4016 is the joypad in port 1.
+            [ports.read(0x4016, &ppu) & 0x18, ports.read(0x4017, &ppu)]
+        };
+
+        // D3 is clear while the pixels around the aim point have just been
+        // drawn, and D4 is set while the trigger is held.
+        assert_eq!(read_at(40, &mut ports), [0x00, 0x08]);
+        assert_eq!(read_at(51, &mut ports), [0x00, 0x00]);
+        ports
+            .device_mut::<Zapper>(Port::Two)
+            .unwrap()
+            .set_trigger(true);
+        assert_eq!(read_at(51, &mut ports), [0x00, 0x10]);
+        assert_eq!(read_at(80, &mut ports), [0x00, 0x18]);
+
+        // The Famicom Zapper is read through $4017 alone.
+        ports.disconnect(Port::Two);
+        let mut zapper = Zapper::famicom();
+        zapper.aim(Some((100, 50)));
+        zapper.set_trigger(true);
+        ports.connect(Port::Expansion, zapper);
+        assert_eq!(read_at(51, &mut ports), [0x00, 0x10]);
+        assert_eq!(read_at(80, &mut ports), [0x00, 0x18]);
+    }
+
+    #[test]
+    fn arkanoid_shifts_out_the_knob() {
+        let mut ports = InputPorts::new();
+        let mut vaus = Arkanoid::new();
+        vaus.set_position(0x5A);
+        vaus.set_button(true);
+        ports.connect(Port::Two, vaus);
+        let mut famicom = Arkanoid::famicom();
+        famicom.set_position(0xF0);
+        ports.connect(Port::Expansion, famicom);
+
+        ports.write(1);
+        ports.write(0);
+        // Inverted and MSB first: $A5 on D3 and $0F on D1.
+        assert_eq!(
+            read_bits(&mut ports, 0x4017, 0x08, 8),
+            [1, 0, 1, 0, 0, 1, 0, 1]
+        );
+        ports.write(1);
+        ports.write(0);
+        assert_eq!(
+            read_bits(&mut ports, 0x4017, 0x02, 8),
+            [0, 0, 0, 0, 1, 1, 1, 1]
+        );
+        // The NES button is on D4 of $4017; the Famicom one on D1 of $4016.
+        let ppu = Ppu::new();
+        assert_eq!(ports.read(0x4017, &ppu) & 0x10, 0x10);
+        assert_eq!(ports.read(0x4016, &ppu) & 0x02, 0x00);
+        ports
+            .device_mut::<Arkanoid>(Port::Expansion)
+            .unwrap()
+            .set_button(true);
+        assert_eq!(ports.read(0x4016, &ppu) & 0x02, 0x02);
+    }
+
+    #[test]
+    fn power_pad_shifts_out_both_lines() {
+        let mut ports = InputPorts::new();
+        let mut pad = PowerPad::new();
+        for button in [2, 3, 9] {
+            pad.set_button(button, true);
+        }
+        ports.connect(Port::Two, pad);
+
+        // While the strobe is high, the first bit of each line repeats.
+        ports.write(1);
+        assert_eq!(read_bits(&mut ports, 0x4017, 0x08, 3), [1, 1, 1]);
+        assert_eq!(read_bits(&mut ports, 0x4017, 0x10, 3), [0, 0, 0]);
+
+        // D3 shifts out 2, 1, 5, 9, 6, 10, 11, 7 and D4 shifts out 4, 3,
+        // 12, 8, each then reading 1s.
+        ports.write(0);
+        let ppu = Ppu::new();
+        let reads: Vec<u8> = (0..10).map(|_| ports.read(0x4017, &ppu)).collect();
+        let line = |mask| {
+            reads
+                .iter()
+                .map(|&data| (data & mask != 0) as u8)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(line(0x08), [1, 0, 0, 1, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(line(0x10), [0, 1, 0, 0, 1, 1, 1, 1, 1, 1]);
+    }
+}
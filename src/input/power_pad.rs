@@ -0,0 +1,103 @@
+use super::InputDevice;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Buttons shifted out on D3, then on D4, numbered as printed on side B.
+const D3_ORDER: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const D4_ORDER: [u8; 4] = [4, 3, 12, 8];
+
+/// The Power Pad (Family Trainer) floor mat: twelve buttons read through
+/// two shift registers on D3 and D4 of its controller port, 1 meaning
+/// pressed. Once a register is empty it returns 1s.
+#[derive(Debug, Clone, Default)]
+pub struct PowerPad {
+    /// Bit n-1 is button n.
+    buttons: u16,
+    shift: [u8; 2],
+    remaining: [u8; 2],
+    strobe: bool,
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Presses or releases button `button` (1-12).
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        assert!((1..=12).contains(&button), "no Power Pad button {button}");
+        let mask = 1 << (button - 1);
+        if pressed {
+            self.buttons |= mask;
+        } else {
+            self.buttons &= !mask;
+        }
+    }
+
+    /// Sets all buttons at once, bit n-1 for button n.
+    pub fn set_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons & 0x0FFF;
+    }
+
+    pub fn buttons(&self) -> u16 {
+        self.buttons
+    }
+
+    fn pressed(&self, button: u8) -> bool {
+        self.buttons & 1 << (button - 1) != 0
+    }
+
+    fn latch(&mut self) {
+        let pack = |order: &[u8]| {
+            order.iter().enumerate().fold(0u8, |bits, (i, &button)| {
+                bits | (self.pressed(button) as u8) << i
+            })
+        };
+        self.shift = [pack(&D3_ORDER), pack(&D4_ORDER)];
+        self.remaining = [D3_ORDER.len() as u8, D4_ORDER.len() as u8];
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, _addr: u16, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let mut data = 0;
+        for line in 0..2 {
+            let bit = if self.remaining[line] == 0 {
+                1
+            } else {
+                self.shift[line] & 0x01
+            };
+            if !self.strobe && self.remaining[line] > 0 {
+                self.shift[line] >>= 1;
+                self.remaining[line] -= 1;
+            }
+            data |= bit << (3 + line);
+        }
+        data
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.buttons);
+        w.write(&self.shift);
+        w.write(&self.remaining);
+        w.write(&self.strobe);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.buttons)?;
+        r.read_into(&mut self.shift)?;
+        r.read_into(&mut self.remaining)?;
+        r.read_into(&mut self.strobe)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use super::InputDevice;
+use crate::ppu::{Ppu, HEIGHT, NTSC_PALETTE, WIDTH};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Pixels either side of the aim point the photodiode sees.
+const RADIUS: i32 = 2;
+
+/// Scanlines a lit pixel keeps the sensor triggered after being drawn.
+const DECAY_LINES: i32 = 20;
+
+/// Luma (0-255) above which a pixel counts as lit.
+const BRIGHTNESS: u32 = 0x80;
+
+const DOTS_PER_LINE: i32 = 341;
+
+/// The Zapper light gun. D3 reads 0 while the photodiode sees a bright
+/// pixel the beam has recently drawn near the aim point, D4 reads 1 while
+/// the trigger is held.
+///
+/// The NES Zapper is read through its controller port. The Famicom one
+/// sits on the expansion port and is always read through $4017.
+#[derive(Debug, Clone, Default)]
+pub struct Zapper {
+    famicom: bool,
+    aim: Option<(u8, u8)>,
+    trigger: bool,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The Famicom Zapper, for the expansion port.
+    pub fn famicom() -> Self {
+        Zapper {
+            famicom: true,
+            ..Self::default()
+        }
+    }
+
+    /// Points the gun at pixel (x, y), or away from the screen with `None`.
+    pub fn aim(&mut self, aim: Option<(u8, u8)>) {
+        self.aim = aim;
+    }
+
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    /// Whether the photodiode currently sees light.
+    pub fn senses_light(&self, ppu: &Ppu) -> bool {
+        let Some((x, y)) = self.aim else {
+            return false;
+        };
+        let scanline = ppu.scanline() as i32;
+        let beam = if scanline < HEIGHT as i32 + DECAY_LINES {
+            scanline * DOTS_PER_LINE + ppu.dot() as i32
+        } else {
+            // Pre-render and late vblank lines: everything has faded.
+            return false;
+        };
+
+        let frame = ppu.frame_buffer();
+        for py in (y as i32 - RADIUS)..=(y as i32 + RADIUS) {
+            for px in (x as i32 - RADIUS)..=(x as i32 + RADIUS) {
+                if !(0..WIDTH as i32).contains(&px) || !(0..HEIGHT as i32).contains(&py) {
+                    continue;
+                }
+                // Pixel x is output on dot x + 1.
+                let age = beam - (py * DOTS_PER_LINE + px + 1);
+                if !(0..DECAY_LINES * DOTS_PER_LINE).contains(&age) {
+                    continue;
+                }
+                if luma(frame[py as usize * WIDTH + px as usize]) >= BRIGHTNESS {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Brightness of a frame buffer entry. Emphasis bits are ignored.
+fn luma(pixel: u16) -> u32 {
+    let rgb = NTSC_PALETTE[pixel as usize & 0x3F];
+    let (r, g, b) = (rgb >> 16, (rgb >> 8) & 0xFF, rgb & 0xFF);
+    (r * 299 + g * 587 + b * 114) / 1000
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _data: u8) {}
+
+    fn read(&mut self, addr: u16, ppu: &Ppu) -> u8 {
+        if self.famicom && addr == 0x4016 {
+            return 0;
+        }
+        let dark = !self.senses_light(ppu);
+        (dark as u8) << 3 | (self.trigger as u8) << 4
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write(&self.aim);
+        w.write(&self.trigger);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.aim)?;
+        r.read_into(&mut self.trigger)?;
+        Ok(())
+    }
+}
@@ -1,2 +1,14 @@
+pub mod apu;
 pub mod bus;
+pub mod cartridge;
 pub mod cpu;
+pub mod debug;
+pub mod input;
+pub mod memory_map;
+pub mod movie;
+pub mod nes;
+pub mod ppu;
+pub mod region;
+pub mod state;
+pub mod testrom;
+pub mod wav;
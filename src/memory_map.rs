@@ -0,0 +1,656 @@
+use crate::apu::Apu;
+use crate::bus::{BusDevice, BusEvent};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::cpu::Cpu;
+use crate::input::InputPorts;
+use crate::ppu::Ppu;
+use crate::region::Region;
+use crate::state::{Chunk, StateError, StateWriter};
+
+/// CPU/PPU alignment: how many master clocks the PPU trails the CPU by.
+const PPU_OFFSET: u64 = 1;
+
+/// Contents of internal RAM at power-on. Real consoles come up with
+/// semi-random contents, which some games depend on by accident.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    /// Every byte $FF.
+    Ones,
+    /// Pseudo-random bytes from the given seed.
+    Random(u64),
+    /// The pattern repeated across RAM.
+    Pattern(Vec<u8>),
+}
+
+impl RamInit {
+    fn fill(&self, ram: &mut [u8]) {
+        match self {
+            RamInit::Zeros => ram.fill(0x00),
+            RamInit::Ones => ram.fill(0xFF),
+            RamInit::Random(seed) => {
+                // xorshift64*, which needs a nonzero state.
+                let mut state = seed | 1;
+                for byte in ram {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *byte = (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8;
+                }
+            }
+            RamInit::Pattern(pattern) if pattern.is_empty() => ram.fill(0x00),
+            RamInit::Pattern(pattern) => {
+                for (byte, &value) in ram.iter_mut().zip(pattern.iter().cycle()) {
+                    *byte = value;
+                }
+            }
+        }
+    }
+}
+
+/// A read of cartridge ROM that is not one of the CPU's bus accesses, from
+/// [`MemoryMap::take_rom_reads`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomRead {
+    /// A pattern table fetch from CHR ROM, made by rendering or, with `cpu`
+    /// set, for a read of $2007.
+    Pattern { offset: usize, cpu: bool },
+    /// A DMC sample byte from PRG ROM.
+    Sample { offset: usize },
+}
+
+/// The CPU address space: internal RAM, PPU and APU registers and the cartridge.
+pub struct MemoryMap {
+    ram: [u8; 0x800],
+    ram_init: RamInit,
+    vram: [u8; 0x1000],
+    ppu: Ppu,
+    apu: Apu,
+    input: InputPorts,
+    cartridge: Option<Cartridge>,
+    region: Region,
+    region_override: Option<Region>,
+    addr: u16,
+    data: u8,
+    master_clock: u64,
+    ppu_clock: u64,
+    cpu_cycles: u64,
+    nmi_line: bool,
+    oam_dma: Option<u8>,
+    frame: u64,
+    /// ROM reads not yet taken, kept only while `log_rom_reads` is set.
+    rom_reads: Vec<RomRead>,
+    log_rom_reads: bool,
+}
+
+/// The PPU address space: cartridge CHR below $2000, nametables above.
+struct PpuMemory<'a> {
+    vram: &'a mut [u8; 0x1000],
+    cartridge: Option<&'a mut Cartridge>,
+    rom_reads: Option<&'a mut Vec<RomRead>>,
+    /// Whether accesses are for the CPU, through $2007.
+    cpu: bool,
+}
+
+impl PpuMemory<'_> {
+    fn mirroring(&self) -> Mirroring {
+        match &self.cartridge {
+            Some(cartridge) => cartridge.mapper().mirroring(),
+            None => Mirroring::Horizontal,
+        }
+    }
+}
+
+impl BusDevice for PpuMemory<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match (addr, &mut self.cartridge) {
+            (0x0000..=0x1FFF, Some(cartridge)) => {
+                let data = cartridge.mapper_mut().ppu_read(addr);
+                if let Some(rom_reads) = &mut self.rom_reads {
+                    if let Some(offset) = cartridge.mapper().chr_rom_offset(addr) {
+                        rom_reads.push(RomRead::Pattern {
+                            offset,
+                            cpu: self.cpu,
+                        });
+                    }
+                }
+                data
+            }
+            (0x0000..=0x1FFF, None) => 0,
+            (_, Some(cartridge)) => match cartridge.mapper_mut().nametable_read(addr) {
+                Some(data) => data,
+                None => self.vram[self.mirroring().nametable_offset(addr)],
+            },
+            (_, None) => self.vram[self.mirroring().nametable_offset(addr)],
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        let addr = addr & 0x3FFF;
+        match (addr, &mut self.cartridge) {
+            (0x0000..=0x1FFF, Some(cartridge)) => cartridge.mapper_mut().ppu_write(addr, data),
+            (0x0000..=0x1FFF, None) => {}
+            (_, Some(cartridge)) => {
+                if !cartridge.mapper_mut().nametable_write(addr, data) {
+                    self.vram[self.mirroring().nametable_offset(addr)] = data;
+                }
+            }
+            (_, None) => self.vram[self.mirroring().nametable_offset(addr)] = data,
+        }
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap::new()
+    }
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        MemoryMap {
+            ram: [0; 0x800],
+            ram_init: RamInit::default(),
+            vram: [0; 0x1000],
+            ppu: Ppu::new(),
+            apu: Apu::new(),
+            input: InputPorts::new(),
+            cartridge: None,
+            region: Region::Ntsc,
+            region_override: None,
+            addr: 0,
+            data: 0,
+            master_clock: 0,
+            ppu_clock: 0,
+            cpu_cycles: 0,
+            nmi_line: false,
+            oam_dma: None,
+            frame: 0,
+            rom_reads: Vec::new(),
+            log_rom_reads: false,
+        }
+    }
+
+    /// Puts RAM, the PPU, the APU, the cartridge board and the cycle
+    /// counters back in their power-up state. The input devices and region
+    /// are kept.
+    pub fn power_on(&mut self) {
+        self.ram_init.fill(&mut self.ram);
+        self.vram = [0; 0x1000];
+        self.ppu.power_on();
+        self.apu.power_on();
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.power_on();
+        }
+        self.addr = 0;
+        self.data = 0;
+        self.master_clock = 0;
+        self.ppu_clock = 0;
+        self.cpu_cycles = 0;
+        self.nmi_line = false;
+        self.oam_dma = None;
+        self.frame = 0;
+    }
+
+    /// Writes the bus, PPU, APU, cartridge and input chunks of a save state.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.chunk(*b"BUS ", 1, |w| {
+            w.write(&self.region);
+            w.write(&self.ram);
+            w.write(&self.vram);
+            w.write(&self.addr);
+            w.write(&self.data);
+            w.write(&self.master_clock);
+            w.write(&self.ppu_clock);
+            w.write(&self.cpu_cycles);
+            w.write(&self.nmi_line);
+            w.write(&self.oam_dma);
+            w.write(&self.frame);
+        });
+        w.chunk(*b"PPU ", 2, |w| w.write(&self.ppu));
+        w.chunk(*b"APU ", 1, |w| w.write(&self.apu));
+        if let Some(cartridge) = &self.cartridge {
+            w.chunk(*b"CART", 2, |w| cartridge.save_state(w));
+        }
+        w.chunk(*b"INPT", 1, |w| self.input.save_state(w));
+    }
+
+    /// Loads one chunk of a save state. Chunks for other components are
+    /// ignored.
+    pub fn load_chunk(&mut self, chunk: &Chunk<'_>) -> Result<(), StateError> {
+        match &chunk.tag {
+            b"BUS " => {
+                let mut r = chunk.reader(1)?;
+                let region = r.read()?;
+                self.apply_region(region);
+                r.read_into(&mut self.ram)?;
+                r.read_into(&mut self.vram)?;
+                r.read_into(&mut self.addr)?;
+                r.read_into(&mut self.data)?;
+                r.read_into(&mut self.master_clock)?;
+                r.read_into(&mut self.ppu_clock)?;
+                r.read_into(&mut self.cpu_cycles)?;
+                r.read_into(&mut self.nmi_line)?;
+                r.read_into(&mut self.oam_dma)?;
+                r.read_into(&mut self.frame)?;
+            }
+            b"PPU " => chunk.reader(2)?.read_into(&mut self.ppu)?,
+            b"APU " => chunk.reader(1)?.read_into(&mut self.apu)?,
+            b"CART" => match self.cartridge.as_mut() {
+                Some(cartridge) => cartridge.load_state(chunk.version, &mut chunk.reader(2)?)?,
+                None => return Err(StateError::WrongCartridge),
+            },
+            b"INPT" => self.input.load_state(&mut chunk.reader(1)?)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Reset button: the PPU and APU are partially reset, RAM is kept.
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+    }
+
+    /// Sets how RAM is filled at the next power-on.
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+    }
+
+    pub fn ram_init(&self) -> &RamInit {
+        &self.ram_init
+    }
+
+    /// Inserts a cartridge. Unless overridden, the region follows its header.
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        let region = self.region_override.unwrap_or(cartridge.header().region);
+        self.cartridge = Some(cartridge);
+        self.apply_region(region);
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Forces a region regardless of the cartridge header; `None` returns to
+    /// automatic selection.
+    pub fn set_region_override(&mut self, region: Option<Region>) {
+        self.region_override = region;
+        let region = region
+            .or_else(|| self.cartridge.as_ref().map(|c| c.header().region))
+            .unwrap_or_default();
+        self.apply_region(region);
+    }
+
+    fn apply_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.mapper_mut().set_region(region);
+        }
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    /// The 2KB of internal RAM, mirrored through $0000-$1FFF.
+    pub fn ram(&self) -> &[u8; 0x800] {
+        &self.ram
+    }
+
+    /// What the CPU would read at `addr`, without side effects. PPU, APU
+    /// and input registers are not read and show the open bus value.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF],
+            0x2000..=0x401F => self.data,
+            _ => self
+                .cartridge
+                .as_ref()
+                .and_then(|cartridge| cartridge.mapper().cpu_peek(addr))
+                .unwrap_or(self.data),
+        }
+    }
+
+    /// Changes the RAM or ROM byte the CPU would read at `addr`, without
+    /// the side effects of a write: mapper registers are left alone and ROM
+    /// is patched. Returns false for PPU, APU and mapper registers and open
+    /// bus, which have no byte to change.
+    pub fn patch(&mut self, addr: u16, data: u8) -> bool {
+        match self.patch_target(addr) {
+            Some(byte) => {
+                *byte = data;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether [`MemoryMap::patch`] would succeed at `addr`.
+    pub fn can_patch(&mut self, addr: u16) -> bool {
+        self.patch_target(addr).is_some()
+    }
+
+    fn patch_target(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x0000..=0x1FFF => Some(&mut self.ram[addr as usize & 0x07FF]),
+            0x2000..=0x401F => None,
+            _ => self
+                .cartridge
+                .as_mut()
+                .and_then(|cartridge| cartridge.mapper_mut().prg_mut(addr)),
+        }
+    }
+
+    /// The last value on the CPU data bus: what was read or written on the
+    /// previous cycle.
+    pub fn data_bus(&self) -> u8 {
+        self.data
+    }
+
+    /// Puts `addr` and what is there on the bus, as if the CPU had just
+    /// fetched it. Used to redirect a CPU that is between instructions.
+    pub(crate) fn refetch(&mut self, addr: u16) {
+        self.addr = addr;
+        self.data = self.peek(addr);
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    /// The devices read through $4016 and $4017.
+    pub fn input(&self) -> &InputPorts {
+        &self.input
+    }
+
+    pub fn input_mut(&mut self) -> &mut InputPorts {
+        &mut self.input
+    }
+
+    pub fn cpu_cycles(&self) -> u64 {
+        self.cpu_cycles
+    }
+
+    /// Starts or stops keeping the ROM reads that the CPU's bus accesses
+    /// don't show, for [`crate::debug::CodeDataLog`].
+    pub fn set_rom_read_logging(&mut self, enabled: bool) {
+        self.log_rom_reads = enabled;
+    }
+
+    /// The ROM reads kept since the last call, oldest first.
+    pub fn take_rom_reads(&mut self) -> std::vec::Drain<'_, RomRead> {
+        self.rom_reads.drain(..)
+    }
+
+    /// Runs one CPU cycle. The PPU is caught up to just before the bus access,
+    /// the access is performed, and the PPU then runs to the end of the cycle,
+    /// so register writes land on the same dot they would on hardware.
+    /// Returns the CPU's access.
+    pub fn clock(&mut self, cpu: &mut Cpu) -> BusEvent {
+        let cartridge_irq = self
+            .cartridge
+            .as_ref()
+            .is_some_and(|cartridge| cartridge.mapper().irq());
+        if self.apu.irq() || cartridge_irq {
+            cpu.irq();
+        }
+
+        let event = cpu.clock(self.addr, self.data);
+        match event {
+            BusEvent::Read(addr) => {
+                // DMA halts the CPU on its next read cycle.
+                if self.oam_dma.is_some() || self.apu.dmc_dma_request().is_some() {
+                    self.run_dma(addr);
+                }
+                self.begin_cycle(true);
+                self.data = self.read(addr);
+                self.addr = addr;
+                self.end_cycle(true);
+            }
+            BusEvent::Write(addr, data) => {
+                self.begin_cycle(false);
+                self.write(addr, data);
+                self.addr = addr;
+                self.data = data;
+                self.end_cycle(false);
+            }
+        }
+
+        let nmi = self.ppu.nmi();
+        if nmi && !self.nmi_line {
+            cpu.nmi();
+        }
+        self.nmi_line = nmi;
+
+        if self.ppu.frame() != self.frame {
+            self.frame = self.ppu.frame();
+            self.apu.end_frame();
+        }
+        event
+    }
+
+    // Reads sample the bus late in the cycle and writes drive it early, so the
+    // split around the access point differs by two master clocks.
+    fn begin_cycle(&mut self, read: bool) {
+        let half = self.region.cpu_divider() / 2;
+        self.master_clock += if read { half - 1 } else { half + 1 };
+        self.run_ppu(self.master_clock - PPU_OFFSET);
+    }
+
+    fn end_cycle(&mut self, read: bool) {
+        let half = self.region.cpu_divider() - self.region.cpu_divider() / 2;
+        self.master_clock += if read { half + 1 } else { half - 1 };
+        self.run_ppu(self.master_clock - PPU_OFFSET);
+        if let Some(cartridge) = self.cartridge.as_mut() {
+            let mapper = cartridge.mapper_mut();
+            mapper.clock();
+            self.apu.set_expansion_output(mapper.audio_output());
+        }
+        self.apu.clock();
+        self.cpu_cycles += 1;
+    }
+
+    fn run_ppu(&mut self, until: u64) {
+        let divider = self.region.ppu_divider();
+        let mut memory = PpuMemory {
+            vram: &mut self.vram,
+            cartridge: self.cartridge.as_mut(),
+            rom_reads: self.log_rom_reads.then_some(&mut self.rom_reads),
+            cpu: false,
+        };
+        while self.ppu_clock + divider <= until {
+            self.ppu.clock(&mut memory);
+            self.ppu_clock += divider;
+        }
+    }
+
+    /// Runs pending OAM and DMC DMA while the CPU is halted on a read of `addr`.
+    fn run_dma(&mut self, addr: u16) {
+        if let Some(page) = self.oam_dma.take() {
+            // One halt cycle, plus an alignment cycle so reads fall on even cycles.
+            self.dma_halt_cycle(addr);
+            if self.cpu_cycles % 2 == 1 {
+                self.dma_halt_cycle(addr);
+            }
+
+            for offset in 0..=0xFF {
+                if self.apu.dmc_dma_request().is_some() {
+                    // A DMC fetch during OAM DMA steals a read slot plus one cycle.
+                    self.dmc_dma_read();
+                    self.dma_halt_cycle(addr);
+                }
+
+                self.begin_cycle(true);
+                let data = self.read((page as u16) << 8 | offset);
+                self.end_cycle(true);
+
+                self.begin_cycle(false);
+                self.write(0x2004, data);
+                self.end_cycle(false);
+            }
+        }
+
+        if self.apu.dmc_dma_request().is_some() {
+            self.dma_halt_cycle(addr);
+            self.dma_halt_cycle(addr);
+            if self.cpu_cycles % 2 == 1 {
+                self.dma_halt_cycle(addr);
+            }
+            self.dmc_dma_read();
+        }
+    }
+
+    /// A cycle in which the halted CPU keeps its read on the bus. On 2A03s
+    /// that read really happens, clocking read-sensitive registers again.
+    fn dma_halt_cycle(&mut self, addr: u16) {
+        self.begin_cycle(true);
+        if self.region.dmc_dma_repeats_reads() {
+            self.read(addr);
+        }
+        self.end_cycle(true);
+    }
+
+    fn dmc_dma_read(&mut self) {
+        if let Some(addr) = self.apu.dmc_dma_request() {
+            self.begin_cycle(true);
+            let data = self.read(addr);
+            self.end_cycle(true);
+            self.apu.dmc_dma_complete(data);
+            let cartridge = self.cartridge.as_ref().filter(|_| self.log_rom_reads);
+            if let Some(offset) = cartridge.and_then(|c| c.mapper().prg_rom_offset(addr)) {
+                self.rom_reads.push(RomRead::Sample { offset });
+            }
+        }
+    }
+}
+
+impl BusDevice for MemoryMap {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF],
+            0x2000..=0x3FFF => {
+                let mut memory = PpuMemory {
+                    vram: &mut self.vram,
+                    cartridge: self.cartridge.as_mut(),
+                    rom_reads: self.log_rom_reads.then_some(&mut self.rom_reads),
+                    cpu: true,
+                };
+                self.ppu.cpu_read(addr, &mut memory)
+            }
+            0x4015 => self.apu.read_status() | (self.data & 0x20),
+            // Only D0-D4 are driven by the ports; the rest is open bus.
+            0x4016 | 0x4017 => self.input.read(addr, &self.ppu) | (self.data & 0xE0),
+            0x4000..=0x401F => self.data,
+            _ => self
+                .cartridge
+                .as_mut()
+                .and_then(|cartridge| cartridge.mapper_mut().cpu_read(addr))
+                .unwrap_or(self.data),
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if addr < 0x4020 {
+            if let Some(cartridge) = self.cartridge.as_mut() {
+                cartridge.mapper_mut().snoop_write(addr, data);
+            }
+        }
+
+        match addr {
+            0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF] = data,
+            0x2000..=0x3FFF => {
+                let mut memory = PpuMemory {
+                    vram: &mut self.vram,
+                    cartridge: self.cartridge.as_mut(),
+                    rom_reads: self.log_rom_reads.then_some(&mut self.rom_reads),
+                    cpu: true,
+                };
+                self.ppu.cpu_write(addr, data, &mut memory);
+            }
+            0x4014 => self.oam_dma = Some(data),
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write(addr, data),
+            0x4016 => self.input.write(data),
+            0x4018..=0x401F => {}
+            _ => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.mapper_mut().cpu_write(addr, data);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::program_image;
+    use crate::cpu::Variant;
+    use crate::input::{Buttons, Port};
+
+    #[test]
+    fn joypad_reads() {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x01,       // LDA #$01
+            0x8D, 0x16, 0x40, // STA $4016
+            0xA9, 0x00,       // LDA #$00
+            0x8D, 0x16, 0x40, // STA $4016
+            0xA2, 0x00,       // LDX #$00
+            0xAD, 0x16, 0x40, // loop: LDA $4016
+            0x9D, 0x00, 0x03, // STA $0300,X
+            0xAD, 0x17, 0x40, // LDA $4017
+            0x9D, 0x10, 0x03, // STA $0310,X
+            0xE8,             // INX
+            0xE0, 0x0A,       // CPX #$0A
+            0xD0, 0xEF,       // BNE loop
+            0x4C, 0x1D, 0x80, // JMP *
+        ];
+
+        let mut map = MemoryMap::new();
+        map.insert_cartridge(Cartridge::from_ines(&program_image(&program)).unwrap());
+        let input = map.input_mut();
+        input
+            .joypad_mut(Port::One)
+            .unwrap()
+            .set_buttons(Buttons::A | Buttons::START | Buttons::RIGHT);
+        input
+            .joypad_mut(Port::Two)
+            .unwrap()
+            .set_buttons(Buttons::B | Buttons::UP);
+
+        let mut cpu = Cpu::new(Variant::Ricoh2A03);
+        for _ in 0..1000 {
+            map.clock(&mut cpu);
+        }
+
+        // Upper bits hold the $40 left on the bus by the operand fetch; after
+        // eight buttons the shift register returns 1s.
+        let port1: Vec<u8> = (0..10).map(|i| map.read(0x0300 + i)).collect();
+        let port2: Vec<u8> = (0..10).map(|i| map.read(0x0310 + i)).collect();
+        assert_eq!(
+            port1,
+            [0x41, 0x40, 0x40, 0x41, 0x40, 0x40, 0x40, 0x41, 0x41, 0x41]
+        );
+        assert_eq!(
+            port2,
+            [0x40, 0x41, 0x40, 0x40, 0x41, 0x40, 0x40, 0x40, 0x41, 0x41]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! FCEUX's text movie format: `key value` header lines followed by one
+//! `|commands|pad|pad|expansion|` line per frame.
+
+use super::{Commands, Movie, MovieError, MovieFrame, MovieStart};
+use crate::input::Buttons;
+use crate::region::Region;
+use crate::state;
+use std::fmt::Write;
+
+/// Button columns, leftmost first, as bit numbers in [`Buttons`].
+const BUTTON_CHARS: &[u8; 8] = b"RLDUTSBA";
+
+fn syntax(line: usize, message: impl Into<String>) -> MovieError {
+    MovieError::Syntax {
+        line,
+        message: message.into(),
+    }
+}
+
+pub(super) fn parse(text: &str) -> Result<Movie, MovieError> {
+    let mut movie = Movie::new();
+    let mut version = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim_end_matches('\r');
+        if line.starts_with('|') {
+            movie
+                .frames
+                .push(parse_frame(line, movie.four_score, number)?);
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let number_value = || {
+            value
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| syntax(number, format!("bad value for {key}")))
+        };
+        match key {
+            "version" => version = Some(number_value()?),
+            "binary" if number_value()? != 0 => return Err(MovieError::BinaryInput),
+            "palFlag" if number_value()? != 0 => movie.region = Region::Pal,
+            "fourscore" => movie.four_score = number_value()? != 0,
+            "port0" | "port1" => match number_value()? {
+                0 | 1 => {}
+                2 => return Err(MovieError::UnsupportedDevice("zapper".into())),
+                other => {
+                    return Err(MovieError::UnsupportedDevice(format!(
+                        "port device {other}"
+                    )))
+                }
+            },
+            "port2" if number_value()? != 0 => {
+                return Err(MovieError::UnsupportedDevice(format!(
+                    "expansion device {value}"
+                )))
+            }
+            "rerecordCount" => movie.rerecord_count = number_value()?,
+            "romFilename" => movie.rom_filename = value.to_string(),
+            "romChecksum" => movie.rom_checksum = Some(value.to_string()),
+            "comment" => movie.comments.push(value.to_string()),
+            "savestate" => {
+                let state = decode_blob(value).ok_or_else(|| syntax(number, "bad savestate"))?;
+                if state::chunks(&state).is_err() {
+                    return Err(MovieError::ForeignSaveState);
+                }
+                movie.start = MovieStart::SaveState(state);
+            }
+            _ => {}
+        }
+    }
+
+    match version {
+        Some(3) => Ok(movie),
+        Some(version) => Err(MovieError::UnsupportedVersion(version)),
+        None => Err(syntax(1, "missing version")),
+    }
+}
+
+fn parse_frame(line: &str, four_score: bool, number: usize) -> Result<MovieFrame, MovieError> {
+    let fields: Vec<&str> = line.split('|').collect();
+    let pads = if four_score { 4 } else { 2 };
+    // Leading empty field, commands, pads, expansion, trailing empty field.
+    if fields.len() < pads + 3 {
+        return Err(syntax(number, "too few fields"));
+    }
+
+    let commands = fields[1]
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| syntax(number, "bad commands"))?;
+    let mut frame = MovieFrame {
+        commands: Commands(commands),
+        ..MovieFrame::default()
+    };
+    for (pad, field) in frame.pads.iter_mut().zip(&fields[2..2 + pads]) {
+        *pad = parse_pad(field).ok_or_else(|| syntax(number, "bad joypad field"))?;
+    }
+    Ok(frame)
+}
+
+/// Any character other than '.' or ' ' marks a held button.
+fn parse_pad(field: &str) -> Option<Buttons> {
+    if field.is_empty() {
+        return Some(Buttons::NONE);
+    }
+    let bytes = field.as_bytes();
+    if bytes.len() != BUTTON_CHARS.len() {
+        return None;
+    }
+    let bits = bytes
+        .iter()
+        .fold(0u8, |bits, &c| bits << 1 | !matches!(c, b'.' | b' ') as u8);
+    Some(Buttons(bits))
+}
+
+fn write_pad(out: &mut String, buttons: Buttons) {
+    for (i, &c) in BUTTON_CHARS.iter().enumerate() {
+        let held = buttons.0 & 0x80 >> i != 0;
+        out.push(if held { c as char } else { '.' });
+    }
+}
+
+pub(super) fn write(movie: &Movie) -> String {
+    let mut out = String::new();
+    let pal = movie.region == Region::Pal;
+    // Writing to a String cannot fail.
+    let _ = writeln!(out, "version 3");
+    let _ = writeln!(out, "rerecordCount {}", movie.rerecord_count);
+    let _ = writeln!(out, "palFlag {}", pal as u8);
+    let _ = writeln!(out, "romFilename {}", movie.rom_filename);
+    if let Some(checksum) = &movie.rom_checksum {
+        let _ = writeln!(out, "romChecksum {checksum}");
+    }
+    let _ = writeln!(out, "guid 00000000-0000-0000-0000-000000000000");
+    let _ = writeln!(out, "fourscore {}", movie.four_score as u8);
+    let _ = writeln!(out, "microphone 0");
+    let _ = writeln!(out, "port0 1");
+    let _ = writeln!(out, "port1 1");
+    let _ = writeln!(out, "port2 0");
+    let _ = writeln!(out, "FDS 0");
+    let _ = writeln!(out, "NewPPU 0");
+    for comment in &movie.comments {
+        let _ = writeln!(out, "comment {comment}");
+    }
+    if let MovieStart::SaveState(state) = &movie.start {
+        let _ = write!(out, "savestate 0x");
+        for byte in state {
+            let _ = write!(out, "{byte:02X}");
+        }
+        out.push('\n');
+    }
+
+    let pads = if movie.four_score { 4 } else { 2 };
+    for frame in &movie.frames {
+        let _ = write!(out, "|{}|", frame.commands.0);
+        for &buttons in &frame.pads[..pads] {
+            write_pad(&mut out, buttons);
+            out.push('|');
+        }
+        out.push_str("|\n");
+    }
+    out
+}
+
+/// Decodes FM2's `0x`-prefixed hex or `base64:` blobs.
+pub(super) fn decode_blob(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix("0x") {
+        if hex.len() % 2 != 0 {
+            return None;
+        }
+        return (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect();
+    }
+
+    let base64 = value.strip_prefix("base64:")?;
+    let mut out = Vec::new();
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in base64.bytes().take_while(|&c| c != b'=') {
+        let digit = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = acc << 6 | digit as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "version 3\n\
+        emuVersion 22020\n\
+        rerecordCount 12\n\
+        palFlag 0\n\
+        romFilename smb\n\
+        romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==\n\
+        guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n\
+        fourscore 0\n\
+        port0 1\n\
+        port1 1\n\
+        port2 0\n\
+        comment author someone\n\
+        |0|........|........||\n\
+        |1|R..U...A|.L....B.||\n\
+        |0|....T...|........||\n";
+
+    #[test]
+    fn parses_the_header() {
+        let movie = parse(SAMPLE).unwrap();
+        assert_eq!(movie.rerecord_count, 12);
+        assert_eq!(movie.rom_filename, "smb");
+        assert_eq!(
+            movie.rom_checksum.as_deref(),
+            Some("base64:jjYwGG411HcjG/j9UOVM3Q==")
+        );
+        assert_eq!(movie.comments, ["author someone"]);
+        assert_eq!(movie.region, Region::Ntsc);
+        assert!(!movie.four_score);
+        assert_eq!(movie.start, MovieStart::PowerOn);
+    }
+
+    #[test]
+    fn parses_frames() {
+        let movie = parse(SAMPLE).unwrap();
+        assert_eq!(movie.frames.len(), 3);
+        assert_eq!(movie.frames[0], MovieFrame::default());
+        assert_eq!(movie.frames[1].commands, Commands::SOFT_RESET);
+        assert_eq!(
+            movie.frames[1].pads[0],
+            Buttons::RIGHT | Buttons::UP | Buttons::A
+        );
+        assert_eq!(movie.frames[1].pads[1], Buttons::LEFT | Buttons::B);
+        assert_eq!(movie.frames[2].pads[0], Buttons::START);
+    }
+
+    #[test]
+    fn round_trips() {
+        let movie = parse(SAMPLE).unwrap();
+        assert_eq!(parse(&write(&movie)).unwrap(), movie);
+
+        let pal = parse(&SAMPLE.replace("palFlag 0", "palFlag 1")).unwrap();
+        assert_eq!(pal.region, Region::Pal);
+        assert_eq!(parse(&write(&pal)).unwrap(), pal);
+    }
+
+    #[test]
+    fn round_trips_four_score_movies() {
+        let text = SAMPLE
+            .replace("fourscore 0", "fourscore 1")
+            .replace("||\n", "|........|.....S..||\n");
+        let movie = parse(&text).unwrap();
+        assert!(movie.four_score);
+        assert_eq!(movie.frames[0].pads[3], Buttons::SELECT);
+        assert_eq!(parse(&write(&movie)).unwrap(), movie);
+    }
+
+    #[test]
+    fn round_trips_save_state_starts() {
+        let state = crate::nes::Nes::new().save_state();
+        let movie = Movie {
+            start: MovieStart::SaveState(state),
+            ..parse(SAMPLE).unwrap()
+        };
+        assert_eq!(parse(&write(&movie)).unwrap(), movie);
+    }
+
+    #[test]
+    fn rejects_foreign_save_states() {
+        let fceux = SAMPLE.replace("comment", "savestate base64:AAEC\ncomment");
+        assert_eq!(parse(&fceux), Err(MovieError::ForeignSaveState));
+        let garbled = SAMPLE.replace("comment", "savestate 0xABC\ncomment");
+        assert!(matches!(
+            parse(&garbled),
+            Err(MovieError::Syntax { line: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_play() {
+        assert_eq!(
+            parse(&SAMPLE.replace("version 3", "version 2")),
+            Err(MovieError::UnsupportedVersion(2))
+        );
+        assert!(matches!(
+            parse(&SAMPLE.replace("version 3\n", "")),
+            Err(MovieError::Syntax { line: 1, .. })
+        ));
+        assert_eq!(
+            parse(&format!("binary 1\n{SAMPLE}")),
+            Err(MovieError::BinaryInput)
+        );
+        assert_eq!(
+            parse(&SAMPLE.replace("port1 1", "port1 2")),
+            Err(MovieError::UnsupportedDevice("zapper".into()))
+        );
+        assert!(matches!(
+            parse(&SAMPLE.replace("|0|....T...|", "|0|..T|")),
+            Err(MovieError::Syntax { line: 15, .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_blobs() {
+        assert_eq!(decode_blob("0x00FF10"), Some(vec![0x00, 0xFF, 0x10]));
+        assert_eq!(decode_blob("0x0"), None);
+        assert_eq!(decode_blob("base64:AAEC"), Some(vec![0, 1, 2]));
+        assert_eq!(decode_blob("base64:TWE="), Some(b"Ma".to_vec()));
+        assert_eq!(decode_blob("base64:A*"), None);
+        assert_eq!(decode_blob("AAEC"), None);
+    }
+}
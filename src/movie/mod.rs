@@ -0,0 +1,245 @@
+mod fm2;
+
+use crate::cartridge::Cartridge;
+use crate::input::{Buttons, FourScore, InputPorts, Joypad, Port};
+use crate::region::Region;
+use std::ops::{BitOr, BitOrAssign};
+
+/// Console-level events recorded alongside a frame's input, using FM2's
+/// command bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Commands(pub u8);
+
+impl Commands {
+    pub const NONE: Commands = Commands(0x00);
+    pub const SOFT_RESET: Commands = Commands(0x01);
+    pub const HARD_RESET: Commands = Commands(0x02);
+
+    pub fn contains(self, commands: Commands) -> bool {
+        self.0 & commands.0 == commands.0
+    }
+}
+
+impl BitOr for Commands {
+    type Output = Commands;
+
+    fn bitor(self, rhs: Commands) -> Commands {
+        Commands(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Commands {
+    fn bitor_assign(&mut self, rhs: Commands) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// What one frame of a movie holds: the commands to run before the frame
+/// and the joypads during it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: Commands,
+    /// Players 1-4. Players 3 and 4 are only used with a Four Score.
+    pub pads: [Buttons; 4],
+}
+
+/// The console state a movie begins from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MovieStart {
+    #[default]
+    PowerOn,
+    /// A save state blob to load before the first frame.
+    SaveState(Vec<u8>),
+}
+
+/// A recording of per-frame joypad input.
+///
+/// Only joypads, optionally through a Four Score, are supported; that
+/// covers the bulk of existing TAS movies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub start: MovieStart,
+    pub region: Region,
+    pub four_score: bool,
+    pub rom_filename: String,
+    /// Checksum as written by the recording emulator, kept verbatim. FM2
+    /// movies hold the MD5 of the ROM, see [`Movie::matches_rom`].
+    pub rom_checksum: Option<String>,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+    pub frames: Vec<MovieFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    UnsupportedVersion(u32),
+    /// A port holds something other than joypads.
+    UnsupportedDevice(String),
+    /// FM2's binary input encoding.
+    BinaryInput,
+    /// The movie starts from another emulator's save state.
+    ForeignSaveState,
+}
+
+impl std::fmt::Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovieError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {version}")
+            }
+            MovieError::UnsupportedDevice(device) => write!(f, "unsupported input device {device}"),
+            MovieError::BinaryInput => write!(f, "binary movie input is not supported"),
+            MovieError::ForeignSaveState => {
+                write!(f, "movie starts from a save state made by another emulator")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl Movie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses an FCEUX FM2 movie. Movies that start from an FCEUX save
+    /// state are rejected; only those from [`Movie::to_fm2`] can be played.
+    pub fn from_fm2(text: &str) -> Result<Movie, MovieError> {
+        fm2::parse(text)
+    }
+
+    /// Writes the movie in FCEUX's FM2 format. Dendy timing is written as
+    /// NTSC, and a save state start can only be replayed by this emulator.
+    pub fn to_fm2(&self) -> String {
+        fm2::write(self)
+    }
+
+    /// False when the movie's checksum is an MD5 and differs from
+    /// `cartridge`'s. Checksums in other forms can't be checked and match.
+    pub fn matches_rom(&self, cartridge: &Cartridge) -> bool {
+        match self.rom_checksum.as_deref().and_then(fm2::decode_blob) {
+            Some(md5) if md5.len() == 16 => md5 == cartridge.md5(),
+            _ => true,
+        }
+    }
+
+    /// Plugs in the devices the movie was recorded with.
+    pub fn connect_devices(&self, input: &mut InputPorts) {
+        if self.four_score {
+            input.connect_four_score();
+        } else {
+            input.connect(Port::One, Joypad::new());
+            input.connect(Port::Two, Joypad::new());
+        }
+    }
+}
+
+/// Collects a frame of input at a time from the connected joypads.
+#[derive(Debug, Clone, Default)]
+pub struct MovieRecorder {
+    movie: Movie,
+    pending: Commands,
+}
+
+impl MovieRecorder {
+    /// Starts recording. `movie` supplies the start state and settings;
+    /// any frames it has are kept, so recording can resume a movie.
+    pub fn new(movie: Movie) -> Self {
+        MovieRecorder {
+            movie,
+            pending: Commands::NONE,
+        }
+    }
+
+    /// Notes a reset, which is saved with the next recorded frame.
+    pub fn record_commands(&mut self, commands: Commands) {
+        self.pending |= commands;
+    }
+
+    /// Records the buttons held for the frame about to run.
+    pub fn record_frame(&mut self, input: &InputPorts) {
+        let mut pads = [Buttons::NONE; 4];
+        if self.movie.four_score {
+            for (port, index) in [(Port::One, 0), (Port::Two, 1)] {
+                if let Some(four_score) = input.device::<FourScore>(port) {
+                    pads[index] = four_score.joypad(0).buttons();
+                    pads[index + 2] = four_score.joypad(1).buttons();
+                }
+            }
+        } else {
+            for (port, index) in [(Port::One, 0), (Port::Two, 1)] {
+                if let Some(joypad) = input.device::<Joypad>(port) {
+                    pads[index] = joypad.buttons();
+                }
+            }
+        }
+        self.movie.frames.push(MovieFrame {
+            commands: std::mem::take(&mut self.pending),
+            pads,
+        });
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds a movie's input back a frame at a time.
+#[derive(Debug, Clone)]
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, frame: 0 }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Index of the next frame to be played.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    /// Sets the joypads for the next frame and returns the commands the
+    /// host must carry out before running it, or `None` at the end of the
+    /// movie. The devices must be those from [`Movie::connect_devices`].
+    /// [`crate::nes::Nes::play_frame`] does this and runs the frame.
+    pub fn next_frame(&mut self, input: &mut InputPorts) -> Option<Commands> {
+        let frame = *self.movie.frames.get(self.frame)?;
+        self.frame += 1;
+        if self.movie.four_score {
+            for (port, index) in [(Port::One, 0), (Port::Two, 1)] {
+                if let Some(four_score) = input.device_mut::<FourScore>(port) {
+                    four_score.joypad_mut(0).set_buttons(frame.pads[index]);
+                    four_score.joypad_mut(1).set_buttons(frame.pads[index + 2]);
+                }
+            }
+        } else {
+            for (port, index) in [(Port::One, 0), (Port::Two, 1)] {
+                if let Some(joypad) = input.joypad_mut(port) {
+                    joypad.set_buttons(frame.pads[index]);
+                }
+            }
+        }
+        Some(frame.commands)
+    }
+}
@@ -0,0 +1,462 @@
+use crate::apu::Apu;
+use crate::bus::BusEvent;
+use crate::cartridge::{Cartridge, LoadError};
+use crate::cpu::{Cpu, Variant};
+use crate::input::InputPorts;
+use crate::memory_map::{MemoryMap, RamInit};
+use crate::movie::{Commands, Movie, MoviePlayer, MovieStart};
+use crate::ppu::Ppu;
+use crate::state::{self, StateError, StateWriter};
+
+/// The whole console: the CPU and everything on its bus.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let rom = std::fs::read("game.nes")?;
+/// let mut nes = nesters::nes::Nes::from_ines(&rom)?;
+/// loop {
+///     nes.run_frame();
+///     // Show nes.ppu().frame_buffer(), play nes.apu().samples().
+/// }
+/// # }
+/// ```
+pub struct Nes {
+    cpu: Cpu,
+    memory: MemoryMap,
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Nes::new()
+    }
+}
+
+impl Nes {
+    /// A console with no cartridge, powered on.
+    pub fn new() -> Self {
+        let mut nes = Nes {
+            cpu: Cpu::new(Variant::Ricoh2A03),
+            memory: MemoryMap::new(),
+        };
+        nes.power_on();
+        nes
+    }
+
+    /// Loads an iNES image and powers on.
+    pub fn from_ines(data: &[u8]) -> Result<Nes, LoadError> {
+        let mut nes = Nes::new();
+        nes.insert_cartridge(Cartridge::from_ines(data)?);
+        Ok(nes)
+    }
+
+    /// Swaps the cartridge and power cycles, as the console would need.
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.memory.insert_cartridge(cartridge);
+        self.power_on();
+    }
+
+    /// Cold start. RAM is filled as set by [`Nes::set_ram_init`], the
+    /// cartridge's registers and RAM are cleared and the CPU starts through
+    /// its reset sequence.
+    pub fn power_on(&mut self) {
+        self.cpu = Cpu::power_on(Variant::Ricoh2A03);
+        self.memory.power_on();
+    }
+
+    /// Presses the reset button. The CPU abandons its current instruction
+    /// and runs the reset sequence, the PPU and APU are partially reset and
+    /// RAM keeps its contents.
+    pub fn reset(&mut self) {
+        self.cpu.rst();
+        self.memory.reset();
+    }
+
+    /// Sets how RAM is filled at the next power-on.
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.memory.set_ram_init(ram_init);
+    }
+
+    /// Saves the whole machine. States can be taken between any two CPU
+    /// cycles, mid-instruction included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.chunk(*b"CPU ", 2, |w| w.write(&self.cpu));
+        self.memory.save_state(&mut w);
+        w.finish()
+    }
+
+    /// Restores a state from [`Nes::save_state`]. The cartridge is checked
+    /// first, but a state that is damaged further in can leave the console
+    /// partly loaded. The frame buffer isn't saved, so it shows the old
+    /// picture until the PPU draws over it.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut chunks = state::chunks(data)?;
+        chunks.sort_by_key(|chunk| &chunk.tag != b"CART");
+        for chunk in &chunks {
+            match &chunk.tag {
+                b"CPU " => chunk.reader(2)?.read_into(&mut self.cpu)?,
+                _ => self.memory.load_chunk(chunk)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the console in the state `movie` starts from and connects its
+    /// input devices, ready for [`Nes::play_frame`] or a
+    /// [`crate::movie::MovieRecorder`]. The movie's region is forced until
+    /// [`Nes::stop_movie`]. Fails with [`StateError::WrongCartridge`] when
+    /// the movie's ROM checksum doesn't match the cartridge.
+    pub fn start_movie(&mut self, movie: &Movie) -> Result<(), StateError> {
+        if let Some(cartridge) = self.memory.cartridge() {
+            if !movie.matches_rom(cartridge) {
+                return Err(StateError::WrongCartridge);
+            }
+        }
+        self.memory.set_region_override(Some(movie.region));
+        movie.connect_devices(self.memory.input_mut());
+        match &movie.start {
+            MovieStart::PowerOn => self.power_on(),
+            MovieStart::SaveState(state) => self.load_state(state)?,
+        }
+        Ok(())
+    }
+
+    /// Runs the next frame of a movie started with [`Nes::start_movie`],
+    /// after any resets recorded with it. At the end of the movie, stops
+    /// it and returns false without running.
+    pub fn play_frame(&mut self, player: &mut MoviePlayer) -> bool {
+        let Some(commands) = player.next_frame(self.memory.input_mut()) else {
+            self.stop_movie();
+            return false;
+        };
+        if commands.contains(Commands::HARD_RESET) {
+            self.power_on();
+        } else if commands.contains(Commands::SOFT_RESET) {
+            self.reset();
+        }
+        self.run_frame();
+        true
+    }
+
+    /// Ends a movie, handing the region back to the cartridge header.
+    pub fn stop_movie(&mut self) {
+        self.memory.set_region_override(None);
+    }
+
+    /// Runs one CPU cycle, with the PPU and APU keeping pace. Returns the
+    /// CPU's bus access.
+    pub fn clock(&mut self) -> BusEvent {
+        self.memory.clock(&mut self.cpu)
+    }
+
+    pub fn run_cycles(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.clock();
+        }
+    }
+
+    /// Runs until the PPU completes the frame in progress.
+    pub fn run_frame(&mut self) {
+        let frame = self.memory.ppu().frame();
+        while self.memory.ppu().frame() == frame {
+            self.clock();
+        }
+    }
+
+    /// Runs CPU cycles until `predicate` holds, checking after each one.
+    /// Returns the number of cycles run.
+    pub fn run_until<F: FnMut(&Nes) -> bool>(&mut self, mut predicate: F) -> u64 {
+        let mut cycles = 0;
+        while !predicate(self) {
+            self.clock();
+            cycles += 1;
+        }
+        cycles
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Registers can be changed between instructions; use
+    /// [`Nes::set_pc`] to move PC.
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// Continues execution at `pc`. Only valid between instructions, see
+    /// [`Cpu::at_instruction_start`].
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.set_pc(pc);
+        self.memory.refetch(pc);
+    }
+
+    pub fn memory(&self) -> &MemoryMap {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut MemoryMap {
+        &mut self.memory
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        self.memory.ppu()
+    }
+
+    pub fn apu(&self) -> &Apu {
+        self.memory.apu()
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        self.memory.apu_mut()
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.memory.cartridge()
+    }
+
+    pub fn input(&self) -> &InputPorts {
+        self.memory.input()
+    }
+
+    pub fn input_mut(&mut self) -> &mut InputPorts {
+        self.memory.input_mut()
+    }
+
+    /// CPU cycles run since power-on.
+    pub fn cpu_cycles(&self) -> u64 {
+        self.memory.cpu_cycles()
+    }
+
+    /// Frames completed since power-on.
+    pub fn frame(&self) -> u64 {
+        self.memory.ppu().frame()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::BusDevice;
+    use crate::cartridge::program_image;
+
+    #[test]
+    fn power_on_and_reset() {
+        #[rustfmt::skip]
+        let program = [
+            0xBA,             // TSX
+            0x86, 0x12,       // STX $12
+            0x08,             // PHP
+            0x68,             // PLA
+            0x85, 0x13,       // STA $13
+            0xA9, 0x42,       // LDA #$42
+            0x85, 0x10,       // STA $10
+            0xE6, 0x11,       // loop: INC $11
+            0x4C, 0x0B, 0x80, // JMP loop
+        ];
+        let mut nes = Nes::from_ines(&program_image(&program)).unwrap();
+        nes.run_frame();
+        let ram = nes.memory().ram();
+        assert_eq!(ram[0x10], 0x42);
+        assert_ne!(ram[0x11], 0);
+        assert_eq!(ram[0x12], 0xFD);
+        assert_ne!(ram[0x13] & 0x04, 0, "I flag clear after power-on");
+        assert_eq!(ram[0x200], 0x00);
+
+        nes.memory_mut().write(0x0010, 0x00);
+        nes.reset();
+        let cycles = nes.run_until(|nes| nes.memory().ram()[0x10] == 0x42);
+        assert!(cycles < 40, "reset took {cycles} cycles");
+        assert_eq!(nes.memory().ram()[0x12], 0xFA);
+
+        nes.set_ram_init(RamInit::Ones);
+        nes.power_on();
+        assert_eq!(nes.memory().ram()[0x200], 0xFF);
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x1E,       // LDA #$1E
+            0x8D, 0x01, 0x20, // STA $2001
+            0xE6, 0x10,       // loop: INC $10
+            0xA5, 0x10,       // LDA $10
+            0x8D, 0x07, 0x20, // STA $2007
+            0x8D, 0x00, 0x40, // STA $4000
+            0x4C, 0x05, 0x80, // JMP loop
+        ];
+        let mut nes = Nes::from_ines(&program_image(&program)).unwrap();
+        nes.run_cycles(12_345);
+        let state = nes.save_state();
+
+        nes.run_frame();
+        nes.run_cycles(777);
+        let ram = nes.memory().ram().to_vec();
+        let frame = nes.ppu().frame_buffer().to_vec();
+
+        nes.load_state(&state).unwrap();
+        nes.run_frame();
+        nes.run_cycles(777);
+        assert_eq!(nes.memory().ram()[..], ram[..]);
+        assert!(nes.ppu().frame_buffer() == frame);
+        assert_eq!(nes.save_state(), {
+            let mut other = Nes::from_ines(&program_image(&program)).unwrap();
+            other.load_state(&state).unwrap();
+            other.run_frame();
+            other.run_cycles(777);
+            other.save_state()
+        });
+
+        let mut image = program_image(&program);
+        image[4] = 2;
+        image.splice(16..16, vec![0xEA; 0x4000]);
+        let mut other = Nes::from_ines(&image).unwrap();
+        assert_eq!(other.load_state(&state), Err(StateError::WrongCartridge));
+        let mut image = program_image(&program);
+        image[16 + 0x3000] = 0x60;
+        let mut other = Nes::from_ines(&image).unwrap();
+        assert_eq!(other.load_state(&state), Err(StateError::WrongCartridge));
+        assert_eq!(other.load_state(b"nope"), Err(StateError::BadMagic));
+    }
+
+    #[test]
+    fn movie_record_and_play() {
+        use crate::input::{Buttons, Port};
+        use crate::movie::{MoviePlayer, MovieRecorder};
+
+        // Sums the joypad bits into $10 and counts passes in PRG RAM.
+        let program = crate::asm!(
+            ".org $8000",
+            "loop: LDA #1; STA $4016; LDA #0; STA $4016",
+            "LDX #8",
+            "read: LDA $4016; AND #1; CLC; ADC $10; STA $10; DEX; BNE read",
+            "INC $6000",
+            "JMP loop",
+        );
+        let mut nes = Nes::from_ines(&program_image(&program.bytes)).unwrap();
+        let pads = [Buttons::A, Buttons::START | Buttons::UP, Buttons::NONE];
+
+        let run = |nes: &mut Nes| {
+            nes.run_frame();
+            nes.run_cycles(1234);
+        };
+        run(&mut nes);
+        nes.start_movie(&Movie::new()).unwrap();
+        let mut recorder = MovieRecorder::new(Movie::new());
+        for buttons in pads.iter().cycle().take(10) {
+            let joypad = nes.input_mut().joypad_mut(Port::One).unwrap();
+            joypad.set_buttons(*buttons);
+            recorder.record_frame(nes.input());
+            nes.run_frame();
+        }
+        let recorded = nes.save_state();
+        assert_ne!(nes.memory().ram()[0x10], 0);
+
+        // Whatever ran before, playback starts from the same power-on.
+        let movie = recorder.finish();
+        let mut nes = Nes::from_ines(&program_image(&program.bytes)).unwrap();
+        run(&mut nes);
+        run(&mut nes);
+        nes.start_movie(&movie).unwrap();
+        let mut player = MoviePlayer::new(movie);
+        while nes.play_frame(&mut player) {}
+        assert_eq!(nes.save_state(), recorded);
+    }
+
+    #[test]
+    fn movie_playback_applies_resets() {
+        use crate::movie::{Commands, MoviePlayer, MovieRecorder};
+
+        // Counts frames in RAM, which a soft reset keeps and power-on clears.
+        let program = crate::asm!(
+            ".org $8000",
+            "INC $10",
+            "loop: LDA $2002; BPL loop",
+            "INC $11",
+            "JMP loop",
+        );
+        let mut nes = Nes::from_ines(&program_image(&program.bytes)).unwrap();
+        nes.start_movie(&Movie::new()).unwrap();
+        let mut recorder = MovieRecorder::new(Movie::new());
+        for frame in 0..12 {
+            match frame {
+                4 => {
+                    recorder.record_commands(Commands::SOFT_RESET);
+                    nes.reset();
+                }
+                8 => {
+                    recorder.record_commands(Commands::HARD_RESET);
+                    nes.power_on();
+                }
+                _ => {}
+            }
+            recorder.record_frame(nes.input());
+            nes.run_frame();
+            if frame == 7 {
+                assert_eq!(nes.memory().ram()[0x10], 2);
+            }
+        }
+        let recorded = nes.save_state();
+
+        let movie = recorder.finish();
+        assert_eq!(movie.frames[4].commands, Commands::SOFT_RESET);
+        let mut nes = Nes::from_ines(&program_image(&program.bytes)).unwrap();
+        nes.start_movie(&movie).unwrap();
+        let mut player = MoviePlayer::new(movie);
+        while nes.play_frame(&mut player) {
+            if player.frame() == 8 {
+                assert_eq!(nes.memory().ram()[0x10], 2);
+            }
+        }
+        assert_eq!(nes.save_state(), recorded);
+        assert_eq!(nes.memory().ram()[0x10], 1);
+    }
+
+    #[test]
+    fn movies_check_the_rom_and_region() {
+        use crate::region::Region;
+
+        let mut nes = Nes::from_ines(&program_image(&[])).unwrap();
+        let md5 = nes.cartridge().unwrap().md5();
+        let mut movie = Movie {
+            region: Region::Pal,
+            rom_checksum: Some(format!("0x{}", md5.map(|b| format!("{b:02x}")).concat())),
+            ..Movie::new()
+        };
+        nes.start_movie(&movie).unwrap();
+        assert_eq!(nes.memory().region(), Region::Pal);
+        nes.stop_movie();
+        assert_eq!(nes.memory().region(), Region::Ntsc);
+
+        movie.rom_checksum = Some(format!("0x{}", "00".repeat(16)));
+        assert_eq!(nes.start_movie(&movie), Err(StateError::WrongCartridge));
+        // Checksums that aren't an MD5 can't be checked.
+        movie.rom_checksum = Some("unknown".into());
+        nes.start_movie(&movie).unwrap();
+    }
+
+    /// Runs nestest in its automated mode, from $C000, on the 2A03: the
+    /// official opcodes, which check that ADC and SBC ignore D, then the
+    /// unofficial ones from $C6BD. The ROM isn't redistributable, so this
+    /// needs `test_roms/nestest.nes`.
+    #[test]
+    #[ignore = "needs test_roms/nestest.nes"]
+    fn nestest() {
+        let path = "test_roms/nestest.nes";
+        let image = std::fs::read(path).unwrap_or_else(|why| panic!("Couldn't read {path}: {why}"));
+        let mut nes = Nes::from_ines(&image).unwrap();
+        // Through the reset sequence.
+        nes.run_until(|nes| nes.cpu().at_instruction_start() && nes.cpu().pc() != 0);
+        nes.set_pc(0xC000);
+        let at = |nes: &Nes, pc| nes.cpu().at_instruction_start() && nes.cpu().pc() == pc;
+        nes.run_until(|nes| at(nes, 0xC6BD) || nes.cpu_cycles() > 100_000);
+        assert_eq!(nes.cpu().pc(), 0xC6BD, "official opcodes did not finish");
+        assert_eq!(nes.memory().ram()[0x02], 0, "official opcode error code");
+        // The final RTS, which would return to the caller in manual mode.
+        nes.run_until(|nes| at(nes, 0xC66E) || nes.cpu_cycles() > 100_000);
+        assert_eq!(nes.cpu().pc(), 0xC66E, "unofficial opcodes did not finish");
+        let ram = nes.memory().ram();
+        assert_eq!((ram[0x02], ram[0x03]), (0, 0), "nestest error codes");
+    }
+}
@@ -0,0 +1,888 @@
+mod palette;
+
+pub use palette::NTSC_PALETTE;
+
+use crate::bus::BusDevice;
+use crate::region::Region;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+const DOTS_PER_LINE: u16 = 341;
+
+/// The second $2006 write reaches v a few dots after the CPU write lands.
+const VRAM_ADDR_UPDATE_DELAY: u8 = 3;
+
+pub struct Ppu {
+    region: Region,
+    ctrl: u8,
+    mask: u8,
+    vblank: bool,
+    sprite_zero_hit: bool,
+    sprite_overflow: bool,
+    oam_addr: u8,
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+    read_buffer: u8,
+    io_latch: u8,
+    vram_addr_delay: u8,
+    vram_addr_pending: u16,
+
+    oam: [u8; 256],
+    secondary_oam: [u8; 32],
+    palette: [u8; 32],
+
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    odd_frame: bool,
+    suppress_vblank: bool,
+    /// Set by power-on and reset until the end of the next vblank; writes to $2000,
+    /// $2001, $2005 and $2006 are ignored meanwhile.
+    warming_up: bool,
+
+    nt_latch: u8,
+    at_latch: u8,
+    bg_lo_latch: u8,
+    bg_hi_latch: u8,
+    bg_shift_lo: u16,
+    bg_shift_hi: u16,
+    at_shift_lo: u16,
+    at_shift_hi: u16,
+
+    sprite_count: usize,
+    sprite_pattern_lo: [u8; 8],
+    sprite_pattern_hi: [u8; 8],
+    sprite_attr: [u8; 8],
+    sprite_x: [u8; 8],
+    sprite_zero_in_line: bool,
+    next_sprite_count: usize,
+    sprite_zero_next: bool,
+
+    frame_buffer: Box<[u16]>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            region: Region::Ntsc,
+            ctrl: 0,
+            mask: 0,
+            vblank: false,
+            sprite_zero_hit: false,
+            sprite_overflow: false,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            vram_addr_delay: 0,
+            vram_addr_pending: 0,
+            oam: [0; 256],
+            secondary_oam: [0xFF; 32],
+            palette: [0; 32],
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            odd_frame: false,
+            suppress_vblank: false,
+            warming_up: false,
+            nt_latch: 0,
+            at_latch: 0,
+            bg_lo_latch: 0,
+            bg_hi_latch: 0,
+            bg_shift_lo: 0,
+            bg_shift_hi: 0,
+            at_shift_lo: 0,
+            at_shift_hi: 0,
+            sprite_count: 0,
+            sprite_pattern_lo: [0; 8],
+            sprite_pattern_hi: [0; 8],
+            sprite_attr: [0; 8],
+            sprite_x: [0; 8],
+            sprite_zero_in_line: false,
+            next_sprite_count: 0,
+            sprite_zero_next: false,
+            frame_buffer: vec![0; WIDTH * HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    /// Puts the PPU in its power-up state, keeping the region. As after
+    /// reset, register writes are ignored until the end of the first vblank.
+    pub fn power_on(&mut self) {
+        *self = Ppu {
+            region: self.region,
+            warming_up: true,
+            ..Ppu::new()
+        };
+    }
+
+    /// Reset button: PPUCTRL, PPUMASK, the scroll and the $2005/$2006
+    /// latch are cleared, as is the read buffer. OAM, palette and VRAM are
+    /// untouched.
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.read_buffer = 0;
+        self.odd_frame = false;
+        self.warming_up = true;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        if self.scanline >= region.scanlines() {
+            self.scanline = 0;
+        }
+    }
+
+    fn vblank_line(&self) -> u16 {
+        self.region.vblank_line()
+    }
+
+    fn prerender_line(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
+    /// The scanline of the next dot to be rendered.
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// The next dot to be rendered within the current scanline.
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    /// Number of completed frames.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// State of the /NMI output, true when asserted.
+    pub fn nmi(&self) -> bool {
+        self.vblank && self.ctrl & 0x80 != 0
+    }
+
+    /// Rendered pixels, one per entry: the 6-bit colour in bits 0-5 and the
+    /// PPUMASK emphasis bits in bits 6-8.
+    pub fn frame_buffer(&self) -> &[u16] {
+        &self.frame_buffer
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.mask & 0x18 != 0
+    }
+
+    fn rendering_line(&self) -> bool {
+        self.scanline < 240 || self.scanline == self.prerender_line()
+    }
+
+    /// CPU read of a PPU register ($2000-$3FFF, mirrored every 8 bytes).
+    pub fn cpu_read<B: BusDevice + ?Sized>(&mut self, addr: u16, mem: &mut B) -> u8 {
+        let data = match addr & 0x0007 {
+            0x0002 => {
+                // Reading one dot before the flag is raised suppresses it for the frame.
+                if self.scanline == self.vblank_line() && self.dot == 1 {
+                    self.suppress_vblank = true;
+                }
+                let data = (self.vblank as u8) << 7
+                    | (self.sprite_zero_hit as u8) << 6
+                    | (self.sprite_overflow as u8) << 5
+                    | (self.io_latch & 0x1F);
+                self.vblank = false;
+                self.w = false;
+                data
+            }
+            0x0004 => {
+                let data = self.oam[self.oam_addr as usize];
+                if self.oam_addr & 0x03 == 0x02 {
+                    data & 0xE3 // attribute bits 2-4 are unimplemented
+                } else {
+                    data
+                }
+            }
+            0x0007 => {
+                let addr = self.v & 0x3FFF;
+                let data = if addr >= 0x3F00 {
+                    // Palette reads bypass the buffer, which picks up the nametable underneath.
+                    self.read_buffer = mem.read(addr - 0x1000);
+                    self.read_palette(addr) | (self.io_latch & 0xC0)
+                } else {
+                    let data = self.read_buffer;
+                    self.read_buffer = mem.read(addr);
+                    data
+                };
+                self.increment_vram_addr();
+                data
+            }
+            _ => self.io_latch,
+        };
+
+        self.io_latch = data;
+        data
+    }
+
+    /// CPU write of a PPU register ($2000-$3FFF, mirrored every 8 bytes).
+    pub fn cpu_write<B: BusDevice + ?Sized>(&mut self, addr: u16, data: u8, mem: &mut B) {
+        self.io_latch = data;
+
+        if self.warming_up && matches!(addr & 0x0007, 0x0000 | 0x0001 | 0x0005 | 0x0006) {
+            return;
+        }
+
+        match addr & 0x0007 {
+            0x0000 => {
+                self.ctrl = data;
+                self.t = (self.t & !0x0C00) | ((data as u16 & 0x03) << 10);
+                // A write landing on dot 257 races the t->v horizontal copy, so the
+                // new nametable X bit reaches v directly.
+                if self.dot == 258 && self.rendering_enabled() && self.rendering_line() {
+                    self.v = (self.v & !0x0400) | ((data as u16 & 0x01) << 10);
+                }
+            }
+            0x0001 => {
+                self.mask = data;
+            }
+            0x0003 => {
+                self.oam_addr = data;
+            }
+            0x0004 => {
+                if self.rendering_enabled() && self.rendering_line() {
+                    // Writes during rendering are dropped but bump the high 6 bits.
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
+                } else {
+                    self.oam[self.oam_addr as usize] = data;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                }
+            }
+            0x0005 => {
+                if !self.w {
+                    self.t = (self.t & !0x001F) | (data as u16 >> 3);
+                    self.x = data & 0x07;
+                } else {
+                    self.t = (self.t & !0x73E0)
+                        | ((data as u16 & 0x07) << 12)
+                        | ((data as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            }
+            0x0006 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | data as u16;
+                    self.vram_addr_pending = self.t;
+                    self.vram_addr_delay = VRAM_ADDR_UPDATE_DELAY;
+                }
+                self.w = !self.w;
+            }
+            0x0007 => {
+                let addr = self.v & 0x3FFF;
+                if addr >= 0x3F00 {
+                    self.write_palette(addr, data);
+                } else {
+                    mem.write(addr, data);
+                }
+                self.increment_vram_addr();
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the PPU by one dot.
+    pub fn clock<B: BusDevice + ?Sized>(&mut self, mem: &mut B) {
+        if self.vram_addr_delay > 0 {
+            self.vram_addr_delay -= 1;
+            if self.vram_addr_delay == 0 {
+                self.v = self.vram_addr_pending;
+            }
+        }
+
+        let rendering = self.rendering_enabled();
+        let visible = self.scanline < 240;
+        let prerender = self.scanline == self.prerender_line();
+
+        if prerender && self.dot == 1 {
+            self.vblank = false;
+            self.warming_up = false;
+            self.sprite_zero_hit = false;
+            self.sprite_overflow = false;
+        }
+
+        if rendering && (visible || prerender) {
+            self.fetch(mem, visible, prerender);
+        }
+
+        if visible && (1..=256).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        if self.scanline == self.vblank_line() && self.dot == 1 {
+            if !self.suppress_vblank {
+                self.vblank = true;
+            }
+            self.suppress_vblank = false;
+        }
+
+        // Odd frames skip the last dot of the pre-render line while rendering.
+        if prerender
+            && self.dot == 339
+            && self.odd_frame
+            && rendering
+            && self.region.skips_odd_frame_dot()
+        {
+            self.dot += 1;
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > self.prerender_line() {
+                self.scanline = 0;
+                self.frame += 1;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    fn fetch<B: BusDevice + ?Sized>(&mut self, mem: &mut B, visible: bool, prerender: bool) {
+        let dot = self.dot;
+
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.shift_background();
+
+            match (dot - 1) % 8 {
+                0 => {
+                    self.load_background();
+                    self.nt_latch = mem.read(0x2000 | (self.v & 0x0FFF));
+                }
+                2 => {
+                    let v = self.v;
+                    let addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    let mut at = mem.read(addr);
+                    if v & 0x0040 != 0 {
+                        at >>= 4;
+                    }
+                    if v & 0x0002 != 0 {
+                        at >>= 2;
+                    }
+                    self.at_latch = at & 0x03;
+                }
+                4 => {
+                    self.bg_lo_latch = mem.read(self.background_pattern_addr());
+                }
+                6 => {
+                    self.bg_hi_latch = mem.read(self.background_pattern_addr() + 8);
+                }
+                7 => {
+                    self.increment_x();
+                }
+                _ => {}
+            }
+        }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.load_background();
+                self.v = (self.v & !0x041F) | (self.t & 0x041F);
+                if visible {
+                    self.evaluate_sprites();
+                }
+            }
+            280..=304 if prerender => {
+                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            320 => {
+                if visible {
+                    self.fetch_sprites(mem);
+                } else {
+                    self.sprite_count = 0;
+                    self.sprite_zero_in_line = false;
+                }
+            }
+            338 | 340 => {
+                self.nt_latch = mem.read(0x2000 | (self.v & 0x0FFF));
+            }
+            _ => {}
+        }
+
+        if (257..=320).contains(&dot) {
+            self.oam_addr = 0;
+        }
+    }
+
+    fn background_pattern_addr(&self) -> u16 {
+        ((self.ctrl as u16 & 0x10) << 8) | ((self.nt_latch as u16) << 4) | ((self.v >> 12) & 0x07)
+    }
+
+    fn shift_background(&mut self) {
+        self.bg_shift_lo <<= 1;
+        self.bg_shift_hi <<= 1;
+        self.at_shift_lo <<= 1;
+        self.at_shift_hi <<= 1;
+    }
+
+    fn load_background(&mut self) {
+        self.bg_shift_lo = (self.bg_shift_lo & 0xFF00) | self.bg_lo_latch as u16;
+        self.bg_shift_hi = (self.bg_shift_hi & 0xFF00) | self.bg_hi_latch as u16;
+        self.at_shift_lo = (self.at_shift_lo & 0xFF00) | ((self.at_latch as u16 & 0x01) * 0xFF);
+        self.at_shift_hi = (self.at_shift_hi & 0xFF00) | ((self.at_latch as u16 >> 1) * 0xFF);
+    }
+
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+        } else {
+            self.v &= !0x7000;
+            let mut y = (self.v & 0x03E0) >> 5;
+            if y == 29 {
+                y = 0;
+                self.v ^= 0x0800;
+            } else if y == 31 {
+                y = 0;
+            } else {
+                y += 1;
+            }
+            self.v = (self.v & !0x03E0) | (y << 5);
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        if self.rendering_enabled() && self.rendering_line() {
+            // $2007 access during rendering triggers both scroll increments at once.
+            self.increment_x();
+            self.increment_y();
+        } else {
+            let step = if self.ctrl & 0x04 != 0 { 32 } else { 1 };
+            self.v = self.v.wrapping_add(step) & 0x7FFF;
+        }
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl & 0x20 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Finds the sprites for the next scanline, including the hardware's
+    /// diagonal OAM scan once eight sprites have been found.
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height();
+        let line = self.scanline;
+        let in_range = |y: u8| line >= y as u16 && line < y as u16 + height;
+
+        self.secondary_oam = [0xFF; 32];
+        let mut count = 0;
+        let mut zero = false;
+        let mut n = 0;
+        let mut m = 0;
+
+        while n < 64 {
+            if count < 8 {
+                if in_range(self.oam[n * 4]) {
+                    self.secondary_oam[count * 4..count * 4 + 4]
+                        .copy_from_slice(&self.oam[n * 4..n * 4 + 4]);
+                    zero |= n == 0;
+                    count += 1;
+                }
+                n += 1;
+            } else {
+                if in_range(self.oam[n * 4 + m]) {
+                    self.sprite_overflow = true;
+                    break;
+                }
+                n += 1;
+                m = (m + 1) & 0x03;
+            }
+        }
+
+        self.next_sprite_count = count;
+        self.sprite_zero_next = zero;
+    }
+
+    fn fetch_sprites<B: BusDevice + ?Sized>(&mut self, mem: &mut B) {
+        let height = self.sprite_height();
+
+        for i in 0..self.next_sprite_count {
+            let y = self.secondary_oam[i * 4] as u16;
+            let tile = self.secondary_oam[i * 4 + 1] as u16;
+            let attr = self.secondary_oam[i * 4 + 2];
+            let x = self.secondary_oam[i * 4 + 3];
+
+            let mut row = self.scanline - y;
+            if attr & 0x80 != 0 {
+                row = height - 1 - row;
+            }
+
+            let addr = if height == 16 {
+                ((tile & 0x01) << 12) | ((tile & 0xFE) << 4) | ((row & 0x08) << 1) | (row & 0x07)
+            } else {
+                ((self.ctrl as u16 & 0x08) << 9) | (tile << 4) | row
+            };
+
+            let mut lo = mem.read(addr);
+            let mut hi = mem.read(addr + 8);
+            if attr & 0x40 != 0 {
+                lo = lo.reverse_bits();
+                hi = hi.reverse_bits();
+            }
+
+            self.sprite_pattern_lo[i] = lo;
+            self.sprite_pattern_hi[i] = hi;
+            self.sprite_attr[i] = attr;
+            self.sprite_x[i] = x;
+        }
+
+        self.sprite_count = self.next_sprite_count;
+        self.sprite_zero_in_line = self.sprite_zero_next;
+    }
+
+    fn render_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+
+        let mut bg_pixel = 0;
+        let mut bg_palette = 0;
+        if self.mask & 0x08 != 0 && (x >= 8 || self.mask & 0x02 != 0) {
+            let bit = 0x8000 >> self.x;
+            bg_pixel =
+                ((self.bg_shift_hi & bit != 0) as u8) << 1 | (self.bg_shift_lo & bit != 0) as u8;
+            bg_palette =
+                ((self.at_shift_hi & bit != 0) as u8) << 1 | (self.at_shift_lo & bit != 0) as u8;
+        }
+
+        let mut sp_pixel = 0;
+        let mut sp_palette = 0;
+        let mut sp_behind = false;
+        let mut sp_zero = false;
+        if self.mask & 0x10 != 0 && (x >= 8 || self.mask & 0x04 != 0) {
+            for i in 0..self.sprite_count {
+                let offset = x as i16 - self.sprite_x[i] as i16;
+                if !(0..8).contains(&offset) {
+                    continue;
+                }
+                let shift = 7 - offset;
+                let pixel = ((self.sprite_pattern_hi[i] >> shift) & 0x01) << 1
+                    | ((self.sprite_pattern_lo[i] >> shift) & 0x01);
+                if pixel != 0 {
+                    sp_pixel = pixel;
+                    sp_palette = (self.sprite_attr[i] & 0x03) + 4;
+                    sp_behind = self.sprite_attr[i] & 0x20 != 0;
+                    sp_zero = i == 0 && self.sprite_zero_in_line;
+                    break;
+                }
+            }
+        }
+
+        if sp_zero && bg_pixel != 0 && sp_pixel != 0 && x != 255 {
+            self.sprite_zero_hit = true;
+        }
+
+        let (pixel, palette) = match (bg_pixel, sp_pixel) {
+            (0, 0) => (0, 0),
+            (0, _) => (sp_pixel, sp_palette),
+            (_, 0) => (bg_pixel, bg_palette),
+            _ if sp_behind => (bg_pixel, bg_palette),
+            _ => (sp_pixel, sp_palette),
+        };
+
+        let color = if !self.rendering_enabled() && self.v & 0x3F00 == 0x3F00 {
+            // With rendering off, pointing v into palette RAM displays that entry.
+            self.read_palette(self.v)
+        } else {
+            self.read_palette(0x3F00 | (palette as u16) << 2 | pixel as u16)
+        };
+
+        self.frame_buffer[self.scanline as usize * WIDTH + x] =
+            color as u16 | (self.mask as u16 & 0xE0) << 1;
+    }
+
+    fn palette_index(addr: u16) -> usize {
+        let index = addr as usize & 0x1F;
+        // $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries.
+        if index & 0x13 == 0x10 {
+            index & !0x10
+        } else {
+            index
+        }
+    }
+
+    fn read_palette(&self, addr: u16) -> u8 {
+        let mask = if self.mask & 0x01 != 0 { 0x30 } else { 0x3F };
+        self.palette[Self::palette_index(addr)] & mask
+    }
+
+    fn write_palette(&mut self, addr: u16, data: u8) {
+        self.palette[Self::palette_index(addr)] = data & 0x3F;
+    }
+}
+
+// The region is console configuration and is restored by the memory map.
+// The frame buffer is left out as it is redrawn every frame; version 1
+// chunks still end with it.
+crate::state::stateful!(Ppu {
+    ctrl,
+    mask,
+    vblank,
+    sprite_zero_hit,
+    sprite_overflow,
+    oam_addr,
+    v,
+    t,
+    x,
+    w,
+    read_buffer,
+    io_latch,
+    vram_addr_delay,
+    vram_addr_pending,
+    oam,
+    secondary_oam,
+    palette,
+    scanline,
+    dot,
+    frame,
+    odd_frame,
+    suppress_vblank,
+    warming_up,
+    nt_latch,
+    at_latch,
+    bg_lo_latch,
+    bg_hi_latch,
+    bg_shift_lo,
+    bg_shift_hi,
+    at_shift_lo,
+    at_shift_hi,
+    sprite_count,
+    sprite_pattern_lo,
+    sprite_pattern_hi,
+    sprite_attr,
+    sprite_x,
+    sprite_zero_in_line,
+    next_sprite_count,
+    sprite_zero_next,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Vram([u8; 0x4000]);
+
+    impl BusDevice for Vram {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.0[addr as usize & 0x3FFF]
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.0[addr as usize & 0x3FFF] = data
+        }
+    }
+
+    fn run_to(ppu: &mut Ppu, vram: &mut Vram, scanline: u16, dot: u16) {
+        while ppu.scanline() != scanline || ppu.dot() != dot {
+            ppu.clock(vram);
+        }
+    }
+
+    #[test]
+    fn vblank_flag_and_read_race() {
+        let mut vram = Vram([0; 0x4000]);
+        let mut ppu = Ppu::new();
+
+        run_to(&mut ppu, &mut vram, 241, 2);
+        assert_eq!(ppu.cpu_read(0x2002, &mut vram) & 0x80, 0x80);
+        assert_eq!(ppu.cpu_read(0x2002, &mut vram) & 0x80, 0x00);
+
+        // Reading just before the flag is raised suppresses it for that frame.
+        run_to(&mut ppu, &mut vram, 241, 1);
+        assert_eq!(ppu.cpu_read(0x2002, &mut vram) & 0x80, 0x00);
+        ppu.clock(&mut vram);
+        assert_eq!(ppu.cpu_read(0x2002, &mut vram) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn ctrl_write_on_dot_257_reaches_v() {
+        let mut vram = Vram([0; 0x4000]);
+        let mut ppu = Ppu::new();
+        ppu.cpu_write(0x2001, 0x08, &mut vram);
+
+        // Before dot 257 the bit goes to t and is copied as usual.
+        for (scanline, data) in [(8, 0x00), (9, 0x01)] {
+            run_to(&mut ppu, &mut vram, scanline, 257);
+            ppu.cpu_write(0x2000, data, &mut vram);
+            ppu.clock(&mut vram);
+            assert_eq!(ppu.v & 0x0400, (data as u16) << 10);
+        }
+        ppu.cpu_write(0x2000, 0x00, &mut vram);
+
+        run_to(&mut ppu, &mut vram, 10, 258);
+        ppu.cpu_write(0x2000, 0x01, &mut vram);
+        assert_eq!(ppu.v & 0x0400, 0x0400);
+
+        // One dot later the copy has already happened and v is left alone.
+        run_to(&mut ppu, &mut vram, 11, 259);
+        ppu.cpu_write(0x2000, 0x00, &mut vram);
+        assert_eq!(ppu.v & 0x0400, 0x0400);
+    }
+
+    #[test]
+    fn scroll_write_near_the_horizontal_copy() {
+        let mut vram = Vram([0; 0x4000]);
+        let mut ppu = Ppu::new();
+        ppu.cpu_write(0x2001, 0x08, &mut vram);
+        let scroll_x = |ppu: &mut Ppu, vram: &mut Vram, coarse_x: u8| {
+            ppu.cpu_write(0x2005, coarse_x << 3, vram);
+            ppu.cpu_write(0x2005, 0, vram);
+        };
+
+        // Written before dot 257, coarse X is copied to v for the next line.
+        run_to(&mut ppu, &mut vram, 10, 257);
+        scroll_x(&mut ppu, &mut vram, 5);
+        ppu.clock(&mut vram);
+        assert_eq!(ppu.v & 0x001F, 5);
+
+        // One dot later it misses the copy and waits a whole line.
+        run_to(&mut ppu, &mut vram, 11, 258);
+        scroll_x(&mut ppu, &mut vram, 9);
+        run_to(&mut ppu, &mut vram, 11, 320);
+        assert_eq!(ppu.v & 0x001F, 5);
+        run_to(&mut ppu, &mut vram, 12, 258);
+        assert_eq!(ppu.v & 0x001F, 9);
+    }
+
+    #[test]
+    fn address_write_during_rendering() {
+        let mut vram = Vram([0; 0x4000]);
+        let mut ppu = Ppu::new();
+        ppu.cpu_write(0x2001, 0x08, &mut vram);
+
+        // The new address lands three dots after the write, then rendering
+        // carries on from it: the tile fetch ending on dot 104 bumps coarse X.
+        run_to(&mut ppu, &mut vram, 20, 100);
+        ppu.cpu_write(0x2006, 0x21, &mut vram);
+        ppu.cpu_write(0x2006, 0x08, &mut vram);
+        run_to(&mut ppu, &mut vram, 20, 102);
+        assert_ne!(ppu.v, 0x2108);
+        run_to(&mut ppu, &mut vram, 20, 104);
+        assert_eq!(ppu.v, 0x2108);
+        ppu.clock(&mut vram);
+        assert_eq!(ppu.v, 0x2109);
+    }
+
+    #[test]
+    fn second_2006_write_is_delayed() {
+        let mut vram = Vram([0; 0x4000]);
+        let mut ppu = Ppu::new();
+
+        ppu.cpu_write(0x2006, 0x21, &mut vram);
+        ppu.cpu_write(0x2006, 0x08, &mut vram);
+        assert_eq!(ppu.v, 0x0000);
+        for _ in 0..VRAM_ADDR_UPDATE_DELAY {
+            ppu.clock(&mut vram);
+        }
+        assert_eq!(ppu.v, 0x2108);
+    }
+
+    #[test]
+    fn writes_ignored_after_power_on() {
+        let mut vram = Vram([0; 0x4000]);
+        let mut ppu = Ppu::new();
+        ppu.power_on();
+
+        let write_addr = |ppu: &mut Ppu, vram: &mut Vram| {
+            ppu.cpu_write(0x2006, 0x21, vram);
+            ppu.cpu_write(0x2006, 0x08, vram);
+            for _ in 0..VRAM_ADDR_UPDATE_DELAY {
+                ppu.clock(vram);
+            }
+        };
+        run_to(&mut ppu, &mut vram, 261, 1);
+        write_addr(&mut ppu, &mut vram);
+        assert_eq!(ppu.v, 0x0000);
+
+        run_to(&mut ppu, &mut vram, 0, 0);
+        write_addr(&mut ppu, &mut vram);
+        assert_eq!(ppu.v, 0x2108);
+    }
+
+    /// Runs every ROM below `test_roms/DIR` through the test ROM runner.
+    /// ROMs listed in `DIR/hashes.txt`, in the `HASH NAME` lines that
+    /// `nesters-testrom --record` writes, are checked by their final
+    /// frame; the rest by blargg's status protocol. The ROMs aren't
+    /// redistributable, so these tests are ignored by default.
+    fn run_test_roms(dir: &str) {
+        use crate::testrom::{Outcome, Runner};
+        use std::path::Path;
+
+        let dir = Path::new("test_roms").join(dir);
+        let hashes = std::fs::read_to_string(dir.join("hashes.txt")).unwrap_or_default();
+        let hash = |name: &str| {
+            hashes.lines().find_map(|line| {
+                let (hash, rom) = line.split_once(' ')?;
+                (rom.trim() == name).then(|| u64::from_str_radix(hash, 16).unwrap())
+            })
+        };
+        let mut roms: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap_or_else(|why| panic!("{}: {why}", dir.display()))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+            .collect();
+        roms.sort();
+        assert!(!roms.is_empty(), "no ROMs in {}", dir.display());
+
+        let runner = Runner::default();
+        let failures: Vec<String> = roms
+            .iter()
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                let image = std::fs::read(path).unwrap();
+                runner.run(&name, &image, hash(&name))
+            })
+            .filter(|result| result.outcome != Outcome::Passed)
+            .map(|result| {
+                let outcome = result.outcome.name();
+                format!("{}: {outcome}: {}", result.name, result.message)
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    #[ignore = "needs test_roms/ppu_vbl_nmi"]
+    fn ppu_vbl_nmi_roms() {
+        run_test_roms("ppu_vbl_nmi/rom_singles");
+    }
+
+    /// scanline.nes only draws its result, so this needs a hash of the
+    /// final frame recorded from a run whose screen was checked.
+    #[test]
+    #[ignore = "needs test_roms/scanline with a hashes.txt"]
+    fn scanline_rom() {
+        run_test_roms("scanline");
+    }
+}
@@ -0,0 +1,193 @@
+use crate::state::{StateError, StateReader, StateWriter, Stateful};
+
+/// Console timing model. Selects clock dividers, frame geometry and APU rate tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclone timing: PAL clocks and 50 Hz, but a 3:1 CPU/PPU ratio, NTSC APU
+    /// tables and vblank shifted down to scanline 291.
+    Dendy,
+}
+
+impl Stateful for Region {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&(*self as u8));
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        *self = match r.read::<u8>()? {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Dendy,
+            _ => return Err(StateError::Invalid("region")),
+        };
+        Ok(())
+    }
+}
+
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// Frame counter step points in CPU cycles after a $4017 write, for the
+// 4-step and 5-step sequences.
+const NTSC_FRAME_STEPS: [[u32; 6]; 2] = [
+    [7457, 14913, 22371, 29828, 29829, 29830],
+    [7457, 14913, 22371, 29829, 37281, 37282],
+];
+const PAL_FRAME_STEPS: [[u32; 6]; 2] = [
+    [8313, 16627, 24939, 33252, 33253, 33254],
+    [8313, 16627, 24939, 33253, 41565, 41566],
+];
+
+impl Region {
+    /// Selects the region from a cartridge header: the NES 2.0 timing field, or
+    /// the rarely set iNES 1.0 TV system bit.
+    pub fn from_header_bytes(header: &[u8; 16]) -> Region {
+        if header[7] & 0x0C == 0x08 {
+            match header[12] & 0x03 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                // 2 is "multiple-region"; run those as NTSC.
+                _ => Region::Ntsc,
+            }
+        } else if header[9] & 0x01 != 0 && header[12..16].iter().all(|&b| b == 0) {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    /// Master clock frequency in Hz.
+    pub fn master_clock(&self) -> f64 {
+        match self {
+            Region::Ntsc => 236.25e6 / 11.0,
+            Region::Pal | Region::Dendy => 26.601712e6,
+        }
+    }
+
+    /// Master clock ticks per CPU cycle.
+    pub fn cpu_divider(&self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Master clock ticks per PPU dot.
+    pub fn ppu_divider(&self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock(&self) -> f64 {
+        self.master_clock() / self.cpu_divider() as f64
+    }
+
+    /// Scanlines per frame, including the pre-render line.
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The scanline on which the vblank flag is raised.
+    pub fn vblank_line(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Whether odd frames skip a dot on the pre-render line while rendering.
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        matches!(self, Region::Ntsc)
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let dots =
+            self.scanlines() as f64 * 341.0 - if self.skips_odd_frame_dot() { 0.5 } else { 0.0 };
+        self.master_clock() / (dots * self.ppu_divider() as f64)
+    }
+
+    /// CPU cycles at which the APU frame counter steps, for the 4-step
+    /// (`five_step == false`) or 5-step sequence.
+    pub fn frame_counter_steps(&self, five_step: bool) -> &'static [u32; 6] {
+        let steps = match self {
+            Region::Ntsc | Region::Dendy => &NTSC_FRAME_STEPS,
+            Region::Pal => &PAL_FRAME_STEPS,
+        };
+        &steps[five_step as usize]
+    }
+
+    /// Noise channel timer periods in CPU cycles.
+    pub fn noise_periods(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+            Region::Pal => &PAL_NOISE_PERIODS,
+        }
+    }
+
+    /// DMC output rates in CPU cycles per bit.
+    pub fn dmc_rates(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+            Region::Pal => &PAL_DMC_RATES,
+        }
+    }
+
+    /// Whether DMC DMA halts the CPU by repeating its read cycle, which clocks
+    /// read-sensitive registers ($2007, $4016/$4017) a second time. The PAL
+    /// 2A07 fixed this.
+    pub fn dmc_dma_repeats_reads(&self) -> bool {
+        !matches!(self, Region::Pal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_from_header() {
+        let mut header = [0u8; 16];
+        header[0..4].copy_from_slice(b"NES\x1A");
+        assert_eq!(Region::from_header_bytes(&header), Region::Ntsc);
+
+        header[9] = 0x01;
+        assert_eq!(Region::from_header_bytes(&header), Region::Pal);
+
+        header[9] = 0x00;
+        header[7] = 0x08;
+        header[12] = 0x03;
+        assert_eq!(Region::from_header_bytes(&header), Region::Dendy);
+        header[12] = 0x02;
+        assert_eq!(Region::from_header_bytes(&header), Region::Ntsc);
+    }
+
+    #[test]
+    fn clock_ratios() {
+        let ratio = |r: Region| r.cpu_divider() as f64 / r.ppu_divider() as f64;
+        assert_eq!(ratio(Region::Ntsc), 3.0);
+        assert_eq!(ratio(Region::Pal), 3.2);
+        assert_eq!(ratio(Region::Dendy), 3.0);
+        assert!((Region::Dendy.frame_rate() - 50.0).abs() < 0.1);
+        assert!((Region::Ntsc.frame_rate() - 60.1).abs() < 0.1);
+    }
+}
@@ -0,0 +1,317 @@
+//! Save states: a magic number, then chunks of `tag, version, length,
+//! payload`. Each component writes its own chunk, so loaders can skip chunks
+//! they do not know and leave components whose chunk is missing as they are.
+
+mod rewind;
+
+pub use rewind::Rewind;
+
+const MAGIC: &[u8; 4] = b"NSST";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    Truncated,
+    /// A chunk written by a newer version of a component.
+    UnsupportedVersion {
+        chunk: [u8; 4],
+        version: u16,
+    },
+    /// The state was saved with a different cartridge inserted.
+    WrongCartridge,
+    Invalid(&'static str),
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::Truncated => write!(f, "save state truncated"),
+            StateError::UnsupportedVersion { chunk, version } => write!(
+                f,
+                "unsupported version {version} of chunk {}",
+                String::from_utf8_lossy(chunk)
+            ),
+            StateError::WrongCartridge => write!(f, "save state is for another cartridge"),
+            StateError::Invalid(what) => write!(f, "invalid save state: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Something whose state can be written to and restored from a chunk.
+pub trait Stateful {
+    fn save(&self, w: &mut StateWriter);
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError>;
+}
+
+/// Implements [`Stateful`] by saving the listed fields in order.
+macro_rules! stateful {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::state::Stateful for $type {
+            fn save(&self, w: &mut $crate::state::StateWriter) {
+                $(w.write(&self.$field);)*
+            }
+
+            fn load(
+                &mut self,
+                r: &mut $crate::state::StateReader<'_>,
+            ) -> Result<(), $crate::state::StateError> {
+                $(r.read_into(&mut self.$field)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use stateful;
+
+#[derive(Debug)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+impl StateWriter {
+    /// Starts a save state file.
+    pub fn new() -> Self {
+        StateWriter {
+            data: MAGIC.to_vec(),
+        }
+    }
+
+    /// Writes a chunk whose payload is produced by `body`.
+    pub fn chunk(&mut self, tag: [u8; 4], version: u16, body: impl FnOnce(&mut StateWriter)) {
+        self.data.extend_from_slice(&tag);
+        self.write_raw(&version.to_le_bytes());
+        let length_at = self.data.len();
+        self.write_raw(&[0; 4]);
+        body(self);
+        let length = (self.data.len() - length_at - 4) as u32;
+        self.data[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub fn write<T: Stateful + ?Sized>(&mut self, value: &T) {
+        value.save(self);
+    }
+
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    pub fn read_raw(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.read_raw(N)?.try_into().unwrap())
+    }
+
+    /// Restores a value saved by [`StateWriter::write`] in place.
+    pub fn read_into<T: Stateful + ?Sized>(&mut self, value: &mut T) -> Result<(), StateError> {
+        value.load(self)
+    }
+
+    /// Reads a value saved by [`StateWriter::write`].
+    pub fn read<T: Stateful + Default>(&mut self) -> Result<T, StateError> {
+        let mut value = T::default();
+        value.load(self)?;
+        Ok(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// A chunk of a save state.
+pub struct Chunk<'a> {
+    pub tag: [u8; 4],
+    pub version: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    /// A reader over the payload, after checking the version is at most
+    /// `supported`.
+    pub fn reader(&self, supported: u16) -> Result<StateReader<'a>, StateError> {
+        if self.version > supported {
+            return Err(StateError::UnsupportedVersion {
+                chunk: self.tag,
+                version: self.version,
+            });
+        }
+        Ok(StateReader::new(self.data))
+    }
+}
+
+/// Splits a save state file into its chunks.
+pub fn chunks(data: &[u8]) -> Result<Vec<Chunk<'_>>, StateError> {
+    let mut r = StateReader::new(data);
+    if r.read_raw(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+        return Err(StateError::BadMagic);
+    }
+    let mut chunks = Vec::new();
+    while !r.is_empty() {
+        let tag = r.read_array()?;
+        let version = u16::from_le_bytes(r.read_array()?);
+        let length = u32::from_le_bytes(r.read_array()?);
+        let data = r.read_raw(length as usize)?;
+        chunks.push(Chunk { tag, version, data });
+    }
+    Ok(chunks)
+}
+
+macro_rules! stateful_number {
+    ($($type:ty),*) => {$(
+        impl Stateful for $type {
+            fn save(&self, w: &mut StateWriter) {
+                w.write_raw(&self.to_le_bytes());
+            }
+
+            fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+                *self = <$type>::from_le_bytes(r.read_array()?);
+                Ok(())
+            }
+        }
+    )*};
+}
+
+stateful_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, f32, f64);
+
+impl Stateful for usize {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&(*self as u64));
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        let value: u64 = r.read()?;
+        *self = usize::try_from(value).map_err(|_| StateError::Invalid("size out of range"))?;
+        Ok(())
+    }
+}
+
+impl Stateful for bool {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&(*self as u8));
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        *self = r.read::<u8>()? != 0;
+        Ok(())
+    }
+}
+
+impl<T: Stateful, const N: usize> Stateful for [T; N] {
+    fn save(&self, w: &mut StateWriter) {
+        self[..].save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        self[..].load(r)
+    }
+}
+
+/// Slices keep their length; loading one saved with another length fails.
+impl<T: Stateful> Stateful for [T] {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&(self.len() as u32));
+        for item in self {
+            w.write(item);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        if r.read::<u32>()? as usize != self.len() {
+            return Err(StateError::Invalid("array length mismatch"));
+        }
+        for item in self {
+            item.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Stateful + ?Sized> Stateful for Box<T> {
+    fn save(&self, w: &mut StateWriter) {
+        (**self).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        (**self).load(r)
+    }
+}
+
+/// Vectors are resized to the saved length.
+impl<T: Stateful + Default> Stateful for Vec<T> {
+    fn save(&self, w: &mut StateWriter) {
+        self[..].save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        let len = r.read::<u32>()? as usize;
+        if len > r.data.len() {
+            return Err(StateError::Truncated);
+        }
+        self.clear();
+        for _ in 0..len {
+            self.push(r.read()?);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Stateful + Default> Stateful for Option<T> {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&self.is_some());
+        if let Some(value) = self {
+            w.write(value);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        *self = if r.read::<bool>()? {
+            Some(r.read()?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+}
+
+impl<A: Stateful, B: Stateful> Stateful for (A, B) {
+    fn save(&self, w: &mut StateWriter) {
+        w.write(&self.0);
+        w.write(&self.1);
+    }
+
+    fn load(&mut self, r: &mut StateReader<'_>) -> Result<(), StateError> {
+        r.read_into(&mut self.0)?;
+        r.read_into(&mut self.1)
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+
+/// A ring buffer of recent save states for stepping backwards.
+///
+/// Only the newest state is kept whole. Each older one is stored as the
+/// run-length encoded XOR against its successor; consecutive frames differ
+/// in a few hundred bytes, so a deep buffer costs little and its size stays
+/// flat once full.
+///
+/// ```
+/// # use nesters::{nes::Nes, state::Rewind};
+/// let mut nes = Nes::new();
+/// let mut rewind = Rewind::new(600);
+/// for _ in 0..3 {
+///     nes.run_frame();
+///     rewind.push(nes.save_state());
+/// }
+/// while let Some(state) = rewind.pop() {
+///     nes.load_state(&state).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    /// Oldest first; the last one rebuilds the state before `newest`.
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// A buffer holding up to `capacity` states.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "rewind capacity must be at least 1");
+        Rewind {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.newest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes currently held.
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    /// Adds the newest state, dropping the oldest if full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&state, &previous));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(state);
+    }
+
+    /// Removes and returns the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self
+            .deltas
+            .pop_back()
+            .map(|delta| apply_delta(&newest, &delta));
+        Some(newest)
+    }
+}
+
+/// Encodes `target` relative to `base`: the target length, then pairs of
+/// (unchanged run, changed run) lengths with the changed bytes XORed
+/// against the base. Bytes past the end of `base` count as zero.
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let byte = |i: usize| target[i] ^ base.get(i).copied().unwrap_or(0);
+    let mut out = Vec::new();
+    write_varint(&mut out, target.len());
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        while i < target.len() && byte(i) == 0 {
+            i += 1;
+        }
+        write_varint(&mut out, i - start);
+        let start = i;
+        while i < target.len() && byte(i) != 0 {
+            i += 1;
+        }
+        write_varint(&mut out, i - start);
+        out.extend((start..i).map(byte));
+    }
+    out
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut out: Vec<u8> = (0..len)
+        .map(|i| base.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut i = 0;
+    while i < len {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        for byte in &mut out[i..i + changed] {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+        i += changed;
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= (byte as usize & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_states_newest_first() {
+        let states = [vec![1, 2, 3], vec![1, 5, 3], vec![4, 5, 3]];
+        let mut rewind = Rewind::new(10);
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        assert_eq!(rewind.len(), 3);
+        for state in states.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(state));
+        }
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_when_full() {
+        let mut rewind = Rewind::new(3);
+        for i in 0..5u8 {
+            rewind.push(vec![i; 4]);
+        }
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.pop(), Some(vec![4; 4]));
+        assert_eq!(rewind.pop(), Some(vec![3; 4]));
+        assert_eq!(rewind.pop(), Some(vec![2; 4]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn rewinding_past_the_oldest_state() {
+        let mut rewind = Rewind::new(2);
+        for i in 0..3u8 {
+            rewind.push(vec![i]);
+        }
+        assert_eq!(rewind.pop(), Some(vec![2]));
+        assert_eq!(rewind.pop(), Some(vec![1]));
+        // The oldest delta is gone; there is nothing further back.
+        assert_eq!(rewind.pop(), None);
+        assert_eq!(rewind.pop(), None);
+        assert_eq!((rewind.len(), rewind.memory_used()), (0, 0));
+
+        // The buffer starts over from the next push.
+        rewind.push(vec![7]);
+        rewind.push(vec![8]);
+        assert_eq!(rewind.pop(), Some(vec![8]));
+        assert_eq!(rewind.pop(), Some(vec![7]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn pushes_after_a_rewind_branch_off() {
+        let mut rewind = Rewind::new(5);
+        for i in 0..3u8 {
+            rewind.push(vec![i; 2]);
+        }
+        assert_eq!(rewind.pop(), Some(vec![2; 2]));
+        rewind.push(vec![9; 2]);
+        assert_eq!(rewind.pop(), Some(vec![9; 2]));
+        assert_eq!(rewind.pop(), Some(vec![1; 2]));
+        assert_eq!(rewind.pop(), Some(vec![0; 2]));
+    }
+
+    #[test]
+    fn deltas_across_length_changes_and_long_runs() {
+        // Runs longer than a one-byte varint, and states that grow and
+        // shrink.
+        let mut long = vec![0u8; 1000];
+        long[300] = 1;
+        long[999] = 2;
+        let states = [vec![5; 10], long.clone(), vec![5; 3], long, Vec::new()];
+        let mut rewind = Rewind::new(states.len());
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        // Mostly unchanged states cost a few bytes each.
+        assert!(rewind.memory_used() < 1100);
+        for state in states.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(state));
+        }
+    }
+}
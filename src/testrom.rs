@@ -0,0 +1,441 @@
+//! Headless runner for community test ROMs.
+//!
+//! Two kinds of ROM are understood:
+//!
+//! - Those following blargg's protocol, which report through PRG RAM: the
+//!   signature `$DE $B0 $61` at $6001, a status byte at $6000 that is $80
+//!   while running, $81 when the ROM wants the reset button pressed, and
+//!   otherwise the result code, 0 for a pass. A zero-terminated message
+//!   follows at $6004.
+//! - Those that only draw their verdict, checked by hashing the frame after
+//!   a set number of frames against a hash recorded from a known-good run.
+
+use crate::nes::Nes;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+/// Frames to wait after a reset request; the protocol asks for at least
+/// 100ms.
+const RESET_DELAY: u64 = 6;
+
+/// How one ROM did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// Still running when time ran out.
+    TimedOut,
+    /// The ROM never wrote the signature and has no expected frame hash;
+    /// its hash can be recorded as the expected one if the screen is right.
+    Unknown,
+    /// The ROM could not be loaded.
+    Error,
+}
+
+impl Outcome {
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Passed => "passed",
+            Outcome::Failed => "failed",
+            Outcome::TimedOut => "timed out",
+            Outcome::Unknown => "unknown",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: Outcome,
+    /// The result code from $6000, for ROMs using the status protocol.
+    pub code: Option<u8>,
+    /// The ROM's own message, or why it could not be run.
+    pub message: String,
+    /// Hash of the last frame, see [`frame_hash`].
+    pub hash: u64,
+    pub frames: u64,
+    pub time: Duration,
+}
+
+/// How to run each ROM.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    /// Longest a ROM using the status protocol may run.
+    pub timeout_frames: u64,
+    /// When a screen-checked ROM's frame is hashed. A ROM that has not
+    /// written the status signature by then is taken to be screen-checked.
+    pub screen_frames: u64,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Runner {
+            timeout_frames: 60 * 60,
+            screen_frames: 60 * 10,
+        }
+    }
+}
+
+impl Runner {
+    /// Runs an iNES image. With `expected_hash` the final frame decides the
+    /// outcome; otherwise the status protocol does.
+    pub fn run(&self, name: &str, image: &[u8], expected_hash: Option<u64>) -> TestResult {
+        let start = Instant::now();
+        let mut result = TestResult {
+            name: name.to_string(),
+            outcome: Outcome::Error,
+            code: None,
+            message: String::new(),
+            hash: 0,
+            frames: 0,
+            time: Duration::ZERO,
+        };
+        let mut nes = match Nes::from_ines(image) {
+            Ok(nes) => nes,
+            Err(e) => {
+                result.message = e.to_string();
+                return result;
+            }
+        };
+
+        match expected_hash {
+            Some(expected) => {
+                for _ in 0..self.screen_frames {
+                    nes.run_frame();
+                }
+                result.hash = frame_hash(&nes);
+                result.outcome = if result.hash == expected {
+                    Outcome::Passed
+                } else {
+                    result.message =
+                        format!("frame hash {:016x}, expected {expected:016x}", result.hash);
+                    Outcome::Failed
+                };
+            }
+            None => {
+                result.outcome = self.run_status(&mut nes, &mut result.code);
+                result.message = status_text(&nes);
+                result.hash = frame_hash(&nes);
+            }
+        }
+        result.frames = nes.frame();
+        result.time = start.elapsed();
+        result
+    }
+
+    fn run_status(&self, nes: &mut Nes, code: &mut Option<u8>) -> Outcome {
+        let mut seen = false;
+        let mut reset_at = None;
+        let mut resetting = false;
+        while nes.frame() < self.timeout_frames {
+            nes.run_frame();
+            if !has_signature(nes) {
+                // Status ROMs sign at once; this one is checked by screen.
+                if !seen && nes.frame() >= self.screen_frames {
+                    break;
+                }
+                continue;
+            }
+            seen = true;
+            match nes.memory().peek(0x6000) {
+                0x80 => resetting = false,
+                0x81 => {
+                    if resetting {
+                        continue;
+                    }
+                    let at = *reset_at.get_or_insert(nes.frame() + RESET_DELAY);
+                    if nes.frame() >= at {
+                        nes.reset();
+                        reset_at = None;
+                        resetting = true;
+                    }
+                }
+                status => {
+                    *code = Some(status);
+                    return if status == 0 {
+                        Outcome::Passed
+                    } else {
+                        Outcome::Failed
+                    };
+                }
+            }
+        }
+        if seen {
+            Outcome::TimedOut
+        } else {
+            Outcome::Unknown
+        }
+    }
+}
+
+fn has_signature(nes: &Nes) -> bool {
+    (0..3).all(|i| nes.memory().peek(0x6001 + i) == SIGNATURE[i as usize])
+}
+
+/// The message at $6004, if the ROM wrote the signature.
+fn status_text(nes: &Nes) -> String {
+    if !has_signature(nes) {
+        return String::new();
+    }
+    let bytes: Vec<u8> = (0x6004..0x8000)
+        .map(|addr| nes.memory().peek(addr))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+/// FNV-1a hash of the frame buffer, emphasis bits included.
+pub fn frame_hash(nes: &Nes) -> u64 {
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    for &pixel in nes.ppu().frame_buffer() {
+        for byte in pixel.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+    hash
+}
+
+/// A JSON report: totals by outcome and one object per ROM.
+pub fn json_report(results: &[TestResult]) -> String {
+    let mut out = String::from("{\n  \"summary\": {");
+    for (i, outcome) in OUTCOMES.iter().enumerate() {
+        let count = results.iter().filter(|r| r.outcome == *outcome).count();
+        let key = outcome.name().replace(' ', "_");
+        let separator = if i == 0 { "" } else { ", " };
+        write!(out, "{separator}\"{key}\": {count}").unwrap();
+    }
+    out.push_str("},\n  \"results\": [");
+    for (i, result) in results.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        let code = result
+            .code
+            .map_or("null".to_string(), |code| code.to_string());
+        write!(
+            out,
+            "    {{\"name\": \"{}\", \"outcome\": \"{}\", \"code\": {code}, \
+             \"message\": \"{}\", \"hash\": \"{:016x}\", \"frames\": {}, \"time\": {:.3}}}",
+            escape_json(&result.name),
+            result.outcome.name(),
+            escape_json(&result.message),
+            result.hash,
+            result.frames,
+            result.time.as_secs_f64(),
+        )
+        .unwrap();
+    }
+    out.push_str("\n  ]\n}\n");
+    out
+}
+
+const OUTCOMES: [Outcome; 5] = [
+    Outcome::Passed,
+    Outcome::Failed,
+    Outcome::TimedOut,
+    Outcome::Unknown,
+    Outcome::Error,
+];
+
+/// A JUnit XML report. Failures and timeouts are failures, ROMs that could
+/// not be loaded are errors and unknown outcomes are skipped.
+pub fn junit_report(results: &[TestResult]) -> String {
+    let count = |outcomes: &[Outcome]| {
+        results
+            .iter()
+            .filter(|r| outcomes.contains(&r.outcome))
+            .count()
+    };
+    let time: f64 = results.iter().map(|r| r.time.as_secs_f64()).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<testsuite name=\"nesters\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{time:.3}\">",
+        results.len(),
+        count(&[Outcome::Failed, Outcome::TimedOut]),
+        count(&[Outcome::Error]),
+        count(&[Outcome::Unknown]),
+    )
+    .unwrap();
+    for result in results {
+        write!(
+            out,
+            "  <testcase name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&result.name),
+            result.time.as_secs_f64()
+        )
+        .unwrap();
+        let message = escape_xml(&result.message);
+        let body = match (result.outcome, result.code) {
+            (Outcome::Passed, _) => None,
+            (Outcome::Failed, Some(code)) => Some(format!(
+                "<failure message=\"result code {code}\">{message}</failure>"
+            )),
+            (Outcome::Failed, None) => Some(format!("<failure message=\"{message}\"/>")),
+            (Outcome::TimedOut, _) => Some(format!(
+                "<failure message=\"timed out after {} frames\">{message}</failure>",
+                result.frames
+            )),
+            (Outcome::Unknown, _) => Some(format!(
+                "<skipped message=\"no status signature, frame hash {:016x}\"/>",
+                result.hash
+            )),
+            (Outcome::Error, _) => Some(format!("<error message=\"{message}\"/>")),
+        };
+        match body {
+            Some(body) => writeln!(out, ">\n    {body}\n  </testcase>").unwrap(),
+            None => writeln!(out, "/>").unwrap(),
+        }
+    }
+    out.push_str("</testsuite>\n");
+    out
+}
+
+fn escape_json(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("&#10;"),
+            c if (c as u32) < 0x20 => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An NROM image whose program reports `code` and `text` through the
+    /// status protocol, after asking for a reset once.
+    fn status_rom(code: u8, text: &str) -> Vec<u8> {
+        let mut program = vec![
+            0xA9, 0xDE, 0x8D, 0x01, 0x60, // LDA #$DE, STA $6001
+            0xA9, 0xB0, 0x8D, 0x02, 0x60, // LDA #$B0, STA $6002
+            0xA9, 0x61, 0x8D, 0x03, 0x60, // LDA #$61, STA $6003
+            0xAD, 0x00, 0x60, //             LDA $6000
+            0xC9, 0x81, //                   CMP #$81
+            0xF0, 0x08, //                   BEQ reset_done
+            0xA9, 0x81, 0x8D, 0x00, 0x60, // LDA #$81, STA $6000
+            0x4C, 0x1B, 0x80, //             JMP *
+        ];
+        // reset_done:
+        for (i, byte) in text.bytes().chain([0]).enumerate() {
+            let addr = 0x6004 + i as u16;
+            let [low, high] = addr.to_le_bytes();
+            program.extend([0xA9, byte, 0x8D, low, high]);
+        }
+        let jump = 0x8000 + program.len() as u16 + 5;
+        let [low, high] = jump.to_le_bytes();
+        program.extend([0xA9, code, 0x8D, 0x00, 0x60, 0x4C, low, high]);
+
+        crate::cartridge::program_image(&program)
+    }
+
+    fn runner() -> Runner {
+        Runner {
+            timeout_frames: 60,
+            screen_frames: 2,
+        }
+    }
+
+    #[test]
+    fn passes_with_result_code_zero() {
+        let passed = runner().run("pass.nes", &status_rom(0, "Passed"), None);
+        assert_eq!(passed.outcome, Outcome::Passed);
+        assert_eq!(passed.code, Some(0));
+        assert_eq!(passed.message, "Passed");
+        assert!(passed.frames > RESET_DELAY);
+    }
+
+    #[test]
+    fn fails_with_a_nonzero_result_code() {
+        let failed = runner().run("fail.nes", &status_rom(0x42, "Failed #66"), None);
+        assert_eq!(failed.outcome, Outcome::Failed);
+        assert_eq!(failed.code, Some(0x42));
+        assert_eq!(failed.message, "Failed #66");
+
+        let results = [failed];
+        assert!(json_report(&results).contains(r#""code": 66, "message": "Failed #66""#));
+        assert!(junit_report(&results)
+            .contains("<failure message=\"result code 66\">Failed #66</failure>"));
+    }
+
+    #[test]
+    fn times_out_while_still_running() {
+        let program = [
+            0xA9, 0xDE, 0x8D, 0x01, 0x60, // LDA #$DE, STA $6001
+            0xA9, 0xB0, 0x8D, 0x02, 0x60, // LDA #$B0, STA $6002
+            0xA9, 0x61, 0x8D, 0x03, 0x60, // LDA #$61, STA $6003
+            0xA9, 0x80, 0x8D, 0x00, 0x60, // LDA #$80, STA $6000
+            0x4C, 0x14, 0x80, //             JMP *
+        ];
+        let image = crate::cartridge::program_image(&program);
+        let result = runner().run("running.nes", &image, None);
+        assert_eq!(result.outcome, Outcome::TimedOut);
+        assert_eq!(result.code, None);
+        assert_eq!(result.frames, 60);
+    }
+
+    #[test]
+    fn unsigned_roms_are_unknown() {
+        let image = crate::cartridge::program_image(&[0x4C, 0x00, 0x80]); // JMP *
+        let result = runner().run("silent.nes", &image, None);
+        assert_eq!(result.outcome, Outcome::Unknown);
+        assert_eq!(result.frames, 2);
+        assert!(junit_report(&[result]).contains("skipped=\"1\""));
+    }
+
+    #[test]
+    fn checks_the_screen_against_a_hash() {
+        let screen = runner().run("screen.nes", &status_rom(0, ""), Some(0));
+        assert_eq!(screen.outcome, Outcome::Failed);
+        assert_eq!(screen.code, None);
+        let again = runner().run("screen.nes", &status_rom(0, ""), Some(screen.hash));
+        assert_eq!(again.outcome, Outcome::Passed);
+    }
+
+    #[test]
+    fn unloadable_roms_are_errors() {
+        let broken = runner().run("broken.nes", b"NES", None);
+        assert_eq!(broken.outcome, Outcome::Error);
+        assert!(!broken.message.is_empty());
+    }
+
+    #[test]
+    fn reports_escape_messages() {
+        let runner = runner();
+        let results = [
+            runner.run("pass.nes", &status_rom(0, "Passed"), None),
+            runner.run("fail.nes", &status_rom(3, "Failed <\"3\">"), None),
+            runner.run("broken.nes", b"NES", None),
+        ];
+        let json = json_report(&results);
+        assert!(json.contains("\"passed\": 1, \"failed\": 1"));
+        assert!(json.contains(r#""message": "Failed <\"3\">""#));
+        let junit = junit_report(&results);
+        assert!(junit.contains("tests=\"3\" failures=\"1\" errors=\"1\""));
+        assert!(junit.contains("Failed &lt;&quot;3&quot;&gt;"));
+    }
+}
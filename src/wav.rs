@@ -0,0 +1,185 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Streams 16-bit PCM samples to a RIFF WAVE file. The chunk sizes are
+/// patched in by [`WavWriter::finish`].
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    channels: u16,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut inner: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * 2;
+        inner.write_all(b"RIFF")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        inner.write_all(b"WAVEfmt ")?;
+        inner.write_all(&16u32.to_le_bytes())?;
+        inner.write_all(&1u16.to_le_bytes())?; // PCM
+        inner.write_all(&channels.to_le_bytes())?;
+        inner.write_all(&sample_rate.to_le_bytes())?;
+        inner.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        inner.write_all(&block_align.to_le_bytes())?;
+        inner.write_all(&16u16.to_le_bytes())?;
+        inner.write_all(b"data")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            inner,
+            channels,
+            data_len: 0,
+        })
+    }
+
+    /// Writes interleaved samples in the range -1.0..1.0; out of range values clip.
+    /// Fails without writing anything once the file would pass the 4GB
+    /// the RIFF sizes can describe.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&pcm.to_le_bytes());
+        }
+        let data_len = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|len| self.data_len.checked_add(len))
+            .filter(|&len| len <= u32::MAX - 36)
+            .ok_or_else(|| io::Error::other("WAVE data would exceed 4GB"))?;
+        self.inner.write_all(&bytes)?;
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Fills in the chunk sizes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(40))?;
+        self.inner.write_all(&self.data_len.to_le_bytes())?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// A decoded 16-bit PCM WAVE file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<i16>,
+}
+
+impl Wav {
+    /// Reads a 16-bit PCM WAVE file, skipping any chunks other than `fmt ` and `data`.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Wav> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut riff = [0u8; 12];
+        reader.read_exact(&mut riff)?;
+        if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+            return Err(invalid("not a RIFF WAVE file"));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk = [0u8; 8];
+            reader.read_exact(&mut chunk)?;
+            let len = u32::from_le_bytes(chunk[4..8].try_into().unwrap()) as usize;
+            // Read what is there rather than trusting the size, and allow the
+            // pad byte to be missing at the end of the file.
+            let mut body = Vec::new();
+            let padded = len as u64 + (len & 1) as u64;
+            reader.by_ref().take(padded).read_to_end(&mut body)?;
+            if body.len() < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            match &chunk[0..4] {
+                b"fmt " => {
+                    if len < 16 {
+                        return Err(invalid("short fmt chunk"));
+                    }
+                    let tag = u16::from_le_bytes([body[0], body[1]]);
+                    let channels = u16::from_le_bytes([body[2], body[3]]);
+                    let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                    let bits = u16::from_le_bytes([body[14], body[15]]);
+                    if tag != 1 || bits != 16 {
+                        return Err(invalid("only 16-bit PCM is supported"));
+                    }
+                    format = Some((sample_rate, channels));
+                }
+                b"data" => {
+                    let (sample_rate, channels) =
+                        format.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                    let samples = body[..len]
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                        .collect();
+                    return Ok(Wav {
+                        sample_rate,
+                        channels,
+                        samples,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Root-mean-square difference against another capture, in full-scale
+    /// units. Length mismatches count the missing tail as silence.
+    pub fn rms_difference(&self, other: &Wav) -> f64 {
+        let len = self.samples.len().max(other.samples.len());
+        if len == 0 {
+            return 0.0;
+        }
+        let sum: f64 = (0..len)
+            .map(|i| {
+                let a = self.samples.get(i).copied().unwrap_or(0) as f64;
+                let b = other.samples.get(i).copied().unwrap_or(0) as f64;
+                ((a - b) / i16::MAX as f64).powi(2)
+            })
+            .sum();
+        (sum / len as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 44100, 1).unwrap();
+        writer.write_samples(&[0.0, 0.5, -0.5, 2.0]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(bytes.len(), 44 + 8);
+
+        let wav = Wav::read(Cursor::new(bytes)).unwrap();
+        assert_eq!(wav.sample_rate, 44100);
+        assert_eq!(wav.channels, 1);
+        assert_eq!(wav.samples, vec![0, 16383, -16383, 32767]);
+        assert_eq!(wav.rms_difference(&wav), 0.0);
+
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 44100, 1).unwrap();
+        writer.data_len = u32::MAX - 40;
+        assert!(writer.write_samples(&[0.0, 0.0]).is_ok());
+        assert!(writer.write_samples(&[0.0]).is_err());
+        assert_eq!(writer.data_len, u32::MAX - 36);
+    }
+
+    #[test]
+    fn oversized_chunk() {
+        let mut bytes = b"RIFF\0\0\0\0WAVEdata".to_vec();
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend([0; 16]);
+        let error = Wav::read(Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,101 @@
+//! Runs `nesters-debug --batch` on a raw PRG image and checks what it
+//! prints.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Writes `contents` to a file in the temporary directory, named for this
+/// process so parallel runs don't collide.
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("nesters-{}-{name}", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn debug(name: &str, args: &[&str], script: &str) -> Output {
+    let program = nesters::asm!(
+        ".org $C000",
+        "reset: LDX #5",
+        "loop: INC $10; DEX; BNE loop",
+        "done: JMP done",
+        ".org $FFFA",
+        ".word done, reset, done",
+    );
+    let rom = temp_file(&format!("{name}.bin"), &program.bytes);
+    let batch = temp_file(&format!("{name}.txt"), script.as_bytes());
+    let output = Command::new(env!("CARGO_BIN_EXE_nesters-debug"))
+        .args(args)
+        .arg("--raw")
+        .arg("--batch")
+        .arg(&batch)
+        .arg(&rom)
+        .output()
+        .unwrap();
+    fs::remove_file(rom).unwrap();
+    fs::remove_file(batch).unwrap();
+    output
+}
+
+#[test]
+fn runs_a_script() {
+    let output = debug(
+        "script",
+        &[],
+        "break $C007\ncontinue\nmem $10 1\nwatch $10\nreset\nc\nd $C000 2\nquit\nregs\n",
+    );
+    assert!(output.status.success());
+    let expected = "\
+$C000              A2 05     LDX #$05                 A:00 X:00 Y:00 P:24 S:FD CYC:7 frame:0
+> break $C007
+breakpoint 1
+> continue
+breakpoint 1 hit
+$C007              4C 07 C0  JMP $C007                A:00 X:00 Y:00 P:26 S:FD CYC:58 frame:0
+> mem $10 1
+$0010  05                                               .
+> watch $10
+watchpoint 2
+> reset
+$C000              A2 05     LDX #$05                 A:00 X:00 Y:00 P:26 S:FA CYC:65 frame:0
+> c
+watchpoint 2 hit at $0010
+$C004              CA        DEX                      A:00 X:05 Y:00 P:24 S:FA CYC:72 frame:0
+> d $C000 2
+$C000  A2 05     LDX #$05
+$C002  E6 10     INC $10
+> quit
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+#[test]
+fn stops_at_the_first_error() {
+    let output = debug("error", &[], "step\nmem nowhere\nstep\n");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with("> mem nowhere\n"), "{stdout}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error.txt:2: bad number 'nowhere'"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn runs_give_up_at_the_frame_limit() {
+    // Nothing stops the loop at `done`, and there's no subroutine to finish.
+    let output = debug(
+        "limit",
+        &["--frame-limit", "2"],
+        "continue\nfinish\nc 1\nregs\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let frames: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.split(" frame:").nth(1))
+        .collect();
+    assert_eq!(frames, ["0", "2", "4", "5", "5"], "{stdout}");
+    assert_eq!(stdout.matches("frame limit reached").count(), 3, "{stdout}");
+}